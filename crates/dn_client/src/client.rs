@@ -1,18 +1,47 @@
-use crate::{ClientRouting, MessageManager, ServerTypeError};
-use crossbeam_channel::{select_biased, Receiver, Sender};
-use dn_controller::{ClientCommand, ClientEvent};
+use crate::{ClientRouting, MessageManager, ServerTypeError, RETRANSMISSION_FLOOD_THRESHOLD};
+use crossbeam_channel::{after, never, select_biased, tick, Receiver, Sender, TrySendError};
+use dn_controller::{ClientCommand, ClientEvent, DiscoveryJitter, DiscoveryMode};
 use dn_message::{
     Assembler, ClientBody, ClientCommunicationBody, ClientContentBody, Message, ServerBody,
     ServerCommunicationBody, ServerContentBody, ServerType,
 };
+use rand::Rng;
+use std::cmp::Reverse;
 use std::collections::hash_map::Entry;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::time::{Duration, Instant};
 use wg_2024::network::SourceRoutingHeader;
 use wg_2024::packet::{
     Ack, FloodRequest, FloodResponse, Fragment, Nack, NackType, NodeType, PacketType,
 };
 use wg_2024::{network::NodeId, packet::Packet};
 
+/// How often the client checks for dropped fragments whose retransmission backoff has expired
+/// and for sessions whose deadline has expired.
+const RETRANSMISSION_TICK: Duration = Duration::from_millis(5);
+
+/// Time during which a flood request with the same `(flood_id, initiator_id)` coming from the same
+/// neighbor isn't answered again.
+pub const FLOOD_RESPONSE_WINDOW: Duration = Duration::from_secs(1);
+
+/// Maximum number of packets other than fragments queued for a single neighbor whose channel is full.
+/// When the queue is full the oldest packet is dropped.
+pub const MAX_CONGESTED_PACKETS: usize = 64;
+
+/// A callback invoked with every message assembled by the client and the ID of its sender.
+pub type MessageCallback = Box<dyn Fn(ServerBody, NodeId) + Send>;
+
+/// What the messages of a batch need, requested once for the whole batch, see `send_batch`.
+///
+/// ### Fields:
+/// - `flood`: Whether a flood request has to be sent at the end of the batch.
+/// - `server_types`: The servers whose type has already been requested in the batch.
+#[derive(Default)]
+struct Batch {
+    flood: bool,
+    server_types: HashSet<NodeId>,
+}
+
 /// Represents errors related to the path of a  packet.
 ///
 /// This enum defines the different types of errors that can occur when dealing with paths in the communication system.
@@ -41,6 +70,22 @@ pub enum PathError {
 /// - `assembler`: The `Assembler` responsible for reassembling fragments for the client.
 /// - `source_routing`: The `ClientRouting` structure used for routing packets from the client.
 /// - `message_manager`: The `MessageManager` that handles message fragments, sessions, and unsent messages.
+/// - `flood_fan_out`: If any, the maximum number of neighbors a flood request is sent to, see `set_flood_fan_out`.
+/// - `flood_offset`: The index of the first neighbor the next capped flood request is sent to.
+/// - `auto_prefetch`: Whether the files linked by a fetched HTML page are requested automatically.
+/// - `answered_floods`: When the last flood request of each flood and initiator was answered, see `handle_flood_request`.
+/// - `control_flood_interval`: If any, the time between two floods while a control message waits for a path.
+/// - `routeless_control`: The destinations of the control messages waiting for a path.
+/// - `last_control_flood`: When the last flood for the waiting control messages was sent.
+/// - `discovery_mode`: When the client first floods the network, see `DiscoveryMode`.
+/// - `discovery_jitter`: If any, picks the delay of a jittered discovery instead of a random one.
+/// - `discovery_pending`: Whether the first flood of a jittered or lazy discovery hasn't been sent yet.
+/// - `backpressure`: Whether packets are sent with `try_send`, queuing them when a channel is full, see `send_to_neighbor`.
+/// - `congested_packets`: The packets other than fragments waiting for room in the channel of a neighbor.
+/// - `congested_dests`: The destinations with fragments queued as unsent because a channel was full.
+/// - `message_callback`: If any, invoked with every assembled message, see `on_message`.
+/// - `batch`: If a batch of messages is being sent, what its messages need, see `send_batch`.
+/// - `removed_uplink`: The last neighbor, kept after the controller removed it until a new one is added, see `add_sender`.
 pub struct Client {
     pub id: NodeId,
     pub controller_send: Sender<ClientEvent>,
@@ -53,6 +98,22 @@ pub struct Client {
     pub assembler: Assembler,
     pub source_routing: ClientRouting,
     message_manager: MessageManager,
+    flood_fan_out: Option<usize>,
+    flood_offset: usize,
+    auto_prefetch: bool,
+    answered_floods: HashMap<(u64, NodeId), Instant>,
+    control_flood_interval: Option<Duration>,
+    routeless_control: HashSet<NodeId>,
+    last_control_flood: Option<Instant>,
+    discovery_mode: DiscoveryMode,
+    discovery_jitter: Option<DiscoveryJitter>,
+    discovery_pending: bool,
+    backpressure: bool,
+    congested_packets: HashMap<NodeId, VecDeque<Packet>>,
+    congested_dests: HashSet<NodeId>,
+    message_callback: Option<MessageCallback>,
+    batch: Option<Batch>,
+    removed_uplink: Option<NodeId>,
 }
 
 impl Client {
@@ -95,16 +156,170 @@ impl Client {
             assembler: Assembler::new(),
             source_routing,
             message_manager: MessageManager::new(),
+            flood_fan_out: None,
+            flood_offset: 0,
+            auto_prefetch: true,
+            answered_floods: HashMap::new(),
+            control_flood_interval: None,
+            routeless_control: HashSet::new(),
+            last_control_flood: None,
+            discovery_mode: DiscoveryMode::Immediate,
+            discovery_jitter: None,
+            discovery_pending: false,
+            backpressure: false,
+            congested_packets: HashMap::new(),
+            congested_dests: HashSet::new(),
+            message_callback: None,
+            batch: None,
+            removed_uplink: None,
+        }
+    }
+
+    /// Enables or disables the prefetching of the files linked by a fetched HTML page.
+    ///
+    /// When disabled, fetched pages are only assembled and notified to the controller.
+    ///
+    /// ### Arguments:
+    /// - `auto_prefetch`: Whether linked files are requested automatically, `true` by default.
+    pub fn set_auto_prefetch(&mut self, auto_prefetch: bool) {
+        self.auto_prefetch = auto_prefetch;
+    }
+
+    /// Sets the maximum number of neighbors a flood request is sent to.
+    ///
+    /// When capped, every flood is sent to the next `fan_out` neighbors in id order, so that
+    /// consecutive floods spread over all the neighbors.
+    ///
+    /// ### Arguments:
+    /// - `fan_out`: The maximum number of neighbors reached by a flood, `None` to reach all of them.
+    pub fn set_flood_fan_out(&mut self, fan_out: Option<usize>) {
+        self.flood_fan_out = fan_out;
+    }
+
+    /// Enables the flood-and-retry of control messages, see `MessageManager::is_control_message`.
+    ///
+    /// A message that can't be sent because its destination is unreachable always triggers a flood. When enabled,
+    /// the client keeps flooding every `interval` as long as a control message is waiting for a path, instead of
+    /// waiting for other traffic to discover it. The message is sent as soon as its destination becomes reachable.
+    ///
+    /// ### Arguments:
+    /// - `interval`: The time between two floods for the same control messages, `None` to flood only once.
+    pub fn set_control_flood_interval(&mut self, interval: Option<Duration>) {
+        self.control_flood_interval = interval;
+        if interval.is_none() {
+            self.routeless_control.clear();
         }
     }
 
+    /// Sets when the client first floods the network to discover the topology.
+    ///
+    /// By default the client floods as soon as it runs. In large simulations the initial flood can be delayed by a
+    /// random amount, or until the first message is sent, so that the nodes don't all flood at once.
+    ///
+    /// ### Arguments:
+    /// - `discovery_mode`: The discovery mode, see `DiscoveryMode`.
+    pub fn set_discovery_mode(&mut self, discovery_mode: DiscoveryMode) {
+        self.discovery_mode = discovery_mode;
+    }
+
+    /// Sets how the delay of a jittered discovery is picked, by default it's random.
+    ///
+    /// ### Arguments:
+    /// - `jitter`: The function called with the maximum delay of `DiscoveryMode::Jittered`, returning the delay to use.
+    pub fn set_discovery_jitter(&mut self, jitter: DiscoveryJitter) {
+        self.discovery_jitter = Some(jitter);
+    }
+
+    /// Enables or disables the backpressure-aware sending, meant for bounded channels.
+    ///
+    /// When enabled, packets are sent with `try_send`: if the channel of a neighbor is full the packet is queued instead of
+    /// blocking, and the queued packets are sent in order as soon as the channel has room again.
+    ///
+    /// ### Arguments:
+    /// - `backpressure`: Whether full channels are handled by queuing the packets, `false` by default.
+    pub fn set_backpressure(&mut self, backpressure: bool) {
+        self.backpressure = backpressure;
+    }
+
+    /// Registers a callback invoked with every message assembled by the client, replacing the previous one.
+    ///
+    /// The `ClientEvent::MessageAssembled` events are still sent to the controller.
+    ///
+    /// ### Arguments:
+    /// - `callback`: The function called with the assembled message and the ID of its sender.
+    pub fn on_message(&mut self, callback: MessageCallback) {
+        self.message_callback = Some(callback);
+    }
+
+    /// Sets how many fragments can be sent to the same destination before they are acknowledged.
+    ///
+    /// The fragments exceeding the window are held back and sent as the ACKs arrive.
+    ///
+    /// ### Arguments:
+    /// - `window`: The maximum number of unacknowledged fragments per destination, `None` to send them all at once.
+    pub fn set_send_window(&mut self, window: Option<usize>) {
+        self.message_manager.set_send_window(window);
+    }
+
+    /// Sets how many sessions can be pending at the same time.
+    ///
+    /// The messages exceeding the limit are queued and sent, in order, as the earlier sessions complete or fail.
+    ///
+    /// ### Arguments:
+    /// - `max_sessions`: The maximum number of pending sessions, `None` to leave them unlimited.
+    pub fn set_max_sessions(&mut self, max_sessions: Option<usize>) {
+        self.message_manager.set_max_sessions(max_sessions);
+    }
+
+    /// Sets how many fetched files the client keeps in its cache.
+    ///
+    /// Requests for a cached file are answered locally, without reaching the content server.
+    ///
+    /// ### Arguments:
+    /// - `capacity`: The maximum number of cached files, `0` disables the cache.
+    pub fn set_file_cache_capacity(&mut self, capacity: usize) {
+        self.message_manager.set_file_cache_capacity(capacity);
+    }
+
+    /// Sets how many levels of links are followed when an HTML page is fetched.
+    ///
+    /// Links found in the pages fetched by the browse session are followed as well, up to `depth` levels.
+    ///
+    /// ### Arguments:
+    /// - `depth`: The maximum depth of the browse session, `0` disables the prefetching of linked files.
+    pub fn set_crawl_depth(&mut self, depth: usize) {
+        self.message_manager.set_crawl_depth(depth);
+    }
+
+    /// Retrieves the files available on each content server, as returned by their last `RespFilesList`.
+    ///
+    /// ### Returns:
+    /// - A `HashMap` mapping each content server to the files it holds.
+    #[must_use]
+    pub fn known_files(&self) -> HashMap<NodeId, Vec<String>> {
+        self.message_manager.get_files_catalog()
+    }
+
+    /// Sets how long a session can wait to be fully acknowledged before the client gives up on it.
+    ///
+    /// When the deadline expires, the session is dropped and `ClientEvent::MessageFailed` is sent to the controller.
+    ///
+    /// ### Arguments:
+    /// - `deadline`: The deadline of the sessions created from now on.
+    pub fn set_session_deadline(&mut self, deadline: Duration) {
+        self.message_manager.set_session_deadline(deadline);
+    }
+
     /// Runs the main event loop for the client, handling commands and packets.
     ///
-    /// This function sends an initial flood request and enters a loop where it waits for and processes commands from the controller
+    /// This function starts the discovery of the network according to the `DiscoveryMode` and enters a loop where it waits for and processes commands from the controller
     /// and packets from the network. It handles commands using the `handle_command` function and packets using the `handle_packet` function.
+    /// Dropped fragments are resent when their retransmission backoff expires, and sessions that exceed their deadline fail.
     /// The loop continues until a `ClientCommand::Return` command is received, which causes the loop to exit and the function to return.
     pub fn run(&mut self) {
-        self.send_flood_request();
+        let discovery_timer = self.start_discovery();
+
+        let retransmission_tick = tick(RETRANSMISSION_TICK);
 
         loop {
             select_biased! {
@@ -120,6 +335,17 @@ impl Client {
                     if let Ok(pckt) = packet {
                         self.handle_packet(pckt);
                     }
+                },
+                recv(discovery_timer) -> _ => {
+                    if self.discovery_pending {
+                        self.send_flood_request();
+                    }
+                },
+                recv(retransmission_tick) -> _ => {
+                    self.send_congested();
+                    self.send_due_retransmissions();
+                    self.fail_expired_sessions();
+                    self.retry_control_floods(Instant::now());
                 }
             }
         }
@@ -137,8 +363,48 @@ impl Client {
             ClientCommand::SendMessage(client_body, to) => {
                 self.handle_send_message(client_body, to);
             }
+            ClientCommand::SendBatch(messages) => self.send_batch(messages),
             ClientCommand::RemoveSender(n) => self.remove_sender(n),
             ClientCommand::AddSender(n, sender) => self.add_sender(n, sender),
+            ClientCommand::QueryTopology(sender) => _ = sender.send(self.source_routing.edges()),
+            ClientCommand::QueryPending => self.send_pending(),
+            ClientCommand::ResetServerTypes => self.message_manager.clear_server_types(),
+            ClientCommand::QueryRoute(dest) => self
+                .controller_send
+                .send(ClientEvent::Route(dest, self.source_routing.get_path(dest)))
+                .expect("Error in controller_send"),
+            ClientCommand::FlushPending(dest) => self.flush_pending(dest),
+            ClientCommand::DropPending(dest) => self.drop_pending(dest),
+            ClientCommand::SetStaticRoute(dest, path) => {
+                if self.source_routing.set_static_route(dest, path) {
+                    self.flush_pending(dest);
+                }
+            }
+            ClientCommand::ClearStaticRoute(dest) => self.source_routing.clear_static_route(dest),
+            ClientCommand::RegisterTo(server) => {
+                if !self.message_manager.is_reg_to_comm(server) {
+                    self.handle_send_message(
+                        ClientBody::ClientCommunication(
+                            ClientCommunicationBody::ReqRegistrationToChat,
+                        ),
+                        server,
+                    );
+                }
+            }
+            ClientCommand::UnregisterFrom(server) => {
+                if self.message_manager.is_reg_to_comm(server) {
+                    self.send_message(
+                        ClientBody::ClientCommunication(
+                            ClientCommunicationBody::ReqUnregistrationFromChat,
+                        ),
+                        server,
+                    );
+                }
+                self.message_manager.remove_reg_to_comm(server);
+            }
+            ClientCommand::SetPriority(dest, level) => {
+                self.message_manager.set_priority(dest, level);
+            }
             ClientCommand::Return => {}
         }
     }
@@ -245,36 +511,79 @@ impl Client {
     //---------- add/rmv sender from client ----------//
     /// Removes a sender from the packet send map and updates the routing.
     ///
-    /// This function removes the entry corresponding to the given `NodeId` (`n`) from the `packet_send` map
-    /// if the map contains more than one entry. It also removes the channel to the neighbor with the given ID from
-    /// the `source_routing`.
+    /// The neighbor is removed with `remove_neighbor`, and if any fragment has been queued again a flood request
+    /// is sent: the new paths are discovered, and the queued fragments are resent over them.
     ///
     /// ### Arguments:
     /// - `n`: The `NodeId` of the sender to remove.
     fn remove_sender(&mut self, n: NodeId) {
+        if self.remove_neighbor(n) {
+            self.send_flood_request();
+        }
+    }
+
+    /// Removes a neighbor from the packet send map and updates the routing, without flooding.
+    ///
+    /// This function removes the entry corresponding to the given `NodeId` (`n`) from the `packet_send` map
+    /// if the map contains more than one entry. It also removes the channel to the neighbor with the given ID from
+    /// the `source_routing`. If the path to any server changes as a result, it sends the unsent messages to those servers.
+    /// The last neighbor isn't removed, so that the client isn't cut off the network, but it's remembered and removed
+    /// as soon as a new neighbor is added.
+    ///
+    /// The fragments sent through the removed neighbor may never be acknowledged, so the ones not acknowledged yet
+    /// are queued again before the paths are updated. The fragments sent through the other neighbors are left untouched.
+    ///
+    /// ### Arguments:
+    /// - `n`: The `NodeId` of the neighbor to remove.
+    ///
+    /// ### Returns:
+    /// - `true`: If any fragment has been queued again, so the caller has to flood.
+    /// - `false`: Otherwise.
+    fn remove_neighbor(&mut self, n: NodeId) -> bool {
         if self.packet_send.len() < 2 {
-            return;
+            if self.packet_send.contains_key(&n) {
+                self.removed_uplink = Some(n);
+            }
+            return false;
         }
 
         self.packet_send.remove(&n);
-        self.source_routing.remove_channel_to_neighbor(n);
+        // the packets waiting for room in the channel are meant for this neighbor only
+        self.congested_packets.remove(&n);
+        let requeued = self.message_manager.requeue_in_flight(n);
+        if let Some(servers_rerouted) = self.source_routing.remove_channel_to_neighbor(n) {
+            self.send_unsent(servers_rerouted);
+        }
+        requeued
     }
 
     /// Adds a sender to the packet send map and updates the routing.
     ///
     /// This function adds a new sender for the given `NodeId` (`n`) to the `packet_send` map if the entry does not already exist.
-    /// After adding the sender, it updates the `source_routing` by adding a channel to the new neighbor. If any servers become reachable as a result,
+    /// After adding the sender, it updates the `source_routing` by adding a channel to the new neighbor. If any servers become reachable or change path as a result,
     /// it sends the unsent messages to those servers.
     ///
+    /// If the controller removed the last neighbor of the client, the client is now attached to the network through the new one:
+    /// the old neighbor is removed, queuing again the fragments sent through it, and a flood request is sent: the paths through
+    /// the new neighbor are discovered, and the queued fragments are resent over them.
+    ///
     /// ### Arguments:
     /// - `n`: The `NodeId` of the neighbor to add.
     /// - `sender`: The `Sender<Packet>` to add for the specified neighbor.
     fn add_sender(&mut self, n: NodeId, sender: Sender<Packet>) {
+        if self.removed_uplink == Some(n) {
+            // the removed neighbor has been added back
+            self.removed_uplink = None;
+        }
         if let Entry::Vacant(e) = self.packet_send.entry(n) {
             e.insert(sender);
             if let Some(servers_became_reachable) = self.source_routing.add_channel_to_neighbor(n) {
                 self.send_unsent(servers_became_reachable);
             }
+            if let Some(uplink) = self.removed_uplink.take() {
+                self.remove_neighbor(uplink);
+                self.send_flood_request();
+            }
         }
     }
 
@@ -282,10 +591,18 @@ impl Client {
     /// Sends a NACK for an unexpected recipient.
     ///
     /// This function constructs the appropriate NACK packet and send it using the `send_packet` function.
+    /// The NACK goes back through the hops the packet already traversed, so if the `hop_index` of the packet is 0
+    /// or out of the hops, there is no usable path back: the NACK is not sent and a flood request is sent instead.
     ///
     /// ### Arguments:
     /// - `packet`: A reference to the `Packet` that was received and whose routing needs to be handled.
-    fn send_unexp_recp(&self, packet: &Packet) {
+    fn send_unexp_recp(&mut self, packet: &Packet) {
+        let hop_index = packet.routing_header.hop_index;
+        if hop_index == 0 || hop_index > packet.routing_header.hops.len() {
+            self.send_flood_request();
+            return;
+        }
+
         let mut path = packet
             .routing_header
             .hops
@@ -308,17 +625,22 @@ impl Client {
             }),
         };
 
-        self.send_packet(nack);
+        if !self.send_packet(nack) {
+            self.request_flood();
+        }
     }
 
     /// Sends unsent fragments.
     ///
     /// This function iterates over the provided list of servers and their corresponding routing paths.
     /// For every server it gets all unsent fragments and send them to the server via the given path.
+    /// Servers with a higher priority are served first.
     ///
     /// ### Arguments:
     /// - `servers`: A vector of tuples, where each tuple contains a `NodeId` (server) and its corresponding routing path (a vector of `NodeId`s).
-    fn send_unsent(&mut self, servers: Vec<(NodeId, Vec<NodeId>)>) {
+    fn send_unsent(&mut self, mut servers: Vec<(NodeId, Vec<NodeId>)>) {
+        servers.sort_by_key(|(server, _)| Reverse(self.message_manager.priority(*server)));
+        let mut stale_next_hop = false;
         for (server, path) in servers {
             if path.len() >= 2 {
                 if let Some(unsents) = self.message_manager.get_unsent_fragments(server) {
@@ -332,11 +654,69 @@ impl Client {
                             pack_type: PacketType::MsgFragment(fragment),
                         };
 
-                        self.send_packet(packet);
+                        if !self.send_packet(packet) {
+                            stale_next_hop = true;
+                        }
                     }
                 }
             }
         }
+        if stale_next_hop {
+            self.request_flood();
+        }
+    }
+
+    /// Retries sending what is queued for a single destination, if there is a path to it.
+    ///
+    /// The unsent fragments are sent through the current path, while the unsent messages are handled
+    /// again as if they were just requested. The queues of the other destinations are left untouched.
+    ///
+    /// ### Arguments:
+    /// - `dest`: The destination whose queued messages have to be sent.
+    fn flush_pending(&mut self, dest: NodeId) {
+        let Some(path) = self.source_routing.get_path(dest) else {
+            return;
+        };
+        self.send_unsent(vec![(dest, path)]);
+
+        if let Some(unsent) = self.message_manager.get_unsent_message(dest) {
+            for client_body in unsent {
+                self.handle_send_message(client_body, dest);
+            }
+        }
+    }
+
+    /// Drops what is queued for a single destination, e.g. a destination known to be dead.
+    ///
+    /// Both the unsent messages and the unsent fragments are dropped, and the controller is notified with
+    /// a `ClientEvent::PendingDropped`. The queues of the other destinations are left untouched.
+    /// The fragments held back by the send window that fit in it are sent afterwards.
+    ///
+    /// ### Arguments:
+    /// - `dest`: The destination whose queued messages have to be dropped.
+    fn drop_pending(&mut self, dest: NodeId) {
+        let (messages, fragments) = self.message_manager.drop_unsent(dest);
+        self.controller_send
+            .send(ClientEvent::PendingDropped {
+                to: dest,
+                messages,
+                fragments,
+            })
+            .expect("Error in controller_send");
+        self.send_held_fragments(dest);
+    }
+
+    /// Notifies the controller about the work the client is still waiting on.
+    ///
+    /// Sends a `ClientEvent::Pending` with the unsent messages, the pending sessions and the unsent fragments.
+    fn send_pending(&self) {
+        self.controller_send
+            .send(ClientEvent::Pending {
+                unsent_messages: self.message_manager.count_unsent_messages(),
+                pending_sessions: self.message_manager.count_pending_sessions(),
+                unsent_fragments: self.message_manager.count_unsent_fragments(),
+            })
+            .expect("Error in controller_send");
     }
 
     /// Sends a message to the specified destination.
@@ -345,11 +725,26 @@ impl Client {
     /// notifying the controller about the fragmentation. Finally, it incremented the session ID.
     ///
     /// If any fragment fails to send, a flood request is initiated.
+    /// If the session limit is reached, the message is queued instead and the controller is notified.
     ///
     /// ### Arguments:
     /// - `client_body`: The body of the message to send.
     /// - `dest`: The destination node ID to send the message to.
     fn send_message(&mut self, client_body: ClientBody, dest: NodeId) {
+        if !self.message_manager.has_session_slot() {
+            self.message_manager.queue_message(client_body, dest);
+            self.controller_send
+                .send(ClientEvent::MessageQueued { to: dest })
+                .expect("Error in controller_send");
+            return;
+        }
+
+        if let ClientBody::ClientContent(ClientContentBody::ReqFile(path)) = &client_body {
+            self.message_manager.add_file_request(dest, path);
+        }
+
+        let is_control = MessageManager::is_control_message(&client_body);
+
         //fragment message and notify controller
         let fragments = self
             .assembler
@@ -363,26 +758,105 @@ impl Client {
             })
             .expect("Error in controller_send");
 
+        let to_send = self
+            .message_manager
+            .hold_fragments(self.session_id, dest, fragments.clone());
         self.message_manager
             .add_pending_session(self.session_id, dest, &fragments);
 
         let mut pkt_not_sended = false;
-        for fragment in fragments {
+        for fragment in to_send {
             if !self.send_fragment(dest, fragment, self.session_id) {
                 pkt_not_sended = true;
             }
         }
         if pkt_not_sended {
-            self.send_flood_request();
+            if is_control && self.control_flood_interval.is_some() {
+                self.routeless_control.insert(dest);
+                self.last_control_flood = Some(Instant::now());
+            }
+            self.request_flood();
+        }
+        if self.discovery_pending {
+            self.request_flood();
         }
 
         self.session_id += 1;
     }
 
+    /// Sends the messages of a batch in a single pass.
+    ///
+    /// Each message is handled as if it was sent on its own, but the type of every server is requested at most once
+    /// and at most one flood request is sent, after all the messages have been handled.
+    /// Registrations are already requested once per server.
+    ///
+    /// ### Arguments:
+    /// - `messages`: The message bodies with their destination node ID, sent in order.
+    fn send_batch(&mut self, messages: Vec<(ClientBody, NodeId)>) {
+        self.batch = Some(Batch::default());
+        for (client_body, dest) in messages {
+            self.handle_send_message(client_body, dest);
+        }
+        if self.batch.take().is_some_and(|batch| batch.flood) {
+            self.send_flood_request();
+        }
+    }
+
+    /// Sends a flood request, or defers it to the end of the batch if a batch is being sent.
+    fn request_flood(&mut self) {
+        match &mut self.batch {
+            Some(batch) => batch.flood = true,
+            None => self.send_flood_request(),
+        }
+    }
+
+    /// Sends a registration request to a communication server, unless one is already in progress.
+    ///
+    /// ### Arguments:
+    /// - `server`: The communication server to register to.
+    fn request_registration(&mut self, server: NodeId) {
+        if self.message_manager.start_registration(server) {
+            self.send_message(
+                ClientBody::ClientCommunication(ClientCommunicationBody::ReqRegistrationToChat),
+                server,
+            );
+        }
+    }
+
+    /// Starts the discovery of the network according to the `DiscoveryMode`.
+    ///
+    /// With `DiscoveryMode::Immediate` the flood request is sent right away, otherwise the discovery is left pending
+    /// until the returned timer fires or, whichever comes first, the first message is sent.
+    ///
+    /// ### Returns:
+    /// - The timer of the jittered discovery, a timer that never fires for the other modes.
+    fn start_discovery(&mut self) -> Receiver<Instant> {
+        match self.discovery_mode {
+            DiscoveryMode::Immediate => {
+                self.send_flood_request();
+                never()
+            }
+            DiscoveryMode::Jittered(max_delay) => {
+                self.discovery_pending = true;
+                let delay = match &self.discovery_jitter {
+                    Some(jitter) => jitter(max_delay),
+                    None => rand::rng().random_range(Duration::ZERO..=max_delay),
+                };
+                after(delay)
+            }
+            DiscoveryMode::Lazy => {
+                self.discovery_pending = true;
+                never()
+            }
+        }
+    }
+
     /// Sends a flood request to all nodes.
     ///
-    /// Creates a `FloodRequest` packet and sends it broadcast. Notifies the controller about the packet sent, resets `already_dropped`,
+    /// Creates a `FloodRequest` packet and sends it broadcast. Notifies the controller about the packet sent,
     /// increments `session_id` and `flood_id`, and reset the topology in the source routing.
+    /// If a flood fan-out is set, only the next neighbors in turn receive the request.
+    /// Neighbors whose channel is closed are skipped and removed, so the flood goes on through the remaining ones.
     fn send_flood_request(&mut self) {
         let flood_request_packet = Packet {
             routing_header: SourceRoutingHeader {
@@ -399,20 +873,44 @@ impl Client {
 
         self.flood_id += 1;
         self.session_id += 1;
+        self.discovery_pending = false;
 
-        self.message_manager.reset_already_dropped();
+        let mut dead_neighbors = Vec::new();
+        for neighbor in self.flood_neighbors() {
+            if !self.send_to_neighbor(neighbor, flood_request_packet.clone()) {
+                dead_neighbors.push(neighbor);
+            }
+        }
 
-        for sender in self.packet_send.values() {
-            sender
-                .send(flood_request_packet.clone())
-                .expect("Error in send");
+        self.source_routing.clear_topology();
 
-            self.controller_send
-                .send(ClientEvent::PacketSent(flood_request_packet.clone()))
-                .expect("Error in controller_send");
+        // the fragments queued again are resent once this flood discovers the new paths
+        for neighbor in dead_neighbors {
+            self.remove_neighbor(neighbor);
         }
+    }
 
-        self.source_routing.clear_topology();
+    /// Selects the neighbors the next flood request is sent to, according to the flood fan-out.
+    ///
+    /// ### Returns:
+    /// - A vector of the neighbors to flood, all of them if no fan-out is set.
+    fn flood_neighbors(&mut self) -> Vec<NodeId> {
+        let mut neighbors = self.packet_send.keys().copied().collect::<Vec<_>>();
+        neighbors.sort_unstable();
+
+        match self.flood_fan_out {
+            Some(fan_out) if fan_out < neighbors.len() => {
+                let offset = self.flood_offset % neighbors.len();
+                self.flood_offset = offset + fan_out;
+                neighbors
+                    .into_iter()
+                    .cycle()
+                    .skip(offset)
+                    .take(fan_out)
+                    .collect()
+            }
+            _ => neighbors,
+        }
     }
 
     /// Sends a message fragment to the specified destination.
@@ -427,7 +925,7 @@ impl Client {
     ///
     /// ### Returns:
     /// - `true`: If the fragment was sent successfully.
-    /// - `false`: Otherwise
+    /// - `false`: Otherwise, the fragment is added to the unsent fragments of `dest`.
     fn send_fragment(&mut self, dest: NodeId, fragment: Fragment, session_id: u64) -> bool {
         if let Some(path) = self.source_routing.get_path(dest) {
             let packet = Packet {
@@ -439,9 +937,7 @@ impl Client {
                 pack_type: PacketType::MsgFragment(fragment),
             };
 
-            self.send_packet(packet);
-
-            true
+            self.send_packet(packet)
         } else {
             self.message_manager
                 .add_unsent_fragment(session_id, dest, &fragment);
@@ -453,23 +949,167 @@ impl Client {
     /// Sends a packet to the next hop in the routing path.
     ///
     /// Increases the hop index and sends the packet to the next hop. Notifies the controller about the sent packet.
+    /// If the next hop isn't a neighbor anymore the packet is dropped and a fragment is added back to the unsent
+    /// fragments of its destination. The same happens if the channel of the next hop is disconnected, e.g. because
+    /// the neighbor crashed, and the neighbor is removed as well. It's up to the caller to flood, once for all the
+    /// packets it sends.
     ///
     /// ### Arguments:
     /// - `packet`: The packet to send.
-    fn send_packet(&self, mut packet: Packet) {
+    ///
+    /// ### Returns:
+    /// - `true`: If the packet was sent, or if it has no next hop.
+    /// - `false`: If the next hop isn't a neighbor anymore or is disconnected.
+    fn send_packet(&mut self, mut packet: Packet) -> bool {
         if let Some(next_hop) = packet.routing_header.next_hop() {
             packet.routing_header.increase_hop_index();
 
-            self.packet_send
-                .get(&next_hop)
-                .unwrap()
-                .send(packet.clone())
-                .expect("Error in send");
+            if !self.packet_send.contains_key(&next_hop) {
+                self.requeue_fragment(&packet);
+                return false;
+            }
+
+            if !self.send_to_neighbor(next_hop, packet.clone()) {
+                // queued before the neighbor is removed, so that it isn't queued twice
+                self.requeue_fragment(&packet);
+                self.remove_neighbor(next_hop);
+                return false;
+            }
+            if let PacketType::MsgFragment(fragment) = &packet.pack_type {
+                self.message_manager.set_first_hop(
+                    packet.session_id,
+                    fragment.fragment_index,
+                    next_hop,
+                );
+            }
+        }
+        true
+    }
+
+    /// Adds a fragment that couldn't be sent back to the unsent fragments of its destination.
+    /// Other packets are dropped.
+    ///
+    /// ### Arguments:
+    /// - `packet`: The packet that couldn't be sent.
+    fn requeue_fragment(&mut self, packet: &Packet) {
+        if let PacketType::MsgFragment(fragment) = &packet.pack_type {
+            if let Some(&dest) = packet.routing_header.hops.last() {
+                self.message_manager
+                    .add_unsent_fragment(packet.session_id, dest, fragment);
+            }
+        }
+    }
+
+    /// Sends a packet to a neighbor and notifies the controller about the sent packet.
+    ///
+    /// With backpressure enabled the packet is sent with `try_send`. If the channel of the neighbor is full, a fragment
+    /// is queued as unsent for its destination and resent by `send_congested`, over the path known at that time.
+    /// Other packets are queued for the neighbor, up to `MAX_CONGESTED_PACKETS`, and later sent in order by `send_congested`.
+    ///
+    /// ### Arguments:
+    /// - `neighbor`: The neighbor to send the packet to.
+    /// - `packet`: The packet to send, ready for the neighbor.
+    ///
+    /// ### Returns:
+    /// - `true`: If the packet has been sent or queued.
+    /// - `false`: If the neighbor is disconnected.
+    fn send_to_neighbor(&mut self, neighbor: NodeId, packet: Packet) -> bool {
+        let sender = &self.packet_send[&neighbor];
+        if self.backpressure {
+            let congested = self.congested_packets.contains_key(&neighbor);
+            let is_fragment = matches!(packet.pack_type, PacketType::MsgFragment(_));
+            if congested && !is_fragment {
+                self.queue_congested(neighbor, packet);
+                return true;
+            }
+            match sender.try_send(packet.clone()) {
+                Ok(()) => {}
+                Err(TrySendError::Full(packet)) => {
+                    if is_fragment {
+                        self.requeue_fragment(&packet);
+                        if let Some(&dest) = packet.routing_header.hops.last() {
+                            self.congested_dests.insert(dest);
+                        }
+                    } else {
+                        self.queue_congested(neighbor, packet);
+                    }
+                    return true;
+                }
+                Err(TrySendError::Disconnected(_)) => return false,
+            }
+        } else if sender.send(packet.clone()).is_err() {
+            return false;
+        }
+
+        self.controller_send
+            .send(ClientEvent::PacketSent(packet))
+            .expect("Error in controller_send");
+        true
+    }
+
+    /// Queues a packet other than a fragment for a neighbor whose channel is full.
+    ///
+    /// If `MAX_CONGESTED_PACKETS` packets are already waiting for the neighbor, the oldest one is dropped.
+    ///
+    /// ### Arguments:
+    /// - `neighbor`: The neighbor the packet is meant for.
+    /// - `packet`: The packet to queue, ready for the neighbor.
+    fn queue_congested(&mut self, neighbor: NodeId, packet: Packet) {
+        let queue = self.congested_packets.entry(neighbor).or_default();
+        if queue.len() >= MAX_CONGESTED_PACKETS {
+            queue.pop_front();
+        }
+        queue.push_back(packet);
+    }
+
+    /// Sends what has been queued because the channel of a neighbor was full.
+    ///
+    /// The packets queued for every neighbor are sent in order, until its channel is full again. If the channel of a
+    /// neighbor is disconnected its queue is dropped and the neighbor is removed. Then the unsent fragments of the
+    /// congested destinations are sent again through their current path.
+    fn send_congested(&mut self) {
+        let mut sent = Vec::new();
+        let mut disconnected = Vec::new();
+        for (neighbor, queue) in &mut self.congested_packets {
+            let Some(sender) = self.packet_send.get(neighbor) else {
+                queue.clear();
+                continue;
+            };
+            while let Some(packet) = queue.pop_front() {
+                match sender.try_send(packet.clone()) {
+                    Ok(()) => sent.push(packet),
+                    Err(TrySendError::Full(packet)) => {
+                        queue.push_front(packet);
+                        break;
+                    }
+                    Err(TrySendError::Disconnected(_)) => {
+                        queue.clear();
+                        disconnected.push(*neighbor);
+                    }
+                }
+            }
+        }
+        self.congested_packets.retain(|_, queue| !queue.is_empty());
 
+        for packet in sent {
             self.controller_send
                 .send(ClientEvent::PacketSent(packet))
                 .expect("Error in controller_send");
         }
+
+        let mut flood = false;
+        for neighbor in disconnected {
+            flood |= self.remove_neighbor(neighbor);
+        }
+        if flood {
+            self.send_flood_request();
+        }
+
+        for dest in std::mem::take(&mut self.congested_dests) {
+            if let Some(path) = self.source_routing.get_path(dest) {
+                self.send_unsent(vec![(dest, path)]);
+            }
+        }
     }
 
     /// Provides some smart sending based on the server's response type.
@@ -478,9 +1118,20 @@ impl Client {
     /// - **`RespServerType`**: Adds the server type to the manager and sends messages based on whether the server type is Communication or Content.
     ///    - If it's a Communication server and the client isn't registered, it sends a registration request.
     ///    - If there are unsent messages, it attempts to resend them.
-    /// - **`ServerCommunication(ErrNotRegistered)`**: If the server is not registered, it sends a registration request to the server.
-    /// - **`ServerCommunication(RegistrationSuccess)`**: If the server successfully registers, it attempts to resend any unsent messages.
-    /// - **`ServerContent(RespFile)`**: If the server returns a file, it checks if the file is HTML. If it is, it extracts internal links and sends requests for each link.
+    /// - **`ServerCommunication(ErrNotRegistered)`**: If the server is not registered, it sends a registration request to the server, unless one is in progress.
+    /// - **`ServerCommunication(RegistrationSuccess | AlreadyRegistered)`**: If the server confirms the registration, the client marks
+    ///    itself as registered and attempts to resend any unsent messages.
+    /// - **`ServerCommunication(MessageAck)`**: The server received the whole message, so its session is cleared.
+    /// - **`ServerContent(RespFilesList)`**: Records the files available on the server.
+    /// - **`ServerContent(RespFile)`**: If the server returns a file, it caches it and starts a new browse session. If auto-prefetching
+    ///    is enabled and the file is HTML, it extracts internal links and requests all the ones not cached yet with a single `ReqFiles`.
+    /// - **`ServerContent(RespTypedFile)`**: Same as `RespFile`, but the file is HTML only if the server tagged it as `text/html`.
+    ///    The file is sniffed only if the server couldn't detect its type.
+    /// - **`ServerContent(RespFiles)`**: Caches all the files the server returned, following the links of the pages requested by
+    ///    the browse session up to the crawl depth. Missing files requested by the browse session are reported with `PrefetchFailed`.
+    /// - **`ServerContent(ErrFileNotFound)`**: Requests the file again to the next known content server, see
+    ///    `MessageManager::content_server_fallback`, until every content server has been tried. Errors for files
+    ///    that weren't requested to the sender are ignored.
     ///
     ///
     /// ### Arguments:
@@ -494,12 +1145,7 @@ impl Client {
                 match server_type {
                     ServerType::Communication if !self.message_manager.is_reg_to_comm(sender) => {
                         if self.message_manager.is_there_unsent_message(sender) {
-                            self.send_message(
-                                ClientBody::ClientCommunication(
-                                    ClientCommunicationBody::ReqRegistrationToChat,
-                                ),
-                                sender,
-                            );
+                            self.request_registration(sender);
                         }
                     }
                     _ => {
@@ -513,52 +1159,178 @@ impl Client {
             }
             ServerBody::ServerCommunication(comm_server_body) => match comm_server_body {
                 ServerCommunicationBody::ErrNotRegistered => {
-                    self.send_message(
-                        ClientBody::ClientCommunication(
-                            ClientCommunicationBody::ReqRegistrationToChat,
-                        ),
-                        sender,
-                    );
+                    self.request_registration(sender);
                 }
-                ServerCommunicationBody::RegistrationSuccess => {
+                ServerCommunicationBody::RegistrationSuccess
+                | ServerCommunicationBody::AlreadyRegistered => {
+                    self.message_manager.add_reg_to_comm(sender);
+
                     if let Some(unsent) = self.message_manager.get_unsent_message(sender) {
-                        for client_body in unsent {
+                        // the registration requested by RegisterTo has just been confirmed
+                        for client_body in unsent.into_iter().filter(|client_body| {
+                            !matches!(
+                                client_body,
+                                ClientBody::ClientCommunication(
+                                    ClientCommunicationBody::ReqRegistrationToChat
+                                )
+                            )
+                        }) {
                             self.send_message(client_body, sender);
                         }
                     }
                 }
-                _ => {}
+                ServerCommunicationBody::MessageAck(session_id) => {
+                    if self.message_manager.confirm_message(*session_id, sender) {
+                        self.send_held_fragments(sender);
+                        self.send_queued_messages();
+                    }
+                }
+                _ => {}
             },
-            ServerBody::ServerContent(ServerContentBody::RespFile(file, _)) => {
-                if MessageManager::is_html_file(file) {
-                    let links = MessageManager::get_internal_links(file);
-                    for link in links {
-                        self.send_message(
-                            ClientBody::ClientContent(ClientContentBody::ReqFile(link)),
-                            sender,
-                        );
+            ServerBody::ServerContent(ServerContentBody::RespFilesList(files)) => {
+                self.message_manager.add_files_list(sender, files);
+            }
+            ServerBody::ServerContent(ServerContentBody::RespFile(file, path)) => {
+                self.browse(file, path, MessageManager::is_html_file(file), sender);
+            }
+            ServerBody::ServerContent(ServerContentBody::RespTypedFile(file, path, mime)) => {
+                let is_html = match mime {
+                    Some(mime) => mime == "text/html",
+                    None => MessageManager::is_html_file(file),
+                };
+                self.browse(file, path, is_html, sender);
+            }
+            ServerBody::ServerContent(ServerContentBody::RespFiles(files)) => {
+                for (path, file) in files {
+                    match file {
+                        Ok(file) => {
+                            self.message_manager.cache_file(sender, path, file.clone());
+
+                            if let Some(level) = self.message_manager.take_crawl_level(sender, path)
+                            {
+                                self.crawl(file, MessageManager::is_html_file(file), level, sender);
+                            }
+                        }
+                        Err(_) => {
+                            if self
+                                .message_manager
+                                .take_crawl_level(sender, path)
+                                .is_some()
+                            {
+                                self.controller_send
+                                    .send(ClientEvent::PrefetchFailed {
+                                        server: sender,
+                                        path: path.clone(),
+                                    })
+                                    .expect("Error in controller_send");
+                            }
+                        }
+                    }
+                }
+            }
+            ServerBody::ServerContent(ServerContentBody::ErrFileNotFound(path)) => {
+                if self.message_manager.remove_file_request(sender, path) {
+                    match self.message_manager.content_server_fallback(path, sender) {
+                        Some(server) => self.send_message(
+                            ClientBody::ClientContent(ClientContentBody::ReqFile(path.clone())),
+                            server,
+                        ),
+                        None => self.message_manager.reset_content_server_fallback(path),
                     }
                 }
             }
+            ServerBody::ServerContent(ServerContentBody::ErrFileTooLarge(path)) => {
+                self.message_manager.remove_file_request(sender, path);
+            }
             _ => {}
         }
     }
 
+    /// Caches a file requested with `ReqFile` and starts a new browse session from it.
+    ///
+    /// ### Arguments:
+    /// - `file`: The fetched file.
+    /// - `path`: The path of the file.
+    /// - `is_html`: Whether the file is an HTML page whose links have to be followed.
+    /// - `server`: The content server the file has been fetched from.
+    fn browse(&mut self, file: &[u8], path: &str, is_html: bool, server: NodeId) {
+        self.message_manager.remove_file_request(server, path);
+        self.message_manager.reset_content_server_fallback(path);
+        self.message_manager.cache_file(server, path, file.to_vec());
+
+        self.message_manager.start_browse_session(server, path);
+        self.crawl(file, is_html, 0, server);
+    }
+
+    /// Requests the files linked by a fetched HTML page, following the links up to the crawl depth.
+    /// Nothing is requested if auto-prefetching is disabled.
+    ///
+    /// ### Arguments:
+    /// - `file`: The fetched file.
+    /// - `is_html`: Whether the file is an HTML page, nothing is requested otherwise.
+    /// - `level`: The depth of the file in the current browse session.
+    /// - `server`: The content server the file has been fetched from.
+    fn crawl(&mut self, file: &[u8], is_html: bool, level: usize, server: NodeId) {
+        if !self.auto_prefetch || !is_html {
+            return;
+        }
+
+        let links = self.message_manager.next_crawl_links(
+            server,
+            level,
+            MessageManager::get_internal_links(file),
+        );
+        if !links.is_empty() {
+            self.send_message(
+                ClientBody::ClientContent(ClientContentBody::ReqFiles(links)),
+                server,
+            );
+        }
+    }
+
     //---------- handle ----------//
     /// Handles sending messages after validating the server type.
     ///
     /// It checks whether the server is valid for the given message, handles server type errors, and sends messages accordingly.
+    /// A request for a file that is already cached is answered locally, without sending anything.
+    /// Messages addressed to the client itself are rejected and reported to the controller.
     ///
     /// ### Arguments:
     /// - `client_body`: The message body to be sent.
     /// - `dest`: The destination node ID.
     fn handle_send_message(&mut self, client_body: ClientBody, dest: NodeId) {
+        if dest == self.id {
+            self.controller_send
+                .send(ClientEvent::SelfAddressedMessage {
+                    body: client_body,
+                    id: self.id,
+                })
+                .expect("Error in controller_send");
+            return;
+        }
+
+        if let ClientBody::ClientContent(ClientContentBody::ReqFile(path)) = &client_body {
+            if let Some(file) = self.message_manager.get_cached_file(dest, path) {
+                self.notify_assembled(
+                    ServerBody::ServerContent(ServerContentBody::RespFile(file, path.clone())),
+                    dest,
+                );
+                return;
+            }
+        }
+
         if let Err(err) = self.message_manager.is_valid_send(&client_body, dest) {
             match err {
                 ServerTypeError::ServerTypeUnknown => {
                     self.message_manager.add_unsent_message(&client_body, dest);
 
-                    self.send_message(ClientBody::ReqServerType, dest);
+                    let requested = self
+                        .batch
+                        .as_mut()
+                        .is_some_and(|batch| !batch.server_types.insert(dest));
+                    if !requested {
+                        self.send_message(ClientBody::ReqServerType, dest);
+                    }
                 }
                 ServerTypeError::WrongServerType => {
                     self.controller_send
@@ -572,18 +1344,20 @@ impl Client {
             }
         } else {
             match &client_body {
+                // pings and unregistrations don't need the client to be registered
+                ClientBody::ClientCommunication(
+                    ClientCommunicationBody::Ping(_)
+                    | ClientCommunicationBody::ReqUnregistrationFromChat,
+                ) => {
+                    self.send_message(client_body, dest);
+                }
                 ClientBody::ClientCommunication(_) => {
                     if self.message_manager.is_reg_to_comm(dest) {
                         self.send_message(client_body, dest);
                     } else {
                         self.message_manager.add_unsent_message(&client_body, dest);
 
-                        self.send_message(
-                            ClientBody::ClientCommunication(
-                                ClientCommunicationBody::ReqRegistrationToChat,
-                            ),
-                            dest,
-                        );
+                        self.request_registration(dest);
                     }
                 }
 
@@ -598,7 +1372,8 @@ impl Client {
     ///
     /// It validates the header's hops and sends an acknowledgment for the fragment. Then, it attempts to reassemble the fragment
     /// into a complete message. If the message is successfully reassembled, it notifies the controller and forwards the message
-    /// to the appropriate handler.
+    /// to the appropriate handler, otherwise it notifies the controller about the reassembly progress.
+    /// The controller is also notified about the messages discarded because they are corrupted.
     ///
     /// ### Arguments:
     /// - `fragment`: The received fragment to be processed.
@@ -629,20 +1404,54 @@ impl Client {
             }),
         };
 
-        self.send_packet(ack);
+        if !self.send_packet(ack) {
+            self.request_flood();
+        }
 
         if let Some(Message::Server(server_body)) =
             self.assembler.handle_fragment(fragment, sender, session_id)
         {
+            self.notify_assembled(server_body.clone(), sender);
+
+            self.smart_sender(&server_body, sender);
+        } else if let Some((received, total)) = self.assembler.progress(sender, session_id) {
             self.controller_send
-                .send(ClientEvent::MessageAssembled {
-                    body: server_body.clone(),
+                .send(ClientEvent::MessageReassemblyProgress {
                     from: sender,
                     to: self.id,
+                    session_id,
+                    received,
+                    total,
+                })
+                .expect("Error in controller_send");
+        }
+        for (from, session_id) in self.assembler.take_corrupted_messages() {
+            self.controller_send
+                .send(ClientEvent::MessageCorrupted {
+                    from,
+                    to: self.id,
+                    session_id,
                 })
                 .expect("Error in controller_send");
+        }
+    }
 
-            self.smart_sender(&server_body, sender);
+    /// Notifies the controller and the registered callback, if any, of a message for the client.
+    ///
+    /// ### Arguments:
+    /// - `server_body`: The assembled message, or the one answered from the cache.
+    /// - `from`: The ID of the server the message comes from.
+    fn notify_assembled(&self, server_body: ServerBody, from: NodeId) {
+        self.controller_send
+            .send(ClientEvent::MessageAssembled {
+                body: server_body.clone(),
+                from,
+                to: self.id,
+            })
+            .expect("Error in controller_send");
+
+        if let Some(callback) = &self.message_callback {
+            callback(server_body, from);
         }
     }
 
@@ -650,10 +1459,19 @@ impl Client {
     ///
     /// It processes the flood response, updating the routing paths with the provided trace. If any servers become reachable,
     /// it sends the unsent messages to those servers.
+    /// Responses whose path trace doesn't contain the client are ignored, since they can't describe a path from the client.
     ///
     /// ### Arguments:
     /// - `flood_response`: The flood response containing the path trace to update the routing information.
     fn handle_flood_response(&mut self, flood_response: &FloodResponse) {
+        if !flood_response
+            .path_trace
+            .iter()
+            .any(|&(node, _)| node == self.id)
+        {
+            return;
+        }
+
         if let Some(servers_became_reachable) =
             self.source_routing.add_path(&flood_response.path_trace)
         {
@@ -664,6 +1482,7 @@ impl Client {
     /// Handles an acknowledgment packet.
     ///
     /// It processes the acknowledgment, confirms the received fragment, and updates the routing information based on the sender.
+    /// After `DUPLICATE_ACK_THRESHOLD` duplicate ACKs of the same fragment, the next pending fragment of the session is resent.
     ///
     /// ### Arguments:
     /// - `ack`: The acknowledgment packet containing the fragment index and related data.
@@ -676,16 +1495,59 @@ impl Client {
 
         let &server = header.hops.first().unwrap();
 
+        if let Some(fragment_index) = self
+            .message_manager
+            .register_duplicate_ack(session_id, ack.fragment_index)
+        {
+            if let Some((dest, fragment)) = self
+                .message_manager
+                .get_pending_fragment(session_id, fragment_index)
+            {
+                if !self.send_fragment(dest, fragment, session_id) {
+                    self.request_flood();
+                }
+            }
+        }
+
         self.message_manager
             .confirm_ack(session_id, ack.fragment_index);
+        self.send_held_fragments(server);
+        self.send_queued_messages();
+
+        // the fragments that couldn't be sent to the server go through the refreshed path right away
+        if let Some(path) = self.source_routing.correct_send_to(server) {
+            self.send_unsent(vec![(server, path)]);
+        }
+    }
 
-        self.source_routing.correct_send_to(server);
+    /// Sends the fragments held back by the send window that fit in it again.
+    ///
+    /// ### Arguments:
+    /// - `dest`: The destination whose fragments have been acknowledged.
+    fn send_held_fragments(&mut self, dest: NodeId) {
+        let mut not_sent = false;
+        for (session_id, fragment) in self.message_manager.release_held_fragments(dest) {
+            if !self.send_fragment(dest, fragment, session_id) {
+                not_sent = true;
+            }
+        }
+        if not_sent {
+            self.request_flood();
+        }
+    }
+
+    /// Sends the messages queued because of the session limit, as long as new sessions can be opened.
+    fn send_queued_messages(&mut self) {
+        while let Some((client_body, dest)) = self.message_manager.take_queued_message() {
+            self.send_message(client_body, dest);
+        }
     }
 
     /// Handles a negative acknowledgment packet.
     ///
     /// It processes different types of NACKs such as routing errors, destination issues, dropped packets, and unexpected recipients.
     /// Depending on the NACK type, the routing table is updated, flood requests are sent, and pending fragments are resent if necessary.
+    /// Dropped fragments are resent with an exponential backoff, and a flood request is sent every `RETRANSMISSION_FLOOD_THRESHOLD` drops.
     ///
     /// ### Arguments:
     /// - `nack`: The negative acknowledgment packet containing the NACK type and fragment index.
@@ -698,7 +1560,9 @@ impl Client {
 
                 self.send_flood_request();
 
-                self.source_routing.remove_node(node);
+                if let Some(servers_rerouted) = self.source_routing.remove_node(node) {
+                    self.send_unsent(servers_rerouted);
+                }
             }
             NackType::DestinationIsDrone => {
                 self.source_routing.correct_exchanged_with(&header.hops);
@@ -707,14 +1571,24 @@ impl Client {
                 //in this scenario, fragment will be added to the unsents fragments
             }
             NackType::Dropped => {
+                // the first hop of a Dropped NACK is the drone that generated it
                 self.source_routing.inc_packet_dropped(&header.hops);
 
-                if self
+                self.message_manager.schedule_retransmission(
+                    session_id,
+                    nack.fragment_index,
+                    Instant::now(),
+                );
+
+                let attempts = self
                     .message_manager
-                    .update_fragment_dropped(session_id, nack.fragment_index)
-                {
+                    .retransmission_attempts(session_id, nack.fragment_index);
+                if attempts % RETRANSMISSION_FLOOD_THRESHOLD == 0 {
                     self.send_flood_request();
                 }
+
+                // the fragment is resent when its backoff expires
+                return;
             }
             NackType::UnexpectedRecipient(_) => {
                 self.source_routing.correct_exchanged_with(&header.hops);
@@ -725,23 +1599,123 @@ impl Client {
             .message_manager
             .get_pending_fragment(session_id, nack.fragment_index)
         {
-            self.send_fragment(dest, fragment.clone(), session_id);
+            if !self.send_fragment(dest, fragment.clone(), session_id) {
+                self.request_flood();
+            }
+        }
+    }
+
+    /// Floods again if a control message is still waiting for a path and the control flood interval has elapsed.
+    ///
+    /// Destinations that became reachable or have nothing left to send are forgotten.
+    ///
+    /// ### Arguments:
+    /// - `now`: The current instant.
+    fn retry_control_floods(&mut self, now: Instant) {
+        let Some(interval) = self.control_flood_interval else {
+            return;
+        };
+
+        let unsent_fragments = self.message_manager.count_unsent_fragments();
+        let source_routing = &mut self.source_routing;
+        self.routeless_control.retain(|dest| {
+            unsent_fragments.contains_key(dest) && source_routing.get_path(*dest).is_none()
+        });
+        if self.routeless_control.is_empty() {
+            return;
+        }
+
+        if !self
+            .last_control_flood
+            .is_some_and(|last| now.duration_since(last) < interval)
+        {
+            self.last_control_flood = Some(now);
+            self.send_flood_request();
+        }
+    }
+
+    /// Resends the dropped fragments whose retransmission backoff has expired.
+    ///
+    /// Fragments that have been acknowledged in the meantime are no longer pending and are skipped.
+    /// Fragments to destinations with a higher priority are resent first.
+    fn send_due_retransmissions(&mut self) {
+        let mut due = self
+            .message_manager
+            .take_due_retransmissions(Instant::now())
+            .into_iter()
+            .filter_map(|(session_id, fragment_index)| {
+                self.message_manager
+                    .get_pending_fragment(session_id, fragment_index)
+                    .map(|(dest, fragment)| (dest, fragment, session_id))
+            })
+            .collect::<Vec<_>>();
+        due.sort_by_key(|(dest, _, _)| Reverse(self.message_manager.priority(*dest)));
+
+        let mut not_sent = false;
+        for (dest, fragment, session_id) in due {
+            if !self.send_fragment(dest, fragment, session_id) {
+                not_sent = true;
+            }
+        }
+        if not_sent {
+            self.request_flood();
+        }
+    }
+
+    /// Drops the sessions whose deadline has expired and notifies the controller about their failure.
+    ///
+    /// The failed sessions don't count in the send window anymore, so the held fragments that fit in it are sent.
+    fn fail_expired_sessions(&mut self) {
+        for (session_id, to) in self.message_manager.take_expired_sessions(Instant::now()) {
+            // the registration request may be the failed session
+            self.message_manager.cancel_registration(to);
+            self.controller_send
+                .send(ClientEvent::MessageFailed { session_id, to })
+                .expect("Error in controller_send");
+            self.send_held_fragments(to);
         }
+        self.send_queued_messages();
     }
 
     /// Handles a flood request and generates a flood response.
     ///
     /// It increments the flood request with the current client's ID, generates a corresponding flood response,
     /// and sends the response packet.
+    /// A request with the same `(flood_id, initiator_id)` as one answered in the last `FLOOD_RESPONSE_WINDOW`
+    /// is ignored, whatever its path trace, so that a neighbor can't make the client flood the network with responses
+    /// by replaying a flood with forged traces.
+    /// A request whose path trace has no node before the client can't be answered, so it's only notified to the controller.
     ///
     /// ### Arguments:
     /// - `session_id`: The session ID for the current request.
     /// - `flood_request`: The received flood request to be processed and responded to.
-    fn handle_flood_request(&self, session_id: u64, mut flood_request: FloodRequest) {
+    fn handle_flood_request(&mut self, session_id: u64, mut flood_request: FloodRequest) {
         flood_request.increment(self.id, NodeType::Client);
+        if flood_request.path_trace.len() < 2 {
+            self.controller_send
+                .send(ClientEvent::MalformedFloodRequest(flood_request))
+                .expect("Error in controller_send");
+            return;
+        }
+
+        let now = Instant::now();
+        self.answered_floods
+            .retain(|_, answered| now.duration_since(*answered) < FLOOD_RESPONSE_WINDOW);
+        match self
+            .answered_floods
+            .entry((flood_request.flood_id, flood_request.initiator_id))
+        {
+            Entry::Occupied(_) => return,
+            Entry::Vacant(entry) => {
+                entry.insert(now);
+            }
+        }
+
         let flood_response = flood_request.generate_response(session_id);
 
-        self.send_packet(flood_response);
+        if !self.send_packet(flood_response) {
+            self.request_flood();
+        }
     }
 }
 
@@ -751,7 +1725,52 @@ impl Client {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::{DEFAULT_SESSION_DEADLINE, DUPLICATE_ACK_THRESHOLD};
     use crossbeam_channel::unbounded;
+    use dn_message::{CommunicationMessage, FileError};
+    use std::sync::{Arc, Mutex};
+
+    /// Creates the client 1 with a channel to each of `neighbors`.
+    ///
+    /// ### Returns:
+    /// - The client, the receiver of its events and the receivers of its neighbors, in the same order.
+    fn test_client<const N: usize>(
+        neighbors: [NodeId; N],
+    ) -> (Client, Receiver<ClientEvent>, [Receiver<Packet>; N]) {
+        let (_, client_recv_command) = unbounded();
+        let (client_send_event, ctrl_recv_event) = unbounded();
+        let (_, client_recv) = unbounded();
+        let mut packet_send = HashMap::new();
+        let neighbor_recv = neighbors.map(|neighbor| {
+            let (send, recv) = unbounded();
+            packet_send.insert(neighbor, send);
+            recv
+        });
+
+        let client = Client::new(
+            1,
+            client_send_event,
+            client_recv_command,
+            packet_send,
+            client_recv,
+        );
+        (client, ctrl_recv_event, neighbor_recv)
+    }
+
+    /// Adds to the topology of the client a path from the client to a server, through drones.
+    fn add_path(client: &mut Client, hops: &[NodeId]) {
+        let last = hops.len() - 1;
+        let path_trace = hops
+            .iter()
+            .enumerate()
+            .map(|(i, &node)| match i {
+                0 => (node, NodeType::Client),
+                i if i == last => (node, NodeType::Server),
+                _ => (node, NodeType::Drone),
+            })
+            .collect();
+        client.source_routing.add_path(&path_trace);
+    }
 
     //---------- CLIENT TEST ----------//
     #[test]
@@ -885,4 +1904,1638 @@ mod tests {
         );
         assert!(!client.check_routing(&not_for_me_fragment).is_ok());
     }
+
+    //---------- FILE CACHE TEST ----------//
+    #[test]
+    fn client_file_cache_test() {
+        let (mut client, ctrl_recv_event, [_recv_2]) = test_client([2]);
+        client
+            .message_manager
+            .add_server_type(6, &ServerType::Content);
+
+        let req_file = ClientBody::ClientContent(ClientContentBody::ReqFile("a.txt".to_string()));
+
+        //---------- first fetch reaches the server ----------//
+        client.handle_send_message(req_file.clone(), 6);
+        client.smart_sender(
+            &ServerBody::ServerContent(ServerContentBody::RespFile(
+                b"quack".to_vec(),
+                "a.txt".to_string(),
+            )),
+            6,
+        );
+
+        //---------- second fetch is served from the cache ----------//
+        client.handle_send_message(req_file, 6);
+
+        let events = ctrl_recv_event.try_iter().collect::<Vec<_>>();
+        let req_file_sent = events
+            .iter()
+            .filter(|event| {
+                matches!(
+                    event,
+                    ClientEvent::MessageFragmented {
+                        body: ClientBody::ClientContent(ClientContentBody::ReqFile(_)),
+                        ..
+                    }
+                )
+            })
+            .count();
+        assert_eq!(req_file_sent, 1);
+        assert!(events.iter().any(|event| matches!(
+            event,
+            ClientEvent::MessageAssembled {
+                body: ServerBody::ServerContent(ServerContentBody::RespFile(file, _)),
+                from: 6,
+                ..
+            } if file == b"quack"
+        )));
+    }
+
+    //---------- SESSION DEADLINE TEST ----------//
+    #[test]
+    fn client_session_deadline_test() {
+        let (mut client, ctrl_recv_event, [_recv_2]) = test_client([2]);
+        client
+            .message_manager
+            .add_server_type(6, &ServerType::Content);
+        client.set_session_deadline(Duration::ZERO);
+
+        // no path to 6 is known, so the fragments are never sent
+        client.handle_send_message(
+            ClientBody::ClientContent(ClientContentBody::ReqFilesList),
+            6,
+        );
+        client.fail_expired_sessions();
+
+        let events = ctrl_recv_event.try_iter().collect::<Vec<_>>();
+        assert!(events.iter().any(|event| matches!(
+            event,
+            ClientEvent::MessageFailed {
+                session_id: 0,
+                to: 6
+            }
+        )));
+        assert!(client.message_manager.get_unsent_fragments(6).is_none());
+
+        // the failure is reported only once
+        client.fail_expired_sessions();
+        assert!(ctrl_recv_event.try_iter().next().is_none());
+    }
+
+    //---------- FILES CATALOG TEST ----------//
+    #[test]
+    fn client_known_files_test() {
+        let (mut client, _ctrl_recv_event, []) = test_client([]);
+        assert!(client.known_files().is_empty());
+
+        let files_6 = vec!["a.txt".to_string(), "b.html".to_string()];
+        let files_7 = vec!["c.png".to_string()];
+        client.smart_sender(
+            &ServerBody::ServerContent(ServerContentBody::RespFilesList(files_6.clone())),
+            6,
+        );
+        client.smart_sender(
+            &ServerBody::ServerContent(ServerContentBody::RespFilesList(files_7.clone())),
+            7,
+        );
+
+        let known_files = client.known_files();
+        assert_eq!(known_files.len(), 2);
+        assert_eq!(known_files[&6], files_6);
+        assert_eq!(known_files[&7], files_7);
+
+        // a new list replaces the previous one of the same server
+        client.smart_sender(
+            &ServerBody::ServerContent(ServerContentBody::RespFilesList(Vec::new())),
+            7,
+        );
+        assert!(client.known_files()[&7].is_empty());
+        assert_eq!(client.known_files()[&6], files_6);
+    }
+
+    //---------- PENDING TEST ----------//
+    #[test]
+    fn client_query_pending_test() {
+        let (mut client, ctrl_recv_event, [_recv_2]) = test_client([2]);
+        client
+            .message_manager
+            .add_server_type(6, &ServerType::Content);
+
+        // one message waiting for the server type of 7
+        client.message_manager.add_unsent_message(
+            &ClientBody::ClientContent(ClientContentBody::ReqFilesList),
+            7,
+        );
+        // one session whose fragments wait for a path to 6
+        client.handle_send_message(
+            ClientBody::ClientContent(ClientContentBody::ReqFilesList),
+            6,
+        );
+        let _ = ctrl_recv_event.try_iter().count();
+
+        client.handle_command(ClientCommand::QueryPending);
+        match ctrl_recv_event.try_recv() {
+            Ok(ClientEvent::Pending {
+                unsent_messages,
+                pending_sessions,
+                unsent_fragments,
+            }) => {
+                assert_eq!(unsent_messages, HashMap::from([(7, 1)]));
+                assert_eq!(pending_sessions, 1);
+                assert_eq!(unsent_fragments, HashMap::from([(6, 1)]));
+            }
+            _ => panic!("expected the pending work"),
+        }
+    }
+
+    //---------- RESET SERVER TYPES TEST ----------//
+    #[test]
+    fn client_reset_server_types_test() {
+        let (mut client, ctrl_recv_event, [_recv_2]) = test_client([2]);
+        client
+            .message_manager
+            .add_server_type(6, &ServerType::Content);
+        let req_files_list = ClientBody::ClientContent(ClientContentBody::ReqFilesList);
+        assert!(client
+            .message_manager
+            .is_valid_send(&req_files_list, 6)
+            .is_ok());
+
+        client.handle_command(ClientCommand::ResetServerTypes);
+        assert!(matches!(
+            client.message_manager.is_valid_send(&req_files_list, 6),
+            Err(ServerTypeError::ServerTypeUnknown)
+        ));
+
+        // the type of 6 is asked again and the request waits for it
+        client.handle_send_message(req_files_list, 6);
+        assert!(client.message_manager.is_there_unsent_message(6));
+        assert!(ctrl_recv_event.try_iter().any(|event| matches!(
+            event,
+            ClientEvent::MessageFragmented {
+                body: ClientBody::ReqServerType,
+                to: 6,
+                ..
+            }
+        )));
+    }
+
+    //---------- DROPPED NACK TEST ----------//
+    #[test]
+    fn client_dropped_nack_test() {
+        let (mut client, _ctrl_recv_event, [_recv_2, _recv_3]) = test_client([2, 3]);
+        add_path(&mut client, &[1, 2, 4, 5, 6]);
+        add_path(&mut client, &[1, 3, 6]);
+        assert_eq!(client.source_routing.get_path(6), Some(vec![1, 3, 6]));
+
+        // drone 3 drops a fragment, so the longer path is preferred
+        client.handle_nack(
+            &Nack {
+                fragment_index: 0,
+                nack_type: NackType::Dropped,
+            },
+            &SourceRoutingHeader {
+                hop_index: 1,
+                hops: vec![3, 1],
+            },
+            0,
+        );
+        assert_eq!(client.source_routing.get_path(6), Some(vec![1, 2, 4, 5, 6]));
+    }
+
+    //---------- DUPLICATE ACK TEST ----------//
+    #[test]
+    fn client_duplicate_ack_test() {
+        let (mut client, _ctrl_recv_event, [recv_2]) = test_client([2]);
+        client
+            .message_manager
+            .add_server_type(6, &ServerType::Content);
+        add_path(&mut client, &[1, 2, 6]);
+
+        // a path long enough to be split in more fragments
+        client.handle_send_message(
+            ClientBody::ClientContent(ClientContentBody::ReqFile("a".repeat(300))),
+            6,
+        );
+        assert!(recv_2.try_iter().count() > 1);
+
+        let header = SourceRoutingHeader::initialize(vec![6, 2, 1]);
+        let ack = Ack { fragment_index: 0 };
+        client.handle_ack(&ack, &header, 0);
+        for _ in 1..DUPLICATE_ACK_THRESHOLD {
+            client.handle_ack(&ack, &header, 0);
+        }
+        assert!(recv_2.try_recv().is_err());
+
+        client.handle_ack(&ack, &header, 0);
+        let resent = recv_2
+            .try_iter()
+            .filter_map(|packet| match packet.pack_type {
+                PacketType::MsgFragment(fragment) => Some(fragment.fragment_index),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(resent, vec![1]);
+    }
+
+    //---------- BROWSE SESSION TEST ----------//
+    #[test]
+    fn client_browse_session_test() {
+        let page_a = b"<!DOCTYPE html><html><body><a href=\"b.html\">b</a></body></html>".to_vec();
+        let page_b = b"<!DOCTYPE html><html><body><img src=\"img.png\"><a href=\"a.html\">a</a></body></html>".to_vec();
+
+        let requested_files = |depth: usize| {
+            let (mut client, ctrl_recv_event, []) = test_client([]);
+            client.set_crawl_depth(depth);
+
+            client.smart_sender(
+                &ServerBody::ServerContent(ServerContentBody::RespFile(
+                    page_a.clone(),
+                    "a.html".to_string(),
+                )),
+                6,
+            );
+            client.smart_sender(
+                &ServerBody::ServerContent(ServerContentBody::RespFiles(vec![(
+                    "b.html".to_string(),
+                    Ok(page_b.clone()),
+                )])),
+                6,
+            );
+
+            ctrl_recv_event
+                .try_iter()
+                .filter_map(|event| match event {
+                    ClientEvent::MessageFragmented {
+                        body: ClientBody::ClientContent(ClientContentBody::ReqFiles(files)),
+                        ..
+                    } => Some(files),
+                    _ => None,
+                })
+                .flatten()
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(requested_files(1), vec!["b.html".to_string()]);
+        // a.html has already been visited, so the cycle isn't followed
+        assert_eq!(
+            requested_files(2),
+            vec!["b.html".to_string(), "img.png".to_string()]
+        );
+    }
+
+    //---------- SELF ADDRESSED MESSAGE TEST ----------//
+    #[test]
+    fn client_self_addressed_message_test() {
+        let (mut client, ctrl_recv_event, [recv_2]) = test_client([2]);
+
+        client.handle_send_message(ClientBody::ReqServerType, 1);
+
+        let events = ctrl_recv_event.try_iter().collect::<Vec<_>>();
+        assert_eq!(events.len(), 1);
+        assert!(matches!(
+            events[0],
+            ClientEvent::SelfAddressedMessage {
+                body: ClientBody::ReqServerType,
+                id: 1
+            }
+        ));
+        assert!(recv_2.try_recv().is_err());
+        assert_eq!(client.session_id, 0);
+    }
+
+    //---------- FLUSH UNSENT TEST ----------//
+    #[test]
+    fn client_flush_unsent_test() {
+        let (mut client, _ctrl_recv_event, [recv_2, _recv_3]) = test_client([2, 3]);
+        let client_send_2 = client.packet_send[&2].clone();
+        client
+            .message_manager
+            .add_server_type(6, &ServerType::Content);
+        add_path(&mut client, &[1, 2, 6]);
+
+        // 6 becomes unreachable, so the fragments are queued
+        client.remove_sender(2);
+        assert!(client.source_routing.get_path(6).is_none());
+        client.handle_send_message(
+            ClientBody::ClientContent(ClientContentBody::ReqFilesList),
+            6,
+        );
+        assert!(recv_2.try_recv().is_err());
+
+        // 6 is reachable again, the queued fragments are flushed
+        client.add_sender(2, client_send_2);
+        let fragments = recv_2
+            .try_iter()
+            .filter(|packet| matches!(packet.pack_type, PacketType::MsgFragment(_)))
+            .collect::<Vec<_>>();
+        assert!(!fragments.is_empty());
+        assert!(fragments
+            .iter()
+            .all(|packet| packet.routing_header.hops == vec![1, 2, 6]));
+        assert!(client.message_manager.get_unsent_fragments(6).is_none());
+    }
+
+    //---------- STATIC ROUTE TEST ----------//
+    #[test]
+    fn client_static_route_test() {
+        let (mut client, _ctrl_recv_event, [recv_2, recv_3]) = test_client([2, 3]);
+        client
+            .message_manager
+            .add_server_type(6, &ServerType::Content);
+        add_path(&mut client, &[1, 2, 6]);
+        add_path(&mut client, &[1, 3, 4, 6]);
+        let send_req = |client: &mut Client| {
+            client.handle_send_message(
+                ClientBody::ClientContent(ClientContentBody::ReqFilesList),
+                6,
+            );
+        };
+
+        // paths not going from the client to the destination through known links are rejected
+        client.handle_command(ClientCommand::SetStaticRoute(6, vec![2, 6]));
+        client.handle_command(ClientCommand::SetStaticRoute(6, vec![1, 3, 4]));
+        client.handle_command(ClientCommand::SetStaticRoute(6, vec![1, 3, 6]));
+        send_req(&mut client);
+        assert!(recv_2.try_recv().is_ok());
+        assert!(recv_3.try_recv().is_err());
+
+        client.handle_command(ClientCommand::SetStaticRoute(6, vec![1, 3, 4, 6]));
+        send_req(&mut client);
+        assert!(recv_2.try_recv().is_err());
+        assert_eq!(
+            recv_3.try_recv().unwrap().routing_header.hops,
+            vec![1, 3, 4, 6]
+        );
+
+        client.handle_command(ClientCommand::ClearStaticRoute(6));
+        send_req(&mut client);
+        assert_eq!(
+            recv_2.try_recv().unwrap().routing_header.hops,
+            vec![1, 2, 6]
+        );
+    }
+
+    //---------- MESSAGE ACK TEST ----------//
+    #[test]
+    fn client_message_ack_test() {
+        let (mut client, _ctrl_recv_event, [_recv_2]) = test_client([2]);
+        client
+            .message_manager
+            .add_server_type(6, &ServerType::Communication);
+        client.message_manager.add_reg_to_comm(6);
+        add_path(&mut client, &[1, 2, 6]);
+
+        let session_id = client.session_id;
+        client.handle_send_message(
+            ClientBody::ClientCommunication(ClientCommunicationBody::ReqClientList),
+            6,
+        );
+        assert_eq!(client.message_manager.count_pending_sessions(), 1);
+
+        // only the destination of the message can acknowledge it
+        let message_ack =
+            ServerBody::ServerCommunication(ServerCommunicationBody::MessageAck(session_id));
+        client.smart_sender(&message_ack, 7);
+        assert_eq!(client.message_manager.count_pending_sessions(), 1);
+        client.smart_sender(&message_ack, 6);
+        assert_eq!(client.message_manager.count_pending_sessions(), 0);
+    }
+
+    //---------- ACK RESENDS UNSENT TEST ----------//
+    #[test]
+    fn client_ack_resends_unsent_test() {
+        let (mut client, _ctrl_recv_event, [recv_2]) = test_client([2]);
+        add_path(&mut client, &[1, 2, 6]);
+        client
+            .message_manager
+            .add_unsent_fragment(4, 6, &Fragment::new(0, 1, [0; 128]));
+
+        // the ACK of another session refreshes the path, the unsent fragment goes through it
+        client.handle_ack(
+            &Ack { fragment_index: 0 },
+            &SourceRoutingHeader {
+                hop_index: 2,
+                hops: vec![6, 2, 1],
+            },
+            3,
+        );
+
+        let packet = recv_2.try_recv().expect("the unsent fragment is sent");
+        assert_eq!(packet.routing_header.hops, vec![1, 2, 6]);
+        assert_eq!(packet.session_id, 4);
+        assert!(matches!(packet.pack_type, PacketType::MsgFragment(_)));
+        assert!(client.message_manager.count_unsent_fragments().is_empty());
+    }
+
+    //---------- FLUSH PENDING TEST ----------//
+    #[test]
+    fn client_flush_pending_test() {
+        let (mut client, _ctrl_recv_event, [recv_2]) = test_client([2]);
+        client
+            .message_manager
+            .add_server_type(6, &ServerType::Content);
+        client
+            .message_manager
+            .add_server_type(7, &ServerType::Content);
+
+        // no path is known, so the fragments are queued
+        for server in [6, 7] {
+            client.handle_send_message(
+                ClientBody::ClientContent(ClientContentBody::ReqFilesList),
+                server,
+            );
+        }
+        assert!(recv_2
+            .try_iter()
+            .all(|packet| matches!(packet.pack_type, PacketType::FloodRequest(_))));
+
+        // 6 is reachable, but nothing is sent until it's flushed
+        add_path(&mut client, &[1, 2, 6]);
+        assert!(recv_2.try_recv().is_err());
+
+        client.handle_command(ClientCommand::FlushPending(7));
+        assert!(recv_2.try_recv().is_err());
+
+        client.handle_command(ClientCommand::FlushPending(6));
+        let fragments = recv_2.try_iter().collect::<Vec<_>>();
+        assert!(!fragments.is_empty());
+        assert!(fragments
+            .iter()
+            .all(|packet| packet.routing_header.hops == vec![1, 2, 6]));
+        assert!(client.message_manager.get_unsent_fragments(6).is_none());
+        assert!(client.message_manager.get_unsent_fragments(7).is_some());
+    }
+
+    //---------- DROP PENDING TEST ----------//
+    #[test]
+    fn client_drop_pending_test() {
+        let (mut client, ctrl_recv_event, [_recv_2]) = test_client([2]);
+        client
+            .message_manager
+            .add_server_type(6, &ServerType::Content);
+
+        // the type of 7 is unknown: the request waits for it, and the ReqServerType for a path
+        client.handle_send_message(
+            ClientBody::ClientContent(ClientContentBody::ReqFilesList),
+            7,
+        );
+        client.handle_send_message(
+            ClientBody::ClientContent(ClientContentBody::ReqFilesList),
+            6,
+        );
+        assert_eq!(
+            client.message_manager.count_unsent_messages().get(&7),
+            Some(&1)
+        );
+        assert!(client
+            .message_manager
+            .count_unsent_fragments()
+            .contains_key(&7));
+        ctrl_recv_event.try_iter().for_each(drop);
+
+        client.handle_command(ClientCommand::DropPending(7));
+        assert!(matches!(
+            ctrl_recv_event.try_recv(),
+            Ok(ClientEvent::PendingDropped {
+                to: 7,
+                messages: 1,
+                fragments: 1,
+            })
+        ));
+        assert!(client.message_manager.count_unsent_messages().is_empty());
+        let unsent_fragments = client.message_manager.count_unsent_fragments();
+        assert!(!unsent_fragments.contains_key(&7));
+        assert!(unsent_fragments.contains_key(&6));
+
+        // nothing left to drop
+        client.handle_command(ClientCommand::DropPending(7));
+        assert!(matches!(
+            ctrl_recv_event.try_recv(),
+            Ok(ClientEvent::PendingDropped {
+                to: 7,
+                messages: 0,
+                fragments: 0,
+            })
+        ));
+    }
+
+    //---------- PRIORITY TEST ----------//
+    #[test]
+    fn client_priority_test() {
+        let (mut client, _ctrl_recv_event, [recv_2]) = test_client([2]);
+        client.handle_command(ClientCommand::SetPriority(7, 5));
+
+        // no path is known yet, so the fragments are queued
+        for dest in [6, 7] {
+            client
+                .message_manager
+                .add_server_type(dest, &ServerType::Content);
+            client.handle_send_message(
+                ClientBody::ClientContent(ClientContentBody::ReqFilesList),
+                dest,
+            );
+        }
+        recv_2.try_iter().for_each(drop);
+
+        client.send_unsent(vec![(6, vec![1, 2, 6]), (7, vec![1, 2, 7])]);
+        let destinations: Vec<NodeId> = recv_2
+            .try_iter()
+            .map(|packet| *packet.routing_header.hops.last().unwrap())
+            .collect();
+        assert_eq!(destinations, vec![7, 6]);
+    }
+
+    //---------- REGISTRATION IN PROGRESS TEST ----------//
+    #[test]
+    fn client_registration_in_progress_test() {
+        let (mut client, _ctrl_recv_event, [recv_2]) = test_client([2]);
+        client
+            .message_manager
+            .add_server_type(6, &ServerType::Communication);
+        add_path(&mut client, &[1, 2, 6]);
+
+        for i in 0..3 {
+            client.handle_send_message(
+                ClientBody::ClientCommunication(ClientCommunicationBody::MessageSend(
+                    CommunicationMessage {
+                        from: 1,
+                        to: 7,
+                        message: i.to_string(),
+                    },
+                )),
+                6,
+            );
+        }
+
+        // only the registration request is sent, the messages wait for it
+        assert_eq!(recv_2.try_iter().count(), 1);
+        assert_eq!(client.message_manager.count_unsent_messages()[&6], 3);
+
+        client.smart_sender(
+            &ServerBody::ServerCommunication(ServerCommunicationBody::RegistrationSuccess),
+            6,
+        );
+        assert_eq!(recv_2.try_iter().count(), 3);
+
+        // a new registration can be requested after it's been dropped
+        client.handle_command(ClientCommand::UnregisterFrom(6));
+        client.handle_command(ClientCommand::RegisterTo(6));
+        // the unregistration and the new registration request
+        assert_eq!(recv_2.try_iter().count(), 2);
+    }
+
+    //---------- MULTIPLE REGISTRATIONS TEST ----------//
+    #[test]
+    fn client_multiple_registrations_test() {
+        let (mut client, _ctrl_recv_event, [recv_2]) = test_client([2]);
+        for server in [6, 7] {
+            client
+                .message_manager
+                .add_server_type(server, &ServerType::Communication);
+            add_path(&mut client, &[1, 2, server]);
+        }
+
+        client.handle_command(ClientCommand::RegisterTo(6));
+        client.handle_command(ClientCommand::RegisterTo(7));
+        let destinations: Vec<NodeId> = recv_2
+            .try_iter()
+            .map(|packet| *packet.routing_header.hops.last().unwrap())
+            .collect();
+        assert!(destinations.contains(&6));
+        assert!(destinations.contains(&7));
+
+        for server in [6, 7] {
+            client.smart_sender(
+                &ServerBody::ServerCommunication(ServerCommunicationBody::RegistrationSuccess),
+                server,
+            );
+        }
+        assert!(client.message_manager.is_reg_to_comm(6));
+        assert!(client.message_manager.is_reg_to_comm(7));
+        // the registration isn't requested again
+        assert!(recv_2.try_recv().is_err());
+
+        client.handle_command(ClientCommand::UnregisterFrom(6));
+        assert!(!client.message_manager.is_reg_to_comm(6));
+        assert!(client.message_manager.is_reg_to_comm(7));
+
+        // the server is told to drop the registration
+        let packet = recv_2.try_recv().unwrap();
+        assert_eq!(packet.routing_header.hops, vec![1, 2, 6]);
+        let PacketType::MsgFragment(fragment) = packet.pack_type else {
+            panic!("expected a fragment");
+        };
+        assert!(matches!(
+            Assembler::new().handle_fragment(&fragment, 1, packet.session_id),
+            Some(Message::Client(ClientBody::ClientCommunication(
+                ClientCommunicationBody::ReqUnregistrationFromChat
+            )))
+        ));
+        assert!(recv_2.try_recv().is_err());
+
+        // nothing is sent when the client isn't registered
+        client.handle_command(ClientCommand::UnregisterFrom(6));
+        assert!(recv_2.try_recv().is_err());
+    }
+
+    //---------- SEND WINDOW TEST ----------//
+    #[test]
+    fn client_send_window_test() {
+        let (mut client, _ctrl_recv_event, [recv_2]) = test_client([2]);
+        client.set_send_window(Some(3));
+        add_path(&mut client, &[1, 2, 6]);
+
+        let body = ClientBody::ClientCommunication(ClientCommunicationBody::MessageSend(
+            CommunicationMessage {
+                from: 1,
+                to: 7,
+                message: "a".repeat(1200),
+            },
+        ));
+        let total = client
+            .assembler
+            .serialize_message(&Message::Client(body.clone()))
+            .len();
+        assert!(total >= 10);
+        client.send_message(body, 6);
+
+        let sent: Vec<Packet> = recv_2.try_iter().collect();
+        assert_eq!(sent.len(), 3);
+
+        // every ACK makes room for one more fragment
+        let header = SourceRoutingHeader {
+            hop_index: 2,
+            hops: vec![6, 2, 1],
+        };
+        client.handle_ack(&Ack { fragment_index: 0 }, &header, sent[0].session_id);
+        let next: Vec<Packet> = recv_2.try_iter().collect();
+        assert_eq!(next.len(), 1);
+        assert_eq!(next[0].get_fragment_index(), 3);
+
+        for fragment_index in 1..total as u64 {
+            client.handle_ack(&Ack { fragment_index }, &header, sent[0].session_id);
+        }
+        assert_eq!(recv_2.try_iter().count(), total - 4);
+        assert_eq!(client.message_manager.count_pending_sessions(), 0);
+    }
+
+    //---------- SEND WINDOW EXPIRED SESSION TEST ----------//
+    #[test]
+    fn client_send_window_expired_session_test() {
+        let (mut client, _ctrl_recv_event, [recv_2]) = test_client([2]);
+        client.set_send_window(Some(1));
+        add_path(&mut client, &[1, 2, 6]);
+        client
+            .message_manager
+            .add_server_type(6, &ServerType::Content);
+
+        client.set_session_deadline(Duration::ZERO);
+        client.handle_send_message(
+            ClientBody::ClientContent(ClientContentBody::ReqFilesList),
+            6,
+        );
+        client.set_session_deadline(DEFAULT_SESSION_DEADLINE);
+        client.handle_send_message(
+            ClientBody::ClientContent(ClientContentBody::ReqFilesList),
+            6,
+        );
+        let sent: Vec<Packet> = recv_2.try_iter().collect();
+        assert_eq!(sent.len(), 1);
+
+        // the first session expires without any ACK, its slot in the window goes to the held fragment
+        client.fail_expired_sessions();
+        let next: Vec<Packet> = recv_2.try_iter().collect();
+        assert_eq!(next.len(), 1);
+        assert_ne!(next[0].session_id, sent[0].session_id);
+        assert_eq!(client.message_manager.count_pending_sessions(), 1);
+    }
+
+    //---------- SEND WINDOW HELD ACK TEST ----------//
+    #[test]
+    fn client_send_window_held_ack_test() {
+        let (mut client, _ctrl_recv_event, [recv_2]) = test_client([2]);
+        client.set_send_window(Some(3));
+        add_path(&mut client, &[1, 2, 6]);
+
+        let body = ClientBody::ClientCommunication(ClientCommunicationBody::MessageSend(
+            CommunicationMessage {
+                from: 1,
+                to: 7,
+                message: "a".repeat(1200),
+            },
+        ));
+        let total = client
+            .assembler
+            .serialize_message(&Message::Client(body.clone()))
+            .len();
+        assert!(total >= 10);
+        client.send_message(body, 6);
+
+        let sent: Vec<Packet> = recv_2.try_iter().collect();
+        assert_eq!(sent.len(), 3);
+
+        // an ACK for a fragment still held must not open the window
+        let header = SourceRoutingHeader {
+            hop_index: 2,
+            hops: vec![6, 2, 1],
+        };
+        let last = total as u64 - 1;
+        client.handle_ack(
+            &Ack {
+                fragment_index: last,
+            },
+            &header,
+            sent[0].session_id,
+        );
+        assert_eq!(recv_2.try_iter().count(), 0);
+
+        // the acked fragment is never sent
+        let mut next = Vec::new();
+        for fragment_index in 0..last {
+            client.handle_ack(&Ack { fragment_index }, &header, sent[0].session_id);
+            next.extend(recv_2.try_iter());
+        }
+        assert_eq!(next.len(), total - 4);
+        assert!(next
+            .iter()
+            .all(|packet| packet.get_fragment_index() != last));
+        assert_eq!(client.message_manager.count_pending_sessions(), 0);
+    }
+
+    //---------- DEAD NEIGHBOR FLOOD TEST ----------//
+    #[test]
+    fn client_flood_dead_neighbor_test() {
+        let (mut client, _ctrl_recv_event, [recv_2, recv_3]) = test_client([2, 3]);
+
+        // 2 crashed, but it hasn't been removed yet
+        drop(recv_2);
+        client.send_flood_request();
+
+        assert!(matches!(
+            recv_3.try_recv().unwrap().pack_type,
+            PacketType::FloodRequest(_)
+        ));
+        assert!(!client.packet_send.contains_key(&2));
+
+        client.send_flood_request();
+        assert!(matches!(
+            recv_3.try_recv().unwrap().pack_type,
+            PacketType::FloodRequest(_)
+        ));
+    }
+
+    //---------- STALE NEXT HOP TEST ----------//
+    #[test]
+    fn client_stale_next_hop_test() {
+        let (mut client, _ctrl_recv_event, [recv_2, recv_3]) = test_client([2, 3]);
+        client
+            .message_manager
+            .add_server_type(6, &ServerType::Content);
+        add_path(&mut client, &[1, 2, 6]);
+
+        // the neighbor is gone but the path to 6 still goes through it
+        client.packet_send.remove(&2);
+        client.handle_send_message(
+            ClientBody::ClientContent(ClientContentBody::ReqFile("a".repeat(1000))),
+            6,
+        );
+
+        assert!(recv_2.try_recv().is_err());
+        let unsent = client.message_manager.get_unsent_fragments(6).unwrap();
+        assert!(unsent.len() > 1);
+        // a single flood for all the fragments of the message
+        let sent: Vec<Packet> = recv_3.try_iter().collect();
+        assert_eq!(sent.len(), 1);
+        assert!(matches!(sent[0].pack_type, PacketType::FloodRequest(_)));
+    }
+
+    //---------- CRASHED NEIGHBOR TEST ----------//
+    #[test]
+    fn client_crashed_neighbor_test() {
+        let (mut client, _ctrl_recv_event, [recv_2, recv_3]) = test_client([2, 3]);
+        client
+            .message_manager
+            .add_server_type(6, &ServerType::Content);
+        add_path(&mut client, &[1, 2, 6]);
+
+        // 2 crashed, but it hasn't been removed yet
+        drop(recv_2);
+        client.handle_send_message(
+            ClientBody::ClientContent(ClientContentBody::ReqFilesList),
+            6,
+        );
+
+        assert!(!client.packet_send.contains_key(&2));
+        assert_eq!(client.message_manager.count_unsent_fragments()[&6], 1);
+        let sent: Vec<Packet> = recv_3.try_iter().collect();
+        assert_eq!(sent.len(), 1);
+        assert!(matches!(sent[0].pack_type, PacketType::FloodRequest(_)));
+    }
+
+    //---------- UPLINK CHANGE TEST ----------//
+    #[test]
+    fn client_uplink_change_test() {
+        let (mut client, _ctrl_recv_event, [recv_2]) = test_client([2]);
+        let (client_send_3, recv_3) = unbounded();
+        client
+            .message_manager
+            .add_server_type(6, &ServerType::Content);
+        add_path(&mut client, &[1, 2, 6]);
+
+        client.handle_send_message(
+            ClientBody::ClientContent(ClientContentBody::ReqFilesList),
+            6,
+        );
+        let fragments = recv_2.try_iter().count();
+        assert!(fragments > 0);
+
+        // the client is moved from drone 2 to drone 3 before any ACK is received:
+        // a new neighbor alone doesn't change the uplink
+        client.add_sender(3, client_send_3);
+        assert!(recv_3.try_recv().is_err());
+        client.remove_sender(2);
+        assert!(recv_3
+            .try_iter()
+            .any(|packet| matches!(packet.pack_type, PacketType::FloodRequest(_))));
+
+        client.handle_flood_response(&FloodResponse {
+            flood_id: 0,
+            path_trace: vec![
+                (1, NodeType::Client),
+                (3, NodeType::Drone),
+                (6, NodeType::Server),
+            ],
+        });
+        let resent: Vec<Packet> = recv_3.try_iter().collect();
+        assert_eq!(resent.len(), fragments);
+        for packet in resent {
+            assert_eq!(packet.routing_header.hops, vec![1, 3, 6]);
+            let PacketType::MsgFragment(fragment) = packet.pack_type else {
+                panic!("expected a fragment");
+            };
+            client.handle_ack(
+                &Ack {
+                    fragment_index: fragment.fragment_index,
+                },
+                &SourceRoutingHeader {
+                    hop_index: 2,
+                    hops: vec![6, 3, 1],
+                },
+                packet.session_id,
+            );
+        }
+        assert_eq!(client.message_manager.count_pending_sessions(), 0);
+    }
+
+    //---------- REMOVED NEIGHBOR TEST ----------//
+    #[test]
+    fn client_removed_neighbor_test() {
+        let (mut client, _ctrl_recv_event, [_recv_2, recv_3]) = test_client([2, 3]);
+        for server in [6, 7] {
+            client
+                .message_manager
+                .add_server_type(server, &ServerType::Content);
+        }
+        add_path(&mut client, &[1, 2, 6]);
+        add_path(&mut client, &[1, 3, 7]);
+
+        for server in [6, 7] {
+            client.handle_send_message(
+                ClientBody::ClientContent(ClientContentBody::ReqFilesList),
+                server,
+            );
+        }
+        recv_3.try_iter().for_each(drop);
+
+        // only the fragments sent through 3 may be lost
+        client.remove_sender(3);
+        let unsent_fragments = client.message_manager.count_unsent_fragments();
+        assert_eq!(unsent_fragments.get(&7), Some(&1));
+        assert!(!unsent_fragments.contains_key(&6));
+    }
+
+    //---------- REATTACH TEST ----------//
+    #[test]
+    fn client_reattach_test() {
+        let (mut client, _ctrl_recv_event, [recv_2]) = test_client([2]);
+        let (client_send_3, recv_3) = unbounded();
+        client
+            .message_manager
+            .add_server_type(6, &ServerType::Content);
+        add_path(&mut client, &[1, 2, 6]);
+
+        client.handle_send_message(
+            ClientBody::ClientContent(ClientContentBody::ReqFilesList),
+            6,
+        );
+        assert_eq!(recv_2.try_iter().count(), 1);
+
+        // the last neighbor is removed first, the client is attached to 3 afterwards
+        client.remove_sender(2);
+        assert!(client.packet_send.contains_key(&2));
+        client.add_sender(3, client_send_3);
+        assert!(!client.packet_send.contains_key(&2));
+        assert_eq!(client.message_manager.count_unsent_fragments()[&6], 1);
+        assert!(recv_3
+            .try_iter()
+            .any(|packet| matches!(packet.pack_type, PacketType::FloodRequest(_))));
+    }
+
+    //---------- UNEXPECTED RECIPIENT TEST ----------//
+    #[test]
+    fn client_unexpected_recipient_test() {
+        let (mut client, _ctrl_recv_event, [recv_2]) = test_client([2]);
+        let fragment = |hop_index| {
+            Packet::new_fragment(
+                SourceRoutingHeader {
+                    hop_index,
+                    hops: vec![2, 7],
+                },
+                0,
+                Fragment::new(0, 1, [0; 128]),
+            )
+        };
+
+        // the packet came from 2
+        client.send_unexp_recp(&fragment(1));
+        let Ok(Packet {
+            pack_type: PacketType::Nack(nack),
+            routing_header,
+            ..
+        }) = recv_2.try_recv()
+        else {
+            panic!("expected a NACK");
+        };
+        assert!(matches!(nack.nack_type, NackType::UnexpectedRecipient(1)));
+        assert_eq!(routing_header.hops, vec![1, 2]);
+
+        // there is no path back
+        client.send_unexp_recp(&fragment(0));
+        let sent: Vec<Packet> = recv_2.try_iter().collect();
+        assert!(!sent.is_empty());
+        assert!(sent
+            .iter()
+            .all(|packet| matches!(packet.pack_type, PacketType::FloodRequest(_))));
+    }
+
+    //---------- SESSION LIMIT TEST ----------//
+    #[test]
+    fn client_session_limit_test() {
+        let (mut client, ctrl_recv_event, [recv_2]) = test_client([2]);
+        client
+            .message_manager
+            .add_server_type(6, &ServerType::Content);
+        add_path(&mut client, &[1, 2, 6]);
+        client.set_max_sessions(Some(2));
+
+        for path in ["a", "b", "c"] {
+            client.handle_send_message(
+                ClientBody::ClientContent(ClientContentBody::ReqFile(path.to_string())),
+                6,
+            );
+        }
+        assert_eq!(client.message_manager.count_pending_sessions(), 2);
+        assert_eq!(client.message_manager.count_queued_messages(), 1);
+        assert!(ctrl_recv_event
+            .try_iter()
+            .any(|event| matches!(event, ClientEvent::MessageQueued { to: 6 })));
+
+        // the first session completes, so the queued message is sent
+        let sent: Vec<Packet> = recv_2.try_iter().collect();
+        assert_eq!(sent.len(), 2);
+        let first = sent[0].clone();
+        let PacketType::MsgFragment(fragment) = first.pack_type else {
+            panic!("expected a fragment");
+        };
+        assert_eq!(fragment.total_n_fragments, 1);
+        client.handle_ack(
+            &Ack {
+                fragment_index: fragment.fragment_index,
+            },
+            &SourceRoutingHeader {
+                hop_index: 2,
+                hops: vec![6, 2, 1],
+            },
+            first.session_id,
+        );
+        assert_eq!(client.message_manager.count_pending_sessions(), 2);
+        assert_eq!(client.message_manager.count_queued_messages(), 0);
+        assert_eq!(recv_2.try_iter().count(), 1);
+    }
+
+    //---------- CONTROL FLOOD TEST ----------//
+    #[test]
+    fn client_control_flood_test() {
+        let (mut client, _ctrl_recv_event, [recv_2]) = test_client([2]);
+        let interval = Duration::from_millis(100);
+        client.set_control_flood_interval(Some(interval));
+        let floods = |recv: &Receiver<Packet>| {
+            recv.try_iter()
+                .filter(|packet| matches!(packet.pack_type, PacketType::FloodRequest(_)))
+                .count()
+        };
+
+        // 6 hasn't been discovered yet
+        let now = Instant::now();
+        client.handle_send_message(ClientBody::ReqServerType, 6);
+        assert_eq!(floods(&recv_2), 1);
+
+        // no response yet, the client floods again once the interval has elapsed
+        client.retry_control_floods(now);
+        assert_eq!(floods(&recv_2), 0);
+        client.retry_control_floods(now + interval * 2);
+        assert_eq!(floods(&recv_2), 1);
+
+        client.handle_flood_response(&FloodResponse {
+            flood_id: 1,
+            path_trace: vec![
+                (1, NodeType::Client),
+                (2, NodeType::Drone),
+                (6, NodeType::Server),
+            ],
+        });
+        let sent: Vec<Packet> = recv_2.try_iter().collect();
+        assert_eq!(sent.len(), 1);
+        assert!(matches!(sent[0].pack_type, PacketType::MsgFragment(_)));
+        assert_eq!(sent[0].routing_header.hops, vec![1, 2, 6]);
+
+        // the request has been sent, so there is nothing to flood for
+        client.retry_control_floods(now + interval * 4);
+        assert_eq!(floods(&recv_2), 0);
+    }
+
+    //---------- FLOOD RATE LIMIT TEST ----------//
+    #[test]
+    fn client_flood_rate_limit_test() {
+        let (mut client, _ctrl_recv_event, [recv_2]) = test_client([2]);
+        let flood_request = |flood_id| FloodRequest {
+            flood_id,
+            initiator_id: 2,
+            path_trace: vec![(2, NodeType::Drone)],
+        };
+
+        client.handle_flood_request(0, flood_request(1));
+        client.handle_flood_request(0, flood_request(1));
+        assert_eq!(recv_2.try_iter().count(), 1);
+
+        // a new flood from the same initiator is answered
+        client.handle_flood_request(0, flood_request(2));
+        assert_eq!(recv_2.try_iter().count(), 1);
+    }
+
+    //---------- FLOOD WITH FORGED TRACE TEST ----------//
+    #[test]
+    fn client_flood_forged_trace_test() {
+        let (mut client, _ctrl_recv_event, [recv_2, recv_3]) = test_client([2, 3]);
+        let flood_request = |prev_hop| FloodRequest {
+            flood_id: 1,
+            initiator_id: 4,
+            path_trace: vec![(4, NodeType::Client), (prev_hop, NodeType::Drone)],
+        };
+
+        client.handle_flood_request(0, flood_request(2));
+        assert_eq!(recv_2.try_iter().count(), 1);
+
+        // the same flood is ignored whatever node precedes the client in its trace
+        for prev_hop in [3, 5, 6] {
+            client.handle_flood_request(0, flood_request(prev_hop));
+        }
+        assert_eq!(recv_2.try_iter().count(), 0);
+        assert_eq!(recv_3.try_iter().count(), 0);
+    }
+
+    //---------- MALFORMED FLOOD REQUEST TEST ----------//
+    #[test]
+    fn client_malformed_flood_request_test() {
+        let (mut client, ctrl_recv_event, [recv_2]) = test_client([2]);
+
+        client.handle_flood_request(
+            0,
+            FloodRequest {
+                flood_id: 1,
+                initiator_id: 2,
+                path_trace: vec![],
+            },
+        );
+        assert!(recv_2.try_recv().is_err());
+        let events: Vec<ClientEvent> = ctrl_recv_event.try_iter().collect();
+        assert_eq!(events.len(), 1);
+        assert!(matches!(
+            &events[0],
+            ClientEvent::MalformedFloodRequest(request) if request.path_trace == vec![(1, NodeType::Client)]
+        ));
+
+        // a well-formed request with the same ids is still answered
+        client.handle_flood_request(
+            0,
+            FloodRequest {
+                flood_id: 1,
+                initiator_id: 2,
+                path_trace: vec![(2, NodeType::Drone)],
+            },
+        );
+        assert_eq!(recv_2.try_iter().count(), 1);
+    }
+
+    //---------- FLOOD FAN-OUT TEST ----------//
+    #[test]
+    fn client_flood_fan_out_test() {
+        let (mut client, _ctrl_recv_event, [recv_2, recv_3]) = test_client([2, 3]);
+        client.set_flood_fan_out(Some(1));
+
+        client.send_flood_request();
+        assert_eq!(recv_2.try_iter().count(), 1);
+        assert_eq!(recv_3.try_iter().count(), 0);
+
+        // the next flood is sent to the other neighbor
+        client.send_flood_request();
+        assert_eq!(recv_2.try_iter().count(), 0);
+        assert_eq!(recv_3.try_iter().count(), 1);
+
+        client.set_flood_fan_out(None);
+        client.send_flood_request();
+        assert_eq!(recv_2.try_iter().count(), 1);
+        assert_eq!(recv_3.try_iter().count(), 1);
+    }
+
+    //---------- AUTO PREFETCH TEST ----------//
+    #[test]
+    fn client_auto_prefetch_test() {
+        let (mut client, ctrl_recv_event, []) = test_client([]);
+        client.set_auto_prefetch(false);
+
+        let page = b"<!DOCTYPE html><html><body><img src=\"img.png\"></body></html>".to_vec();
+        client.smart_sender(
+            &ServerBody::ServerContent(ServerContentBody::RespFile(page, "a.html".to_string())),
+            6,
+        );
+
+        assert!(!ctrl_recv_event
+            .try_iter()
+            .any(|event| matches!(event, ClientEvent::MessageFragmented { .. })));
+        assert!(client.message_manager.is_file_cached(6, "a.html"));
+    }
+
+    //---------- PREFETCH FAILED TEST ----------//
+    #[test]
+    fn client_prefetch_failed_test() {
+        let (mut client, ctrl_recv_event, []) = test_client([]);
+
+        let page = b"<!DOCTYPE html><html><body><img src=\"img.png\"></body></html>".to_vec();
+        client.smart_sender(
+            &ServerBody::ServerContent(ServerContentBody::RespFile(page, "a.html".to_string())),
+            6,
+        );
+        // b.png was not prefetched
+        client.smart_sender(
+            &ServerBody::ServerContent(ServerContentBody::RespFiles(vec![
+                ("img.png".to_string(), Err(FileError::NotFound)),
+                ("b.png".to_string(), Err(FileError::NotFound)),
+            ])),
+            6,
+        );
+
+        let failed = ctrl_recv_event
+            .try_iter()
+            .filter_map(|event| match event {
+                ClientEvent::PrefetchFailed { server, path } => Some((server, path)),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(failed, vec![(6, "img.png".to_string())]);
+    }
+
+    //---------- TYPED FILE TEST ----------//
+    #[test]
+    fn client_typed_file_test() {
+        let (mut client, ctrl_recv_event, []) = test_client([]);
+        let prefetched = |client: &mut Client, mime: Option<&str>| {
+            // the page doesn't start with a tag, so it can't be sniffed
+            let page = b"page: <a href=\"b.html\">b</a>".to_vec();
+            client.smart_sender(
+                &ServerBody::ServerContent(ServerContentBody::RespTypedFile(
+                    page,
+                    "a.html".to_string(),
+                    mime.map(str::to_string),
+                )),
+                6,
+            );
+            ctrl_recv_event
+                .try_iter()
+                .any(|event| matches!(event, ClientEvent::MessageFragmented { .. }))
+        };
+
+        assert!(!prefetched(&mut client, None));
+        assert!(!prefetched(&mut client, Some("text/plain")));
+        assert!(prefetched(&mut client, Some("text/html")));
+        assert!(client.message_manager.is_file_cached(6, "a.html"));
+    }
+
+    //---------- MESSAGE CALLBACK TEST ----------//
+    #[test]
+    fn client_message_callback_test() {
+        let (mut client, ctrl_recv_event, [_recv_2]) = test_client([2]);
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let callback_received = received.clone();
+        client.on_message(Box::new(move |body, from| {
+            callback_received.lock().unwrap().push((body, from));
+        }));
+
+        let fragments =
+            client
+                .assembler
+                .serialize_message(&Message::Server(ServerBody::RespServerType(
+                    ServerType::Content,
+                )));
+        let header = SourceRoutingHeader {
+            hop_index: 2,
+            hops: vec![6, 2, 1],
+        };
+        for fragment in &fragments {
+            client.handle_fragment(fragment, &header, 0);
+        }
+
+        let received = received.lock().unwrap();
+        assert_eq!(received.len(), 1);
+        assert!(matches!(
+            received[0],
+            (ServerBody::RespServerType(ServerType::Content), 6)
+        ));
+        // the controller is still notified
+        assert!(ctrl_recv_event
+            .try_iter()
+            .any(|event| matches!(event, ClientEvent::MessageAssembled { from: 6, .. })));
+    }
+
+    #[test]
+    fn client_message_callback_cache_test() {
+        let (mut client, ctrl_recv_event, [recv_2]) = test_client([2]);
+        client
+            .message_manager
+            .add_server_type(6, &ServerType::Content);
+        client
+            .message_manager
+            .cache_file(6, "a.txt", b"quack".to_vec());
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let callback_received = received.clone();
+        client.on_message(Box::new(move |body, from| {
+            callback_received.lock().unwrap().push((body, from));
+        }));
+
+        client.handle_send_message(
+            ClientBody::ClientContent(ClientContentBody::ReqFile("a.txt".to_string())),
+            6,
+        );
+
+        // the request is answered from the cache, the callback fires like for a fetched file
+        assert!(recv_2.try_recv().is_err());
+        let received = received.lock().unwrap();
+        assert_eq!(received.len(), 1);
+        assert!(matches!(
+            &received[0],
+            (ServerBody::ServerContent(ServerContentBody::RespFile(file, path)), 6)
+                if file == b"quack" && path == "a.txt"
+        ));
+        assert!(ctrl_recv_event
+            .try_iter()
+            .any(|event| matches!(event, ClientEvent::MessageAssembled { from: 6, .. })));
+    }
+
+    //---------- FILE NOT FOUND FALLBACK TEST ----------//
+    #[test]
+    fn client_file_not_found_fallback_test() {
+        let (mut client, _ctrl_recv_event, [recv_2]) = test_client([2]);
+        for server in [6, 7] {
+            client
+                .message_manager
+                .add_server_type(server, &ServerType::Content);
+            add_path(&mut client, &[1, 2, server]);
+        }
+        let destinations = |recv: &Receiver<Packet>| {
+            recv.try_iter()
+                .filter(|packet| matches!(packet.pack_type, PacketType::MsgFragment(_)))
+                .map(|packet| *packet.routing_header.hops.last().unwrap())
+                .collect::<HashSet<_>>()
+        };
+
+        let first = client
+            .message_manager
+            .select_content_server("a.txt")
+            .unwrap();
+        let other = if first == 6 { 7 } else { 6 };
+        client.handle_send_message(
+            ClientBody::ClientContent(ClientContentBody::ReqFile("a.txt".to_string())),
+            first,
+        );
+        assert_eq!(destinations(&recv_2), HashSet::from([first]));
+
+        // an error for a file that wasn't requested to the server is ignored
+        client.smart_sender(
+            &ServerBody::ServerContent(ServerContentBody::ErrFileNotFound("b.txt".to_string())),
+            first,
+        );
+        assert!(destinations(&recv_2).is_empty());
+
+        // the file is requested again to the other content server
+        let not_found =
+            ServerBody::ServerContent(ServerContentBody::ErrFileNotFound("a.txt".to_string()));
+        client.smart_sender(&not_found, first);
+        assert_eq!(destinations(&recv_2), HashSet::from([other]));
+
+        // every content server has been tried
+        client.smart_sender(&not_found, other);
+        assert!(destinations(&recv_2).is_empty());
+        assert_eq!(
+            client.message_manager.select_content_server("a.txt"),
+            Some(first)
+        );
+    }
+
+    //---------- CORRUPTED MESSAGE TEST ----------//
+    #[test]
+    fn client_corrupted_message_test() {
+        let (mut client, ctrl_recv_event, [_recv_2]) = test_client([2]);
+
+        let mut fragments =
+            client
+                .assembler
+                .serialize_message(&Message::Server(ServerBody::ServerContent(
+                    ServerContentBody::RespFile(vec![b'a'; 300], "a.txt".to_string()),
+                )));
+        assert!(fragments.len() > 1);
+        fragments[1].data[0] ^= 1;
+        let header = SourceRoutingHeader {
+            hop_index: 2,
+            hops: vec![6, 2, 1],
+        };
+        for fragment in &fragments {
+            client.handle_fragment(fragment, &header, 0);
+        }
+
+        let events: Vec<ClientEvent> = ctrl_recv_event.try_iter().collect();
+        assert!(!events
+            .iter()
+            .any(|event| matches!(event, ClientEvent::MessageAssembled { .. })));
+        let corrupted = events
+            .iter()
+            .filter(|event| {
+                matches!(
+                    event,
+                    ClientEvent::MessageCorrupted {
+                        from: 6,
+                        to: 1,
+                        session_id: 0,
+                    }
+                )
+            })
+            .count();
+        assert_eq!(corrupted, 1);
+    }
+
+    //---------- SEND BATCH TEST ----------//
+    #[test]
+    fn client_send_batch_test() {
+        let (mut client, ctrl_recv_event, [recv_2]) = test_client([2]);
+        // the chat servers aren't reachable yet, the type of 8 is unknown
+        for server in [6, 7] {
+            client
+                .message_manager
+                .add_server_type(server, &ServerType::Communication);
+        }
+        let message = |to| {
+            ClientBody::ClientCommunication(ClientCommunicationBody::MessageSend(
+                CommunicationMessage {
+                    from: 1,
+                    to,
+                    message: "hi".to_string(),
+                },
+            ))
+        };
+
+        client.handle_command(ClientCommand::SendBatch(vec![
+            (message(20), 6),
+            (message(21), 7),
+            (message(22), 6),
+            (message(23), 7),
+            (
+                ClientBody::ClientContent(ClientContentBody::ReqFilesList),
+                8,
+            ),
+            (ClientBody::ClientContent(ClientContentBody::ReqFileTree), 8),
+        ]));
+
+        let fragmented: Vec<(ClientBody, NodeId)> = ctrl_recv_event
+            .try_iter()
+            .filter_map(|event| match event {
+                ClientEvent::MessageFragmented { body, to, .. } => Some((body, to)),
+                _ => None,
+            })
+            .collect();
+        let registrations = |server| {
+            fragmented
+                .iter()
+                .filter(|(body, to)| {
+                    *to == server
+                        && matches!(
+                            body,
+                            ClientBody::ClientCommunication(
+                                ClientCommunicationBody::ReqRegistrationToChat
+                            )
+                        )
+                })
+                .count()
+        };
+        assert_eq!(registrations(6), 1);
+        assert_eq!(registrations(7), 1);
+        assert_eq!(
+            fragmented
+                .iter()
+                .filter(|(body, to)| *to == 8 && matches!(body, ClientBody::ReqServerType))
+                .count(),
+            1
+        );
+        assert_eq!(fragmented.len(), 3);
+
+        // a single flood for the whole batch
+        let floods = recv_2
+            .try_iter()
+            .filter(|packet| matches!(packet.pack_type, PacketType::FloodRequest(_)))
+            .count();
+        assert_eq!(floods, 1);
+        assert!(client.batch.is_none());
+    }
+
+    //---------- QUERY ROUTE TEST ----------//
+    #[test]
+    fn client_query_route_test() {
+        let (mut client, ctrl_recv_event, [_recv_2, _recv_3]) = test_client([2, 3]);
+        client.handle_command(ClientCommand::QueryRoute(6));
+        assert!(matches!(
+            ctrl_recv_event.try_recv(),
+            Ok(ClientEvent::Route(6, None))
+        ));
+
+        // 1-2-6 and 1-3-4-6
+        client.handle_flood_response(&FloodResponse {
+            flood_id: 0,
+            path_trace: vec![
+                (1, NodeType::Client),
+                (3, NodeType::Drone),
+                (4, NodeType::Drone),
+                (6, NodeType::Server),
+            ],
+        });
+        client.handle_flood_response(&FloodResponse {
+            flood_id: 0,
+            path_trace: vec![
+                (1, NodeType::Client),
+                (2, NodeType::Drone),
+                (6, NodeType::Server),
+            ],
+        });
+
+        client.handle_command(ClientCommand::QueryRoute(6));
+        match ctrl_recv_event.try_iter().last() {
+            Some(ClientEvent::Route(6, Some(path))) => assert_eq!(path, vec![1, 2, 6]),
+            _ => panic!("expected the route to 6"),
+        }
+    }
+
+    //---------- DISCOVERY MODE TEST ----------//
+    #[test]
+    fn client_jittered_discovery_test() {
+        let max_delay = Duration::from_millis(200);
+        let (flood_send, flood_recv) = unbounded();
+        let requested_delays = Arc::new(Mutex::new(Vec::new()));
+        let mut controllers = Vec::new();
+        let mut handles = Vec::new();
+        let start = Instant::now();
+        for id in 1..=8 {
+            let (send_command, client_recv_command) = unbounded();
+            let (client_send_event, _ctrl_recv_event) = unbounded();
+            let (_send_to_client, client_recv) = unbounded();
+            let (client_send_drone, recv_drone) = unbounded::<Packet>();
+
+            let mut client = Client::new(
+                id,
+                client_send_event,
+                client_recv_command,
+                HashMap::from([(100, client_send_drone)]),
+                client_recv,
+            );
+            client.set_discovery_mode(DiscoveryMode::Jittered(max_delay));
+            // the clients are spread evenly over the maximum delay
+            let jitter_delays = requested_delays.clone();
+            client.set_discovery_jitter(Arc::new(move |max_delay: Duration| {
+                jitter_delays.lock().unwrap().push(max_delay);
+                max_delay * u32::from(id) / 8
+            }));
+            controllers.push(send_command);
+            handles.push(std::thread::spawn(move || client.run()));
+
+            let flood_send = flood_send.clone();
+            std::thread::spawn(move || {
+                if recv_drone.recv().is_ok() {
+                    _ = flood_send.send((id, Instant::now()));
+                }
+            });
+        }
+
+        let floods: Vec<(NodeId, Instant)> = (0..8)
+            .map(|_| {
+                flood_recv
+                    .recv_timeout(max_delay * 5)
+                    .expect("every client floods within the maximum delay")
+            })
+            .collect();
+        for controller in controllers {
+            controller.send(ClientCommand::Return).unwrap();
+        }
+        for handle in handles {
+            handle.join().unwrap();
+        }
+
+        assert_eq!(*requested_delays.lock().unwrap(), vec![max_delay; 8]);
+        // no client floods before its own delay
+        for (id, flood) in floods {
+            assert!(flood.duration_since(start) >= max_delay * u32::from(id) / 8);
+        }
+    }
+
+    //---------- BACKPRESSURE TEST ----------//
+    #[test]
+    fn client_backpressure_test() {
+        let (mut client, ctrl_recv_event, []) = test_client([]);
+        let (client_send_2, recv_2) = crossbeam_channel::bounded(1);
+        client.packet_send.insert(2, client_send_2);
+        add_path(&mut client, &[1, 2, 6]);
+        client.set_backpressure(true);
+        let fragment = |fragment_index| Packet {
+            routing_header: SourceRoutingHeader {
+                hop_index: 0,
+                hops: vec![1, 2, 6],
+            },
+            session_id: 0,
+            pack_type: PacketType::MsgFragment(Fragment::new(fragment_index, 3, [0; 128])),
+        };
+
+        // the channel has room for a single packet, the others are queued as unsent instead of blocking
+        for fragment_index in 0..3 {
+            client.send_packet(fragment(fragment_index));
+        }
+        assert_eq!(client.message_manager.count_unsent_fragments()[&6], 2);
+        assert!(client.congested_packets.is_empty());
+
+        let mut received = Vec::new();
+        while received.len() < 3 {
+            let packet = recv_2.try_recv().expect("no packet is lost");
+            received.push(packet.get_fragment_index());
+            client.send_congested();
+        }
+        assert_eq!(received, vec![0, 1, 2]);
+        assert!(client.message_manager.count_unsent_fragments().is_empty());
+        assert!(client.congested_dests.is_empty());
+
+        let sent = ctrl_recv_event
+            .try_iter()
+            .filter(|event| matches!(event, ClientEvent::PacketSent(_)))
+            .count();
+        assert_eq!(sent, 3);
+    }
+
+    //---------- CONGESTED QUEUE TEST ----------//
+    #[test]
+    fn client_congested_queue_test() {
+        let (mut client, _ctrl_recv_event, [_recv_3]) = test_client([3]);
+        let (client_send_2, recv_2) = crossbeam_channel::bounded(1);
+        client.packet_send.insert(2, client_send_2);
+        client.set_backpressure(true);
+        let ack = |fragment_index| Packet {
+            routing_header: SourceRoutingHeader {
+                hop_index: 0,
+                hops: vec![1, 2, 6],
+            },
+            session_id: 0,
+            pack_type: PacketType::Ack(Ack { fragment_index }),
+        };
+
+        // the queue of a neighbor is capped, the oldest packets are dropped
+        for fragment_index in 0..MAX_CONGESTED_PACKETS as u64 + 2 {
+            client.send_packet(ack(fragment_index));
+        }
+        let queue = &client.congested_packets[&2];
+        assert_eq!(queue.len(), MAX_CONGESTED_PACKETS);
+        assert!(matches!(
+            queue.front().unwrap().pack_type,
+            PacketType::Ack(Ack { fragment_index: 2 })
+        ));
+
+        // once the neighbor is disconnected its queue isn't retried anymore
+        drop(recv_2);
+        client.send_congested();
+        assert!(client.congested_packets.is_empty());
+        assert!(!client.packet_send.contains_key(&2));
+    }
 }
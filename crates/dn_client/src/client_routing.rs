@@ -155,12 +155,16 @@ impl DroneInfo {
 /// - `servers_info`: Information about known servers and their routing paths.
 /// - `drones_info`: Information about drone nodes and their performance metrics.
 /// - `clients`: Set of known client nodes in the network.
+/// - `static_routes`: Paths set by the user, used instead of the computed ones.
+/// - `blacklist`: Drones that are never used in the computed paths.
 pub struct ClientRouting {
     client_id: NodeId,
     topology: UnGraphMap<NodeId, ()>,
     servers_info: HashMap<NodeId, ServerInfo>,
     drones_info: HashMap<NodeId, DroneInfo>,
     clients: HashSet<NodeId>,
+    static_routes: HashMap<NodeId, Path>,
+    blacklist: HashSet<NodeId>,
 }
 
 impl ClientRouting {
@@ -188,6 +192,8 @@ impl ClientRouting {
             servers_info: HashMap::new(),
             drones_info: HashMap::new(),
             clients,
+            static_routes: HashMap::new(),
+            blacklist: HashSet::new(),
         }
     }
 
@@ -214,7 +220,11 @@ impl ClientRouting {
     ///
     /// ### Arguments:
     /// - `neighbor`: The ID of the neighbor node to disconnect from.
-    pub fn remove_channel_to_neighbor(&mut self, neighbor: NodeId) {
+    ///
+    /// ### Returns:
+    /// - `Some(Vec<(NodeId, Path)>)`: List of servers whose path changed after removing the channel.
+    /// - `None`: If the channel didn't exist or no path changed.
+    pub fn remove_channel_to_neighbor(&mut self, neighbor: NodeId) -> Option<Vec<(NodeId, Path)>> {
         if self
             .topology
             .remove_edge(self.client_id, neighbor)
@@ -224,7 +234,9 @@ impl ClientRouting {
                 self.topology.remove_node(neighbor);
             }
 
-            self.compute_routing_paths();
+            self.compute_routing_paths()
+        } else {
+            None
         }
     }
 
@@ -238,8 +250,8 @@ impl ClientRouting {
     /// - `neighbor`: The ID of the node to connect to.
     ///
     /// ### Returns:
-    /// - `Some(Vec<(NodeId, Path)>)`: List of servers that became reachable after adding the channel.
-    /// - `None`: If the channel already existed.
+    /// - `Some(Vec<(NodeId, Path)>)`: List of servers that became reachable or whose path changed after adding the channel.
+    /// - `None`: If the channel already existed or no path changed.
     pub fn add_channel_to_neighbor(&mut self, neighbor: NodeId) -> Option<Vec<(NodeId, Path)>> {
         if !self.topology.contains_node(neighbor) {
             self.topology.add_node(neighbor);
@@ -266,11 +278,17 @@ impl ClientRouting {
     ///
     /// ### Arguments:
     /// - `node`: The ID of the node to remove.
-    pub fn remove_node(&mut self, node: NodeId) {
+    ///
+    /// ### Returns:
+    /// - `Some(Vec<(NodeId, Path)>)`: List of servers whose path changed after removing the node.
+    /// - `None`: If the node didn't exist or no path changed.
+    pub fn remove_node(&mut self, node: NodeId) -> Option<Vec<(NodeId, Path)>> {
         if self.topology.remove_node(node) {
             self.drones_info.remove(&node);
 
-            self.compute_routing_paths();
+            self.compute_routing_paths()
+        } else {
+            None
         }
     }
 
@@ -284,8 +302,8 @@ impl ClientRouting {
     /// - `path`: A reference to the flood path containing nodes and their types.
     ///
     /// ### Returns:
-    /// - `Some(Vec<(NodeId, Path)>)`: List of servers that became reachable after topology update.
-    /// - `None`: If the path is empty or if no topology changes were needed.
+    /// - `Some(Vec<(NodeId, Path)>)`: List of servers that became reachable or whose path changed after topology update.
+    /// - `None`: If the path is empty, if no topology changes were needed or if no path changed.
     pub fn add_path(&mut self, path: &FloodPath) -> Option<Vec<(NodeId, Path)>> {
         //check if path is empty and
         let mut iter = path.iter();
@@ -331,17 +349,74 @@ impl ClientRouting {
         }
     }
 
+    /// Seeds the network topology with a previously exported one, see `export_topology`.
+    ///
+    /// Nodes are added and classified as drones, servers or clients based on their type, as in `add_path`.
+    /// Edges whose endpoints are not in the topology after adding the nodes are ignored.
+    /// Only recomputes routing paths if the topology was actually modified
+    ///
+    /// ### Arguments:
+    /// - `nodes`: The nodes of the topology with their types.
+    /// - `edges`: The pairs of connected nodes.
+    ///
+    /// ### Returns:
+    /// - `Some(Vec<(NodeId, Path)>)`: List of servers that became reachable or whose path changed after the import.
+    /// - `None`: If no topology changes were needed or if no path changed.
+    pub fn import_topology(
+        &mut self,
+        nodes: &[(NodeId, NodeType)],
+        edges: &[(NodeId, NodeId)],
+    ) -> Option<Vec<(NodeId, Path)>> {
+        let mut something_changed = false;
+        for &(node, node_type) in nodes {
+            if !self.topology.contains_node(node) {
+                self.topology.add_node(node);
+                match node_type {
+                    NodeType::Drone => {
+                        self.drones_info.entry(node).or_default();
+                    }
+                    NodeType::Server => {
+                        self.servers_info.entry(node).or_default();
+                    }
+                    NodeType::Client => {
+                        self.clients.insert(node);
+                    }
+                }
+                something_changed = true;
+            }
+        }
+
+        for &(a, b) in edges {
+            if self.topology.contains_node(a)
+                && self.topology.contains_node(b)
+                && self.topology.add_edge(a, b, ()).is_none()
+            {
+                something_changed = true;
+            }
+        }
+
+        if something_changed {
+            self.compute_routing_paths()
+        } else {
+            None
+        }
+    }
+
     /// Update drone's information in the path from client to the given server.
     ///
-    /// Call function below
+    /// Call function below, then refresh the path to that server only.
     ///
     /// ### Arguments:
     /// - `server`: The ID of the target server.
+    ///
+    /// ### Returns:
+    /// - `Some(Vec<NodeId>)`: The recomputed path to the server.
+    /// - `None`: If the server is unknown or unreachable.
     //---------- update info on packet exchanged ----------//
-    pub fn correct_send_to(&mut self, server: NodeId) {
-        if let Some(server_info) = self.servers_info.get(&server) {
-            self.correct_exchanged_with(&server_info.path.clone());
-        }
+    pub fn correct_send_to(&mut self, server: NodeId) -> Option<Path> {
+        let path = self.servers_info.get(&server)?.path.clone();
+        self.correct_exchanged_with(&path);
+        self.recompute_path_to(server)
     }
 
     /// Updates drone statistics for successful message transmission along a path.
@@ -357,18 +432,14 @@ impl ClientRouting {
             }
         }
     }
-    /// Update the information of drones on a path.
+    /// Updates the information of drones after a `Dropped` NACK.
     ///
-    /// This function returns an option to the previously computed path to `destination`,
-    /// if the server is known and if it's marked as reachable.
-    /// If the server is unknown, or it's marked as unreachable, it returns None.
+    /// The drop is attributed only to the first node of `path`, the drone that generated the NACK,
+    /// while every other drone on the NACK path is credited with a correctly traveled packet.
+    /// Nodes that are not known drones are ignored.
     ///
     /// ### Arguments:
-    /// - `destination`: A reference to the ID of the destination node.
-    ///
-    /// ### Returns:
-    /// - `Some(Vec<NodeId>)`: An option to a valid path to the destination node ID.
-    /// - `None`: Otherwise.
+    /// - `path`: A reference to the path followed by the NACK, starting from the drone that dropped the packet.
     pub fn inc_packet_dropped(&mut self, path: &Path) {
         let mut iter = path.iter();
 
@@ -387,11 +458,47 @@ impl ClientRouting {
         self.compute_routing_paths();
     }
 
+    //---------- topology view ----------//
+    /// Returns all the edges of the client's view of the network topology.
+    ///
+    /// ### Returns:
+    /// - `Vec<(NodeId, NodeId)>`: The pairs of connected nodes.
+    #[must_use]
+    pub fn edges(&self) -> Vec<(NodeId, NodeId)> {
+        self.topology.all_edges().map(|(a, b, ())| (a, b)).collect()
+    }
+
+    /// Exports the client's view of the network topology, so that it can be imported by `import_topology`.
+    ///
+    /// ### Returns:
+    /// - `Vec<(NodeId, NodeType)>`: The nodes with their types, in ascending id order.
+    /// - `Vec<(NodeId, NodeId)>`: The pairs of connected nodes.
+    #[must_use]
+    pub fn export_topology(&self) -> (Vec<(NodeId, NodeType)>, Vec<(NodeId, NodeId)>) {
+        let mut nodes: Vec<(NodeId, NodeType)> = self
+            .topology
+            .nodes()
+            .map(|node| {
+                let node_type = if self.servers_info.contains_key(&node) {
+                    NodeType::Server
+                } else if self.clients.contains(&node) {
+                    NodeType::Client
+                } else {
+                    NodeType::Drone
+                };
+                (node, node_type)
+            })
+            .collect();
+        nodes.sort_unstable_by_key(|(node, _)| *node);
+
+        (nodes, self.edges())
+    }
+
     //---------- compute source routing ----------//
     /// Retrieves an option to the previously computed path to `destination`, if any.
     ///
-    /// This function returns an option to the previously computed path to `destination`,
-    /// if the server is known and if it's marked as reachable.
+    /// This function returns an option to the static route to `destination` if one is set,
+    /// otherwise to the previously computed path, if the server is known and if it's marked as reachable.
     /// If the server is unknown, or it's marked as unreachable, it returns None.
     ///
     /// ### Arguments:
@@ -402,29 +509,164 @@ impl ClientRouting {
     /// - `None`: Otherwise.
     #[must_use]
     pub fn get_path(&self, destination: NodeId) -> Option<Path> {
+        if let Some(path) = self.static_routes.get(&destination) {
+            return Some(path.clone());
+        }
         match self.servers_info.get(&destination) {
             Some(server_info) if server_info.reachable => Some(server_info.path.clone()),
             _ => None,
         }
     }
 
+    /// Sets a path that is always used to reach `destination`, regardless of the computed ones.
+    ///
+    /// ### Arguments:
+    /// - `destination`: The ID of the destination node.
+    /// - `path`: The path to use, from the client to `destination`.
+    ///
+    /// ### Returns:
+    /// - `true`: If the route has been set.
+    /// - `false`: If the path doesn't start at the client or doesn't end at `destination`,
+    ///   if two consecutive hops are not linked in the known topology or if it goes through a blacklisted node.
+    pub fn set_static_route(&mut self, destination: NodeId, path: Path) -> bool {
+        if path.len() < 2
+            || path.first() != Some(&self.client_id)
+            || path.last() != Some(&destination)
+            || path.iter().any(|node| self.blacklist.contains(node))
+            || path
+                .windows(2)
+                .any(|hop| !self.topology.contains_edge(hop[0], hop[1]))
+        {
+            return false;
+        }
+        self.static_routes.insert(destination, path);
+        true
+    }
+
+    /// Removes the static route to `destination`, the computed path is used again.
+    ///
+    /// ### Arguments:
+    /// - `destination`: The ID of the destination node.
+    pub fn clear_static_route(&mut self, destination: NodeId) {
+        self.static_routes.remove(&destination);
+    }
+
+    /// Prevents the computed paths from going through `node`, as if it were absent.
+    ///
+    /// Unlike `remove_node`, the node is kept in the topology and stays blacklisted across floods.
+    ///
+    /// ### Arguments:
+    /// - `node`: The ID of the node to avoid.
+    ///
+    /// ### Returns:
+    /// - `Some(Vec<(NodeId, Path)>)`: List of servers whose path changed.
+    /// - `None`: If no path changed.
+    pub fn blacklist(&mut self, node: NodeId) -> Option<Vec<(NodeId, Path)>> {
+        if self.blacklist.insert(node) {
+            self.compute_routing_paths()
+        } else {
+            None
+        }
+    }
+
+    /// Allows the computed paths to go through `node` again.
+    ///
+    /// ### Arguments:
+    /// - `node`: The ID of the node to allow.
+    ///
+    /// ### Returns:
+    /// - `Some(Vec<(NodeId, Path)>)`: List of servers whose path changed.
+    /// - `None`: If `node` wasn't blacklisted or no path changed.
+    pub fn unblacklist(&mut self, node: NodeId) -> Option<Vec<(NodeId, Path)>> {
+        if self.blacklist.remove(&node) {
+            self.compute_routing_paths()
+        } else {
+            None
+        }
+    }
+
     /// Compute the path from the client to all known servers
-    /// and return servers which became reachable after updating
+    /// and return servers which became reachable or whose path changed after updating
     ///
     /// This function returns an option to a list of pairs (server, serverPath),
-    /// which contains all servers became reachable after updating their routing path, with their path.
+    /// which contains all servers became reachable or rerouted after updating their routing path, with their path.
     ///
     /// # Returns
-    /// - `Some<Vec<(NodeId, Vec<NodeId>)>>`:  List of server became reachable with their path if any,
-    /// - `None`: If no server became reachable after update.
+    /// - `Some<Vec<(NodeId, Vec<NodeId>)>>`:  List of server became reachable or rerouted with their path if any,
+    /// - `None`: If no server became reachable and no path changed after update.
     pub fn compute_routing_paths(&mut self) -> Option<Vec<(NodeId, Path)>> {
         if self.servers_info.is_empty() {
             return None; //No server in the topology
         }
 
-        let mut servers_became_reachable: Vec<(NodeId, Path)> = Vec::new();
+        let mut servers_updated: Vec<(NodeId, Path)> = Vec::new();
+        let (distances, visited) = self.search(None);
 
-        //init
+        //compute single path for every server
+        for (&server, server_info) in &mut self.servers_info {
+            if visited.contains(&server) {
+                if let Some(path) = Self::build_path(self.client_id, &distances, server) {
+                    if !server_info.reachable || server_info.path != path {
+                        server_info.reachable = true;
+                        server_info.path.clone_from(&path);
+                        servers_updated.push((server, path));
+                    }
+                }
+            } else {
+                server_info.reachable = false;
+            }
+        }
+
+        if servers_updated.is_empty() {
+            None
+        } else {
+            Some(servers_updated)
+        }
+    }
+
+    /// Recompute the path from the client to a single server.
+    ///
+    /// Cheaper than `compute_routing_paths` since the search stops as soon as `server` is reached,
+    /// the paths to the other servers are left untouched.
+    ///
+    /// ### Arguments:
+    /// - `server`: The ID of the server.
+    ///
+    /// ### Returns:
+    /// - `Some(Vec<NodeId>)`: The new path to the server.
+    /// - `None`: If the server is unknown or unreachable.
+    pub fn recompute_path_to(&mut self, server: NodeId) -> Option<Path> {
+        if !self.servers_info.contains_key(&server) {
+            return None;
+        }
+
+        let (distances, visited) = self.search(Some(server));
+        let path = if visited.contains(&server) {
+            Self::build_path(self.client_id, &distances, server)
+        } else {
+            None
+        };
+
+        let server_info = self.servers_info.get_mut(&server)?;
+        match &path {
+            Some(path) => {
+                server_info.reachable = true;
+                server_info.path.clone_from(path);
+            }
+            None => server_info.reachable = false,
+        }
+        path
+    }
+
+    /// Search the shortest paths from the client, weighting each drone with its RPS factor.
+    ///
+    /// ### Arguments:
+    /// - `target`: If any, the search stops as soon as this node is reached.
+    ///
+    /// ### Returns:
+    /// - The predecessor and the distance of every reached node, and the set of visited nodes.
+    #[allow(clippy::type_complexity)]
+    fn search(&self, target: Option<NodeId>) -> (HashMap<NodeId, (NodeId, f64)>, HashSet<NodeId>) {
         let mut queue: BinaryHeap<(Reverse<QP>, NodeId)> = BinaryHeap::new();
         queue.push((Reverse(QP::new(0.0)), self.client_id));
 
@@ -438,11 +680,17 @@ impl ClientRouting {
                 let mut distance = qp.prio;
                 if !visited.contains(&node) {
                     visited.insert(node);
+                    if target == Some(node) {
+                        break;
+                    }
 
                     if !self.servers_info.contains_key(&node) {
                         for neighbor in self.topology.neighbors(node) {
-                            //if neighbor it's not visited yet && it's not a client
-                            if !visited.contains(&neighbor) && !self.clients.contains(&neighbor) {
+                            //if neighbor it's not visited yet && it's not a client && it's not blacklisted
+                            if !visited.contains(&neighbor)
+                                && !self.clients.contains(&neighbor)
+                                && !self.blacklist.contains(&neighbor)
+                            {
                                 distance += 1.0;
                                 if let Some(drone_info) = self.drones_info.get(&neighbor) {
                                     distance *= drone_info.rps_factor();
@@ -465,44 +713,30 @@ impl ClientRouting {
             }
         }
 
-        //compute single path for every server
-        for (&server, server_info) in &mut self.servers_info {
-            if visited.contains(&server) {
-                let mut path: Path = Vec::new();
-                path.push(server);
-                let mut last = server;
-
-                let mut pathable = true;
-
-                while last != self.client_id && pathable {
-                    if let Some((pred, _)) = distances.get(&last) {
-                        path.push(*pred);
-                        last = *pred;
-                    } else {
-                        pathable = false;
-                    }
-                }
-
-                if pathable {
-                    path.reverse();
-
-                    server_info.path.clone_from(&path);
+        (distances, visited)
+    }
 
-                    if !server_info.reachable {
-                        server_info.reachable = true;
-                        servers_became_reachable.push((server, path.clone()));
-                    }
-                }
-            } else {
-                server_info.reachable = false;
-            }
+    /// Build the path from the client to `server` following the predecessors found by `search`.
+    ///
+    /// ### Returns:
+    /// - `Some(Vec<NodeId>)`: The path to the server.
+    /// - `None`: If a node of the path has no predecessor.
+    fn build_path(
+        client_id: NodeId,
+        distances: &HashMap<NodeId, (NodeId, f64)>,
+        server: NodeId,
+    ) -> Option<Path> {
+        let mut path: Path = vec![server];
+        let mut last = server;
+
+        while last != client_id {
+            let (pred, _) = distances.get(&last)?;
+            path.push(*pred);
+            last = *pred;
         }
 
-        if servers_became_reachable.is_empty() {
-            None
-        } else {
-            Some(servers_became_reachable)
-        }
+        path.reverse();
+        Some(path)
     }
 }
 
@@ -687,6 +921,86 @@ mod tests {
         );
     }
 
+    #[test] //---------- DROPPED NACK ----------//
+    fn client_routing_dropped_nack_test() {
+        use wg_2024::network::SourceRoutingHeader;
+        use wg_2024::packet::{Nack, NackType, Packet};
+
+        let mut client_routing = ClientRouting::new(1);
+        let path: FloodPath = vec![(1, Client), (2, Drone), (3, Drone), (4, Drone), (6, Server)];
+        client_routing.add_path(&path);
+
+        // drone 3 dropped the fragment and sent the NACK back through 2
+        let nack = Packet::new_nack(
+            SourceRoutingHeader {
+                hop_index: 2,
+                hops: vec![3, 2, 1],
+            },
+            0,
+            Nack {
+                fragment_index: 0,
+                nack_type: NackType::Dropped,
+            },
+        );
+        client_routing.inc_packet_dropped(&nack.routing_header.hops);
+
+        let info = |drone| client_routing.drones_info.get(&drone).unwrap();
+        assert_eq!(info(3).packet_dropped, 1);
+        assert_eq!(info(2).packet_dropped, 0);
+        assert_eq!(info(2).packet_traveled, 1);
+        assert_eq!(info(4).packet_dropped, 0);
+        assert_eq!(info(4).packet_traveled, 0);
+    }
+
+    #[test] //---------- BLACKLIST ----------//
+    fn client_routing_blacklist_test() {
+        let mut client_routing = ClientRouting::new(1);
+        let short: FloodPath = vec![(1, Client), (2, Drone), (6, Server)];
+        let long: FloodPath = vec![(1, Client), (3, Drone), (4, Drone), (6, Server)];
+        client_routing.add_path(&short);
+        client_routing.add_path(&long);
+        assert_eq!(client_routing.get_path(6), Some(vec![1, 2, 6]));
+
+        let rerouted = client_routing.blacklist(2);
+        assert_eq!(rerouted, Some(vec![(6, vec![1, 3, 4, 6])]));
+        assert_eq!(client_routing.get_path(6), Some(vec![1, 3, 4, 6]));
+
+        // the blacklist survives a new flood
+        client_routing.clear_topology();
+        client_routing.add_path(&short);
+        client_routing.add_path(&long);
+        assert_eq!(client_routing.get_path(6), Some(vec![1, 3, 4, 6]));
+
+        // without alternatives the server is unreachable
+        client_routing.blacklist(3);
+        assert_eq!(client_routing.get_path(6), None);
+
+        client_routing.unblacklist(2);
+        assert_eq!(client_routing.get_path(6), Some(vec![1, 2, 6]));
+        assert_eq!(client_routing.unblacklist(2), None);
+    }
+
+    #[test] //---------- STATIC ROUTE ----------//
+    fn client_routing_static_route_test() {
+        let mut client_routing = ClientRouting::new(1);
+        client_routing.add_path(&vec![(1, Client), (2, Drone), (6, Server)]);
+        client_routing.add_path(&vec![(1, Client), (3, Drone), (4, Drone), (6, Server)]);
+
+        // hops not linked in the topology are rejected
+        assert!(!client_routing.set_static_route(6, vec![1, 3, 6]));
+        assert!(!client_routing.set_static_route(6, vec![1, 4, 6]));
+        assert_eq!(client_routing.get_path(6), Some(vec![1, 2, 6]));
+
+        // so are the blacklisted nodes
+        client_routing.blacklist(4);
+        assert!(!client_routing.set_static_route(6, vec![1, 3, 4, 6]));
+        assert_eq!(client_routing.get_path(6), Some(vec![1, 2, 6]));
+
+        client_routing.unblacklist(4);
+        assert!(client_routing.set_static_route(6, vec![1, 3, 4, 6]));
+        assert_eq!(client_routing.get_path(6), Some(vec![1, 3, 4, 6]));
+    }
+
     #[test] //---------- COMPUTE ROUTING ----------//
     fn client_routing_test_part3() {
         /*
@@ -758,5 +1072,88 @@ mod tests {
 
         assert_eq!(servers_became_reachable[0].0, 8);
         assert_eq!(servers_became_reachable[0].1, vec![1, 4, 5, 8]);
+
+        //---------- test path changed ----------//
+        let servers_rerouted = client_routing.remove_node(3).unwrap();
+        assert_eq!(servers_rerouted.len(), 2);
+        assert!(servers_rerouted.contains(&(6, vec![1, 4, 5, 6])));
+        assert!(servers_rerouted.contains(&(7, vec![1, 4, 5, 7])));
+        assert!(client_routing.compute_routing_paths().is_none());
+    }
+
+    #[test] //---------- RECOMPUTE SINGLE PATH ----------//
+    fn client_routing_recompute_path_to_test() {
+        let mut client_routing = ClientRouting::new(1);
+        client_routing.add_path(&vec![(1, Client), (2, Drone), (3, Drone), (6, Server)]);
+        client_routing.add_path(&vec![(1, Client), (4, Drone), (5, Drone), (6, Server)]);
+        client_routing.add_path(&vec![(1, Client), (4, Drone), (7, Server)]);
+
+        assert_eq!(client_routing.get_path(6), Some(vec![1, 2, 3, 6]));
+
+        // 3 becomes unreliable
+        let drone_info = client_routing.drones_info.get_mut(&3).unwrap();
+        drone_info.packet_traveled = 100;
+        drone_info.packet_dropped = 50;
+
+        let path = client_routing.recompute_path_to(6);
+        assert_eq!(path, Some(vec![1, 4, 5, 6]));
+        assert_eq!(client_routing.get_path(6), path);
+        // the other servers are left untouched
+        assert_eq!(client_routing.get_path(7), Some(vec![1, 4, 7]));
+
+        client_routing.compute_routing_paths();
+        assert_eq!(client_routing.get_path(6), path);
+
+        assert!(client_routing.recompute_path_to(8).is_none()); //server doesn't exist
+        client_routing.remove_node(4);
+        client_routing.remove_node(3);
+        assert!(client_routing.recompute_path_to(6).is_none()); //server unreachable
+        assert!(client_routing.get_path(6).is_none());
+    }
+
+    #[test] //---------- EXPORT & IMPORT TOPOLOGY ----------//
+    fn client_routing_import_topology_test() {
+        /*
+        topology with 8 nodes: 1(Client), 2(Drone), 3(Drone), 4(Drone), 5(Drone), 6(Server), 7(Server), 8(Client)
+        paths: 1-2-3-6, 1-4-5-6, 1-4-7, 1-2-8
+        */
+        let mut client_routing = ClientRouting::new(1);
+        client_routing.add_path(&vec![(1, Client), (2, Drone), (3, Drone), (6, Server)]);
+        client_routing.add_path(&vec![(1, Client), (4, Drone), (5, Drone), (6, Server)]);
+        client_routing.add_path(&vec![(1, Client), (4, Drone), (7, Server)]);
+        client_routing.add_path(&vec![(1, Client), (2, Drone), (8, Client)]);
+
+        let (nodes, edges) = client_routing.export_topology();
+        assert_eq!(
+            nodes,
+            vec![
+                (1, Client),
+                (2, Drone),
+                (3, Drone),
+                (4, Drone),
+                (5, Drone),
+                (6, Server),
+                (7, Server),
+                (8, Client)
+            ]
+        );
+
+        let mut imported = ClientRouting::new(1);
+        let servers_updated = imported.import_topology(&nodes, &edges).unwrap();
+        assert_eq!(servers_updated.len(), 2);
+        assert!(imported.clients.contains(&8));
+        for destination in 1..=8 {
+            assert_eq!(
+                imported.get_path(destination),
+                client_routing.get_path(destination)
+            );
+        }
+        assert_eq!(imported.get_path(7), Some(vec![1, 4, 7]));
+
+        // importing the same topology again changes nothing
+        assert!(imported.import_topology(&nodes, &edges).is_none());
+        // edges to unknown nodes are ignored
+        assert!(imported.import_topology(&[], &[(7, 9)]).is_none());
+        assert!(!imported.topology.contains_node(9));
     }
 }
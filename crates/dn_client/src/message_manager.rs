@@ -1,7 +1,10 @@
-use dn_message::{ClientBody, ServerType};
+use dn_message::{ClientBody, ClientCommunicationBody, ServerType};
 use scraper::{Html, Selector};
-use std::collections::{HashMap, HashSet};
+use std::borrow::Cow;
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::str;
+use std::time::{Duration, Instant};
 use wg_2024::network::NodeId;
 use wg_2024::packet::Fragment;
 
@@ -17,6 +20,124 @@ pub enum ServerTypeError {
 //---------- CUSTOM TYPES ----------//
 type PendingFragments = HashMap<u64, Fragment>;
 
+//---------- RETRANSMISSION BACKOFF ----------//
+/// Delay before the first retransmission of a dropped fragment; it doubles at every further drop.
+pub const RETRANSMISSION_BASE_DELAY: Duration = Duration::from_millis(10);
+/// Upper bound of the retransmission delay of a dropped fragment.
+pub const RETRANSMISSION_MAX_DELAY: Duration = Duration::from_millis(640);
+/// Number of drops of the same fragment after which the client floods to refresh its topology.
+pub const RETRANSMISSION_FLOOD_THRESHOLD: u32 = 3;
+/// Number of duplicate ACKs of the same fragment after which the next pending fragment is assumed lost.
+pub const DUPLICATE_ACK_THRESHOLD: u32 = 3;
+
+//---------- SESSION DEADLINE ----------//
+/// Default time after which a session that hasn't been fully acknowledged is considered failed.
+pub const DEFAULT_SESSION_DEADLINE: Duration = Duration::from_secs(30);
+
+//---------- FILE CACHE ----------//
+/// Default number of files kept in the client's file cache.
+pub const DEFAULT_FILE_CACHE_CAPACITY: usize = 32;
+
+//---------- BROWSE SESSION ----------//
+/// Default number of levels of links followed from a fetched HTML page.
+pub const DEFAULT_CRAWL_DEPTH: usize = 1;
+
+/// Least recently used cache of the files fetched from content servers.
+///
+/// ### Fields:
+/// - `files`: A `HashMap` mapping `(server, path)` to the content of the file.
+/// - `recency`: The cached keys, from the least to the most recently used.
+/// - `capacity`: The maximum number of cached files.
+pub struct FileCache {
+    files: HashMap<(NodeId, String), Vec<u8>>,
+    recency: VecDeque<(NodeId, String)>,
+    capacity: usize,
+}
+
+impl FileCache {
+    /// Creates a new empty `FileCache` holding at most `capacity` files.
+    #[must_use]
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            files: HashMap::new(),
+            recency: VecDeque::new(),
+            capacity,
+        }
+    }
+
+    /// Retrieves a cached file, marking it as the most recently used.
+    ///
+    /// ### Arguments:
+    /// - `server`: The content server the file has been fetched from.
+    /// - `path`: The path of the file.
+    ///
+    /// ### Returns:
+    /// - `Some(Vec<u8>)`: The content of the file if it is cached.
+    /// - `None`: Otherwise.
+    pub fn get(&mut self, server: NodeId, path: &str) -> Option<Vec<u8>> {
+        let key = (server, path.to_string());
+        let file = self.files.get(&key)?.clone();
+        self.touch(key);
+        Some(file)
+    }
+
+    /// Checks if a file is cached, without changing its recency.
+    #[must_use]
+    pub fn contains(&self, server: NodeId, path: &str) -> bool {
+        self.files.contains_key(&(server, path.to_string()))
+    }
+
+    /// Caches a file, evicting the least recently used ones if the cache is full.
+    ///
+    /// ### Arguments:
+    /// - `server`: The content server the file has been fetched from.
+    /// - `path`: The path of the file.
+    /// - `file`: The content of the file.
+    pub fn insert(&mut self, server: NodeId, path: &str, file: Vec<u8>) {
+        if self.capacity == 0 {
+            return;
+        }
+
+        let key = (server, path.to_string());
+        self.files.insert(key.clone(), file);
+        self.touch(key);
+        self.evict();
+    }
+
+    /// Changes the maximum number of cached files, evicting the least recently used ones if needed.
+    pub fn set_capacity(&mut self, capacity: usize) {
+        self.capacity = capacity;
+        self.evict();
+    }
+
+    /// Returns the number of cached files.
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.files.len()
+    }
+
+    /// Checks if the cache is empty.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.files.is_empty()
+    }
+
+    fn touch(&mut self, key: (NodeId, String)) {
+        self.recency.retain(|cached| *cached != key);
+        self.recency.push_back(key);
+    }
+
+    fn evict(&mut self) {
+        while self.files.len() > self.capacity {
+            if let Some(key) = self.recency.pop_front() {
+                self.files.remove(&key);
+            } else {
+                break;
+            }
+        }
+    }
+}
+
 //---------- MESSAGE MANAGER ----------//
 /// Manages the state and operations related to message fragments and sessions.
 ///
@@ -28,18 +149,54 @@ type PendingFragments = HashMap<u64, Fragment>;
 ///   which tracks the pending fragments for active sessions.
 /// - `unsent_fragments`: A `HashMap` mapping from `NodeId` to a vector of tuples `(session_id, fragment)` to track
 ///   fragments that have not been sent yet.
-/// - `already_dropped`: A `HashSet` storing pairs of `(session_id, fragment_id)` that have been dropped.
 /// - `communication_servers`: A `HashMap` mapping `NodeId` to a boolean value indicating whether a server has already been logged.
 /// - `content_servers`: A `HashSet` of `NodeId` values representing content servers.
 /// - `unsent_messages`: A `HashMap` mapping `NodeId` to a vector of `ClientBody` instances for unsent messages.
+/// - `file_not_found_on`: A `HashMap` mapping a file path to the content servers that answered `ErrFileNotFound` for it.
+/// - `file_requests`: A `HashSet` of the `(server, path)` requested with `ReqFile` and not answered yet.
+/// - `retransmission_attempts`: A `HashMap` mapping `(session_id, fragment_index)` to the number of times the fragment has been dropped.
+/// - `delayed_fragments`: A vector of `(due, session_id, fragment_index)` of dropped fragments waiting for their backoff to expire.
+/// - `file_cache`: A `FileCache` of the files already fetched from content servers.
+/// - `session_deadlines`: A `HashMap` mapping each pending session to the instant after which it is considered failed.
+/// - `session_deadline`: The time given to a session to be fully acknowledged.
+/// - `files_catalog`: A `HashMap` mapping each content server to the last list of files it returned.
+/// - `duplicate_acks`: A `HashMap` mapping `(session_id, fragment_index)` to the number of ACKs received for an already acknowledged fragment.
+/// - `crawl_depth`: The number of levels of links followed from a fetched HTML page.
+/// - `crawl_visited`: A `HashSet` of the `(server, path)` already visited by the current browse session.
+/// - `crawl_levels`: A `HashMap` mapping each `(server, path)` requested by the browse session to its depth.
+/// - `registering`: A `HashSet` of the communication servers a registration request has been sent to, without a reply yet.
+/// - `priorities`: A `HashMap` mapping `NodeId` to the priority of its traffic, destinations not in the map have priority 0.
+/// - `send_window`: The maximum number of unacknowledged fragments per destination, `None` if unlimited.
+/// - `held_fragments`: A `HashMap` mapping `NodeId` to the `(session_id, fragment)` not sent yet because of the send window.
+/// - `max_sessions`: The maximum number of sessions pending at the same time, `None` if unlimited.
+/// - `queued_messages`: A `VecDeque` of the `(client_body, dest)` not sent yet because of the session limit, in order.
+/// - `first_hops`: A `HashMap` mapping `(session_id, fragment_index)` to the neighbor the fragment was last sent to.
 pub struct MessageManager {
     pending_sessions: HashMap<u64, (NodeId, PendingFragments)>, // (dest, session_id) -> (fragment_index -> fragment)
     unsent_fragments: HashMap<NodeId, Vec<(u64, Fragment)>>, // dest -> Vec<(session_id, fragment)>
-    already_dropped: HashSet<(u64, u64)>,
 
     communication_servers: HashMap<NodeId, bool>, //server_id -> already logged
     content_servers: HashSet<NodeId>,
     unsent_messages: HashMap<NodeId, Vec<ClientBody>>,
+    file_not_found_on: HashMap<String, HashSet<NodeId>>, // path -> servers without the file
+    file_requests: HashSet<(NodeId, String)>,            // (server, requested path)
+    retransmission_attempts: HashMap<(u64, u64), u32>,   // (session_id, fragment_index) -> drops
+    delayed_fragments: Vec<(Instant, u64, u64)>,         // (due, session_id, fragment_index)
+    file_cache: FileCache,
+    session_deadlines: HashMap<u64, Instant>, // session_id -> deadline
+    session_deadline: Duration,
+    files_catalog: HashMap<NodeId, Vec<String>>, // server_id -> files
+    duplicate_acks: HashMap<(u64, u64), u32>,    // (session_id, fragment_index) -> duplicates
+    crawl_depth: usize,
+    crawl_visited: HashSet<(NodeId, String)>,
+    crawl_levels: HashMap<(NodeId, String), usize>, // (server, path) -> depth
+    registering: HashSet<NodeId>,
+    priorities: HashMap<NodeId, u8>,
+    send_window: Option<usize>,
+    held_fragments: HashMap<NodeId, VecDeque<(u64, Fragment)>>, // dest -> (session_id, fragment)
+    max_sessions: Option<usize>,
+    queued_messages: VecDeque<(ClientBody, NodeId)>,
+    first_hops: HashMap<(u64, u64), NodeId>, // (session_id, fragment_index) -> neighbor
 }
 
 impl Default for MessageManager {
@@ -61,11 +218,29 @@ impl MessageManager {
         Self {
             pending_sessions: HashMap::new(),
             unsent_fragments: HashMap::new(),
-            already_dropped: HashSet::new(),
 
             communication_servers: HashMap::new(),
             content_servers: HashSet::new(),
             unsent_messages: HashMap::new(),
+            file_not_found_on: HashMap::new(),
+            file_requests: HashSet::new(),
+            retransmission_attempts: HashMap::new(),
+            delayed_fragments: Vec::new(),
+            file_cache: FileCache::new(DEFAULT_FILE_CACHE_CAPACITY),
+            session_deadlines: HashMap::new(),
+            session_deadline: DEFAULT_SESSION_DEADLINE,
+            files_catalog: HashMap::new(),
+            duplicate_acks: HashMap::new(),
+            crawl_depth: DEFAULT_CRAWL_DEPTH,
+            crawl_visited: HashSet::new(),
+            crawl_levels: HashMap::new(),
+            registering: HashSet::new(),
+            priorities: HashMap::new(),
+            send_window: None,
+            held_fragments: HashMap::new(),
+            max_sessions: None,
+            queued_messages: VecDeque::new(),
+            first_hops: HashMap::new(),
         }
     }
 
@@ -202,6 +377,94 @@ impl MessageManager {
         self.unsent_messages.remove(&dest)
     }
 
+    //---------- content server selection ----------//
+    /// Selects the content server that should serve the given `path`.
+    ///
+    /// The choice is made with rendezvous (highest random weight) hashing over the known content servers,
+    /// so the same path is always routed to the same server while the set of servers doesn't change,
+    /// and different paths are spread across all the mirrors.
+    /// Servers that already answered `ErrFileNotFound` for `path` are skipped.
+    ///
+    /// ### Arguments:
+    /// - `path`: The path of the requested file.
+    ///
+    /// ### Returns:
+    /// - `Some(NodeId)`: The content server selected for the path.
+    /// - `None`: If there is no known content server left to try.
+    #[must_use]
+    pub fn select_content_server(&self, path: &str) -> Option<NodeId> {
+        let excluded = self.file_not_found_on.get(path);
+
+        self.content_servers
+            .iter()
+            .copied()
+            .filter(|server| !excluded.is_some_and(|excluded| excluded.contains(server)))
+            .max_by_key(|&server| (Self::rendezvous_weight(server, path), server))
+    }
+
+    /// Records that `server` doesn't have the file at `path` and selects the next content server to try.
+    ///
+    /// ### Arguments:
+    /// - `path`: The path of the requested file.
+    /// - `server`: The content server that answered `ErrFileNotFound`.
+    ///
+    /// ### Returns:
+    /// - `Some(NodeId)`: The next content server to ask for the file.
+    /// - `None`: If every known content server has already been tried.
+    pub fn content_server_fallback(&mut self, path: &str, server: NodeId) -> Option<NodeId> {
+        self.file_not_found_on
+            .entry(path.to_string())
+            .or_default()
+            .insert(server);
+
+        self.select_content_server(path)
+    }
+
+    /// Records that the file at `path` has been requested to `server` with `ReqFile`.
+    ///
+    /// ### Arguments:
+    /// - `server`: The content server the file has been requested to.
+    /// - `path`: The path of the requested file.
+    pub fn add_file_request(&mut self, server: NodeId, path: &str) {
+        self.file_requests.insert((server, path.to_string()));
+    }
+
+    /// Forgets the request of the file at `path` to `server`, once the file or an error for it has been received.
+    ///
+    /// ### Arguments:
+    /// - `server`: The content server that answered.
+    /// - `path`: The path of the file.
+    ///
+    /// ### Returns:
+    /// - `true`: If the file had been requested to `server` and was waiting for an answer.
+    /// - `false`: Otherwise.
+    pub fn remove_file_request(&mut self, server: NodeId, path: &str) -> bool {
+        self.file_requests.remove(&(server, path.to_string()))
+    }
+
+    /// Forgets the content servers that failed to serve `path`, e.g. after the file has been received.
+    ///
+    /// ### Arguments:
+    /// - `path`: The path of the file.
+    pub fn reset_content_server_fallback(&mut self, path: &str) {
+        self.file_not_found_on.remove(path);
+    }
+
+    /// Computes the rendezvous hashing weight of a `(server, path)` pair.
+    ///
+    /// The 64-bit FNV-1a hash is used since, unlike `DefaultHasher`, it doesn't change between Rust releases,
+    /// so every build of the client selects the same server for a path.
+    fn rendezvous_weight(server: NodeId, path: &str) -> u64 {
+        const FNV_OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+        const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+
+        path.bytes()
+            .chain([server])
+            .fold(FNV_OFFSET_BASIS, |hash, byte| {
+                (hash ^ u64::from(byte)).wrapping_mul(FNV_PRIME)
+            })
+    }
+
     //---------- add ----------//
     /// Adds a server of a specific type to the corresponding server collection.
     ///
@@ -222,11 +485,61 @@ impl MessageManager {
         }
     }
 
+    /// Marks the given communication server as one the client is registered to.
+    ///
+    /// ### Arguments:
+    /// - `server`: The `NodeId` of the communication server that confirmed the registration.
+    pub fn add_reg_to_comm(&mut self, server: NodeId) {
+        self.registering.remove(&server);
+        self.communication_servers.insert(server, true);
+    }
+
+    /// Marks a registration to the given communication server as in progress.
+    ///
+    /// ### Arguments:
+    /// - `server`: The `NodeId` of the communication server.
+    ///
+    /// ### Returns:
+    /// - `true`: If the registration request has to be sent.
+    /// - `false`: If a registration to the server is already in progress.
+    pub fn start_registration(&mut self, server: NodeId) -> bool {
+        self.registering.insert(server)
+    }
+
+    /// Marks the registration to the given communication server as not in progress anymore, so it can be requested again.
+    ///
+    /// ### Arguments:
+    /// - `server`: The `NodeId` of the communication server.
+    pub fn cancel_registration(&mut self, server: NodeId) {
+        self.registering.remove(&server);
+    }
+
+    /// Marks the given communication server as one the client is not registered to anymore.
+    ///
+    /// ### Arguments:
+    /// - `server`: The `NodeId` of the communication server.
+    pub fn remove_reg_to_comm(&mut self, server: NodeId) {
+        self.registering.remove(&server);
+        if let Some(registered) = self.communication_servers.get_mut(&server) {
+            *registered = false;
+        }
+    }
+
+    /// Forgets the type of every known server.
+    ///
+    /// This function clears the content and communication servers collections, including the registrations to the
+    /// communication servers, so that the next message to any server asks for its type again.
+    pub fn clear_server_types(&mut self) {
+        self.content_servers.clear();
+        self.communication_servers.clear();
+        self.registering.clear();
+    }
+
     /// Adds a new pending session with its associated fragments.
     ///
     /// This function stores a new pending session in the `pending_sessions` collection,
     /// mapping the `session_id` to its destination and fragments. Each fragment is added to the `pending_fragment`
-    /// map using its `fragment_index` as the key.
+    /// map using its `fragment_index` as the key. The session has to be fully acknowledged before its deadline.
     ///
     /// ### Arguments:
     /// - `session_id`: The unique ID for the session.
@@ -246,6 +559,8 @@ impl MessageManager {
 
         self.pending_sessions
             .insert(session_id, (dest, pending_fragment));
+        self.session_deadlines
+            .insert(session_id, Instant::now() + self.session_deadline);
     }
 
     /// Adds an unsent fragment to the collection for the specified destination.
@@ -262,6 +577,68 @@ impl MessageManager {
         unsents.push((session_id, fragment.clone()));
     }
 
+    /// Records the neighbor a fragment has been sent to.
+    ///
+    /// ### Arguments:
+    /// - `session_id`: The session ID of the fragment.
+    /// - `fragment_index`: The index of the fragment.
+    /// - `neighbor`: The first hop of the path the fragment has been sent over.
+    pub fn set_first_hop(&mut self, session_id: u64, fragment_index: u64, neighbor: NodeId) {
+        if self.pending_sessions.contains_key(&session_id) {
+            self.first_hops
+                .insert((session_id, fragment_index), neighbor);
+        }
+    }
+
+    /// Queues again the fragments sent through `neighbor` but not acknowledged yet, so that they are resent once a
+    /// new path is known.
+    ///
+    /// Used when the client changes its attachment to the network: the fragments sent through a removed neighbor
+    /// may never be acknowledged, while the ones sent through the other neighbors are still on their way.
+    /// Fragments held back by the send window or already waiting in `unsent_fragments` are left untouched.
+    ///
+    /// ### Arguments:
+    /// - `neighbor`: The neighbor that has been removed.
+    ///
+    /// ### Returns:
+    /// - `true`: If any fragment has been queued again.
+    /// - `false`: If no fragment was in flight through `neighbor`.
+    pub fn requeue_in_flight(&mut self, neighbor: NodeId) -> bool {
+        let mut in_flight = self
+            .pending_sessions
+            .iter()
+            .flat_map(|(&session_id, (dest, pending_fragment))| {
+                pending_fragment
+                    .values()
+                    .map(move |fragment| (*dest, session_id, fragment))
+            })
+            .filter(|&(dest, session_id, fragment)| {
+                let is_same = |(id, f): &(u64, Fragment)| {
+                    *id == session_id && f.fragment_index == fragment.fragment_index
+                };
+                self.first_hops.get(&(session_id, fragment.fragment_index)) == Some(&neighbor)
+                    && !self
+                        .held_fragments
+                        .get(&dest)
+                        .is_some_and(|held| held.iter().any(is_same))
+                    && !self
+                        .unsent_fragments
+                        .get(&dest)
+                        .is_some_and(|unsents| unsents.iter().any(is_same))
+            })
+            .map(|(dest, session_id, fragment)| (dest, session_id, fragment.clone()))
+            .collect::<Vec<_>>();
+        in_flight.sort_by_key(|(_, session_id, fragment)| (*session_id, fragment.fragment_index));
+
+        let requeued = !in_flight.is_empty();
+        for (dest, session_id, fragment) in in_flight {
+            self.first_hops
+                .remove(&(session_id, fragment.fragment_index));
+            self.add_unsent_fragment(session_id, dest, &fragment);
+        }
+        requeued
+    }
+
     /// Adds an unsent message to the collection for the specified destination.
     ///
     /// This function inserts the provided `client_body` into the `unsent_messages` collection for the given `dest` node.
@@ -275,97 +652,611 @@ impl MessageManager {
         unsents.push(client_body.clone());
     }
 
-    //---------- fragment dropped managment ----------//
-    /// Updates the dropped status of a fragment for a given session.
+    //---------- pending work ----------//
+    /// Counts the unsent messages of each destination.
+    #[must_use]
+    pub fn count_unsent_messages(&self) -> HashMap<NodeId, usize> {
+        self.unsent_messages
+            .iter()
+            .map(|(&dest, messages)| (dest, messages.len()))
+            .collect()
+    }
+
+    /// Counts the sessions that haven't been fully acknowledged yet.
+    #[must_use]
+    pub fn count_pending_sessions(&self) -> usize {
+        self.pending_sessions.len()
+    }
+
+    /// Counts the fragments waiting for a path to each destination.
+    #[must_use]
+    pub fn count_unsent_fragments(&self) -> HashMap<NodeId, usize> {
+        self.unsent_fragments
+            .iter()
+            .map(|(&dest, fragments)| (dest, fragments.len()))
+            .collect()
+    }
+
+    /// Drops the unsent messages and fragments of a destination.
     ///
-    /// This function checks if the specified fragment, identified by `session_id` and `fragment_index`,
-    /// has already been marked as dropped. If it has, the fragment is removed from the `already_dropped` set,
-    /// and `true` is returned. If the fragment was not previously dropped, it is added to the set, and `false` is returned.
+    /// The sessions of the dropped fragments are not acknowledged anymore, so they fail once their deadline expires.
     ///
     /// ### Arguments:
-    /// - `session_id`: The session ID for the fragment.
-    /// - `fragment_index`: The index of the fragment within the session.
+    /// - `dest`: The destination whose queues are cleared.
     ///
     /// ### Returns:
-    /// - `true`: If the fragment was previously dropped and is now marked as not dropped.
-    /// - `false`: Otherwise.
-    pub fn update_fragment_dropped(&mut self, session_id: u64, fragment_index: u64) -> bool {
-        if self.already_dropped.contains(&(session_id, fragment_index)) {
-            self.already_dropped.remove(&(session_id, fragment_index));
-            true
-        } else {
-            self.already_dropped.insert((session_id, fragment_index));
-            false
+    /// - The number of dropped messages and the number of dropped fragments.
+    pub fn drop_unsent(&mut self, dest: NodeId) -> (usize, usize) {
+        let messages = self.unsent_messages.remove(&dest).map_or(0, Vec::len);
+        let fragments = self.unsent_fragments.remove(&dest).map_or(0, Vec::len);
+        (messages, fragments)
+    }
+
+    //---------- files catalog ----------//
+    /// Records the list of files returned by a content server, replacing the previous one.
+    ///
+    /// ### Arguments:
+    /// - `server`: The content server that returned the list.
+    /// - `files`: The files available on the server.
+    pub fn add_files_list(&mut self, server: NodeId, files: &[String]) {
+        self.files_catalog.insert(server, files.to_vec());
+    }
+
+    /// Retrieves the files known to be available on each content server.
+    ///
+    /// ### Returns:
+    /// - A `HashMap` mapping each content server that returned a list of files to the files it holds.
+    #[must_use]
+    pub fn get_files_catalog(&self) -> HashMap<NodeId, Vec<String>> {
+        self.files_catalog.clone()
+    }
+
+    //---------- file cache ----------//
+    /// Retrieves a file previously fetched from `server`, if it is still cached.
+    ///
+    /// ### Arguments:
+    /// - `server`: The content server the file has been fetched from.
+    /// - `path`: The path of the file.
+    ///
+    /// ### Returns:
+    /// - `Some(Vec<u8>)`: The content of the file if it is cached.
+    /// - `None`: Otherwise.
+    pub fn get_cached_file(&mut self, server: NodeId, path: &str) -> Option<Vec<u8>> {
+        self.file_cache.get(server, path)
+    }
+
+    /// Checks if a file fetched from `server` is cached.
+    #[must_use]
+    pub fn is_file_cached(&self, server: NodeId, path: &str) -> bool {
+        self.file_cache.contains(server, path)
+    }
+
+    /// Caches a file fetched from `server`.
+    ///
+    /// ### Arguments:
+    /// - `server`: The content server the file has been fetched from.
+    /// - `path`: The path of the file.
+    /// - `file`: The content of the file.
+    pub fn cache_file(&mut self, server: NodeId, path: &str, file: Vec<u8>) {
+        self.file_cache.insert(server, path, file);
+    }
+
+    /// Changes the maximum number of files kept in the file cache.
+    pub fn set_file_cache_capacity(&mut self, capacity: usize) {
+        self.file_cache.set_capacity(capacity);
+    }
+
+    //---------- browse session ----------//
+    /// Changes the number of levels of links followed from a fetched HTML page, `0` disables the prefetching.
+    pub fn set_crawl_depth(&mut self, depth: usize) {
+        self.crawl_depth = depth;
+    }
+
+    /// Starts a new browse session from a page explicitly requested by the user.
+    ///
+    /// The pages visited by the previous session are forgotten.
+    ///
+    /// ### Arguments:
+    /// - `server`: The content server the page has been fetched from.
+    /// - `path`: The path of the page.
+    pub fn start_browse_session(&mut self, server: NodeId, path: &str) {
+        self.crawl_visited.clear();
+        self.crawl_levels.clear();
+        self.crawl_visited.insert((server, path.to_string()));
+    }
+
+    /// Retrieves and removes the depth of a file requested by the current browse session.
+    ///
+    /// ### Returns:
+    /// - `Some(depth)`: If the file has been requested by the browse session.
+    /// - `None`: Otherwise.
+    pub fn take_crawl_level(&mut self, server: NodeId, path: &str) -> Option<usize> {
+        self.crawl_levels.remove(&(server, path.to_string()))
+    }
+
+    /// Selects the links of a page that the browse session has to follow.
+    ///
+    /// No link is followed from pages at the maximum depth. Links already visited by the session or cached
+    /// are skipped, so that cycles between pages are never followed twice.
+    ///
+    /// ### Arguments:
+    /// - `server`: The content server the page has been fetched from.
+    /// - `level`: The depth of the page, `0` for the page requested by the user.
+    /// - `links`: The links found in the page.
+    ///
+    /// ### Returns:
+    /// - The links to be requested, which are marked as visited at depth `level + 1`.
+    pub fn next_crawl_links(
+        &mut self,
+        server: NodeId,
+        level: usize,
+        links: Vec<String>,
+    ) -> Vec<String> {
+        if level >= self.crawl_depth {
+            return Vec::new();
         }
+
+        let mut next = Vec::new();
+        for link in links {
+            if self.file_cache.contains(server, &link)
+                || !self.crawl_visited.insert((server, link.clone()))
+            {
+                continue;
+            }
+            self.crawl_levels.insert((server, link.clone()), level + 1);
+            next.push(link);
+        }
+        next
+    }
+
+    //---------- retransmission backoff ----------//
+    /// Schedules the retransmission of a dropped fragment with an exponential backoff.
+    ///
+    /// Every drop of the same fragment doubles the delay before it is sent again, starting from
+    /// `RETRANSMISSION_BASE_DELAY` and up to `RETRANSMISSION_MAX_DELAY`, so that a persistently lossy
+    /// path isn't flooded with retransmissions.
+    ///
+    /// ### Arguments:
+    /// - `session_id`: The session ID of the dropped fragment.
+    /// - `fragment_index`: The index of the dropped fragment.
+    /// - `now`: The instant the drop has been notified.
+    ///
+    /// ### Returns:
+    /// - The delay after which the fragment has to be sent again.
+    pub fn schedule_retransmission(
+        &mut self,
+        session_id: u64,
+        fragment_index: u64,
+        now: Instant,
+    ) -> Duration {
+        let attempts = self
+            .retransmission_attempts
+            .entry((session_id, fragment_index))
+            .or_insert(0);
+        *attempts += 1;
+
+        let delay = RETRANSMISSION_BASE_DELAY
+            .saturating_mul(1 << (*attempts - 1).min(16))
+            .min(RETRANSMISSION_MAX_DELAY);
+
+        self.delayed_fragments
+            .push((now + delay, session_id, fragment_index));
+
+        delay
+    }
+
+    /// Returns how many times a fragment has been dropped since it was last acknowledged.
+    ///
+    /// ### Arguments:
+    /// - `session_id`: The session ID of the fragment.
+    /// - `fragment_index`: The index of the fragment.
+    #[must_use]
+    pub fn retransmission_attempts(&self, session_id: u64, fragment_index: u64) -> u32 {
+        self.retransmission_attempts
+            .get(&(session_id, fragment_index))
+            .copied()
+            .unwrap_or(0)
+    }
+
+    /// Retrieves and removes the dropped fragments whose backoff has expired.
+    ///
+    /// ### Arguments:
+    /// - `now`: The current instant.
+    ///
+    /// ### Returns:
+    /// - A vector of `(session_id, fragment_index)` of the fragments that can be sent again.
+    pub fn take_due_retransmissions(&mut self, now: Instant) -> Vec<(u64, u64)> {
+        let mut due = Vec::new();
+        self.delayed_fragments
+            .retain(|&(instant, session_id, fragment_index)| {
+                if instant <= now {
+                    due.push((session_id, fragment_index));
+                    false
+                } else {
+                    true
+                }
+            });
+        due
+    }
+
+    //---------- session deadline ----------//
+    /// Changes the time given to the sessions created from now on to be fully acknowledged.
+    pub fn set_session_deadline(&mut self, deadline: Duration) {
+        self.session_deadline = deadline;
     }
 
-    /// Resets the list of already dropped fragments.
+    /// Retrieves and removes the sessions whose deadline has expired.
     ///
-    /// This function clears the `already_dropped` set, effectively removing all entries of fragments that have been marked as dropped.
-    pub fn reset_already_dropped(&mut self) {
-        self.already_dropped.clear();
+    /// All the state associated with an expired session (pending, unsent, dropped and delayed fragments)
+    /// is cleared, so that it is never sent again.
+    ///
+    /// ### Arguments:
+    /// - `now`: The current instant.
+    ///
+    /// ### Returns:
+    /// - A vector of `(session_id, dest)` of the failed sessions.
+    pub fn take_expired_sessions(&mut self, now: Instant) -> Vec<(u64, NodeId)> {
+        let expired = self
+            .session_deadlines
+            .iter()
+            .filter(|(_, deadline)| **deadline <= now)
+            .map(|(session_id, _)| *session_id)
+            .collect::<Vec<_>>();
+
+        expired
+            .into_iter()
+            .filter_map(|session_id| {
+                self.session_deadlines.remove(&session_id);
+                self.remove_session(session_id)
+            })
+            .collect()
+    }
+
+    /// Removes a pending session and all its fragments.
+    ///
+    /// ### Returns:
+    /// - `Some((session_id, dest))`: If the session was pending.
+    /// - `None`: Otherwise.
+    fn remove_session(&mut self, session_id: u64) -> Option<(u64, NodeId)> {
+        let (dest, _) = self.pending_sessions.remove(&session_id)?;
+
+        if let Entry::Occupied(mut unsents) = self.unsent_fragments.entry(dest) {
+            unsents.get_mut().retain(|(id, _)| *id != session_id);
+            if unsents.get().is_empty() {
+                unsents.remove();
+            }
+        }
+        if let Entry::Occupied(mut held) = self.held_fragments.entry(dest) {
+            held.get_mut().retain(|(id, _)| *id != session_id);
+            if held.get().is_empty() {
+                held.remove();
+            }
+        }
+        self.retransmission_attempts
+            .retain(|(id, _), _| *id != session_id);
+        self.delayed_fragments
+            .retain(|(_, id, _)| *id != session_id);
+        self.duplicate_acks.retain(|(id, _), _| *id != session_id);
+        self.first_hops.retain(|(id, _), _| *id != session_id);
+
+        Some((session_id, dest))
     }
 
     //---------- ack managment ----------//
     /// Confirms the acknowledgment of a fragment for a given session.
     ///
     /// This function removes the specified fragment, identified by `session_id` and `fragment_index`,
-    /// from both the `already_dropped` set and the `pending_sessions` collection.
+    /// from the retransmission backoff, the held fragments and the `pending_sessions` collection.
     /// If no more fragments remain in the session, the session is removed from the `pending_sessions` collection.
     ///
     /// ### Arguments:
     /// - `session_id`: The session ID of the fragment being acknowledged.
     /// - `fragment_index`: The index of the fragment being acknowledged.
     pub fn confirm_ack(&mut self, session_id: u64, fragment_index: u64) {
-        self.already_dropped.remove(&(session_id, fragment_index));
+        self.retransmission_attempts
+            .remove(&(session_id, fragment_index));
+        self.first_hops.remove(&(session_id, fragment_index));
 
-        if let Some((_, pending_fragment)) = self.pending_sessions.get_mut(&session_id) {
+        if let Some((dest, pending_fragment)) = self.pending_sessions.get_mut(&session_id) {
             pending_fragment.remove(&fragment_index);
+            // a held fragment acked anyway must not be counted as held anymore
+            if let Entry::Occupied(mut held) = self.held_fragments.entry(*dest) {
+                held.get_mut().retain(|(id, fragment)| {
+                    *id != session_id || fragment.fragment_index != fragment_index
+                });
+                if held.get().is_empty() {
+                    held.remove();
+                }
+            }
             if pending_fragment.is_empty() {
                 self.pending_sessions.remove(&session_id);
+                self.session_deadlines.remove(&session_id);
+                self.duplicate_acks.retain(|(id, _), _| *id != session_id);
             }
         }
     }
 
+    /// Confirms that a whole message has been received, as reported by a `MessageAck`.
+    ///
+    /// The session is removed at once, together with the fragments that are still pending.
+    ///
+    /// ### Arguments:
+    /// - `session_id`: The session ID of the acknowledged message.
+    /// - `server`: The server that acknowledged the message, it must be the destination of the session.
+    ///
+    /// ### Returns:
+    /// - `true`: If the session was pending.
+    /// - `false`: Otherwise.
+    pub fn confirm_message(&mut self, session_id: u64, server: NodeId) -> bool {
+        if !self
+            .pending_sessions
+            .get(&session_id)
+            .is_some_and(|(dest, _)| *dest == server)
+        {
+            return false;
+        }
+
+        self.session_deadlines.remove(&session_id);
+        self.remove_session(session_id).is_some()
+    }
+
+    //---------- priorities ----------//
+    /// Sets the priority of the traffic to a destination: when fragments to several destinations are sent
+    /// together, the ones to destinations with a higher priority go first.
+    ///
+    /// ### Arguments:
+    /// - `dest`: The destination `NodeId`.
+    /// - `level`: The priority of the destination, 0 is the default.
+    pub fn set_priority(&mut self, dest: NodeId, level: u8) {
+        if level == 0 {
+            self.priorities.remove(&dest);
+        } else {
+            self.priorities.insert(dest, level);
+        }
+    }
+
+    /// Returns the priority of the traffic to a destination.
+    #[must_use]
+    pub fn priority(&self, dest: NodeId) -> u8 {
+        self.priorities.get(&dest).copied().unwrap_or(0)
+    }
+
+    //---------- send window ----------//
+    /// Sets the maximum number of fragments that can be sent to the same destination without being acknowledged.
+    ///
+    /// ### Arguments:
+    /// - `window`: The maximum number of unacknowledged fragments per destination, `None` to send them all at once.
+    pub fn set_send_window(&mut self, window: Option<usize>) {
+        self.send_window = window;
+    }
+
+    /// Counts the fragments sent to `dest` that haven't been acknowledged yet.
+    fn in_flight(&self, dest: NodeId) -> usize {
+        let pending = self
+            .pending_sessions
+            .values()
+            .filter(|(session_dest, _)| *session_dest == dest)
+            .map(|(_, pending_fragment)| pending_fragment.len())
+            .sum::<usize>();
+        let held = self.held_fragments.get(&dest).map_or(0, VecDeque::len);
+        pending.saturating_sub(held)
+    }
+
+    /// Splits the fragments of a new session into the ones that fit in the send window and the ones to hold back.
+    ///
+    /// Must be called before `add_pending_session`. Fragments are sent in order, so nothing is sent
+    /// while older fragments to the same destination are held.
+    ///
+    /// ### Arguments:
+    /// - `session_id`: The session ID of the fragments.
+    /// - `dest`: The destination `NodeId` of the session.
+    /// - `fragments`: The fragments of the session.
+    ///
+    /// ### Returns:
+    /// - The fragments that can be sent now, the others are held until `release_held_fragments`.
+    pub fn hold_fragments(
+        &mut self,
+        session_id: u64,
+        dest: NodeId,
+        mut fragments: Vec<Fragment>,
+    ) -> Vec<Fragment> {
+        let Some(window) = self.send_window else {
+            return fragments;
+        };
+
+        let allowed = if self.held_fragments.contains_key(&dest) {
+            0
+        } else {
+            window.saturating_sub(self.in_flight(dest))
+        };
+        if allowed < fragments.len() {
+            let held = fragments.split_off(allowed);
+            self.held_fragments
+                .entry(dest)
+                .or_default()
+                .extend(held.into_iter().map(|fragment| (session_id, fragment)));
+        }
+        fragments
+    }
+
+    /// Takes the held fragments to `dest` that fit in the send window, after some fragments have been acknowledged.
+    ///
+    /// ### Arguments:
+    /// - `dest`: The destination `NodeId`.
+    ///
+    /// ### Returns:
+    /// - A vector of `(session_id, fragment)` to send, in order.
+    pub fn release_held_fragments(&mut self, dest: NodeId) -> Vec<(u64, Fragment)> {
+        let window = self.send_window.unwrap_or(usize::MAX);
+        let allowed = window.saturating_sub(self.in_flight(dest));
+
+        let Entry::Occupied(mut held) = self.held_fragments.entry(dest) else {
+            return Vec::new();
+        };
+        let count = allowed.min(held.get().len());
+        let released = held.get_mut().drain(..count).collect();
+        if held.get().is_empty() {
+            held.remove();
+        }
+        released
+    }
+
+    //---------- session limit ----------//
+    /// Sets the maximum number of sessions that can be pending at the same time.
+    ///
+    /// ### Arguments:
+    /// - `max_sessions`: The maximum number of pending sessions, `None` to leave them unlimited.
+    pub fn set_max_sessions(&mut self, max_sessions: Option<usize>) {
+        self.max_sessions = max_sessions;
+    }
+
+    /// Checks if a new session can be opened without exceeding the session limit.
+    ///
+    /// ### Returns:
+    /// - `true`: If there is no limit or fewer sessions than the limit are pending.
+    /// - `false`: Otherwise.
+    #[must_use]
+    pub fn has_session_slot(&self) -> bool {
+        !self
+            .max_sessions
+            .is_some_and(|max_sessions| self.pending_sessions.len() >= max_sessions)
+    }
+
+    /// Queues a message that can't be sent because of the session limit.
+    ///
+    /// ### Arguments:
+    /// - `client_body`: The body of the message.
+    /// - `dest`: The destination `NodeId` of the message.
+    pub fn queue_message(&mut self, client_body: ClientBody, dest: NodeId) {
+        self.queued_messages.push_back((client_body, dest));
+    }
+
+    /// Takes the oldest message queued because of the session limit, if a new session can be opened.
+    ///
+    /// ### Returns:
+    /// - `Some((ClientBody, NodeId))`: The message and its destination.
+    /// - `None`: If no message is queued or the limit is still reached.
+    pub fn take_queued_message(&mut self) -> Option<(ClientBody, NodeId)> {
+        if self.has_session_slot() {
+            self.queued_messages.pop_front()
+        } else {
+            None
+        }
+    }
+
+    /// Counts the messages queued because of the session limit.
+    #[must_use]
+    pub fn count_queued_messages(&self) -> usize {
+        self.queued_messages.len()
+    }
+
+    /// Counts the ACKs received for a fragment that has already been acknowledged.
+    ///
+    /// When the same fragment is acknowledged `DUPLICATE_ACK_THRESHOLD` times more, the first fragment
+    /// of the session following it that is still pending is assumed lost, so that it can be resent
+    /// without waiting for a NACK or for the session deadline.
+    /// Must be called before `confirm_ack`, since it relies on the fragment not being pending anymore.
+    ///
+    /// ### Arguments:
+    /// - `session_id`: The session ID of the acknowledged fragment.
+    /// - `fragment_index`: The index of the acknowledged fragment.
+    ///
+    /// ### Returns:
+    /// - `Some(fragment_index)`: The index of the fragment to be resent.
+    /// - `None`: If the ACK is not a duplicate, the threshold hasn't been reached or no later fragment is pending.
+    pub fn register_duplicate_ack(&mut self, session_id: u64, fragment_index: u64) -> Option<u64> {
+        let (_, pending_fragment) = self.pending_sessions.get(&session_id)?;
+        if pending_fragment.contains_key(&fragment_index) {
+            return None;
+        }
+
+        let duplicates = self
+            .duplicate_acks
+            .entry((session_id, fragment_index))
+            .or_insert(0);
+        *duplicates += 1;
+        if *duplicates < DUPLICATE_ACK_THRESHOLD {
+            return None;
+        }
+        *duplicates = 0;
+
+        pending_fragment
+            .keys()
+            .filter(|&&index| index > fragment_index)
+            .min()
+            .copied()
+    }
+
+    //---------- control messages ----------//
+    /// Checks if a message is a small control message, which carries no content of its own.
+    ///
+    /// ### Arguments:
+    /// - `client_body`: The body of the message to check.
+    ///
+    /// ### Returns:
+    /// - `true`: If the message is `ReqServerType`, a registration, a client list request or a ping.
+    /// - `false`: Otherwise.
+    #[must_use]
+    pub fn is_control_message(client_body: &ClientBody) -> bool {
+        matches!(
+            client_body,
+            ClientBody::ReqServerType
+                | ClientBody::ClientCommunication(
+                    ClientCommunicationBody::ReqRegistrationToChat
+                        | ClientCommunicationBody::ReqUnregistrationFromChat
+                        | ClientCommunicationBody::ReqClientList
+                        | ClientCommunicationBody::Ping(_)
+                )
+        )
+    }
+
     //---------- file html x external links ----------//
     /// Checks if a given file is an HTML file based on its MIME type.
     ///
     /// This function uses the `infer` library to detect the MIME type of the provided `file` and checks if the MIME type
-    /// is `"text/html"`, indicating the file is an HTML file.
+    /// is `"text/html"`, indicating the file is an HTML file. The raw bytes are checked first, the file is decoded
+    /// (see `decode_text`) only if it starts with a byte order mark, or if the raw check fails and the file looks like
+    /// text, so that binary files aren't copied.
     ///
     /// ### Arguments:
-    /// - `file`: A reference to a `Vec<u8>` representing the file to check.
+    /// - `file`: The bytes of the file to check.
     ///
     /// ### Returns:
     /// - `true`: If the MIME type of the file is `"text/html"`.
     /// - `false`: Otherwise.
     #[must_use]
-    pub fn is_html_file(file: &Vec<u8>) -> bool {
-        let info = infer::get(file.as_slice());
-        if let Some(info) = info {
-            info.mime_type() == "text/html"
-        } else {
-            false
+    pub fn is_html_file(file: &[u8]) -> bool {
+        let is_html =
+            |bytes: &[u8]| infer::get(bytes).is_some_and(|info| info.mime_type() == "text/html");
+
+        let has_bom = file.starts_with(&[0xEF, 0xBB, 0xBF])
+            || file.starts_with(&[0xFF, 0xFE])
+            || file.starts_with(&[0xFE, 0xFF]);
+        if !has_bom && is_html(file) {
+            return true;
         }
+
+        let is_text = file
+            .iter()
+            .all(|&byte| byte >= 0x20 || matches!(byte, b'\t' | b'\n' | b'\r' | 0x0C));
+        (has_bom || (is_text && str::from_utf8(file).is_err()))
+            && is_html(Self::decode_text(file).as_bytes())
     }
 
     /// Extracts all internal links (href and src attributes) from an HTML file.
     ///
-    /// This function parses the provided `file` (as a `Vec<u8>`) as HTML and extracts all links
+    /// This function parses the provided `file` as HTML and extracts all links
     /// from the `href` attributes of `<a>` tags and the `src` attributes of `<img>` tags,
     /// excluding those that start with a hash (`#`). It returns a vector of strings containing the links.
+    /// The file is decoded first, so that documents not encoded in UTF-8 are parsed as well, see `decode_text`.
     ///
     /// ### Arguments:
-    /// - `file`: A reference to a `Vec<u8>` representing the HTML file to parse.
+    /// - `file`: The bytes of the HTML file to parse.
     ///
     /// ### Returns:
     /// - A `Vec<String>` containing all extracted internal links from the HTML document.
-    pub fn get_internal_links(file: &Vec<u8>) -> Vec<String> {
-        let Ok(content) = str::from_utf8(file.as_slice()) else {
-            return Vec::new();
-        };
-
-        let document = Html::parse_document(content);
+    pub fn get_internal_links(file: &[u8]) -> Vec<String> {
+        let document = Html::parse_document(&Self::decode_text(file));
 
         let Ok(a_selector) = Selector::parse("a[href]") else {
             return Vec::new();
@@ -391,6 +1282,37 @@ impl MessageManager {
 
         links
     }
+
+    /// Decodes a text file, detecting its encoding from the byte order mark.
+    ///
+    /// UTF-8 without BOM is the fast path and doesn't allocate. Files starting with a UTF-16 BOM (little or big endian)
+    /// are decoded as UTF-16, while files that are neither UTF-8 nor UTF-16 are decoded as latin-1,
+    /// which maps every byte to a character.
+    ///
+    /// ### Arguments:
+    /// - `file`: The bytes of the file to decode.
+    ///
+    /// ### Returns:
+    /// - The content of the file as a string, with the BOM removed.
+    fn decode_text(file: &[u8]) -> Cow<str> {
+        let utf16 = |bytes: &[u8], from_bytes: fn([u8; 2]) -> u16| {
+            let units = bytes
+                .chunks_exact(2)
+                .map(|pair| from_bytes([pair[0], pair[1]]))
+                .collect::<Vec<_>>();
+            Cow::Owned(String::from_utf16_lossy(&units))
+        };
+
+        match file {
+            [0xEF, 0xBB, 0xBF, rest @ ..] => String::from_utf8_lossy(rest),
+            [0xFF, 0xFE, rest @ ..] => utf16(rest, u16::from_le_bytes),
+            [0xFE, 0xFF, rest @ ..] => utf16(rest, u16::from_be_bytes),
+            _ => match str::from_utf8(file) {
+                Ok(content) => Cow::Borrowed(content),
+                Err(_) => Cow::Owned(file.iter().map(|&byte| char::from(byte)).collect()),
+            },
+        }
+    }
 }
 
 //---------------------------//
@@ -409,7 +1331,6 @@ mod tests {
 
         assert!(message_manager.pending_sessions.is_empty());
         assert!(message_manager.unsent_fragments.is_empty());
-        assert!(message_manager.already_dropped.is_empty());
         assert!(message_manager.communication_servers.is_empty());
         assert!(message_manager.content_servers.is_empty());
         assert!(message_manager.unsent_messages.is_empty());
@@ -487,16 +1408,6 @@ mod tests {
         assert!(unsent_fragments.is_none());
         assert!(fragment2.is_none());
 
-        //---------- already dropped ----------//
-        assert!(!message_manager.update_fragment_dropped(session_id, 0));
-        assert_eq!(message_manager.already_dropped.len(), 1);
-        assert!(!message_manager.update_fragment_dropped(session_id, 1));
-        assert_eq!(message_manager.already_dropped.len(), 2);
-        assert!(message_manager.update_fragment_dropped(session_id, 0));
-        assert_eq!(message_manager.already_dropped.len(), 1);
-        message_manager.reset_already_dropped();
-        assert_eq!(message_manager.already_dropped.len(), 0);
-
         //---------- servers checks ----------//
         let message = ClientBody::ClientContent(ClientContentBody::ReqFile("A".to_string()));
 
@@ -553,4 +1464,233 @@ mod tests {
         assert!(vec.contains(&"https://example.com/image.jpg".to_string()));
         assert!(vec.contains(&"../relative-image.jpg".to_string()));
     }
+
+    //---------- CONTENT SERVER SELECTOR TEST ----------//
+    #[test]
+    fn content_server_selector_test() {
+        let mut message_manager = MessageManager::new();
+        let path = "media/quack.png";
+
+        assert!(message_manager.select_content_server(path).is_none());
+
+        message_manager.add_server_type(7, &ServerType::Content);
+        message_manager.add_server_type(8, &ServerType::Content);
+        message_manager.add_server_type(9, &ServerType::Communication);
+
+        //---------- same path, same server ----------//
+        let selected = message_manager.select_content_server(path).unwrap();
+        // the weights don't depend on the build, so neither does the selection
+        assert_eq!(selected, 8);
+        for _ in 0..10 {
+            assert_eq!(message_manager.select_content_server(path), Some(selected));
+        }
+
+        //---------- fail over on not found ----------//
+        let other = if selected == 7 { 8 } else { 7 };
+        assert_eq!(
+            message_manager.content_server_fallback(path, selected),
+            Some(other)
+        );
+        assert_eq!(message_manager.select_content_server(path), Some(other));
+        assert!(message_manager
+            .content_server_fallback(path, other)
+            .is_none());
+
+        //---------- reset ----------//
+        message_manager.reset_content_server_fallback(path);
+        assert_eq!(message_manager.select_content_server(path), Some(selected));
+
+        //---------- stable weights ----------//
+        assert_eq!(
+            MessageManager::rendezvous_weight(6, "a.txt"),
+            0xa08a_7623_0422_c354
+        );
+        assert_eq!(
+            MessageManager::rendezvous_weight(0, ""),
+            0xaf63_bd4c_8601_b7df
+        );
+    }
+
+    //---------- FILE REQUESTS TEST ----------//
+    #[test]
+    fn file_requests_test() {
+        let mut message_manager = MessageManager::new();
+        message_manager.add_file_request(6, "a.txt");
+        message_manager.add_file_request(6, "b.txt");
+
+        // the answers are matched by path, whatever their order
+        assert!(message_manager.remove_file_request(6, "b.txt"));
+        assert!(!message_manager.remove_file_request(6, "b.txt"));
+        assert!(!message_manager.remove_file_request(7, "a.txt"));
+        assert!(message_manager.remove_file_request(6, "a.txt"));
+    }
+
+    //---------- RETRANSMISSION BACKOFF TEST ----------//
+    #[test]
+    fn retransmission_backoff_test() {
+        let mut message_manager = MessageManager::new();
+        let now = Instant::now();
+        let (session_id, fragment_index) = (3, 1);
+
+        let mut last_delay = Duration::ZERO;
+        for attempt in 1..=4 {
+            let delay = message_manager.schedule_retransmission(session_id, fragment_index, now);
+            assert!(delay > last_delay);
+            assert_eq!(
+                message_manager.retransmission_attempts(session_id, fragment_index),
+                attempt
+            );
+
+            // not resent before the backoff expires
+            assert!(message_manager
+                .take_due_retransmissions(now + delay - Duration::from_millis(1))
+                .is_empty());
+            assert_eq!(
+                message_manager.take_due_retransmissions(now + delay),
+                vec![(session_id, fragment_index)]
+            );
+
+            last_delay = delay;
+        }
+
+        //---------- capped ----------//
+        for _ in 0..20 {
+            message_manager.schedule_retransmission(session_id, fragment_index, now);
+        }
+        assert_eq!(
+            message_manager.schedule_retransmission(session_id, fragment_index, now),
+            RETRANSMISSION_MAX_DELAY
+        );
+
+        //---------- reset on ack ----------//
+        message_manager.confirm_ack(session_id, fragment_index);
+        assert_eq!(
+            message_manager.retransmission_attempts(session_id, fragment_index),
+            0
+        );
+    }
+
+    //---------- FILE CACHE TEST ----------//
+    #[test]
+    fn file_cache_test() {
+        let mut cache = FileCache::new(2);
+        assert!(cache.is_empty());
+        assert!(cache.get(7, "a").is_none());
+
+        cache.insert(7, "a", vec![1]);
+        cache.insert(7, "b", vec![2]);
+        assert_eq!(cache.len(), 2);
+
+        // "a" becomes the most recently used, so "b" is evicted
+        assert_eq!(cache.get(7, "a"), Some(vec![1]));
+        cache.insert(8, "a", vec![3]);
+        assert_eq!(cache.len(), 2);
+        assert!(!cache.contains(7, "b"));
+        assert!(cache.contains(7, "a"));
+        assert!(cache.contains(8, "a"));
+
+        cache.set_capacity(1);
+        assert_eq!(cache.len(), 1);
+        assert!(cache.contains(8, "a"));
+
+        cache.set_capacity(0);
+        cache.insert(7, "c", vec![4]);
+        assert!(cache.is_empty());
+    }
+
+    //---------- SESSION DEADLINE TEST ----------//
+    #[test]
+    fn session_deadline_test() {
+        let mut message_manager = MessageManager::new();
+        let fragment = Fragment {
+            fragment_index: 0,
+            total_n_fragments: 1,
+            length: 0,
+            data: [0u8; 128],
+        };
+
+        message_manager.add_pending_session(4, 9, &vec![fragment.clone()]);
+        message_manager.add_unsent_fragment(4, 9, &fragment);
+        message_manager.schedule_retransmission(4, 0, Instant::now());
+
+        assert!(message_manager
+            .take_expired_sessions(Instant::now())
+            .is_empty());
+
+        let expired =
+            message_manager.take_expired_sessions(Instant::now() + DEFAULT_SESSION_DEADLINE);
+        assert_eq!(expired, vec![(4, 9)]);
+        assert!(message_manager.pending_sessions.is_empty());
+        assert!(message_manager.get_unsent_fragments(9).is_none());
+        assert_eq!(message_manager.retransmission_attempts(4, 0), 0);
+        assert!(message_manager
+            .take_due_retransmissions(Instant::now() + RETRANSMISSION_MAX_DELAY)
+            .is_empty());
+
+        // an expired session is reported only once
+        assert!(message_manager
+            .take_expired_sessions(Instant::now() + DEFAULT_SESSION_DEADLINE)
+            .is_empty());
+    }
+
+    #[test]
+    fn duplicate_ack_test() {
+        let mut message_manager = MessageManager::new();
+        let fragments = (0..3)
+            .map(|fragment_index| Fragment {
+                fragment_index,
+                total_n_fragments: 3,
+                length: 0,
+                data: [0u8; 128],
+            })
+            .collect::<Vec<_>>();
+        message_manager.add_pending_session(4, 9, &fragments);
+
+        // the first ACK of a fragment is not a duplicate
+        assert_eq!(message_manager.register_duplicate_ack(4, 0), None);
+        message_manager.confirm_ack(4, 0);
+
+        assert_eq!(message_manager.register_duplicate_ack(4, 0), None);
+        assert_eq!(message_manager.register_duplicate_ack(4, 0), None);
+        assert_eq!(message_manager.register_duplicate_ack(4, 0), Some(1));
+        // the counter starts over after a retransmission
+        assert_eq!(message_manager.register_duplicate_ack(4, 0), None);
+
+        // no fragment after the last one can be inferred as lost
+        message_manager.confirm_ack(4, 2);
+        for _ in 0..DUPLICATE_ACK_THRESHOLD {
+            assert_eq!(message_manager.register_duplicate_ack(4, 2), None);
+        }
+
+        // ACKs of completed sessions are ignored
+        message_manager.confirm_ack(4, 1);
+        assert!(message_manager.duplicate_acks.is_empty());
+        assert_eq!(message_manager.register_duplicate_ack(4, 0), None);
+    }
+
+    //---------- HTML ENCODING TEST ----------//
+    #[test]
+    fn html_encoding_test() {
+        let html = "<!DOCTYPE html><html><body><a href=\"città.html\">link</a></body></html>";
+
+        let mut utf16_le = vec![0xFF, 0xFE];
+        utf16_le.extend(html.encode_utf16().flat_map(u16::to_le_bytes));
+        let mut utf16_be = vec![0xFE, 0xFF];
+        utf16_be.extend(html.encode_utf16().flat_map(u16::to_be_bytes));
+        let latin_1 = html
+            .chars()
+            .map(|c| u8::try_from(u32::from(c)).unwrap())
+            .collect::<Vec<_>>();
+
+        for file in [utf16_le, utf16_be, latin_1] {
+            assert!(MessageManager::is_html_file(&file));
+            assert_eq!(
+                MessageManager::get_internal_links(&file),
+                vec!["città.html".to_string()]
+            );
+        }
+
+        let png = [0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A, 0x00, 0x00];
+        assert!(!MessageManager::is_html_file(&png));
+    }
 }
@@ -1,12 +1,57 @@
 use crossbeam_channel::Sender;
-use dn_message::{ClientBody, ServerBody};
-use wg_2024::{network::NodeId, packet::Packet};
+use dn_message::{ClientBody, Message, ServerBody};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+use wg_2024::{
+    controller::DroneEvent,
+    network::NodeId,
+    packet::{FloodRequest, Packet},
+};
+
+// when a client or a communication server first floods the network to discover the topology
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DiscoveryMode {
+    // as soon as the node starts
+    #[default]
+    Immediate,
+    // after a random delay up to the given one, so that the nodes don't all flood at once
+    Jittered(Duration),
+    // when the node sends its first message
+    Lazy,
+}
+
+// picks the delay of a jittered discovery given the maximum one, a random delay is used if none is set
+pub type DiscoveryJitter = Arc<dyn Fn(Duration) -> Duration + Send + Sync>;
 
 #[allow(clippy::module_name_repetitions)]
 pub enum ClientCommand {
     AddSender(NodeId, Sender<Packet>),
     SendMessage(ClientBody, NodeId),
+    // send several messages in one pass, the server types and the flood they need are requested once
+    SendBatch(Vec<(ClientBody, NodeId)>),
     RemoveSender(NodeId),
+    // the client replies with the edges of its view of the topology
+    QueryTopology(Sender<Vec<(NodeId, NodeId)>>),
+    // the client replies with ClientEvent::Pending
+    QueryPending,
+    // forget the types of the servers, they will be asked again
+    ResetServerTypes,
+    // the client replies with ClientEvent::Route
+    QueryRoute(NodeId),
+    // retry sending the messages queued for a node, if it's reachable
+    FlushPending(NodeId),
+    // forget the messages and fragments queued for a node, the client replies with ClientEvent::PendingDropped
+    DropPending(NodeId),
+    // always use the path to reach a node, ignored if it doesn't go from the client to the node
+    SetStaticRoute(NodeId, Vec<NodeId>),
+    ClearStaticRoute(NodeId),
+    // register to a communication server, several registrations can be kept at the same time
+    RegisterTo(NodeId),
+    // forget the registration, the server isn't notified
+    UnregisterFrom(NodeId),
+    // fragments to destinations with a higher level are sent first, 0 by default
+    SetPriority(NodeId, u8),
     Return,
 }
 
@@ -14,6 +59,18 @@ pub enum ClientCommand {
 pub enum ServerCommand {
     AddSender(NodeId, Sender<Packet>),
     RemoveSender(NodeId),
+    // the server replies with the edges of its view of the topology
+    QueryTopology(Sender<Vec<(NodeId, NodeId)>>),
+    // unsolicited message to a client
+    PushMessage(ServerBody, NodeId),
+    // send a message as if the server originated it, through its usual send path
+    SendMessage(Message, NodeId),
+    // communication servers reply with their sorted registered clients, content servers with None
+    QueryRoster(Sender<Option<Vec<NodeId>>>),
+    // the server replies with ServerEvent::Sessions
+    QuerySessions,
+    // drop the registration of a client, it isn't notified and has to register again to send messages
+    Deregister(NodeId),
     Return,
 }
 
@@ -31,6 +88,73 @@ pub enum ServerEvent {
         from: NodeId,
         to: NodeId,
     },
+    MessageReassemblyProgress {
+        from: NodeId,
+        to: NodeId,
+        session_id: u64,
+        received: u64,
+        total: u64,
+    },
+    // the server received a request it can't handle, e.g. a communication request on a content server
+    UnsupportedRequest {
+        category: &'static str,
+        from: NodeId,
+        to: NodeId,
+    },
+    // the server has no path to the recipient of a fragment it has to send
+    RouteNotFound {
+        from: NodeId,
+        to: NodeId,
+        session_id: u64,
+        fragment_index: u64,
+    },
+    // a fragment waiting for a path to its recipient has been discarded to make room for a newer one
+    WaitingFragmentEvicted {
+        from: NodeId,
+        to: NodeId,
+        session_id: u64,
+        fragment_index: u64,
+    },
+    // a fragment has been retransmitted too many times, the whole session has been abandoned
+    DeliveryFailed {
+        from: NodeId,
+        to: NodeId,
+        session_id: u64,
+        fragment_index: u64,
+    },
+    // the server discarded a message whose checksum doesn't match its data
+    MessageCorrupted {
+        from: NodeId,
+        to: NodeId,
+        session_id: u64,
+    },
+    // the server discarded a message declaring more fragments than its maximum message size allows
+    MessageTooLarge {
+        from: NodeId,
+        to: NodeId,
+        session_id: u64,
+        size: usize,
+    },
+    // every fragment of a file sent by a content server has been acknowledged
+    FileDelivered {
+        to: NodeId,
+        path: String,
+    },
+    // a content server refused to serve a file larger than its maximum file size
+    FileTooLarge {
+        from: NodeId,
+        to: NodeId,
+        path: String,
+    },
+    // what the server is still holding
+    Sessions {
+        // sessions not fully acknowledged yet
+        pending: usize,
+        // fragments waiting for a path, per destination
+        waiting_fragments: HashMap<NodeId, usize>,
+        // messages waiting for a path, per destination
+        pending_messages: HashMap<NodeId, usize>,
+    },
 }
 
 pub enum ClientEvent {
@@ -47,4 +171,62 @@ pub enum ClientEvent {
         from: NodeId,
         to: NodeId,
     },
+    MessageReassemblyProgress {
+        from: NodeId,
+        to: NodeId,
+        session_id: u64,
+        received: u64,
+        total: u64,
+    },
+    // the client discarded a message whose checksum doesn't match its data
+    MessageCorrupted {
+        from: NodeId,
+        to: NodeId,
+        session_id: u64,
+    },
+    // the session hasn't been fully acknowledged before its deadline
+    MessageFailed {
+        session_id: u64,
+        to: NodeId,
+    },
+    // what the client is still waiting on
+    Pending {
+        // messages waiting for the server type or the registration, per destination
+        unsent_messages: HashMap<NodeId, usize>,
+        // sessions not fully acknowledged yet
+        pending_sessions: usize,
+        // fragments waiting for a path, per destination
+        unsent_fragments: HashMap<NodeId, usize>,
+    },
+    // the path the client would use to reach a server, None if it's unreachable
+    Route(NodeId, Option<Vec<NodeId>>),
+    // the client has been asked to send a message to itself, nothing is sent
+    SelfAddressedMessage {
+        body: ClientBody,
+        id: NodeId,
+    },
+    // a file linked by a page and prefetched by the client was not found on the server
+    PrefetchFailed {
+        server: NodeId,
+        path: String,
+    },
+    // a flood request with no node before the client in its path trace, it isn't answered
+    MalformedFloodRequest(FloodRequest),
+    // too many sessions are pending, the message is sent once one of them completes
+    MessageQueued {
+        to: NodeId,
+    },
+    // the messages and fragments queued for a node have been dropped
+    PendingDropped {
+        to: NodeId,
+        messages: usize,
+        fragments: usize,
+    },
+}
+
+// events of every kind of node, see `SimulationController::unified_events`
+pub enum SimEvent {
+    Drone(DroneEvent),
+    Client(ClientEvent),
+    Server(ServerEvent),
 }
@@ -1,15 +1,23 @@
-use crate::{ClientCommand, ClientEvent, ServerCommand, ServerEvent};
+use crate::recorder::load_trace;
+use crate::{ClientCommand, ClientEvent, ServerCommand, ServerEvent, SimEvent};
 use core::result;
-use crossbeam_channel::{Receiver, SendError, Sender};
-use dn_message::ClientBody;
+use crossbeam_channel::{
+    never, select, unbounded, Receiver, RecvError, RecvTimeoutError, SendError, Sender,
+};
+use dn_message::{ClientBody, Message, ServerBody};
 use petgraph::algo::connected_components;
 use petgraph::prelude::UnGraphMap;
 use rayon::ThreadPool;
-use std::collections::HashMap;
+use std::collections::{BTreeSet, HashMap};
 use std::fmt::Debug;
-use wg_2024::packet::Packet;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+use wg_2024::packet::{Packet, PacketType};
 use wg_2024::{
     controller::{DroneCommand, DroneEvent},
+    drone::Drone,
     network::NodeId,
 };
 
@@ -19,6 +27,8 @@ pub enum Error {
     Missing,
     /// crossbeam's `SendError`
     SendError,
+    /// crossbeam's `RecvError`
+    RecvError,
     /// you are trying to call a function that's intended
     /// only for one type of node on another type of node
     /// e.g. calling `set_pdr` on a client
@@ -31,6 +41,15 @@ pub enum Error {
     /// edge already exist
     /// when trying to add an edge
     EdgeExists,
+    /// no drone implementation belongs to the group
+    /// when trying to replace a drone
+    UnknownGroup,
+    /// the trace can't be read or holds a packet without a receiver
+    /// when trying to replay a trace
+    InvalidTrace,
+    /// the drone has been started without a forwarding delay
+    /// when trying to set its delay
+    NotDelayed,
 }
 
 impl<T> From<SendError<T>> for Error {
@@ -39,10 +58,53 @@ impl<T> From<SendError<T>> for Error {
     }
 }
 
+impl From<RecvError> for Error {
+    fn from(_: RecvError) -> Self {
+        Self::RecvError
+    }
+}
+
 pub type Result<T> = result::Result<T, Error>;
 
 pub type Topology = UnGraphMap<NodeId, ()>;
 
+/// difference between the controller's topology and the one known by a node.
+/// Edges are normalized as `(min, max)` and sorted
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TopologyDiff {
+    /// edges present in the network but unknown to the node
+    pub missing_edges: Vec<(NodeId, NodeId)>,
+    /// edges known by the node that are not in the network
+    pub extra_edges: Vec<(NodeId, NodeId)>,
+}
+
+/// forwarding delay of a drone, see `set_drone_delay`.
+/// Every packet received by the drone is held for a time uniformly
+/// distributed in `min..=max` before the drone can relay it
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DelaySpec {
+    pub min: Duration,
+    pub max: Duration,
+}
+
+impl DelaySpec {
+    #[must_use]
+    pub fn fixed(delay: Duration) -> Self {
+        Self {
+            min: delay,
+            max: delay,
+        }
+    }
+}
+
+/// kind of a node as reported by `describe_network`
+#[derive(Debug, Clone, PartialEq)]
+pub enum NodeSummary {
+    Drone { pdr: f32, group_name: String },
+    Client,
+    Server,
+}
+
 #[derive(Debug, Clone)]
 pub struct Node {
     pub packet_send: Sender<Packet>,
@@ -55,6 +117,8 @@ pub enum NodeType {
         sender: Sender<DroneCommand>,
         pdr: f32,
         group_name: String,
+        // `None` if the drone forwards without any delay, see `set_drone_delay`
+        delay: Option<Sender<DelaySpec>>,
     },
     Client {
         sender: Sender<ClientCommand>,
@@ -86,9 +150,26 @@ impl NodeType {
     }
 }
 
+/// channels of a drone replacing another one, see `replace_drone`.
+/// `packet_recv` is the raw channel of the drone, the delays sent on
+/// `delay_recv` are up to whoever builds it. `delay_recv` is `None`
+/// if the replaced drone forwarded without any delay
+pub struct DroneChannels {
+    pub id: NodeId,
+    pub controller_send: Sender<DroneEvent>,
+    pub controller_recv: Receiver<DroneCommand>,
+    pub packet_recv: Receiver<Packet>,
+    pub packet_send: HashMap<NodeId, Sender<Packet>>,
+    pub delay_recv: Option<Receiver<DelaySpec>>,
+    pub pdr: f32,
+}
+
 pub struct SimulationControllerOptions {
     pub nodes: HashMap<NodeId, Node>,
     pub drone_recv: Receiver<DroneEvent>,
+    // given to the drones spawned by `replace_drone`, which can't be used without it.
+    // The drone events don't end while the controller holds it
+    pub drone_send: Option<Sender<DroneEvent>>,
     pub server_recv: Receiver<ServerEvent>,
     pub client_recv: Receiver<ClientEvent>,
     pub topology: Topology,
@@ -101,12 +182,15 @@ pub struct SimulationController {
     nodes: HashMap<NodeId, Node>,
 
     drone_recv: Receiver<DroneEvent>,
+    drone_send: Option<Sender<DroneEvent>>,
     client_recv: Receiver<ClientEvent>,
     server_recv: Receiver<ServerEvent>,
 
     topology: Topology,
 
-    #[allow(unused)]
+    // packets dropped by each drone, counted before the drone events are forwarded to `drone_recv`
+    drop_stats: Arc<Mutex<HashMap<NodeId, u64>>>,
+
     drone_pool: ThreadPool,
     #[allow(unused)]
     client_pool: ThreadPool,
@@ -117,12 +201,15 @@ pub struct SimulationController {
 impl SimulationController {
     #[must_use]
     pub fn new(opt: SimulationControllerOptions) -> Self {
+        let drop_stats = Arc::default();
         Self {
             nodes: opt.nodes,
-            drone_recv: opt.drone_recv,
+            drone_recv: count_drops(opt.drone_recv, Arc::clone(&drop_stats)),
+            drone_send: opt.drone_send,
             server_recv: opt.server_recv,
             client_recv: opt.client_recv,
             topology: opt.topology,
+            drop_stats,
             drone_pool: opt.drone_pool,
             client_pool: opt.client_pool,
             server_pool: opt.server_pool,
@@ -144,6 +231,57 @@ impl SimulationController {
         self.client_recv.clone()
     }
 
+    /// merges the events of drones, clients and servers into a single stream.
+    /// A forwarding thread takes the events from the receivers returned by `get_drone_recv`,
+    /// `get_client_recv` and `get_server_recv`, so they shouldn't be read at the same time.
+    /// The thread stops when the returned receiver is dropped or every node is gone,
+    /// the drone events end only after the controller is dropped if it can replace drones
+    #[must_use]
+    pub fn unified_events(&self) -> Receiver<SimEvent> {
+        let (send, recv) = unbounded();
+        let mut drone_recv = self.drone_recv.clone();
+        let mut client_recv = self.client_recv.clone();
+        let mut server_recv = self.server_recv.clone();
+
+        thread::spawn(move || {
+            // a disconnected receiver is always ready, so it's replaced with `never`
+            let mut open = 3;
+            while open > 0 {
+                let event = select! {
+                    recv(drone_recv) -> event => match event {
+                        Ok(event) => SimEvent::Drone(event),
+                        Err(_) => {
+                            drone_recv = never();
+                            open -= 1;
+                            continue;
+                        }
+                    },
+                    recv(client_recv) -> event => match event {
+                        Ok(event) => SimEvent::Client(event),
+                        Err(_) => {
+                            client_recv = never();
+                            open -= 1;
+                            continue;
+                        }
+                    },
+                    recv(server_recv) -> event => match event {
+                        Ok(event) => SimEvent::Server(event),
+                        Err(_) => {
+                            server_recv = never();
+                            open -= 1;
+                            continue;
+                        }
+                    },
+                };
+                if send.send(event).is_err() {
+                    return;
+                }
+            }
+        });
+
+        recv
+    }
+
     #[must_use]
     pub fn get_drone_ids(&self) -> Vec<NodeId> {
         self.nodes
@@ -177,6 +315,20 @@ impl SimulationController {
             .collect()
     }
 
+    /// whether `id` is a node of the network, crashed drones are not
+    #[must_use]
+    pub fn is_alive(&self, id: NodeId) -> bool {
+        self.nodes.contains_key(&id)
+    }
+
+    /// sorted ids of the nodes that haven't crashed
+    #[must_use]
+    pub fn live_node_ids(&self) -> Vec<NodeId> {
+        let mut ids = self.nodes.keys().copied().collect::<Vec<_>>();
+        ids.sort_unstable();
+        ids
+    }
+
     /// # Errors
     /// see `Error`
     fn get_drone_sender(&self, id: NodeId) -> Result<Sender<DroneCommand>> {
@@ -195,6 +347,15 @@ impl SimulationController {
         }
     }
 
+    /// # Errors
+    /// see `Error`
+    fn get_server_sender(&self, id: NodeId) -> Result<Sender<ServerCommand>> {
+        match &self.nodes.get(&id).ok_or(Error::Missing)?.node_type {
+            NodeType::Server { sender } => Ok(sender.clone()),
+            _ => Err(Error::InvalidNode),
+        }
+    }
+
     /// # Errors
     /// see `Error`
     fn add_sender(&self, a: NodeId, b: NodeId) -> Result<()> {
@@ -236,6 +397,39 @@ impl SimulationController {
         Ok(())
     }
 
+    /// Moves `client` from drone `from` to drone `to`.
+    /// The new edge is added before the old one is removed,
+    /// so the client is never disconnected from the network
+    ///
+    /// # Errors
+    /// see `Error`
+    pub fn move_client(&mut self, client: NodeId, from: NodeId, to: NodeId) -> Result<()> {
+        match &self.nodes.get(&client).ok_or(Error::Missing)?.node_type {
+            NodeType::Client { .. } => {}
+            _ => return Err(Error::InvalidNode),
+        }
+        if !self.nodes.contains_key(&to) || !self.topology.contains_edge(client, from) {
+            return Err(Error::Missing);
+        }
+        if self.topology.contains_edge(client, to) {
+            return Err(Error::EdgeExists);
+        }
+
+        self.topology.add_edge(client, to, ());
+        self.topology.remove_edge(client, from);
+        if !self.is_valid_topology() {
+            // restore the topology
+            self.topology.remove_edge(client, to);
+            self.topology.add_edge(client, from, ());
+            return Err(Error::InvalidTopology);
+        }
+
+        self.add_sender(client, to)?;
+        self.add_sender(to, client)?;
+        self.remove_sender(client, from)?;
+        self.remove_sender(from, client)
+    }
+
     /// # Errors
     /// see `Error`
     pub fn crash_drone(&mut self, id: NodeId) -> Result<()> {
@@ -255,6 +449,76 @@ impl SimulationController {
         Ok(())
     }
 
+    /// replaces the implementation of a drone with the one built by `new_drone`,
+    /// keeping its id, pdr and edges. The old drone is crashed after its neighbors
+    /// stop sending to it, then they are given the channel of the new one
+    ///
+    /// # Errors
+    /// see `Error`
+    pub fn replace_drone<F>(&mut self, id: NodeId, group_name: String, new_drone: F) -> Result<()>
+    where
+        F: FnOnce(DroneChannels) -> Box<dyn Drone>,
+    {
+        let sender = self.get_drone_sender(id)?;
+        let pdr = self.get_pdr(id)?;
+        let delayed = matches!(
+            self.nodes[&id].node_type,
+            NodeType::Drone { delay: Some(_), .. }
+        );
+        // without `drone_send` the controller can't spawn drones
+        let controller_send = self.drone_send.clone().ok_or(Error::InvalidNode)?;
+        let neighbors = self.topology.neighbors(id).collect::<Vec<_>>();
+        let packet_send = neighbors
+            .iter()
+            .map(|neighbor| {
+                let node = self.nodes.get(neighbor).ok_or(Error::Missing)?;
+                Ok((*neighbor, node.packet_send.clone()))
+            })
+            .collect::<Result<HashMap<_, _>>>()?;
+
+        for &neighbor in &neighbors {
+            self.remove_sender(neighbor, id)?;
+        }
+        sender.send(DroneCommand::Crash)?;
+
+        let (drone_send, controller_recv) = unbounded();
+        let (delay_send, delay_recv) = if delayed {
+            let (delay_send, delay_recv) = unbounded();
+            (Some(delay_send), Some(delay_recv))
+        } else {
+            (None, None)
+        };
+        let (packet_send_new, packet_recv) = unbounded();
+        let mut drone = new_drone(DroneChannels {
+            id,
+            controller_send,
+            controller_recv,
+            packet_recv,
+            packet_send,
+            delay_recv,
+            pdr,
+        });
+        // the thread of the old drone is freed as soon as it has handled its last packets
+        self.drone_pool.spawn(move || drone.run());
+
+        self.nodes.insert(
+            id,
+            Node {
+                packet_send: packet_send_new,
+                node_type: NodeType::Drone {
+                    sender: drone_send,
+                    pdr,
+                    group_name,
+                    delay: delay_send,
+                },
+            },
+        );
+        for &neighbor in &neighbors {
+            self.add_sender(neighbor, id)?;
+        }
+        Ok(())
+    }
+
     /// # Errors
     /// see `Error`
     pub fn set_pdr(&mut self, id: NodeId, new_pdr: f32) -> Result<()> {
@@ -264,6 +528,22 @@ impl SimulationController {
             .send(DroneCommand::SetPacketDropRate(new_pdr))?)
     }
 
+    /// Sets the forwarding delay of a drone, `DelaySpec::default()` removes it.
+    /// Only the drones started with a forwarding delay can be delayed
+    ///
+    /// # Errors
+    /// see `Error`
+    pub fn set_drone_delay(&mut self, id: NodeId, delay: DelaySpec) -> Result<()> {
+        match &self.nodes.get(&id).ok_or(Error::Missing)?.node_type {
+            NodeType::Drone {
+                delay: Some(sender),
+                ..
+            } => Ok(sender.send(delay)?),
+            NodeType::Drone { delay: None, .. } => Err(Error::NotDelayed),
+            _ => Err(Error::InvalidNode),
+        }
+    }
+
     /// # Errors
     /// see `Error`
     pub fn get_pdr(&self, drone_id: NodeId) -> Result<f32> {
@@ -273,6 +553,18 @@ impl SimulationController {
         }
     }
 
+    /// group of the implementation run by every drone
+    #[must_use]
+    pub fn get_drone_groups(&self) -> HashMap<NodeId, &str> {
+        self.nodes
+            .iter()
+            .filter_map(|(id, node)| match &node.node_type {
+                NodeType::Drone { group_name, .. } => Some((*id, group_name.as_str())),
+                _ => None,
+            })
+            .collect()
+    }
+
     /// # Errors
     /// see `Error`
     pub fn get_group_name(&self, drone_id: NodeId) -> Result<&str> {
@@ -294,6 +586,44 @@ impl SimulationController {
         Ok(sender.send(ClientCommand::SendMessage(body, dest))?)
     }
 
+    /// # Errors
+    /// see `Error`
+    pub fn server_push_message(
+        &self,
+        server_id: NodeId,
+        dest: NodeId,
+        body: ServerBody,
+    ) -> Result<()> {
+        let sender = self.get_server_sender(server_id)?;
+        Ok(sender.send(ServerCommand::PushMessage(body, dest))?)
+    }
+
+    /// makes the server send `message` to `dest`, e.g. to drive server-originated traffic in tests.
+    /// `dest` must be a client and `message` a `Message::Server`
+    ///
+    /// # Errors
+    /// see `Error`
+    pub fn server_send(&self, server_id: NodeId, dest: NodeId, message: Message) -> Result<()> {
+        let sender = self.get_server_sender(server_id)?;
+        let dest_node = self.nodes.get(&dest).ok_or(Error::Missing)?;
+        if !matches!(
+            (&dest_node.node_type, &message),
+            (NodeType::Client { .. }, Message::Server(_))
+        ) {
+            return Err(Error::InvalidNode);
+        }
+        Ok(sender.send(ServerCommand::SendMessage(message, dest))?)
+    }
+
+    /// drops the registration of `client_id` to a communication server
+    ///
+    /// # Errors
+    /// see `Error`
+    pub fn server_deregister(&self, server_id: NodeId, client_id: NodeId) -> Result<()> {
+        let sender = self.get_server_sender(server_id)?;
+        Ok(sender.send(ServerCommand::Deregister(client_id))?)
+    }
+
     /// # Panics
     /// if `hops.len()` == 0
     ///
@@ -305,11 +635,202 @@ impl SimulationController {
         Ok(sender.send(p)?)
     }
 
+    /// re-injects the packets of a trace saved by `PacketRecorder::save`, in the order they were recorded.
+    /// The nodes handle them as usual, e.g. a drone forwards a replayed fragment and answers it with a
+    /// NACK, so only the first hop of the packets created by clients and servers is injected:
+    /// a packet is sent to the node its creator sent it to, a flood request to every neighbor of its initiator.
+    /// The packets forwarded by the other nodes are skipped, the nodes forward them again
+    ///
+    /// # Errors
+    /// see `Error`
+    pub fn replay_trace(&self, path: impl AsRef<Path>) -> Result<()> {
+        let packets = load_trace(path.as_ref()).ok_or(Error::InvalidTrace)?;
+        for packet in packets {
+            let receivers = if let PacketType::FloodRequest(flood_request) = &packet.pack_type {
+                let [(initiator, _)] = flood_request.path_trace[..] else {
+                    continue;
+                };
+                self.neighbors(initiator)
+            } else {
+                let header = &packet.routing_header;
+                if header.hop_index != 1 {
+                    continue;
+                }
+                let &receiver = header.hops.get(1).ok_or(Error::InvalidTrace)?;
+                vec![receiver]
+            };
+            for receiver in receivers {
+                let node = self.nodes.get(&receiver).ok_or(Error::Missing)?;
+                node.packet_send.send(packet.clone())?;
+            }
+        }
+        Ok(())
+    }
+
+    /// packets dropped by each drone, counted before the drone events reach `get_drone_recv`
+    /// or `unified_events`, see `dropping_drone`
+    ///
+    /// # Panics
+    /// if the counting thread panicked while updating the statistics
+    #[must_use]
+    pub fn get_drop_stats(&self) -> HashMap<NodeId, u64> {
+        self.drop_stats.lock().unwrap().clone()
+    }
+
     #[must_use]
     pub fn get_topology(&self) -> &Topology {
         &self.topology
     }
 
+    /// Blocks until the node replies with its view of the topology.
+    ///
+    /// # Errors
+    /// see `Error`
+    pub fn get_node_topology(&self, id: NodeId) -> Result<Vec<(NodeId, NodeId)>> {
+        Ok(self.query_topology(id)?.recv()?)
+    }
+
+    /// asks the node for its view of the topology, which is sent on the returned receiver
+    ///
+    /// # Errors
+    /// see `Error`
+    fn query_topology(&self, id: NodeId) -> Result<Receiver<Vec<(NodeId, NodeId)>>> {
+        let (send, recv) = unbounded();
+        match &self.nodes.get(&id).ok_or(Error::Missing)?.node_type {
+            NodeType::Client { sender } => sender.send(ClientCommand::QueryTopology(send))?,
+            NodeType::Server { sender } => sender.send(ServerCommand::QueryTopology(send))?,
+            NodeType::Drone { .. } => return Err(Error::InvalidNode),
+        }
+        Ok(recv)
+    }
+
+    /// Blocks until every server replies with its registered clients.
+    /// Content servers are not included
+    ///
+    /// # Errors
+    /// see `Error`
+    pub fn gather_client_rosters(&self) -> Result<HashMap<NodeId, Vec<NodeId>>> {
+        let mut replies = Vec::new();
+        for (&id, node) in &self.nodes {
+            if let NodeType::Server { sender } = &node.node_type {
+                let (send, recv) = unbounded();
+                sender.send(ServerCommand::QueryRoster(send))?;
+                replies.push((id, recv));
+            }
+        }
+
+        let mut rosters = HashMap::new();
+        for (id, recv) in replies {
+            if let Some(clients) = recv.recv()? {
+                rosters.insert(id, clients);
+            }
+        }
+        Ok(rosters)
+    }
+
+    /// # Errors
+    /// see `Error`
+    pub fn topology_diff(&self, id: NodeId) -> Result<TopologyDiff> {
+        Ok(self.diff_view(self.get_node_topology(id)?))
+    }
+
+    /// difference between the controller's topology and `view`, see `TopologyDiff`
+    fn diff_view(&self, view: Vec<(NodeId, NodeId)>) -> TopologyDiff {
+        let normalize = |(a, b): (NodeId, NodeId)| (a.min(b), a.max(b));
+
+        let view = view.into_iter().map(normalize).collect::<BTreeSet<_>>();
+        let truth = self
+            .topology
+            .all_edges()
+            .map(|(a, b, ())| normalize((a, b)))
+            .collect::<BTreeSet<_>>();
+
+        TopologyDiff {
+            missing_edges: truth.difference(&view).copied().collect(),
+            extra_edges: view.difference(&truth).copied().collect(),
+        }
+    }
+
+    /// Blocks until the view of the topology of every client and server matches the
+    /// network (see `topology_diff`) or `timeout` elapses, even if a node doesn't reply.
+    /// Returns whether the network converged
+    ///
+    /// # Errors
+    /// see `Error`
+    pub fn wait_for_convergence(&self, timeout: Duration) -> Result<bool> {
+        const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+        let deadline = Instant::now() + timeout;
+        loop {
+            if self.is_converged(deadline)? {
+                return Ok(true);
+            }
+            let now = Instant::now();
+            if now >= deadline {
+                return Ok(false);
+            }
+            thread::sleep(POLL_INTERVAL.min(deadline - now));
+        }
+    }
+
+    /// a node that doesn't reply before `deadline` hasn't converged
+    ///
+    /// # Errors
+    /// see `Error`
+    fn is_converged(&self, deadline: Instant) -> Result<bool> {
+        for (&id, node) in &self.nodes {
+            if matches!(node.node_type, NodeType::Drone { .. }) {
+                continue;
+            }
+            let view = match self.query_topology(id)?.recv_deadline(deadline) {
+                Ok(view) => view,
+                Err(RecvTimeoutError::Timeout) => return Ok(false),
+                Err(RecvTimeoutError::Disconnected) => return Err(Error::RecvError),
+            };
+            if self.diff_view(view) != TopologyDiff::default() {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    /// every node sorted by id, with its kind and its sorted neighbors
+    #[must_use]
+    pub fn describe_network(&self) -> Vec<(NodeId, NodeSummary, Vec<NodeId>)> {
+        let mut description = self
+            .nodes
+            .iter()
+            .map(|(&id, node)| {
+                let summary = match &node.node_type {
+                    NodeType::Drone {
+                        pdr, group_name, ..
+                    } => NodeSummary::Drone {
+                        pdr: *pdr,
+                        group_name: group_name.clone(),
+                    },
+                    NodeType::Client { .. } => NodeSummary::Client,
+                    NodeType::Server { .. } => NodeSummary::Server,
+                };
+                (id, summary, self.neighbors(id))
+            })
+            .collect::<Vec<_>>();
+        description.sort_unstable_by_key(|(id, _, _)| *id);
+        description
+    }
+
+    /// sorted neighbors of `id`, empty if `id` is not in the topology
+    #[must_use]
+    pub fn neighbors(&self, id: NodeId) -> Vec<NodeId> {
+        let mut neighbors = self.topology.neighbors(id).collect::<Vec<_>>();
+        neighbors.sort_unstable();
+        neighbors
+    }
+
+    #[must_use]
+    pub fn degree(&self, id: NodeId) -> usize {
+        self.topology.neighbors(id).count()
+    }
+
     #[must_use]
     pub fn is_valid_topology(&self) -> bool {
         if connected_components(&self.topology) != 1 {
@@ -350,6 +871,36 @@ impl SimulationController {
     }
 }
 
+/// counts the packets dropped by each drone, then forwards the drone events to the returned receiver.
+/// The forwarding thread stops when every sender of `drone_recv` is gone or the returned receiver is dropped
+fn count_drops(
+    drone_recv: Receiver<DroneEvent>,
+    drop_stats: Arc<Mutex<HashMap<NodeId, u64>>>,
+) -> Receiver<DroneEvent> {
+    let (send, recv) = unbounded();
+    thread::spawn(move || {
+        for event in drone_recv {
+            if let DroneEvent::PacketDropped(packet) = &event {
+                if let Some(drone) = dropping_drone(packet) {
+                    *drop_stats.lock().unwrap().entry(drone).or_insert(0) += 1;
+                }
+            }
+            if send.send(event).is_err() {
+                return;
+            }
+        }
+    });
+    recv
+}
+
+/// the protocol increments the hop index before dropping a fragment,
+/// so the drone that dropped a packet is the one before its current hop
+fn dropping_drone(packet: &Packet) -> Option<NodeId> {
+    let header = &packet.routing_header;
+    let index = header.hop_index.checked_sub(1)?;
+    header.hops.get(index).copied()
+}
+
 impl Debug for SimulationController {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "{:#?}", self.nodes)
@@ -373,3 +924,584 @@ impl Drop for SimulationController {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rayon::ThreadPoolBuilder;
+    use std::thread;
+    use wg_2024::network::SourceRoutingHeader;
+
+    fn thread_pool() -> ThreadPool {
+        ThreadPoolBuilder::new().num_threads(1).build().unwrap()
+    }
+
+    fn drone(sender: Sender<DroneCommand>) -> NodeType {
+        NodeType::Drone {
+            sender,
+            pdr: 0.0,
+            group_name: String::new(),
+            delay: None,
+        }
+    }
+
+    fn controller(
+        nodes: Vec<(NodeId, NodeType)>,
+        edges: &[(NodeId, NodeId)],
+    ) -> SimulationController {
+        let nodes = nodes
+            .into_iter()
+            .map(|(id, node_type)| {
+                let node = Node {
+                    packet_send: unbounded().0,
+                    node_type,
+                };
+                (id, node)
+            })
+            .collect();
+
+        let mut topology = Topology::new();
+        for &(a, b) in edges {
+            topology.add_edge(a, b, ());
+        }
+
+        SimulationController::new(SimulationControllerOptions {
+            nodes,
+            drone_recv: unbounded().1,
+            drone_send: None,
+            server_recv: unbounded().1,
+            client_recv: unbounded().1,
+            topology,
+            drone_pool: thread_pool(),
+            client_pool: thread_pool(),
+            server_pool: thread_pool(),
+        })
+    }
+
+    #[test]
+    fn test_replay_trace() {
+        use wg_2024::packet::{FloodRequest, Fragment, Nack, NackType, NodeType as PacketNodeType};
+
+        let flood_request = |path_trace: Vec<(NodeId, PacketNodeType)>| {
+            Packet::new_flood_request(
+                SourceRoutingHeader::initialize(vec![]),
+                0,
+                FloodRequest {
+                    flood_id: 7,
+                    initiator_id: 1,
+                    path_trace,
+                },
+            )
+        };
+        let client_flood = flood_request(vec![(1, PacketNodeType::Client)]);
+        let drone_flood = flood_request(vec![
+            (1, PacketNodeType::Client),
+            (2, PacketNodeType::Drone),
+        ]);
+        let mut fragment = Packet::new_fragment(
+            SourceRoutingHeader {
+                hop_index: 1,
+                hops: vec![1, 2, 3],
+            },
+            5,
+            Fragment::new(0, 1, [7; 128]),
+        );
+        let client_fragment = fragment.clone();
+        fragment.routing_header.increase_hop_index();
+        let drone_fragment = fragment;
+        let ack = Packet::new_ack(
+            SourceRoutingHeader {
+                hop_index: 1,
+                hops: vec![3, 2, 1],
+            },
+            5,
+            0,
+        );
+        // created by 2, which answers the replayed fragment with it again
+        let drone_nack = Packet::new_nack(
+            SourceRoutingHeader {
+                hop_index: 1,
+                hops: vec![2, 1],
+            },
+            5,
+            Nack {
+                fragment_index: 0,
+                nack_type: NackType::Dropped,
+            },
+        );
+
+        // only the first hop of the packets of 1 and 3 is injected, the packets of 2 are skipped
+        let expected: HashMap<NodeId, Vec<&Packet>> = HashMap::from([
+            (1, vec![]),
+            (2, vec![&client_flood, &client_fragment, &ack]),
+            (3, vec![]),
+            (4, vec![]),
+        ]);
+
+        let mut recorder = PacketRecorder::new();
+        for event in [
+            SimEvent::Client(ClientEvent::PacketSent(client_flood.clone())),
+            SimEvent::Drone(DroneEvent::PacketSent(drone_flood.clone())),
+            SimEvent::Drone(DroneEvent::PacketSent(drone_flood.clone())),
+            SimEvent::Client(ClientEvent::PacketSent(client_fragment.clone())),
+            SimEvent::Drone(DroneEvent::PacketDropped(client_fragment.clone())),
+            SimEvent::Drone(DroneEvent::PacketSent(drone_nack)),
+            SimEvent::Drone(DroneEvent::PacketSent(drone_fragment.clone())),
+            SimEvent::Server(ServerEvent::PacketSent(ack.clone())),
+        ] {
+            recorder.record(&event);
+        }
+        assert_eq!(recorder.len(), 3);
+        let path = std::env::temp_dir().join(format!(
+            "dn_controller_test_replay_trace_{}",
+            std::process::id()
+        ));
+        recorder.save(&path).unwrap();
+
+        // a new network with the same topology, whose nodes only collect their packets
+        let (client_send, _client_recv) = unbounded();
+        let (server_send, _server_recv) = unbounded();
+        let (drone_2_send, _drone_2_recv) = unbounded();
+        let (drone_4_send, _drone_4_recv) = unbounded();
+        let mut packet_recvs = HashMap::new();
+        let nodes = [
+            (
+                1,
+                NodeType::Client {
+                    sender: client_send,
+                },
+            ),
+            (2, drone(drone_2_send)),
+            (
+                3,
+                NodeType::Server {
+                    sender: server_send,
+                },
+            ),
+            (4, drone(drone_4_send)),
+        ]
+        .into_iter()
+        .map(|(id, node_type)| {
+            let (packet_send, packet_recv) = unbounded();
+            packet_recvs.insert(id, packet_recv);
+            (
+                id,
+                Node {
+                    packet_send,
+                    node_type,
+                },
+            )
+        })
+        .collect();
+        let mut topology = Topology::new();
+        for (a, b) in [(1, 2), (2, 3), (2, 4), (3, 4)] {
+            topology.add_edge(a, b, ());
+        }
+        let controller = SimulationController::new(SimulationControllerOptions {
+            nodes,
+            drone_recv: unbounded().1,
+            drone_send: None,
+            server_recv: unbounded().1,
+            client_recv: unbounded().1,
+            topology,
+            drone_pool: thread_pool(),
+            client_pool: thread_pool(),
+            server_pool: thread_pool(),
+        });
+        controller.replay_trace(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        for (id, packets) in expected {
+            let received = packet_recvs[&id]
+                .try_iter()
+                .map(|packet| format!("{packet:?}"))
+                .collect::<Vec<_>>();
+            let packets = packets
+                .into_iter()
+                .map(|packet| format!("{packet:?}"))
+                .collect::<Vec<_>>();
+            assert_eq!(received, packets, "packets received by {id}");
+        }
+
+        assert!(matches!(
+            controller.replay_trace(std::env::temp_dir().join("dn_controller_missing_trace")),
+            Err(Error::InvalidTrace)
+        ));
+        drop(controller);
+    }
+
+    #[test]
+    fn test_unified_events() {
+        let (drone_event_send, drone_recv) = unbounded();
+        let (client_event_send, client_recv) = unbounded();
+        let (server_event_send, server_recv) = unbounded();
+        let controller = SimulationController::new(SimulationControllerOptions {
+            nodes: HashMap::new(),
+            drone_recv,
+            drone_send: None,
+            server_recv,
+            client_recv,
+            topology: Topology::new(),
+            drone_pool: thread_pool(),
+            client_pool: thread_pool(),
+            server_pool: thread_pool(),
+        });
+        let events = controller.unified_events();
+
+        let packet = Packet::new_ack(SourceRoutingHeader::initialize(vec![1, 2]), 0, 0);
+        client_event_send
+            .send(ClientEvent::PacketSent(packet))
+            .unwrap();
+        server_event_send
+            .send(ServerEvent::UnsupportedRequest {
+                category: "content",
+                from: 1,
+                to: 3,
+            })
+            .unwrap();
+
+        let timeout = Duration::from_secs(1);
+        let mut client_event = false;
+        let mut server_event = false;
+        for _ in 0..2 {
+            match events.recv_timeout(timeout).unwrap() {
+                SimEvent::Client(ClientEvent::PacketSent(_)) => client_event = true,
+                SimEvent::Server(ServerEvent::UnsupportedRequest { from: 1, .. }) => {
+                    server_event = true;
+                }
+                _ => panic!("unexpected event"),
+            }
+        }
+        assert!(client_event && server_event);
+
+        // the stream ends once every node is gone
+        drop((drone_event_send, client_event_send, server_event_send));
+        assert!(events.recv_timeout(timeout).is_err());
+        drop(controller);
+    }
+
+    #[test]
+    fn test_drop_stats() {
+        let (drone_event_send, drone_recv) = unbounded();
+        let controller = SimulationController::new(SimulationControllerOptions {
+            nodes: HashMap::new(),
+            drone_recv,
+            drone_send: None,
+            server_recv: unbounded().1,
+            client_recv: unbounded().1,
+            topology: Topology::new(),
+            drone_pool: thread_pool(),
+            client_pool: thread_pool(),
+            server_pool: thread_pool(),
+        });
+        let events = controller.get_drone_recv();
+
+        // 3 dropped the fragment after incrementing the hop index to point at 4
+        let dropped = Packet::new_fragment(
+            SourceRoutingHeader {
+                hop_index: 2,
+                hops: vec![1, 3, 4, 5],
+            },
+            0,
+            wg_2024::packet::Fragment::new(0, 1, [0; 128]),
+        );
+        for _ in 0..2 {
+            drone_event_send
+                .send(DroneEvent::PacketDropped(dropped.clone()))
+                .unwrap();
+        }
+
+        // the drops are counted even if the events are read without `unified_events`
+        let timeout = Duration::from_secs(1);
+        for _ in 0..2 {
+            events.recv_timeout(timeout).unwrap();
+        }
+        assert_eq!(controller.get_drop_stats(), HashMap::from([(3, 2)]));
+    }
+
+    #[test]
+    fn test_topology_diff_before_flood() {
+        let (client_send, client_recv) = unbounded();
+        let (drone_send, _drone_recv) = unbounded();
+        let (server_send, _server_recv) = unbounded();
+
+        let controller = controller(
+            vec![
+                (
+                    1,
+                    NodeType::Client {
+                        sender: client_send,
+                    },
+                ),
+                (2, drone(drone_send)),
+                (
+                    3,
+                    NodeType::Server {
+                        sender: server_send,
+                    },
+                ),
+            ],
+            &[(1, 2), (3, 2)],
+        );
+
+        // a client that hasn't flooded yet doesn't know any edge
+        let client = thread::spawn(move || {
+            if let Ok(ClientCommand::QueryTopology(sender)) = client_recv.recv() {
+                sender.send(vec![]).unwrap();
+            }
+            client_recv
+        });
+
+        let diff = controller.topology_diff(1).unwrap();
+        assert_eq!(diff.missing_edges, vec![(1, 2), (2, 3)]);
+        assert!(diff.extra_edges.is_empty());
+
+        assert!(matches!(
+            controller.topology_diff(2),
+            Err(Error::InvalidNode)
+        ));
+
+        // keep the client's receiver alive until the controller is dropped
+        let _client_recv = client.join().unwrap();
+        drop(controller);
+    }
+
+    #[test]
+    fn test_wait_for_convergence_unresponsive() {
+        let (client_send, _client_recv) = unbounded();
+        let (drone_send, _drone_recv) = unbounded();
+        let (server_send, _server_recv) = unbounded();
+
+        let controller = controller(
+            vec![
+                (
+                    1,
+                    NodeType::Client {
+                        sender: client_send,
+                    },
+                ),
+                (2, drone(drone_send)),
+                (
+                    3,
+                    NodeType::Server {
+                        sender: server_send,
+                    },
+                ),
+            ],
+            &[(1, 2), (3, 2)],
+        );
+
+        // the nodes never reply to the topology queries
+        let timeout = Duration::from_millis(100);
+        let start = Instant::now();
+        assert!(!controller.wait_for_convergence(timeout).unwrap());
+        assert!(start.elapsed() < 10 * timeout);
+        drop(controller);
+    }
+
+    #[test]
+    fn test_move_client() {
+        let (client_send, client_recv) = unbounded();
+        let (drone_2_send, drone_2_recv) = unbounded();
+        let (drone_3_send, drone_3_recv) = unbounded();
+        let (server_send, _server_recv) = unbounded();
+
+        let mut controller = controller(
+            vec![
+                (
+                    1,
+                    NodeType::Client {
+                        sender: client_send,
+                    },
+                ),
+                (2, drone(drone_2_send)),
+                (3, drone(drone_3_send)),
+                (
+                    4,
+                    NodeType::Server {
+                        sender: server_send,
+                    },
+                ),
+            ],
+            &[(1, 2), (2, 3), (2, 4), (3, 4)],
+        );
+
+        assert!(matches!(
+            controller.move_client(2, 3, 4),
+            Err(Error::InvalidNode)
+        ));
+        assert!(matches!(
+            controller.move_client(1, 3, 2),
+            Err(Error::Missing)
+        ));
+        assert!(matches!(
+            controller.move_client(1, 2, 2),
+            Err(Error::EdgeExists)
+        ));
+
+        controller.move_client(1, 2, 3).unwrap();
+        assert_eq!(
+            controller.get_topology().neighbors(1).collect::<Vec<_>>(),
+            vec![3]
+        );
+        assert!(controller.is_valid_topology());
+
+        // the new link is added before the old one is removed
+        let commands = client_recv.try_iter().collect::<Vec<_>>();
+        assert_eq!(commands.len(), 2);
+        assert!(matches!(commands[0], ClientCommand::AddSender(3, _)));
+        assert!(matches!(commands[1], ClientCommand::RemoveSender(2)));
+        assert!(matches!(
+            drone_3_recv.try_recv(),
+            Ok(DroneCommand::AddSender(1, _))
+        ));
+        assert!(matches!(
+            drone_2_recv.try_recv(),
+            Ok(DroneCommand::RemoveSender(1))
+        ));
+
+        drop(controller);
+    }
+
+    #[test]
+    fn test_degree() {
+        let (client_send, _client_recv) = unbounded();
+        let (drone_2_send, _drone_2_recv) = unbounded();
+        let (drone_3_send, _drone_3_recv) = unbounded();
+        let (server_send, _server_recv) = unbounded();
+
+        let mut controller = controller(
+            vec![
+                (
+                    1,
+                    NodeType::Client {
+                        sender: client_send,
+                    },
+                ),
+                (2, drone(drone_2_send)),
+                (3, drone(drone_3_send)),
+                (
+                    4,
+                    NodeType::Server {
+                        sender: server_send,
+                    },
+                ),
+            ],
+            &[(1, 2), (2, 3), (2, 4), (3, 4)],
+        );
+
+        assert_eq!(controller.neighbors(2), vec![1, 3, 4]);
+        assert_eq!(controller.degree(1), 1);
+        assert_eq!(controller.degree(3), 2);
+        assert_eq!(controller.degree(42), 0);
+        assert!(controller.neighbors(42).is_empty());
+
+        controller.add_edge(1, 3).unwrap();
+        assert_eq!(controller.neighbors(1), vec![2, 3]);
+        assert_eq!(controller.degree(1), 2);
+        assert_eq!(controller.degree(3), 3);
+
+        controller.remove_edge(1, 2).unwrap();
+        assert_eq!(controller.neighbors(1), vec![3]);
+        assert_eq!(controller.degree(1), 1);
+        assert_eq!(controller.degree(2), 2);
+
+        drop(controller);
+    }
+
+    #[test]
+    fn test_is_alive() {
+        let (client_send, _client_recv) = unbounded();
+        let (drone_2_send, _drone_2_recv) = unbounded();
+        let (drone_3_send, _drone_3_recv) = unbounded();
+        let (drone_5_send, _drone_5_recv) = unbounded();
+        let (server_send, _server_recv) = unbounded();
+
+        let mut controller = controller(
+            vec![
+                (
+                    1,
+                    NodeType::Client {
+                        sender: client_send,
+                    },
+                ),
+                (2, drone(drone_2_send)),
+                (3, drone(drone_3_send)),
+                (5, drone(drone_5_send)),
+                (
+                    4,
+                    NodeType::Server {
+                        sender: server_send,
+                    },
+                ),
+            ],
+            &[(1, 2), (1, 3), (2, 4), (3, 4), (2, 5), (5, 4)],
+        );
+        assert_eq!(controller.live_node_ids(), vec![1, 2, 3, 4, 5]);
+
+        controller.crash_drone(2).unwrap();
+        assert!(!controller.is_alive(2));
+        for id in [1, 4, 5] {
+            assert!(controller.is_alive(id));
+        }
+        assert!(!controller.is_alive(42));
+        assert_eq!(controller.live_node_ids(), vec![1, 3, 4, 5]);
+
+        drop(controller);
+    }
+
+    #[test]
+    fn test_gather_client_rosters() {
+        let (drone_send, _drone_recv) = unbounded();
+        let (server_2_send, server_2_recv) = unbounded();
+        let (server_3_send, server_3_recv) = unbounded();
+        let (server_4_send, server_4_recv) = unbounded();
+
+        let controller = controller(
+            vec![
+                (1, drone(drone_send)),
+                (
+                    2,
+                    NodeType::Server {
+                        sender: server_2_send,
+                    },
+                ),
+                (
+                    3,
+                    NodeType::Server {
+                        sender: server_3_send,
+                    },
+                ),
+                (
+                    4,
+                    NodeType::Server {
+                        sender: server_4_send,
+                    },
+                ),
+            ],
+            &[(1, 2), (1, 3), (1, 4)],
+        );
+
+        // 2 and 3 are communication servers, 4 is a content server
+        let server = |recv: Receiver<ServerCommand>, roster: Option<Vec<NodeId>>| {
+            thread::spawn(move || {
+                if let Ok(ServerCommand::QueryRoster(sender)) = recv.recv() {
+                    sender.send(roster).unwrap();
+                }
+                recv
+            })
+        };
+        let servers = [
+            server(server_2_recv, Some(vec![5, 6])),
+            server(server_3_recv, Some(vec![7])),
+            server(server_4_recv, None),
+        ];
+
+        let rosters = controller.gather_client_rosters().unwrap();
+        assert_eq!(rosters, HashMap::from([(2, vec![5, 6]), (3, vec![7])]));
+
+        // keep the servers' receivers alive until the controller is dropped
+        let _server_recvs = servers.map(|server| server.join().unwrap());
+        drop(controller);
+    }
+}
@@ -2,6 +2,8 @@
 
 mod command;
 mod controller;
+mod recorder;
 
 pub use command::*;
 pub use controller::*;
+pub use recorder::*;
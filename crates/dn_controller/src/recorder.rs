@@ -0,0 +1,256 @@
+use crate::{ClientEvent, ServerEvent, SimEvent};
+use bincode::{config, Decode, Encode};
+use std::collections::HashSet;
+use std::fs;
+use std::io;
+use std::path::Path;
+use wg_2024::network::{NodeId, SourceRoutingHeader};
+use wg_2024::packet::{
+    Ack, FloodRequest, FloodResponse, Fragment, Nack, NackType, NodeType, Packet, PacketType,
+    FRAGMENT_DSIZE,
+};
+
+/// records the packets sent by the clients and the servers from their `PacketSent` events,
+/// so that they can be saved with `save` and replayed with `SimulationController::replay_trace`.
+///
+/// The packets sent by the drones aren't recorded: the drones forward the replayed packets and
+/// create their NACKs and flood responses again, so replaying them would deliver them twice.
+/// A flood request has no routing header, so its receivers aren't known:
+/// it's recorded once and replayed to every neighbor of its initiator
+#[derive(Default)]
+pub struct PacketRecorder {
+    packets: Vec<RecordedPacket>,
+    // (flood_id, initiator_id) of the flood requests already recorded
+    flood_requests: HashSet<(u64, NodeId)>,
+}
+
+impl PacketRecorder {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// records the packet of a client or server `PacketSent` event, the other events are ignored
+    pub fn record(&mut self, event: &SimEvent) {
+        let (SimEvent::Client(ClientEvent::PacketSent(packet))
+        | SimEvent::Server(ServerEvent::PacketSent(packet))) = event
+        else {
+            return;
+        };
+        if let PacketType::FloodRequest(flood_request) = &packet.pack_type {
+            let key = (flood_request.flood_id, flood_request.initiator_id);
+            if !self.flood_requests.insert(key) {
+                return;
+            }
+        }
+        self.packets.push(RecordedPacket::from(packet));
+    }
+
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.packets.len()
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.packets.is_empty()
+    }
+
+    /// writes the recorded packets to `path`, in the order they were recorded
+    ///
+    /// # Errors
+    /// if the file can't be written
+    ///
+    /// # Panics
+    /// never, encoding the packets can't fail
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let bytes = bincode::encode_to_vec(&self.packets, config::standard()).unwrap();
+        fs::write(path, bytes)
+    }
+}
+
+/// reads the packets saved by `PacketRecorder::save`, `None` if the trace can't be read or decoded
+pub(crate) fn load_trace(path: &Path) -> Option<Vec<Packet>> {
+    let bytes = fs::read(path).ok()?;
+    let (packets, _): (Vec<RecordedPacket>, usize) =
+        bincode::decode_from_slice(&bytes, config::standard()).ok()?;
+    Some(packets.into_iter().map(Packet::from).collect())
+}
+
+// mirror of `Packet`, which can't be encoded
+#[derive(Encode, Decode)]
+struct RecordedPacket {
+    hop_index: usize,
+    hops: Vec<NodeId>,
+    session_id: u64,
+    pack_type: RecordedPacketType,
+}
+
+#[derive(Encode, Decode)]
+enum RecordedPacketType {
+    MsgFragment {
+        fragment_index: u64,
+        total_n_fragments: u64,
+        length: u8,
+        data: Vec<u8>,
+    },
+    Ack {
+        fragment_index: u64,
+    },
+    Nack {
+        fragment_index: u64,
+        nack_type: RecordedNackType,
+    },
+    FloodRequest {
+        flood_id: u64,
+        initiator_id: NodeId,
+        path_trace: Vec<(NodeId, RecordedNodeType)>,
+    },
+    FloodResponse {
+        flood_id: u64,
+        path_trace: Vec<(NodeId, RecordedNodeType)>,
+    },
+}
+
+#[derive(Encode, Decode)]
+enum RecordedNackType {
+    ErrorInRouting(NodeId),
+    DestinationIsDrone,
+    Dropped,
+    UnexpectedRecipient(NodeId),
+}
+
+#[derive(Encode, Decode)]
+enum RecordedNodeType {
+    Client,
+    Drone,
+    Server,
+}
+
+impl From<&Packet> for RecordedPacket {
+    fn from(packet: &Packet) -> Self {
+        let pack_type = match &packet.pack_type {
+            PacketType::MsgFragment(fragment) => RecordedPacketType::MsgFragment {
+                fragment_index: fragment.fragment_index,
+                total_n_fragments: fragment.total_n_fragments,
+                length: fragment.length,
+                data: fragment.data.to_vec(),
+            },
+            PacketType::Ack(ack) => RecordedPacketType::Ack {
+                fragment_index: ack.fragment_index,
+            },
+            PacketType::Nack(nack) => RecordedPacketType::Nack {
+                fragment_index: nack.fragment_index,
+                nack_type: match nack.nack_type {
+                    NackType::ErrorInRouting(id) => RecordedNackType::ErrorInRouting(id),
+                    NackType::DestinationIsDrone => RecordedNackType::DestinationIsDrone,
+                    NackType::Dropped => RecordedNackType::Dropped,
+                    NackType::UnexpectedRecipient(id) => RecordedNackType::UnexpectedRecipient(id),
+                },
+            },
+            PacketType::FloodRequest(flood_request) => RecordedPacketType::FloodRequest {
+                flood_id: flood_request.flood_id,
+                initiator_id: flood_request.initiator_id,
+                path_trace: record_path_trace(&flood_request.path_trace),
+            },
+            PacketType::FloodResponse(flood_response) => RecordedPacketType::FloodResponse {
+                flood_id: flood_response.flood_id,
+                path_trace: record_path_trace(&flood_response.path_trace),
+            },
+        };
+        Self {
+            hop_index: packet.routing_header.hop_index,
+            hops: packet.routing_header.hops.clone(),
+            session_id: packet.session_id,
+            pack_type,
+        }
+    }
+}
+
+impl From<RecordedPacket> for Packet {
+    fn from(packet: RecordedPacket) -> Self {
+        let pack_type = match packet.pack_type {
+            RecordedPacketType::MsgFragment {
+                fragment_index,
+                total_n_fragments,
+                length,
+                data,
+            } => {
+                let mut fragment_data = [0; FRAGMENT_DSIZE];
+                let len = data.len().min(FRAGMENT_DSIZE);
+                fragment_data[..len].copy_from_slice(&data[..len]);
+                PacketType::MsgFragment(Fragment {
+                    fragment_index,
+                    total_n_fragments,
+                    length,
+                    data: fragment_data,
+                })
+            }
+            RecordedPacketType::Ack { fragment_index } => PacketType::Ack(Ack { fragment_index }),
+            RecordedPacketType::Nack {
+                fragment_index,
+                nack_type,
+            } => PacketType::Nack(Nack {
+                fragment_index,
+                nack_type: match nack_type {
+                    RecordedNackType::ErrorInRouting(id) => NackType::ErrorInRouting(id),
+                    RecordedNackType::DestinationIsDrone => NackType::DestinationIsDrone,
+                    RecordedNackType::Dropped => NackType::Dropped,
+                    RecordedNackType::UnexpectedRecipient(id) => NackType::UnexpectedRecipient(id),
+                },
+            }),
+            RecordedPacketType::FloodRequest {
+                flood_id,
+                initiator_id,
+                path_trace,
+            } => PacketType::FloodRequest(FloodRequest {
+                flood_id,
+                initiator_id,
+                path_trace: replay_path_trace(path_trace),
+            }),
+            RecordedPacketType::FloodResponse {
+                flood_id,
+                path_trace,
+            } => PacketType::FloodResponse(FloodResponse {
+                flood_id,
+                path_trace: replay_path_trace(path_trace),
+            }),
+        };
+        Packet {
+            pack_type,
+            routing_header: SourceRoutingHeader {
+                hop_index: packet.hop_index,
+                hops: packet.hops,
+            },
+            session_id: packet.session_id,
+        }
+    }
+}
+
+fn record_path_trace(path_trace: &[(NodeId, NodeType)]) -> Vec<(NodeId, RecordedNodeType)> {
+    path_trace
+        .iter()
+        .map(|(id, node_type)| {
+            let node_type = match node_type {
+                NodeType::Client => RecordedNodeType::Client,
+                NodeType::Drone => RecordedNodeType::Drone,
+                NodeType::Server => RecordedNodeType::Server,
+            };
+            (*id, node_type)
+        })
+        .collect()
+}
+
+fn replay_path_trace(path_trace: Vec<(NodeId, RecordedNodeType)>) -> Vec<(NodeId, NodeType)> {
+    path_trace
+        .into_iter()
+        .map(|(id, node_type)| {
+            let node_type = match node_type {
+                RecordedNodeType::Client => NodeType::Client,
+                RecordedNodeType::Drone => NodeType::Drone,
+                RecordedNodeType::Server => NodeType::Server,
+            };
+            (id, node_type)
+        })
+        .collect()
+}
@@ -2,34 +2,145 @@
 
 use crate::Message;
 use bincode::config;
+use std::collections::hash_map::Entry;
 use std::collections::{HashMap, HashSet};
+use std::iter;
 use wg_2024::network::NodeId;
 use wg_2024::packet::Fragment;
 use wg_2024::packet::FRAGMENT_DSIZE as MAX_FRAGMENT_SIZE;
 
+/// First byte of compressed message data. It can't start a `bincode` encoded `Message`, since
+/// `0xFF` is not a valid varint prefix for the enum discriminant.
+const COMPRESSED_TAG: u8 = u8::MAX;
+
+/// First byte of message data carrying a priority, followed by the priority itself and by the
+/// rest of the data. It can't start a `bincode` encoded `Message` either, since its first byte is
+/// the index of a variant of `Message`.
+const PRIORITY_TAG: u8 = u8::MAX - 1;
+
+/// Number of bytes of the CRC-32 appended to the data of every message.
+const CHECKSUM_SIZE: usize = 4;
+
+/// Maximum number of fragments of a message the `Assembler` accepts to reassemble, so that a
+/// single malformed fragment can't make it allocate an unbounded buffer (32 MiB at most).
+pub const MAX_TOTAL_FRAGMENTS: u64 = 1 << 18;
+
+/// When the receiver of a fragment sends its ACK.
+///
+/// With `OnReceipt` the sender learns quickly that the fragment arrived, even if the message is then
+/// discarded or its handling fails. With `OnProcessed` the ACK of the last fragment of a message is
+/// sent only once the whole message has been handled, so it also confirms the handling.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum AckMode {
+    /// The fragment is acknowledged before it is reassembled.
+    #[default]
+    OnReceipt,
+    /// The fragment is acknowledged after it is reassembled, and after its message is handled if it was the last one.
+    OnProcessed,
+}
+
+/// Options applied by `Assembler::serialize_message_with` to the produced fragments.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SerializeOpts {
+    /// Run-length encodes the message data, if it makes it shorter.
+    pub compress: bool,
+    /// Priority of the message, read by the receiver with `Assembler::peek_priority`.
+    pub priority: Option<u8>,
+}
+
+/// Kind of a message, as peeked by `Assembler::peek_message_kind` before the message is complete.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MessageKind {
+    /// `ReqServerType` or `RespServerType`.
+    ServerType,
+    /// `ErrUnsupportedRequestType`.
+    UnsupportedRequest,
+    /// A `ClientContent` or `ServerContent` body.
+    Content,
+    /// A `ClientCommunication` or `ServerCommunication` body.
+    Communication,
+}
+
+impl MessageKind {
+    /// Maps the variant indices `bincode` encodes first, the one of `Message` and the one of its body.
+    fn from_variants(message: u8, body: u8) -> Option<Self> {
+        match (message, body) {
+            (0, 0) | (1, 0) => Some(MessageKind::ServerType),
+            (1, 1) => Some(MessageKind::UnsupportedRequest),
+            (0, 1) | (1, 2) => Some(MessageKind::Content),
+            (0, 2) | (1, 3) => Some(MessageKind::Communication),
+            _ => None,
+        }
+    }
+}
+
 /// The `Assembler` struct is responsible for tracking and reassembling fragmented messages.
 /// Each message is identified by a unique key consisting of a `(NodeId, session_id)` pair.
-#[derive(Default)]
+///
+/// Messages are split into chunks of `fragment_size` bytes. Since the data of a fragment is a
+/// fixed array, the size can only be reduced from the protocol's default of `FRAGMENT_DSIZE`.
+/// Fragments are reassembled using their `length`, so assemblers with different fragment sizes
+/// can talk to each other.
+///
+/// The data of every message ends with its CRC-32, so that a message corrupted in transit is
+/// discarded instead of being decoded.
 pub struct Assembler {
     in_progress_messages: HashMap<(NodeId, u64), MessageBuffer>,
+    fragment_size: usize,
+    corrupted_messages: Vec<(NodeId, u64)>,
+}
+
+impl Default for Assembler {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Assembler {
     /// Creates a new `Assembler` instance.
     ///
-    /// This function initializes the `Assembler` with an empty map to track in-progress messages.
+    /// This function initializes the `Assembler` with an empty map to track in-progress messages
+    /// and the default fragment size.
     ///
     /// # Returns
     /// A new `Assembler` instance.
     #[must_use]
     pub fn new() -> Self {
+        Self::with_fragment_size(MAX_FRAGMENT_SIZE)
+    }
+
+    /// Creates a new `Assembler` instance that splits messages into chunks of `fragment_size` bytes.
+    ///
+    /// # Arguments
+    /// - `fragment_size`: The number of bytes of data of each fragment, clamped to `1..=FRAGMENT_DSIZE`.
+    ///
+    /// # Returns
+    /// A new `Assembler` instance.
+    #[must_use]
+    pub fn with_fragment_size(fragment_size: usize) -> Self {
         Assembler {
             in_progress_messages: HashMap::new(),
+            fragment_size: fragment_size.clamp(1, MAX_FRAGMENT_SIZE),
+            corrupted_messages: Vec::new(),
         }
     }
 
+    /// Returns the number of bytes of data of each serialized fragment.
+    #[must_use]
+    pub fn fragment_size(&self) -> usize {
+        self.fragment_size
+    }
+
     /// Handles an incoming message fragment, adding it to the corresponding message buffer.
     /// If the message is complete, it returns the reassembled `Message`.
+    /// Fragments can arrive in any order, each one is stored at the position given by its index.
+    ///
+    /// Malformed fragments are discarded: a fragment is rejected if its `length` exceeds the
+    /// fragment size, if its index is out of range, if it declares more than `MAX_TOTAL_FRAGMENTS`
+    /// fragments, or if it declares a `total_n_fragments` different from the one of the other
+    /// fragments of the same session.
+    /// A complete message whose checksum doesn't match is discarded as well, and recorded
+    /// as corrupted (see `take_corrupted_messages`).
     ///
     /// # Arguments
     /// - `fragment`: A reference to the incoming fragment.
@@ -38,32 +149,137 @@ impl Assembler {
     ///
     /// # Returns
     /// - `Some(Message)`: If the message has been fully reassembled, it returns the `Message`.
-    /// - `None`: If the message is incomplete, it returns `None`.
+    /// - `None`: If the message is incomplete, corrupted or the fragment is malformed, it returns `None`.
     pub fn handle_fragment(
         &mut self,
         fragment: &Fragment,
         sender_id: NodeId,
         session_id: u64,
     ) -> Option<Message> {
-        let buffer = self
-            .in_progress_messages
-            .entry((sender_id, session_id))
-            .or_insert_with(|| MessageBuffer::new(fragment.total_n_fragments as usize));
+        if !Assembler::is_valid_fragment(fragment) {
+            return None;
+        }
+
+        let buffer = match self.in_progress_messages.entry((sender_id, session_id)) {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => {
+                entry.insert(MessageBuffer::try_new(fragment.total_n_fragments as usize)?)
+            }
+        };
+
+        if buffer.total_fragments() != fragment.total_n_fragments {
+            return None;
+        }
 
         buffer.add_fragment(fragment);
 
         if buffer.is_complete() {
             let message = buffer.to_message();
             self.in_progress_messages.remove(&(sender_id, session_id));
-            Some(message)
+            if message.is_none() {
+                self.corrupted_messages.push((sender_id, session_id));
+            }
+            message
         } else {
             None
         }
     }
 
+    /// Discards a message that is being reassembled, together with the fragments received so far.
+    ///
+    /// # Arguments
+    /// - `sender_id`: The `NodeId` of the sender.
+    /// - `session_id`: The session ID associated with the message.
+    ///
+    /// # Returns
+    /// - `true`: If a message from `sender_id` with `session_id` was being reassembled.
+    /// - `false`: Otherwise.
+    pub fn discard(&mut self, sender_id: NodeId, session_id: u64) -> bool {
+        self.in_progress_messages
+            .remove(&(sender_id, session_id))
+            .is_some()
+    }
+
+    /// Takes the messages discarded because their checksum didn't match, in the order they were completed.
+    ///
+    /// # Returns
+    /// A vector of the `(sender_id, session_id)` of the corrupted messages.
+    pub fn take_corrupted_messages(&mut self) -> Vec<(NodeId, u64)> {
+        std::mem::take(&mut self.corrupted_messages)
+    }
+
+    /// Checks that the length, the index and the total number of fragments of a fragment are in range.
+    ///
+    /// # Arguments
+    /// - `fragment`: A reference to the fragment to check.
+    ///
+    /// # Returns
+    /// - `true`: If the fragment can be safely added to a message buffer.
+    /// - `false`: Otherwise.
+    fn is_valid_fragment(fragment: &Fragment) -> bool {
+        fragment.length as usize <= MAX_FRAGMENT_SIZE
+            && fragment.fragment_index < fragment.total_n_fragments
+            && fragment.total_n_fragments <= MAX_TOTAL_FRAGMENTS
+    }
+
+    /// Returns the reassembly progress of a message that is still incomplete.
+    ///
+    /// # Arguments
+    /// - `sender_id`: The `NodeId` of the sender.
+    /// - `session_id`: The session ID associated with the message.
+    ///
+    /// # Returns
+    /// - `Some((received, total))`: The number of fragments received so far and the total number of fragments.
+    /// - `None`: If no message from `sender_id` with `session_id` is being reassembled.
+    #[must_use]
+    pub fn progress(&self, sender_id: NodeId, session_id: u64) -> Option<(u64, u64)> {
+        self.in_progress_messages
+            .get(&(sender_id, session_id))
+            .map(|buffer| (buffer.received_fragments(), buffer.total_fragments()))
+    }
+
+    /// Returns the kind of a message that is still being reassembled.
+    ///
+    /// The kind is read from the beginning of the first fragment, so it's known as soon as that
+    /// fragment is received, whatever the order of the others. Since the checksum can't be verified
+    /// yet, the kind of a corrupted message may be wrong.
+    ///
+    /// # Arguments
+    /// - `sender_id`: The `NodeId` of the sender.
+    /// - `session_id`: The session ID associated with the message.
+    ///
+    /// # Returns
+    /// - `Some(MessageKind)`: The kind of the message.
+    /// - `None`: If the message is not being reassembled, its first fragment hasn't been received yet or it can't be decoded.
+    #[must_use]
+    pub fn peek_message_kind(&self, sender_id: NodeId, session_id: u64) -> Option<MessageKind> {
+        self.in_progress_messages
+            .get(&(sender_id, session_id))
+            .and_then(MessageBuffer::kind)
+    }
+
+    /// Returns the priority of a message that is still being reassembled.
+    ///
+    /// The priority is read from the beginning of the first fragment, like the kind of the message.
+    ///
+    /// # Arguments
+    /// - `sender_id`: The `NodeId` of the sender.
+    /// - `session_id`: The session ID associated with the message.
+    ///
+    /// # Returns
+    /// - `Some(u8)`: The priority given to the message with `SerializeOpts`.
+    /// - `None`: If the message is not being reassembled, its first fragment hasn't been received yet or it has no priority.
+    #[must_use]
+    pub fn peek_priority(&self, sender_id: NodeId, session_id: u64) -> Option<u8> {
+        self.in_progress_messages
+            .get(&(sender_id, session_id))
+            .and_then(MessageBuffer::priority)
+    }
+
     /// Serializes a message into a vector of fragments.
     ///
-    /// This function splits the message into fragments, each of which contains part of the message data.
+    /// This function splits the message into fragments, each of which contains at most `fragment_size`
+    /// bytes of the message data, followed by its checksum.
     ///
     /// # Arguments
     /// - `message`: A reference to the `Message` to be serialized.
@@ -72,12 +288,40 @@ impl Assembler {
     /// A vector of fragments (`Vec<Fragment>`), each representing a part of the original message.
     #[must_use]
     pub fn serialize_message(&self, message: &Message) -> Vec<Fragment> {
-        let message_data = Assembler::serialize_message_data(message);
-        let total_fragments = message_data.len().div_ceil(MAX_FRAGMENT_SIZE) as u64;
+        self.serialize_message_with(message, SerializeOpts::default())
+    }
+
+    /// Serializes a message into a vector of fragments, applying the given options.
+    ///
+    /// Compressed messages are recognized and decompressed by any `Assembler` while reassembling,
+    /// so the options don't have to be known by the receiver. The priority is put in front of the
+    /// (compressed) data, so it takes two bytes of the first fragment.
+    ///
+    /// # Arguments
+    /// - `message`: A reference to the `Message` to be serialized.
+    /// - `opts`: The options to apply to the message data.
+    ///
+    /// # Returns
+    /// A vector of fragments (`Vec<Fragment>`), each representing a part of the original message.
+    #[must_use]
+    pub fn serialize_message_with(&self, message: &Message, opts: SerializeOpts) -> Vec<Fragment> {
+        let mut message_data = Assembler::serialize_message_data(message);
+        if opts.compress {
+            let compressed = compress(&message_data);
+            if compressed.len() < message_data.len() {
+                message_data = compressed;
+            }
+        }
+        if let Some(priority) = opts.priority {
+            message_data.splice(0..0, [PRIORITY_TAG, priority]);
+        }
+        let checksum = crc32(&message_data);
+        message_data.extend(checksum.to_le_bytes());
+        let total_fragments = message_data.len().div_ceil(self.fragment_size) as u64;
 
         let mut fragments = Vec::new();
 
-        for (i, chunk) in message_data.chunks(MAX_FRAGMENT_SIZE).enumerate() {
+        for (i, chunk) in message_data.chunks(self.fragment_size).enumerate() {
             let mut data = [0u8; MAX_FRAGMENT_SIZE];
             data[..chunk.len()].copy_from_slice(chunk);
             let fragment = Fragment {
@@ -107,11 +351,56 @@ impl Assembler {
     }
 }
 
+/// Splits the priority, if any, from the rest of the message data.
+fn split_priority(data: &[u8]) -> (Option<u8>, &[u8]) {
+    match data {
+        [PRIORITY_TAG, priority, rest @ ..] => (Some(*priority), rest),
+        _ => (None, data),
+    }
+}
+
+/// Run-length encodes the data as `(run length, byte)` pairs, prefixed by `COMPRESSED_TAG`.
+fn compress(data: &[u8]) -> Vec<u8> {
+    let mut compressed = vec![COMPRESSED_TAG];
+    let mut iter = data.iter().peekable();
+    while let Some(&byte) = iter.next() {
+        let mut run = 1u8;
+        while run < u8::MAX && iter.next_if_eq(&&byte).is_some() {
+            run += 1;
+        }
+        compressed.extend([run, byte]);
+    }
+    compressed
+}
+
+/// Computes the CRC-32 (IEEE) of the data.
+fn crc32(data: &[u8]) -> u32 {
+    const POLYNOMIAL: u32 = 0xEDB8_8320;
+
+    let mut crc = u32::MAX;
+    for &byte in data {
+        crc ^= u32::from(byte);
+        for _ in 0..8 {
+            let mask = (crc & 1).wrapping_neg();
+            crc = (crc >> 1) ^ (POLYNOMIAL & mask);
+        }
+    }
+    !crc
+}
+
+/// Decodes the `(run length, byte)` pairs produced by `compress`, without the tag.
+fn decompress(data: &[u8]) -> Vec<u8> {
+    data.chunks_exact(2)
+        .flat_map(|pair| iter::repeat(pair[1]).take(pair[0] as usize))
+        .collect()
+}
+
 /// `MessageBuffer` stores a fragmented message as it is reassembled.
 /// It holds the fragments, tracks the total number of fragments, and maintains a record of the
 /// received fragment indices, ensuring proper reassembly while ignoring duplicates.
 pub struct MessageBuffer {
     fragments: Vec<u8>,
+    lengths: Vec<usize>,
     total_fragments: u64,
     received_indices: HashSet<u64>,
 }
@@ -126,13 +415,31 @@ impl MessageBuffer {
     ///
     /// # Returns
     /// A new `MessageBuffer` instance.
+    ///
+    /// # Panics
+    /// If the size of the buffer overflows a `usize`.
     #[must_use]
     pub fn new(total_n_fragments: usize) -> Self {
-        MessageBuffer {
-            fragments: vec![0; MAX_FRAGMENT_SIZE * total_n_fragments],
+        Self::try_new(total_n_fragments).expect("message buffer size overflow")
+    }
+
+    /// Creates a new `MessageBuffer` with the specified total number of fragments, if the size of
+    /// its buffer doesn't overflow a `usize`.
+    ///
+    /// # Arguments
+    /// - `total_n_fragments`: The total number of fragments the message will have.
+    ///
+    /// # Returns
+    /// - `Some(MessageBuffer)`: A new `MessageBuffer` instance.
+    /// - `None`: If the size of the buffer overflows.
+    #[must_use]
+    pub fn try_new(total_n_fragments: usize) -> Option<Self> {
+        Some(MessageBuffer {
+            fragments: vec![0; MAX_FRAGMENT_SIZE.checked_mul(total_n_fragments)?],
+            lengths: vec![0; total_n_fragments],
             total_fragments: total_n_fragments as u64,
             received_indices: HashSet::new(),
-        }
+        })
     }
 
     /// Adds a fragment to the `MessageBuffer`.
@@ -150,8 +457,7 @@ impl MessageBuffer {
             return; //Ignoring duplicates: assuming the first packet had the correct data
         }
 
-        self.total_fragments = fragment.total_n_fragments;
-
+        self.lengths[fragment.fragment_index as usize] = fragment.length as usize;
         self.fragments[start_index..end_index]
             .copy_from_slice(&fragment.data[..fragment.length as usize]);
     }
@@ -166,20 +472,393 @@ impl MessageBuffer {
         self.received_indices.len() == self.total_fragments as usize
     }
 
-    /// Converts the current vector of u8 into a `Message`.
+    /// Returns the number of distinct fragments received so far.
+    #[must_use]
+    pub fn received_fragments(&self) -> u64 {
+        self.received_indices.len() as u64
+    }
+
+    /// Returns the total number of fragments of the message.
+    #[must_use]
+    pub fn total_fragments(&self) -> u64 {
+        self.total_fragments
+    }
+
+    /// Decodes the kind of the message from its first fragment, decompressing it if needed.
     ///
-    /// This function decodes the stored `fragments` using `bincode` with
-    /// a standard configuration. If decoding fails, it will panic.
+    /// # Returns
+    /// - `Some(MessageKind)`: The kind of the message.
+    /// - `None`: If the first fragment hasn't been received yet or it doesn't start with a known kind.
+    #[must_use]
+    pub fn kind(&self) -> Option<MessageKind> {
+        if !self.received_indices.contains(&0) {
+            return None;
+        }
+        let (_, data) = split_priority(&self.fragments[..self.lengths[0]]);
+        let header = match data.split_first() {
+            Some((&COMPRESSED_TAG, compressed)) => decompress(compressed),
+            _ => data.to_vec(),
+        };
+        match header[..] {
+            [message, body, ..] => MessageKind::from_variants(message, body),
+            _ => None,
+        }
+    }
+
+    /// Decodes the priority of the message from its first fragment.
     ///
     /// # Returns
-    /// A `Message` object reconstructed from the serialized data.
+    /// - `Some(u8)`: The priority of the message.
+    /// - `None`: If the first fragment hasn't been received yet or the message has no priority.
+    #[must_use]
+    pub fn priority(&self) -> Option<u8> {
+        if !self.received_indices.contains(&0) {
+            return None;
+        }
+        split_priority(&self.fragments[..self.lengths[0]]).0
+    }
+
+    /// Converts the current vector of u8 into a `Message`.
     ///
-    /// # Panics
-    /// This function panics if the decoding process fails.
+    /// This function joins the data of the fragments, verifies its checksum, skips its priority,
+    /// decompresses it if needed, and decodes it using `bincode` with a standard configuration.
+    ///
+    /// # Returns
+    /// - `Some(Message)`: The `Message` reconstructed from the serialized data.
+    /// - `None`: If the checksum doesn't match or the decoding fails.
     #[must_use]
-    pub fn to_message(&self) -> Message {
-        bincode::decode_from_slice(&self.fragments, config::standard())
-            .unwrap()
-            .0
+    pub fn to_message(&self) -> Option<Message> {
+        let data = self
+            .fragments
+            .chunks(MAX_FRAGMENT_SIZE)
+            .zip(&self.lengths)
+            .flat_map(|(chunk, &length)| &chunk[..length])
+            .copied()
+            .collect::<Vec<_>>();
+        if data.len() < CHECKSUM_SIZE {
+            return None;
+        }
+        let (data, checksum) = data.split_at(data.len() - CHECKSUM_SIZE);
+        if crc32(data).to_le_bytes() != checksum {
+            return None;
+        }
+        let (_, data) = split_priority(data);
+        let data = match data.split_first() {
+            Some((&COMPRESSED_TAG, compressed)) => decompress(compressed),
+            _ => data.to_vec(),
+        };
+        bincode::decode_from_slice(&data, config::standard())
+            .ok()
+            .map(|(message, _)| message)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ClientBody, ClientContentBody};
+
+    fn fragment(fragment_index: u64, total_n_fragments: u64, length: u8) -> Fragment {
+        Fragment {
+            fragment_index,
+            total_n_fragments,
+            length,
+            data: [0; MAX_FRAGMENT_SIZE],
+        }
+    }
+
+    #[test]
+    fn test_reassemble() {
+        let mut assembler = Assembler::new();
+        let fragments = assembler.serialize_message(&Message::Client(ClientBody::ReqServerType));
+        assert_eq!(fragments.len(), 1);
+
+        let message = assembler.handle_fragment(&fragments[0], 1, 0);
+        assert!(matches!(
+            message,
+            Some(Message::Client(ClientBody::ReqServerType))
+        ));
+    }
+
+    #[test]
+    fn test_reassemble_out_of_order() {
+        let message = Message::Client(ClientBody::ClientContent(ClientContentBody::ReqFile(
+            "a".repeat(450),
+        )));
+        let assembler = Assembler::new();
+        let fragments = assembler.serialize_message(&message);
+        assert_eq!(fragments.len(), 4);
+        // the last fragment is the only shorter one, it must not shift the others
+        assert!((fragments[3].length as usize) < MAX_FRAGMENT_SIZE);
+
+        let mut receiver = Assembler::new();
+        for fragment in fragments[1..].iter().rev() {
+            assert!(receiver.handle_fragment(fragment, 1, 0).is_none());
+        }
+        assert_eq!(receiver.progress(1, 0), Some((3, 4)));
+        assert!(matches!(
+            receiver.handle_fragment(&fragments[0], 1, 0),
+            Some(Message::Client(ClientBody::ClientContent(ClientContentBody::ReqFile(path)))) if path == "a".repeat(450)
+        ));
+    }
+
+    #[test]
+    fn test_fragment_size() {
+        let message = Message::Client(ClientBody::ClientContent(ClientContentBody::ReqFile(
+            "a".repeat(300),
+        )));
+
+        let assembler = Assembler::new();
+        let fragments = assembler.serialize_message(&message);
+        let message_len = fragments.iter().map(|f| f.length as usize).sum::<usize>();
+        assert_eq!(fragments.len(), message_len.div_ceil(MAX_FRAGMENT_SIZE));
+
+        let small_assembler = Assembler::with_fragment_size(32);
+        let small_fragments = small_assembler.serialize_message(&message);
+        assert_eq!(small_fragments.len(), message_len.div_ceil(32));
+        assert!(small_fragments.iter().all(|f| f.length <= 32));
+
+        // fragments of any size are reassembled by the default assembler
+        let mut receiver = Assembler::new();
+        let mut reassembled = None;
+        for fragment in &small_fragments {
+            reassembled = receiver.handle_fragment(fragment, 1, 0);
+        }
+        assert!(matches!(
+            reassembled,
+            Some(Message::Client(ClientBody::ClientContent(ClientContentBody::ReqFile(path)))) if path.len() == 300
+        ));
+
+        assert_eq!(Assembler::with_fragment_size(0).fragment_size(), 1);
+        assert_eq!(
+            Assembler::with_fragment_size(1024).fragment_size(),
+            MAX_FRAGMENT_SIZE
+        );
+    }
+
+    #[test]
+    fn test_serialize_compressed() {
+        let message = Message::Client(ClientBody::ClientContent(ClientContentBody::ReqFile(
+            "a".repeat(1000),
+        )));
+        let assembler = Assembler::new();
+
+        let fragments = assembler.serialize_message(&message);
+        let compressed = assembler.serialize_message_with(
+            &message,
+            SerializeOpts {
+                compress: true,
+                ..SerializeOpts::default()
+            },
+        );
+        assert!(compressed.len() < fragments.len());
+
+        let mut receiver = Assembler::new();
+        let mut reassembled = None;
+        for fragment in &compressed {
+            reassembled = receiver.handle_fragment(fragment, 1, 0);
+        }
+        assert!(matches!(
+            reassembled,
+            Some(Message::Client(ClientBody::ClientContent(ClientContentBody::ReqFile(path)))) if path == "a".repeat(1000)
+        ));
+
+        // data that doesn't shrink is sent uncompressed
+        let message = Message::Client(ClientBody::ReqServerType);
+        let fragments = assembler.serialize_message_with(
+            &message,
+            SerializeOpts {
+                compress: true,
+                ..SerializeOpts::default()
+            },
+        );
+        assert_ne!(fragments[0].data[0], COMPRESSED_TAG);
+    }
+
+    #[test]
+    fn test_serialize_priority() {
+        use crate::{ServerBody, ServerContentBody};
+
+        let file = (0..1000).map(|i| (i / 4) as u8).collect::<Vec<_>>();
+        let message = Message::Server(ServerBody::ServerContent(ServerContentBody::RespFile(
+            file.clone(),
+            "file.txt".to_string(),
+        )));
+        let assembler = Assembler::new();
+        for compress in [false, true] {
+            let fragments = assembler.serialize_message_with(
+                &message,
+                SerializeOpts {
+                    compress,
+                    priority: Some(3),
+                },
+            );
+            assert!(fragments.len() > 1);
+            assert_eq!(fragments[0].data[..2], [PRIORITY_TAG, 3]);
+
+            let mut receiver = Assembler::new();
+            let (last, fragments) = fragments.split_last().unwrap();
+            for fragment in fragments {
+                assert!(receiver.handle_fragment(fragment, 1, 0).is_none());
+            }
+            assert_eq!(receiver.peek_priority(1, 0), Some(3));
+            assert_eq!(receiver.peek_message_kind(1, 0), Some(MessageKind::Content));
+            assert!(matches!(
+                receiver.handle_fragment(last, 1, 0),
+                Some(Message::Server(ServerBody::ServerContent(ServerContentBody::RespFile(data, _)))) if data == file
+            ));
+        }
+
+        // messages serialized without a priority don't have one
+        let fragments = assembler.serialize_message(&message);
+        let mut receiver = Assembler::new();
+        receiver.handle_fragment(&fragments[0], 1, 0);
+        assert_eq!(receiver.peek_priority(1, 0), None);
+    }
+
+    #[test]
+    fn test_corrupted_message() {
+        let message = Message::Client(ClientBody::ClientContent(ClientContentBody::ReqFile(
+            "a".repeat(300),
+        )));
+        let assembler = Assembler::new();
+        let mut fragments = assembler.serialize_message(&message);
+        assert!(fragments.len() > 1);
+        fragments[1].data[0] ^= 1;
+
+        let mut receiver = Assembler::new();
+        let mut reassembled = None;
+        for fragment in &fragments {
+            reassembled = receiver.handle_fragment(fragment, 1, 0);
+        }
+        assert!(reassembled.is_none());
+        assert_eq!(receiver.take_corrupted_messages(), vec![(1, 0)]);
+        assert!(receiver.take_corrupted_messages().is_empty());
+
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+    }
+
+    #[test]
+    fn test_peek_message_kind() {
+        use crate::{ClientCommunicationBody, ServerBody, ServerContentBody, ServerType};
+
+        let file = Message::Server(ServerBody::ServerContent(ServerContentBody::RespFile(
+            (0..1000).map(|i| (i / 4) as u8).collect(),
+            "file.txt".to_string(),
+        )));
+        let assembler = Assembler::new();
+        for compress in [false, true] {
+            let fragments = assembler.serialize_message_with(
+                &file,
+                SerializeOpts {
+                    compress,
+                    ..SerializeOpts::default()
+                },
+            );
+            assert!(fragments.len() > 1);
+            assert_eq!(fragments[0].data[0] == COMPRESSED_TAG, compress);
+
+            let mut receiver = Assembler::new();
+            receiver.handle_fragment(&fragments[1], 1, 0);
+            assert_eq!(receiver.peek_message_kind(1, 0), None);
+            receiver.handle_fragment(&fragments[0], 1, 0);
+            assert_eq!(receiver.peek_message_kind(1, 0), Some(MessageKind::Content));
+        }
+
+        let kinds = [
+            (
+                Message::Client(ClientBody::ReqServerType),
+                MessageKind::ServerType,
+            ),
+            (
+                Message::Client(ClientBody::ClientContent(ClientContentBody::ReqFilesList)),
+                MessageKind::Content,
+            ),
+            (
+                Message::Client(ClientBody::ClientCommunication(
+                    ClientCommunicationBody::ReqClientList,
+                )),
+                MessageKind::Communication,
+            ),
+            (
+                Message::Server(ServerBody::RespServerType(ServerType::Content)),
+                MessageKind::ServerType,
+            ),
+            (
+                Message::Server(ServerBody::ErrUnsupportedRequestType),
+                MessageKind::UnsupportedRequest,
+            ),
+            (
+                Message::Server(ServerBody::ServerCommunication(
+                    crate::ServerCommunicationBody::RegistrationSuccess,
+                )),
+                MessageKind::Communication,
+            ),
+        ];
+        for (message, kind) in kinds {
+            let fragments = assembler.serialize_message(&message);
+            let mut buffer = MessageBuffer::new(fragments.len());
+            buffer.add_fragment(&fragments[0]);
+            assert_eq!(buffer.kind(), Some(kind));
+        }
+    }
+
+    #[test]
+    fn test_over_length_fragment() {
+        let mut assembler = Assembler::new();
+        let over_length = fragment(0, 2, MAX_FRAGMENT_SIZE as u8 + 1);
+
+        assert!(assembler.handle_fragment(&over_length, 1, 0).is_none());
+        assert_eq!(assembler.progress(1, 0), None);
+    }
+
+    #[test]
+    fn test_out_of_range_index() {
+        let mut assembler = Assembler::new();
+
+        assert!(assembler
+            .handle_fragment(&fragment(2, 2, 1), 1, 0)
+            .is_none());
+        assert!(assembler
+            .handle_fragment(&fragment(0, 0, 1), 1, 0)
+            .is_none());
+        assert_eq!(assembler.progress(1, 0), None);
+    }
+
+    #[test]
+    fn test_too_many_fragments() {
+        let mut assembler = Assembler::new();
+
+        assert!(assembler
+            .handle_fragment(&fragment(0, u64::MAX, 1), 1, 0)
+            .is_none());
+        assert!(assembler
+            .handle_fragment(&fragment(0, MAX_TOTAL_FRAGMENTS + 1, 1), 1, 0)
+            .is_none());
+        assert_eq!(assembler.progress(1, 0), None);
+
+        assert!(MessageBuffer::try_new(usize::MAX).is_none());
+    }
+
+    #[test]
+    fn test_mismatched_totals() {
+        let mut assembler = Assembler::new();
+
+        assert!(assembler
+            .handle_fragment(&fragment(0, 3, 128), 1, 0)
+            .is_none());
+        assert_eq!(assembler.progress(1, 0), Some((1, 3)));
+
+        // a fragment of the same session claiming a different total is discarded
+        assert!(assembler
+            .handle_fragment(&fragment(1, 2, 10), 1, 0)
+            .is_none());
+        assert_eq!(assembler.progress(1, 0), Some((1, 3)));
+
+        // the same total in another session is fine
+        assert!(assembler
+            .handle_fragment(&fragment(1, 2, 10), 1, 1)
+            .is_none());
+        assert_eq!(assembler.progress(1, 1), Some((1, 2)));
     }
 }
@@ -8,15 +8,36 @@ pub enum ClientBody {
     ClientCommunication(ClientCommunicationBody),
 }
 
+impl ClientBody {
+    /// Name of the category of the request, useful for diagnostics
+    #[must_use]
+    pub fn category(&self) -> &'static str {
+        match self {
+            ClientBody::ReqServerType => "server type",
+            ClientBody::ClientContent(_) => "content",
+            ClientBody::ClientCommunication(_) => "communication",
+        }
+    }
+}
+
 #[derive(Debug, Clone, Encode, Decode)]
 pub enum ClientContentBody {
     ReqFilesList,
     ReqFile(String),
+    ReqFiles(Vec<String>),
+    /// the server replies with its files grouped by directory
+    ReqFileTree,
+    /// the server replies with the number of its files and their total size, without listing them
+    ReqStats,
 }
 
 #[derive(Debug, Clone, Encode, Decode)]
 pub enum ClientCommunicationBody {
     ReqRegistrationToChat,
+    /// the server drops the registration of the client, no reply is sent
+    ReqUnregistrationFromChat,
     MessageSend(CommunicationMessage),
     ReqClientList,
+    /// echoed back by the server with the same nonce, no registration needed
+    Ping(u64),
 }
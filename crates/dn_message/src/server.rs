@@ -20,7 +20,33 @@ pub enum ServerType {
 pub enum ServerContentBody {
     RespFilesList(Vec<String>),
     RespFile(Vec<u8>, String),
-    ErrFileNotFound,
+    /// file, path and MIME type detected by the server, `None` if it's unknown
+    RespTypedFile(Vec<u8>, String, Option<String>),
+    RespFiles(Vec<(String, Result<Vec<u8>, FileError>)>),
+    RespFileTree(FileTree),
+    /// number of files of the server and their total size in bytes
+    RespStats {
+        file_count: u64,
+        total_bytes: u64,
+    },
+    /// path of the file requested that the server doesn't have
+    ErrFileNotFound(String),
+    /// path of the file requested, larger than the maximum size the server serves
+    ErrFileTooLarge(String),
+}
+
+/// directory of the server, with the names of its files and its subdirectories, both sorted by name
+#[derive(Debug, Clone, Default, PartialEq, Eq, Encode, Decode)]
+pub struct FileTree {
+    pub name: String,
+    pub files: Vec<String>,
+    pub dirs: Vec<FileTree>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode)]
+pub enum FileError {
+    NotFound,
+    TooLarge,
 }
 
 #[derive(Debug, Clone, Encode, Decode)]
@@ -36,4 +62,8 @@ pub enum ServerCommunicationBody {
     ErrWrongClientId,
     ErrNotRegistered,
     RegistrationSuccess,
+    AlreadyRegistered,
+    Pong(u64),
+    /// every fragment of the client's message with this session id has been received
+    MessageAck(u64),
 }
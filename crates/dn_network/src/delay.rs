@@ -0,0 +1,79 @@
+use crossbeam_channel::{at, never, select, unbounded, Receiver};
+use dn_controller::DelaySpec;
+use rand::{rng, Rng};
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
+use std::thread;
+use std::time::{Duration, Instant};
+use wg_2024::packet::Packet;
+
+/// Puts a forwarding delay in front of a drone.
+///
+/// The returned receiver must be given to the drone in place of `packet_recv`.
+/// Every packet is relayed to it once its own delay, sampled at arrival from the
+/// last `DelaySpec` received on `delay_recv` (no delay until the first one), has
+/// elapsed. Delays overlap, so a slow packet doesn't hold back the following ones,
+/// and with a delay range packets may be relayed out of arrival order.
+/// The relay stops as soon as the drone drops its receiver, or once every sender
+/// of `packet_recv` is gone and the scheduled packets have been relayed.
+pub fn delay_shim(
+    packet_recv: Receiver<Packet>,
+    delay_recv: Receiver<DelaySpec>,
+) -> Receiver<Packet> {
+    let (send, recv) = unbounded();
+    thread::spawn(move || {
+        let mut packet_recv = packet_recv;
+        let mut delay = DelaySpec::default();
+        // packets with the same due instant are relayed in arrival order
+        let mut scheduled = BinaryHeap::new();
+        let mut packets = HashMap::new();
+        let mut seq: u64 = 0;
+        let mut disconnected = false;
+        loop {
+            let now = Instant::now();
+            while let Some(&Reverse((due, id))) = scheduled.peek() {
+                if due > now {
+                    break;
+                }
+                scheduled.pop();
+                let packet = packets.remove(&id).unwrap();
+                if send.send(packet).is_err() {
+                    return;
+                }
+            }
+            if disconnected && scheduled.is_empty() {
+                return;
+            }
+
+            let timer = scheduled
+                .peek()
+                .map_or_else(never, |&Reverse((due, _))| at(due));
+            select! {
+                recv(packet_recv) -> packet => match packet {
+                    Ok(packet) => {
+                        if let Some(new_delay) = delay_recv.try_iter().last() {
+                            delay = new_delay;
+                        }
+                        scheduled.push(Reverse((Instant::now() + sample(delay), seq)));
+                        packets.insert(seq, packet);
+                        seq += 1;
+                    }
+                    Err(_) => {
+                        packet_recv = never();
+                        disconnected = true;
+                    }
+                },
+                recv(timer) -> _ => {}
+            }
+        }
+    });
+    recv
+}
+
+fn sample(delay: DelaySpec) -> Duration {
+    if delay.max <= delay.min {
+        delay.min
+    } else {
+        rng().random_range(delay.min..=delay.max)
+    }
+}
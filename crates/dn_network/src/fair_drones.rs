@@ -49,6 +49,13 @@ impl FairDrones {
     pub fn get(&self, i: usize) -> &dyn FairDrone {
         &*self.0[i % self.0.len()]
     }
+
+    pub fn find(&self, group_name: &str) -> Option<&dyn FairDrone> {
+        self.0
+            .iter()
+            .find(|drone| drone.group_name() == group_name)
+            .map(|drone| &**drone)
+    }
 }
 
 macro_rules! fair_drones {
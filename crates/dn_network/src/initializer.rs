@@ -1,17 +1,17 @@
-use crate::fair_drones::{adapter, fair_drones, DroneOptions, FairDrones};
+use crate::delay::delay_shim;
+use crate::fair_drones::{adapter, fair_drones, DroneOptions, FairDrone, FairDrones};
 use crossbeam_channel::{unbounded, Receiver, Sender};
 use dn_client::Client;
 use dn_controller::{
-    ClientEvent, Node, NodeType as ControllerNodeType, ServerEvent, SimulationController,
-    SimulationControllerOptions, Topology,
+    ClientEvent, DelaySpec, DiscoveryMode, Error, Node, NodeType as ControllerNodeType,
+    ServerEvent, SimulationController, SimulationControllerOptions, Topology,
 };
+use dn_message::AckMode;
 use dn_server::content_server::ContentServer;
 use dn_server::{communication_server::CommunicationServer, content_server::ContentServerOptions};
 use petgraph::prelude::{DiGraphMap, UnGraphMap};
-use rayon::{
-    iter::{IntoParallelIterator, ParallelIterator},
-    ThreadPoolBuilder,
-};
+use rayon::{ThreadPool, ThreadPoolBuilder};
+use serde::Deserialize;
 use std::collections::HashMap;
 use wg_2024::{
     config::Config,
@@ -21,7 +21,7 @@ use wg_2024::{
     packet::{NodeType, Packet},
 };
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub enum NetworkInitError {
     /// If a client or server is connected to a non drone.
     Edge,
@@ -37,12 +37,105 @@ pub enum NetworkInitError {
     EdgeCount,
     /// If the graph is not bidirectional.
     Directed,
+    /// If a drone is pinned to a group whose implementation is not available.
+    Group,
+}
+
+/// Initial quality of a link, parsed from the `[[link]]` tables of the config file.
+/// The higher the cost the less the link is used, links without a cost are all equal.
+/// The costs are known only by the communication servers.
+#[derive(Clone, Copy, Debug, Deserialize)]
+pub struct LinkQuality {
+    pub a: NodeId,
+    pub b: NodeId,
+    pub cost: u32,
+}
+
+/// The links with an initial quality, see `LinkQuality`.
+/// Can be parsed from the same file of the `Config`, which ignores the `[[link]]` tables.
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct LinkConfig {
+    #[serde(default)]
+    pub link: Vec<LinkQuality>,
+}
+
+/// A drone that runs the implementation of a specific group instead of the one assigned for fairness.
+#[derive(Clone, Debug, Deserialize)]
+pub struct DronePin {
+    pub id: NodeId,
+    pub group: String,
+}
+
+/// The drones pinned to an implementation, see `DronePin`.
+/// Can be parsed from the same file of the `Config`, which ignores the `[[pin]]` tables.
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct PinConfig {
+    #[serde(default)]
+    pub pin: Vec<DronePin>,
+}
+
+/// Options of the nodes of the network, see `init_network_with_options`.
+#[derive(Clone, Debug, Default)]
+pub struct NodeOptions {
+    /// When the servers acknowledge the fragments they receive, see `AckMode`.
+    pub ack_mode: AckMode,
+    /// The drones started with a forwarding delay, which can then be changed with
+    /// `SimulationController::set_drone_delay`. The other drones receive their packets
+    /// directly, without the relay thread of `delay_shim`.
+    pub drone_delays: HashMap<NodeId, DelaySpec>,
+    /// When the clients and the servers first flood the network, see `DiscoveryMode`.
+    pub discovery_mode: DiscoveryMode,
 }
 
 /// # Errors
 /// see `NetworkInitError`
 pub fn init_network(config: &Config) -> Result<SimulationController, NetworkInitError> {
-    init_network_with_fair_drones(config, &fair_drones())
+    init_network_with_options(config, &NodeOptions::default())
+}
+
+/// # Errors
+/// see `NetworkInitError`
+pub fn init_network_with_options(
+    config: &Config,
+    options: &NodeOptions,
+) -> Result<SimulationController, NetworkInitError> {
+    init_network_with_fair_drones(
+        config,
+        &LinkConfig::default(),
+        &PinConfig::default(),
+        options,
+        &fair_drones(),
+    )
+}
+
+/// # Errors
+/// see `NetworkInitError`
+pub fn init_network_with_links(
+    config: &Config,
+    links: &LinkConfig,
+) -> Result<SimulationController, NetworkInitError> {
+    init_network_with_fair_drones(
+        config,
+        links,
+        &PinConfig::default(),
+        &NodeOptions::default(),
+        &fair_drones(),
+    )
+}
+
+/// # Errors
+/// see `NetworkInitError`
+pub fn init_network_with_pins(
+    config: &Config,
+    pins: &PinConfig,
+) -> Result<SimulationController, NetworkInitError> {
+    init_network_with_fair_drones(
+        config,
+        &LinkConfig::default(),
+        pins,
+        &NodeOptions::default(),
+        &fair_drones(),
+    )
 }
 
 /// # Errors
@@ -51,16 +144,58 @@ pub fn init_network_with_drone<D: Drone + 'static>(
     config: &Config,
     group_name: String,
 ) -> Result<SimulationController, NetworkInitError> {
-    init_network_with_fair_drones(config, &adapter::<D>(group_name))
+    init_network_with_fair_drones(
+        config,
+        &LinkConfig::default(),
+        &PinConfig::default(),
+        &NodeOptions::default(),
+        &adapter::<D>(group_name),
+    )
+}
+
+/// Replaces the implementation of a running drone with the one of `group`, keeping its id, pdr and edges.
+/// See `SimulationController::replace_drone`.
+///
+/// # Errors
+/// `Error::UnknownGroup` if no implementation of `group` is available, otherwise see `Error`
+pub fn replace_drone_impl(
+    controller: &mut SimulationController,
+    id: NodeId,
+    group: &str,
+) -> dn_controller::Result<()> {
+    let drones = fair_drones();
+    let implementation = drones.find(group).ok_or(Error::UnknownGroup)?;
+    controller.replace_drone(id, group.to_string(), |channels| {
+        implementation.drone(DroneOptions {
+            id: channels.id,
+            controller_send: channels.controller_send,
+            controller_recv: channels.controller_recv,
+            packet_recv: match channels.delay_recv {
+                Some(delay_recv) => delay_shim(channels.packet_recv, delay_recv),
+                None => channels.packet_recv,
+            },
+            packet_send: channels.packet_send,
+            pdr: channels.pdr,
+        })
+    })
 }
 
+/// Every node runs its own event loop until it's crashed or returned, so each one needs a
+/// dedicated thread: the drones, the clients and the servers run on three pools with one
+/// thread per node, and a node blocking can't prevent the others from starting or making
+/// progress.
+///
 /// # Errors
 /// see `NetworkInitError`
 fn init_network_with_fair_drones(
     config: &Config,
+    links: &LinkConfig,
+    pins: &PinConfig,
+    options: &NodeOptions,
     drones: &FairDrones,
 ) -> Result<SimulationController, NetworkInitError> {
     let topology = init_topology(config)?;
+    let drones = assign_drones(config, pins, drones)?;
 
     let mut nodes = HashMap::new();
 
@@ -79,30 +214,31 @@ fn init_network_with_fair_drones(
         packets.insert(server.id, unbounded());
     }
 
-    let drone_pool = ThreadPoolBuilder::new().build().unwrap();
-    let client_pool = ThreadPoolBuilder::new().build().unwrap();
-    let server_pool = ThreadPoolBuilder::new().build().unwrap();
+    let drones = drone_options(config, options, &mut nodes, &packets, &drone_send, &drones);
+    let clients = client_options(config, options, &mut nodes, &packets, &client_send);
+    let servers = server_options(config, links, options, &mut nodes, &packets, &server_send);
 
-    let drones = drone_options(config, &mut nodes, &packets, &drone_send, drones);
-    let clients = client_options(config, &mut nodes, &packets, &client_send);
-    let servers = server_options(config, &mut nodes, &packets, &server_send);
+    let drone_pool = thread_pool(drones.len());
+    let client_pool = thread_pool(clients.len());
+    let server_pool = thread_pool(servers.len());
 
-    drone_pool.spawn(|| {
-        drones.into_par_iter().for_each(|mut drone| drone.run());
-    });
-    client_pool.spawn(|| {
-        clients.into_par_iter().for_each(|mut client| client.run());
-    });
-    server_pool.spawn(|| {
-        servers.into_par_iter().for_each(|server| match server {
+    for mut drone in drones {
+        drone_pool.spawn(move || drone.run());
+    }
+    for mut client in clients {
+        client_pool.spawn(move || client.run());
+    }
+    for server in servers {
+        server_pool.spawn(move || match server {
             Server::ContentServer(mut server) => server.run(),
             Server::CommunicationServer(mut server) => server.run(),
         });
-    });
+    }
 
     Ok(SimulationController::new(SimulationControllerOptions {
         nodes,
         drone_recv,
+        drone_send: Some(drone_send),
         server_recv,
         client_recv,
         topology,
@@ -112,6 +248,14 @@ fn init_network_with_fair_drones(
     }))
 }
 
+/// Builds a pool with a thread for each of the `nodes` it has to run.
+fn thread_pool(nodes: usize) -> ThreadPool {
+    ThreadPoolBuilder::new()
+        .num_threads(nodes.max(1))
+        .build()
+        .unwrap()
+}
+
 fn get_packet_send(
     packets: &HashMap<NodeId, (Sender<Packet>, Receiver<Packet>)>,
     node_ids: &[NodeId],
@@ -123,20 +267,53 @@ fn get_packet_send(
         .collect()
 }
 
+/// Chooses the implementation of every drone of the config, in the same order: pinned drones run the
+/// implementation of their group, the others take turns among all the available ones.
+fn assign_drones<'a>(
+    config: &Config,
+    pins: &PinConfig,
+    drones: &'a FairDrones,
+) -> Result<Vec<&'a dyn FairDrone>, NetworkInitError> {
+    let mut pinned = HashMap::new();
+    for pin in &pins.pin {
+        if !config.drone.iter().any(|drone| drone.id == pin.id) {
+            return Err(NetworkInitError::NodeId);
+        }
+        let drone = drones.find(&pin.group).ok_or(NetworkInitError::Group)?;
+        pinned.insert(pin.id, drone);
+    }
+
+    Ok(config
+        .drone
+        .iter()
+        .enumerate()
+        .map(|(i, drone)| pinned.get(&drone.id).copied().unwrap_or(drones.get(i)))
+        .collect())
+}
+
 fn drone_options(
     config: &Config,
+    options: &NodeOptions,
     nodes: &mut HashMap<NodeId, Node>,
     packets: &HashMap<NodeId, (Sender<Packet>, Receiver<Packet>)>,
     controller_send: &Sender<DroneEvent>,
-    drones: &FairDrones,
+    drones: &[&dyn FairDrone],
 ) -> Vec<Box<dyn Drone>> {
     config
         .drone
         .iter()
-        .enumerate()
-        .map(|(i, drone)| {
+        .zip(drones)
+        .map(|(drone, implementation)| {
             // controller
             let (drone_send, controller_recv) = unbounded();
+            // forwarding delay, only for the drones that may be delayed
+            let mut packet_recv = packets[&drone.id].1.clone();
+            let delay = options.drone_delays.get(&drone.id).map(|&delay| {
+                let (delay_send, delay_recv) = unbounded();
+                delay_send.send(delay).unwrap();
+                packet_recv = delay_shim(packet_recv.clone(), delay_recv);
+                delay_send
+            });
             nodes.insert(
                 drone.id,
                 Node {
@@ -144,18 +321,18 @@ fn drone_options(
                     node_type: ControllerNodeType::Drone {
                         sender: drone_send,
                         pdr: drone.pdr,
-                        group_name: drones.get(i).group_name().to_string(),
+                        group_name: implementation.group_name().to_string(),
+                        delay,
                     },
                 },
             );
             let controller_send = controller_send.clone();
             // packet
-            let packet_recv = packets[&drone.id].1.clone();
             let packet_send = get_packet_send(packets, &drone.connected_node_ids);
             let id = drone.id;
             let pdr = drone.pdr;
 
-            drones.get(i).drone(DroneOptions {
+            implementation.drone(DroneOptions {
                 id,
                 controller_send,
                 controller_recv,
@@ -169,6 +346,7 @@ fn drone_options(
 
 fn client_options(
     config: &Config,
+    options: &NodeOptions,
     nodes: &mut HashMap<NodeId, Node>,
     packets: &HashMap<NodeId, (Sender<Packet>, Receiver<Packet>)>,
     controller_send: &Sender<ClientEvent>,
@@ -194,13 +372,15 @@ fn client_options(
             let packet_send = get_packet_send(packets, &client.connected_drone_ids);
             let id = client.id;
 
-            Client::new(
+            let mut client = Client::new(
                 id,
                 controller_send,
                 controller_recv,
                 packet_send,
                 packet_recv,
-            )
+            );
+            client.set_discovery_mode(options.discovery_mode);
+            client
         })
         .collect()
 }
@@ -212,6 +392,8 @@ enum Server {
 
 fn server_options(
     config: &Config,
+    links: &LinkConfig,
+    options: &NodeOptions,
     nodes: &mut HashMap<NodeId, Node>,
     packets: &HashMap<NodeId, (Sender<Packet>, Receiver<Packet>)>,
     controller_send: &Sender<ServerEvent>,
@@ -239,13 +421,19 @@ fn server_options(
             let id = server.id;
 
             if i % 2 == 0 {
-                Server::CommunicationServer(CommunicationServer::new(
+                let mut server = CommunicationServer::new(
                     controller_send,
                     controller_recv,
                     packet_send,
                     packet_recv,
                     id,
-                ))
+                );
+                for link in &links.link {
+                    server.set_link_cost(link.a, link.b, link.cost);
+                }
+                server.set_ack_mode(options.ack_mode);
+                server.set_discovery_mode(options.discovery_mode);
+                Server::CommunicationServer(server)
             } else {
                 Server::ContentServer(ContentServer::new(ContentServerOptions {
                     id,
@@ -253,33 +441,57 @@ fn server_options(
                     controller_recv,
                     packet_recv,
                     packet_send,
+                    max_depth: None,
+                    max_file_size: None,
+                    asset_roots: HashMap::new(),
+                    ack_mode: options.ack_mode,
+                    discovery_mode: options.discovery_mode,
+                    discovery_jitter: None,
                 }))
             }
         })
         .collect()
 }
 
+/// Checks the whole config and reports every violation, instead of stopping at the first one
+/// like `init_network`. Returns an empty vector if the network can be initialized.
+#[must_use]
+pub fn validate_config(config: &Config) -> Vec<NetworkInitError> {
+    check_topology(config).1
+}
+
 fn init_topology(config: &Config) -> Result<Topology, NetworkInitError> {
+    let (topology, errors) = check_topology(config);
+    match errors.into_iter().next() {
+        Some(error) => Err(error),
+        None => Ok(topology),
+    }
+}
+
+/// Builds the topology skipping the invalid nodes and edges, returns it together with every
+/// violation found, in the order they are checked.
+fn check_topology(config: &Config) -> (Topology, Vec<NetworkInitError>) {
     let mut graph = DiGraphMap::new();
     let mut node_types = HashMap::new();
+    let mut errors = Vec::new();
 
     for drone in &config.drone {
         if drone.pdr < 0.0 || drone.pdr > 1.0 {
-            return Err(NetworkInitError::Pdr);
+            errors.push(NetworkInitError::Pdr);
         }
         graph.add_node(drone.id);
         node_types.insert(drone.id, NodeType::Drone);
     }
     for client in &config.client {
         if !(1..=2).contains(&client.connected_drone_ids.len()) {
-            return Err(NetworkInitError::EdgeCount);
+            errors.push(NetworkInitError::EdgeCount);
         }
         graph.add_node(client.id);
         node_types.insert(client.id, NodeType::Client);
     }
     for server in &config.server {
         if server.connected_drone_ids.len() < 2 {
-            return Err(NetworkInitError::EdgeCount);
+            errors.push(NetworkInitError::EdgeCount);
         }
         graph.add_node(server.id);
         node_types.insert(server.id, NodeType::Server);
@@ -288,40 +500,37 @@ fn init_topology(config: &Config) -> Result<Topology, NetworkInitError> {
     for drone in &config.drone {
         for neighbor_id in &drone.connected_node_ids {
             if drone.id == *neighbor_id {
-                return Err(NetworkInitError::SelfLoop);
-            }
-            let _ = *node_types
-                .get(neighbor_id)
-                .ok_or(NetworkInitError::NodeId)?;
-            graph.add_edge(drone.id, *neighbor_id, ());
-        }
-    }
-    for client in &config.client {
-        for neighbor_id in &client.connected_drone_ids {
-            if client.id == *neighbor_id {
-                return Err(NetworkInitError::SelfLoop);
-            }
-            let neighbor_type = *node_types
-                .get(neighbor_id)
-                .ok_or(NetworkInitError::NodeId)?;
-            if neighbor_type != NodeType::Drone {
-                return Err(NetworkInitError::Edge);
+                errors.push(NetworkInitError::SelfLoop);
+            } else if !node_types.contains_key(neighbor_id) {
+                errors.push(NetworkInitError::NodeId);
+            } else {
+                graph.add_edge(drone.id, *neighbor_id, ());
             }
-            graph.add_edge(client.id, *neighbor_id, ());
         }
     }
-    for server in &config.server {
-        for neighbor_id in &server.connected_drone_ids {
-            if server.id == *neighbor_id {
-                return Err(NetworkInitError::SelfLoop);
+    for (id, connected_drone_ids) in config
+        .client
+        .iter()
+        .map(|client| (client.id, &client.connected_drone_ids))
+        .chain(
+            config
+                .server
+                .iter()
+                .map(|server| (server.id, &server.connected_drone_ids)),
+        )
+    {
+        for neighbor_id in connected_drone_ids {
+            if id == *neighbor_id {
+                errors.push(NetworkInitError::SelfLoop);
+                continue;
             }
-            let neighbor_type = *node_types
-                .get(neighbor_id)
-                .ok_or(NetworkInitError::NodeId)?;
-            if neighbor_type != NodeType::Drone {
-                return Err(NetworkInitError::Edge);
+            match node_types.get(neighbor_id) {
+                None => errors.push(NetworkInitError::NodeId),
+                Some(NodeType::Drone) => {
+                    graph.add_edge(id, *neighbor_id, ());
+                }
+                Some(_) => errors.push(NetworkInitError::Edge),
             }
-            graph.add_edge(server.id, *neighbor_id, ());
         }
     }
 
@@ -330,10 +539,551 @@ fn init_topology(config: &Config) -> Result<Topology, NetworkInitError> {
         topology.add_node(node);
     }
     for (a, b, ()) in graph.all_edges() {
-        if !graph.contains_edge(b, a) {
-            return Err(NetworkInitError::Directed);
+        if graph.contains_edge(b, a) {
+            topology.add_edge(a, b, ());
+        } else {
+            errors.push(NetworkInitError::Directed);
+        }
+    }
+    (topology, errors)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use dn_controller::{NodeSummary, PacketRecorder, SimEvent, TopologyDiff};
+    use dn_message::{
+        ClientBody, ClientCommunicationBody, CommunicationMessage, Message, ServerBody,
+        ServerCommunicationBody,
+    };
+    use std::time::{Duration, Instant};
+    use wg_2024::config::{Client, Drone, Server};
+    use wg_2024::network::SourceRoutingHeader;
+    use wg_2024::packet::PacketType;
+
+    const TIMEOUT: Duration = Duration::from_secs(10);
+
+    /// Waits until the controller receives a client event matching `f`.
+    /// Returns `false` if no such event arrives before `TIMEOUT`, so that a routing bug fails fast.
+    fn wait_client_event(
+        controller: &SimulationController,
+        f: impl Fn(&ClientEvent) -> bool,
+    ) -> bool {
+        let client_recv = controller.get_client_recv();
+        let deadline = Instant::now() + TIMEOUT;
+        while let Ok(event) = client_recv.recv_deadline(deadline) {
+            if f(&event) {
+                return true;
+            }
+        }
+        false
+    }
+
+    /// ```text
+    /// 20 - 1 - 10 - 2 - 21
+    ///       \_______/
+    /// ```
+    fn config() -> Config {
+        Config {
+            drone: vec![
+                Drone {
+                    id: 1,
+                    connected_node_ids: vec![2, 10, 20],
+                    pdr: 0.0,
+                },
+                Drone {
+                    id: 2,
+                    connected_node_ids: vec![1, 10, 21],
+                    pdr: 0.0,
+                },
+            ],
+            client: vec![
+                Client {
+                    id: 20,
+                    connected_drone_ids: vec![1],
+                },
+                Client {
+                    id: 21,
+                    connected_drone_ids: vec![2],
+                },
+            ],
+            // the first server is a communication server
+            server: vec![Server {
+                id: 10,
+                connected_drone_ids: vec![1, 2],
+            }],
+        }
+    }
+
+    #[test]
+    fn test_chat_round_trip() {
+        let controller = init_network(&config()).unwrap();
+
+        // register the recipient first, otherwise the message would be rejected
+        controller
+            .client_send_message(
+                21,
+                10,
+                ClientBody::ClientCommunication(ClientCommunicationBody::ReqRegistrationToChat),
+            )
+            .unwrap();
+        assert!(wait_client_event(&controller, |event| matches!(
+            event,
+            ClientEvent::MessageAssembled {
+                body: ServerBody::ServerCommunication(
+                    ServerCommunicationBody::RegistrationSuccess
+                        | ServerCommunicationBody::AlreadyRegistered
+                ),
+                from: 10,
+                to: 21,
+            }
+        )));
+
+        // 20 registers automatically before sending the message
+        controller
+            .client_send_message(
+                20,
+                10,
+                ClientBody::ClientCommunication(ClientCommunicationBody::MessageSend(
+                    CommunicationMessage {
+                        from: 20,
+                        to: 21,
+                        message: "hello".to_string(),
+                    },
+                )),
+            )
+            .unwrap();
+        assert!(wait_client_event(&controller, |event| matches!(
+            event,
+            ClientEvent::MessageAssembled {
+                body: ServerBody::ServerCommunication(ServerCommunicationBody::MessageReceive(
+                    CommunicationMessage { from: 20, to: 21, message }
+                )),
+                from: 10,
+                to: 21,
+            } if message == "hello"
+        )));
+    }
+
+    #[test]
+    fn test_server_send() {
+        let controller = init_network(&config()).unwrap();
+
+        // servers can only send server messages to clients
+        let pong = Message::Server(ServerBody::ServerCommunication(
+            ServerCommunicationBody::Pong(7),
+        ));
+        assert!(matches!(
+            controller.server_send(10, 1, pong.clone()),
+            Err(Error::InvalidNode)
+        ));
+        assert!(matches!(
+            controller.server_send(10, 21, Message::Client(ClientBody::ReqServerType)),
+            Err(Error::InvalidNode)
+        ));
+
+        // 21 isn't registered, the message goes through the plain send path of the server
+        controller.server_send(10, 21, pong).unwrap();
+        assert!(wait_client_event(&controller, |event| matches!(
+            event,
+            ClientEvent::MessageAssembled {
+                body: ServerBody::ServerCommunication(ServerCommunicationBody::Pong(7)),
+                from: 10,
+                to: 21,
+            }
+        )));
+    }
+
+    /// Returns the time the server takes to answer a `ReqServerType` of `client`.
+    fn server_type_round_trip(controller: &SimulationController, client: NodeId) -> Duration {
+        let start = Instant::now();
+        controller
+            .client_send_message(client, 10, ClientBody::ReqServerType)
+            .unwrap();
+        assert!(wait_client_event(controller, |event| matches!(
+            event,
+            ClientEvent::MessageAssembled {
+                body: ServerBody::RespServerType(_),
+                from: 10,
+                to,
+            } if *to == client
+        )));
+        start.elapsed()
+    }
+
+    #[test]
+    fn test_ack_on_processed() {
+        let options = NodeOptions {
+            ack_mode: AckMode::OnProcessed,
+            ..NodeOptions::default()
+        };
+        let controller = init_network_with_options(&config(), &options).unwrap();
+        server_type_round_trip(&controller, 20);
+        server_type_round_trip(&controller, 21);
+    }
+
+    #[test]
+    fn test_lazy_discovery() {
+        let options = NodeOptions {
+            discovery_mode: DiscoveryMode::Lazy,
+            ..NodeOptions::default()
+        };
+        let controller = init_network_with_options(&config(), &options).unwrap();
+        // the nodes flood along with their first message
+        server_type_round_trip(&controller, 20);
+        server_type_round_trip(&controller, 21);
+    }
+
+    #[test]
+    fn test_drone_delay() {
+        // only 1 can be delayed
+        let options = NodeOptions {
+            drone_delays: HashMap::from([(1, DelaySpec::default())]),
+            ..NodeOptions::default()
+        };
+        let mut controller = init_network_with_options(&config(), &options).unwrap();
+        assert!(matches!(
+            controller.set_drone_delay(2, DelaySpec::default()),
+            Err(Error::NotDelayed)
+        ));
+        // let both clients discover their route to 10 before slowing down 1
+        server_type_round_trip(&controller, 20);
+        server_type_round_trip(&controller, 21);
+
+        let delay = Duration::from_millis(100);
+        controller
+            .set_drone_delay(1, DelaySpec::fixed(delay))
+            .unwrap();
+
+        // the request and the response both go through 1
+        let delayed = server_type_round_trip(&controller, 20);
+        let undelayed = server_type_round_trip(&controller, 21);
+        assert!(delayed >= 2 * delay);
+        assert!(undelayed < delayed);
+    }
+
+    #[test]
+    fn test_delay_shim_overlap() {
+        let (packet_send, packet_recv) = unbounded();
+        let (delay_send, delay_recv) = unbounded();
+        let relayed = delay_shim(packet_recv, delay_recv);
+
+        let delay = Duration::from_millis(100);
+        delay_send.send(DelaySpec::fixed(delay)).unwrap();
+        let start = Instant::now();
+        for fragment_index in 0..10 {
+            packet_send
+                .send(Packet::new_ack(
+                    SourceRoutingHeader::initialize(vec![1, 2]),
+                    0,
+                    fragment_index,
+                ))
+                .unwrap();
+        }
+
+        // the delays of the packets overlap instead of adding up
+        for fragment_index in 0..10 {
+            let packet = relayed.recv_timeout(TIMEOUT).unwrap();
+            assert_eq!(packet.get_fragment_index(), fragment_index);
+        }
+        let elapsed = start.elapsed();
+        assert!(elapsed >= delay);
+        assert!(elapsed < 5 * delay);
+
+        // the relay stops once the scheduled packets are relayed
+        drop(packet_send);
+        assert!(relayed.recv_timeout(TIMEOUT).is_err());
+    }
+
+    #[test]
+    fn test_link_quality() {
+        // 20 and 10 are connected through both 1 and 2, but the link 10 - 1 is expensive
+        let file_str = r#"
+            [[drone]]
+            id = 1
+            connected_node_ids = [10, 20]
+            pdr = 0.0
+
+            [[drone]]
+            id = 2
+            connected_node_ids = [10, 20]
+            pdr = 0.0
+
+            [[client]]
+            id = 20
+            connected_drone_ids = [1, 2]
+
+            [[server]]
+            id = 10
+            connected_drone_ids = [1, 2]
+
+            [[link]]
+            a = 10
+            b = 1
+            cost = 50
+        "#;
+        let config: Config = toml::from_str(file_str).unwrap();
+        let links: LinkConfig = toml::from_str(file_str).unwrap();
+        assert_eq!(links.link.len(), 1);
+
+        let controller = init_network_with_links(&config, &links).unwrap();
+        controller
+            .client_send_message(
+                20,
+                10,
+                ClientBody::ClientCommunication(ClientCommunicationBody::ReqRegistrationToChat),
+            )
+            .unwrap();
+
+        let server_recv = controller.get_server_recv();
+        let deadline = Instant::now() + TIMEOUT;
+        loop {
+            let event = server_recv
+                .recv_deadline(deadline)
+                .expect("expected the server to answer 20");
+            if let ServerEvent::PacketSent(Packet {
+                routing_header,
+                pack_type: PacketType::MsgFragment(_),
+                ..
+            }) = event
+            {
+                assert_eq!(routing_header.hops, vec![10, 2, 20]);
+                break;
+            }
+        }
+    }
+
+    #[test]
+    fn test_more_nodes_than_threads() {
+        // 200 - 1 - 2 - ... - n - 201, with more drones than the default number of threads
+        let n = NodeId::try_from(rayon::current_num_threads() + 2).unwrap();
+        let drone = |id: NodeId| {
+            let mut connected_node_ids = vec![if id == 1 { 200 } else { id - 1 }];
+            connected_node_ids.push(if id == n { 201 } else { id + 1 });
+            if id == n - 1 {
+                connected_node_ids.push(201);
+            }
+            Drone {
+                id,
+                connected_node_ids,
+                pdr: 0.0,
+            }
+        };
+        let config = Config {
+            drone: (1..=n).map(drone).collect(),
+            client: vec![Client {
+                id: 200,
+                connected_drone_ids: vec![1],
+            }],
+            server: vec![Server {
+                id: 201,
+                connected_drone_ids: vec![n - 1, n],
+            }],
+        };
+        let controller = init_network(&config).unwrap();
+
+        // the request reaches the server only if the drones of the chain are all running
+        controller
+            .client_send_message(200, 201, ClientBody::ReqServerType)
+            .unwrap();
+        assert!(wait_client_event(&controller, |event| matches!(
+            event,
+            ClientEvent::MessageAssembled {
+                body: ServerBody::RespServerType(_),
+                from: 201,
+                to: 200,
+            }
+        )));
+    }
+
+    #[test]
+    fn test_wait_for_convergence() {
+        let controller = init_network(&config()).unwrap();
+        assert!(controller.wait_for_convergence(TIMEOUT).unwrap());
+        for id in [10, 20, 21] {
+            assert_eq!(
+                controller.topology_diff(id).unwrap(),
+                TopologyDiff::default()
+            );
+        }
+    }
+
+    #[test]
+    fn test_describe_network() {
+        let mut config = config();
+        config.drone[1].pdr = 0.5;
+        let controller = init_network(&config).unwrap();
+
+        let drone = |id: NodeId, pdr| NodeSummary::Drone {
+            pdr,
+            group_name: controller.get_group_name(id).unwrap().to_string(),
+        };
+        assert_eq!(
+            controller.describe_network(),
+            vec![
+                (1, drone(1, 0.0), vec![2, 10, 20]),
+                (2, drone(2, 0.5), vec![1, 10, 21]),
+                (10, NodeSummary::Server, vec![1, 2]),
+                (20, NodeSummary::Client, vec![1]),
+                (21, NodeSummary::Client, vec![2]),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_validate_config() {
+        assert!(validate_config(&config()).is_empty());
+
+        let mut config = config();
+        config.drone[0].pdr = 1.5;
+        config.drone[1].connected_node_ids.push(2);
+        config.server[0].connected_drone_ids.push(30);
+
+        assert_eq!(
+            validate_config(&config),
+            vec![
+                NetworkInitError::Pdr,
+                NetworkInitError::SelfLoop,
+                NetworkInitError::NodeId,
+            ]
+        );
+        // the initialization still stops at the first one
+        assert!(matches!(init_network(&config), Err(NetworkInitError::Pdr)));
+    }
+
+    #[test]
+    fn test_pinned_drones() {
+        let pins: PinConfig = toml::from_str(
+            r#"
+            [[pin]]
+            id = 2
+            group = "skylink"
+            "#,
+        )
+        .unwrap();
+        let controller = init_network_with_pins(&config(), &pins).unwrap();
+        assert_eq!(controller.get_group_name(2).unwrap(), "skylink");
+        assert_eq!(controller.get_drone_groups()[&2], "skylink");
+        assert_eq!(controller.get_drone_groups().len(), 2);
+
+        let unknown = PinConfig {
+            pin: vec![DronePin {
+                id: 2,
+                group: "unknown".to_string(),
+            }],
+        };
+        assert!(matches!(
+            init_network_with_pins(&config(), &unknown),
+            Err(NetworkInitError::Group)
+        ));
+    }
+
+    #[test]
+    fn test_replace_drone_impl() {
+        let pins: PinConfig = toml::from_str(
+            r#"
+            [[pin]]
+            id = 1
+            group = "skylink"
+            "#,
+        )
+        .unwrap();
+        let mut controller = init_network_with_pins(&config(), &pins).unwrap();
+        server_type_round_trip(&controller, 20);
+
+        replace_drone_impl(&mut controller, 1, "rusty_drones").unwrap();
+        assert_eq!(controller.get_group_name(1).unwrap(), "rusty_drones");
+        assert_eq!(controller.neighbors(1), vec![2, 10, 20]);
+        // 20 can only reach the server through 1
+        server_type_round_trip(&controller, 20);
+
+        assert!(matches!(
+            replace_drone_impl(&mut controller, 1, "unknown"),
+            Err(Error::UnknownGroup)
+        ));
+        assert!(matches!(
+            replace_drone_impl(&mut controller, 20, "skylink"),
+            Err(Error::InvalidNode)
+        ));
+    }
+
+    #[test]
+    fn test_drop_stats() {
+        let mut config = config();
+        // every fragment through 1 is dropped and retransmitted
+        config.drone[0].pdr = 1.0;
+        let controller = init_network(&config).unwrap();
+        assert!(controller.get_drop_stats().is_empty());
+
+        controller
+            .client_send_message(20, 10, ClientBody::ReqServerType)
+            .unwrap();
+
+        // the statistics are updated before the events are forwarded
+        let events = controller.unified_events();
+        let deadline = Instant::now() + TIMEOUT;
+        while controller.get_drop_stats().get(&1).copied().unwrap_or(0) < 3 {
+            events
+                .recv_deadline(deadline)
+                .expect("expected drone 1 to drop packets");
+        }
+        assert!(!controller.get_drop_stats().contains_key(&2));
+    }
+
+    #[test]
+    fn test_replay_trace() {
+        let controller = init_network(&config()).unwrap();
+        let events = controller.unified_events();
+        let mut recorder = PacketRecorder::new();
+
+        controller
+            .client_send_message(
+                21,
+                10,
+                ClientBody::ClientCommunication(ClientCommunicationBody::ReqRegistrationToChat),
+            )
+            .unwrap();
+        let deadline = Instant::now() + TIMEOUT;
+        loop {
+            let event = events
+                .recv_deadline(deadline)
+                .expect("expected 21 to register");
+            recorder.record(&event);
+            if matches!(
+                event,
+                SimEvent::Client(ClientEvent::MessageAssembled {
+                    body: ServerBody::ServerCommunication(
+                        ServerCommunicationBody::RegistrationSuccess
+                    ),
+                    from: 10,
+                    to: 21,
+                })
+            ) {
+                break;
+            }
+        }
+        let rosters = controller.gather_client_rosters().unwrap();
+        assert_eq!(rosters[&10], vec![21]);
+
+        let path = std::env::temp_dir().join(format!(
+            "dn_network_test_replay_trace_{}",
+            std::process::id()
+        ));
+        recorder.save(&path).unwrap();
+
+        // 21 never asks the new network to register, the replayed packets do
+        let replayed = init_network(&config()).unwrap();
+        assert!(replayed.gather_client_rosters().unwrap()[&10].is_empty());
+        replayed.replay_trace(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let deadline = Instant::now() + TIMEOUT;
+        while replayed.gather_client_rosters().unwrap() != rosters {
+            assert!(
+                Instant::now() < deadline,
+                "expected the replay to register 21"
+            );
+            std::thread::sleep(Duration::from_millis(10));
         }
-        topology.add_edge(a, b, ());
     }
-    Ok(topology)
 }
@@ -1,3 +1,4 @@
+mod delay;
 mod fair_drones;
 mod initializer;
 
@@ -6,22 +6,56 @@ use wg_2024::packet::Packet;
 pub enum Event {
     // receiver NodeId. Required because it's not present in FloodRequest
     PacketReceived(Packet, NodeId),
+    // with `AckMode::OnProcessed` the ACK of its last fragment waits for `Command::MessageProcessed`
     MessageAssembled {
         body: Message,
         from: NodeId,
         to: NodeId,
+        session_id: u64,
     },
     MessageFragmented {
         body: Message,
         from: NodeId,
         to: NodeId,
     },
+    MessageReassemblyProgress {
+        from: NodeId,
+        to: NodeId,
+        session_id: u64,
+        received: u64,
+        total: u64,
+    },
     PacketSent(Packet),
+    // a message whose checksum doesn't match its data has been discarded
+    MessageCorrupted {
+        from: NodeId,
+        to: NodeId,
+        session_id: u64,
+    },
+    // no known path to the destination, the fragment is kept until the topology changes
+    RouteNotFound {
+        to: NodeId,
+        session_id: u64,
+        fragment_index: u64,
+    },
+    // every fragment of a message sent by this node has been acknowledged,
+    // `path` is set for the file responses
+    MessageDelivered {
+        path: Option<String>,
+        to: NodeId,
+        session_id: u64,
+    },
 }
 
 pub enum Command {
     AddSender(NodeId, Sender<Packet>),
     RemoveSender(NodeId),
     SendMessage(Message, NodeId),
+    QueryTopology(Sender<Vec<(NodeId, NodeId)>>),
+    // the message assembled from the session of `from` has been handled, see `Event::MessageAssembled`
+    MessageProcessed {
+        from: NodeId,
+        session_id: u64,
+    },
     Return,
 }
@@ -1,4 +1,8 @@
 use super::Router;
+use crossbeam_channel::{after, never, Receiver};
+use dn_controller::DiscoveryMode;
+use rand::Rng;
+use std::time::{Duration, Instant};
 use wg_2024::{
     network::SourceRoutingHeader,
     packet::{FloodRequest, FloodResponse, NodeType, Packet, PacketType},
@@ -30,7 +34,10 @@ impl Router {
     }
 
     pub(crate) fn handle_flood_response(&mut self, flood: &FloodResponse) {
-        self.routing.add_path(&flood.path_trace);
+        // a trace that doesn't contain this node can't describe a path from here
+        if flood.path_trace.iter().any(|&(id, _)| id == self.id) {
+            self.routing.add_path(&flood.path_trace);
+        }
     }
 
     pub(crate) fn should_flood(&mut self) -> bool {
@@ -38,7 +45,31 @@ impl Router {
         self.drop_count == 0
     }
 
+    /// Starts the first discovery of the network according to the `DiscoveryMode`, returning the
+    /// timer of the jittered discovery, a timer that never fires for the other modes.
+    pub(crate) fn start_discovery(&mut self) -> Receiver<Instant> {
+        match self.discovery_mode {
+            DiscoveryMode::Immediate => {
+                self.flood();
+                never()
+            }
+            DiscoveryMode::Jittered(max_delay) => {
+                self.discovery_pending = true;
+                let delay = match &self.discovery_jitter {
+                    Some(jitter) => jitter(max_delay),
+                    None => rand::rng().random_range(Duration::ZERO..=max_delay),
+                };
+                after(delay)
+            }
+            DiscoveryMode::Lazy => {
+                self.discovery_pending = true;
+                never()
+            }
+        }
+    }
+
     pub(crate) fn flood(&mut self) {
+        self.discovery_pending = false;
         let session_id = self.inc_session_id();
         self.routing.send_flood_request(session_id);
     }
@@ -1,6 +1,6 @@
 use super::Router;
 use crate::command::{Command, Event};
-use dn_message::Message;
+use dn_message::{Message, ServerBody, ServerContentBody};
 use wg_2024::{
     network::{NodeId, SourceRoutingHeader},
     packet::{Packet, PacketType},
@@ -12,12 +12,30 @@ impl Router {
             Command::AddSender(id, sender) => self.routing.add_sender(id, sender),
             Command::RemoveSender(id) => self.routing.remove_sender(id),
             Command::SendMessage(msg, dst) => self.handle_message(msg, dst),
+            Command::QueryTopology(sender) => _ = sender.send(self.routing.edges()),
+            Command::MessageProcessed { from, session_id } => {
+                if let Some(ack) = self.deferred_acks.remove(&(from, session_id)) {
+                    self.routing.send_packet(ack);
+                }
+            }
             Command::Return => (),
         }
     }
     pub(crate) fn handle_message(&mut self, msg: Message, dst: NodeId) {
+        // lazy discovery, the network is flooded along with the first message
+        if self.discovery_pending {
+            self.flood();
+        }
         let session_id = self.inc_session_id();
         let fragments = self.assembler.serialize_message(&msg);
+        let path = match &msg {
+            Message::Server(ServerBody::ServerContent(
+                ServerContentBody::RespFile(_, path) | ServerContentBody::RespTypedFile(_, path, _),
+            )) => Some(path.clone()),
+            _ => None,
+        };
+        self.pending_sessions
+            .insert(session_id, (path, dst, fragments.len()));
         self.controller_send
             .send(Event::MessageFragmented {
                 body: msg,
@@ -1,5 +1,6 @@
 use super::Router;
 use crate::command::Event;
+use dn_message::assembler::AckMode;
 use wg_2024::network::{NodeId, SourceRoutingHeader};
 use wg_2024::packet::{Ack, Nack, NackType, Packet, PacketType};
 
@@ -39,24 +40,77 @@ impl Router {
                     fragment_index: fragment.fragment_index,
                 }),
             };
-            self.routing.send_packet(ack);
+            if self.ack_mode == AckMode::OnReceipt {
+                self.routing.send_packet(ack.clone());
+            }
+            let from = packet.routing_header.hops[0];
+            let mut deferred = false;
             if let Some(message) =
                 self.assembler
                     .handle_fragment(fragment, sender_id, packet.session_id)
             {
+                if self.ack_mode == AckMode::OnProcessed {
+                    // sent once the controller handled the message
+                    self.deferred_acks
+                        .insert((from, packet.session_id), ack.clone());
+                    deferred = true;
+                }
                 self.controller_send
                     .send(Event::MessageAssembled {
                         body: message,
+                        from,
+                        to: self.id,
+                        session_id: packet.session_id,
+                    })
+                    .unwrap();
+            } else if let Some((received, total)) =
+                self.assembler.progress(sender_id, packet.session_id)
+            {
+                self.controller_send
+                    .send(Event::MessageReassemblyProgress {
+                        from: packet.routing_header.hops[0],
+                        to: self.id,
+                        session_id: packet.session_id,
+                        received,
+                        total,
+                    })
+                    .unwrap();
+            }
+            for (_, session_id) in self.assembler.take_corrupted_messages() {
+                self.controller_send
+                    .send(Event::MessageCorrupted {
                         from: packet.routing_header.hops[0],
                         to: self.id,
+                        session_id,
                     })
                     .unwrap();
             }
+            if self.ack_mode == AckMode::OnProcessed && !deferred {
+                self.routing.send_packet(ack);
+            }
         }
     }
 
     pub(crate) fn handle_ack(&mut self, packet: Packet, fragment_index: u64) {
-        self.routing.ack(packet, fragment_index);
+        let session_id = packet.session_id;
+        // duplicated acks must not be counted twice
+        if !self.routing.ack(packet, fragment_index) {
+            return;
+        }
+        let Some((_, _, remaining)) = self.pending_sessions.get_mut(&session_id) else {
+            return;
+        };
+        *remaining -= 1;
+        if *remaining == 0 {
+            let (path, to, _) = self.pending_sessions.remove(&session_id).unwrap();
+            self.controller_send
+                .send(Event::MessageDelivered {
+                    path,
+                    to,
+                    session_id,
+                })
+                .unwrap();
+        }
     }
 
     pub(crate) fn handle_nack(&mut self, session_id: u64, drop_id: NodeId, nack: &Nack) {
@@ -72,8 +126,10 @@ impl Router {
                 }
                 self.routing.nack(session_id, nack.fragment_index);
             }
-            _ => {
-                unreachable!()
+            // the path can't be fixed by routing again, the message is given up
+            NackType::DestinationIsDrone | NackType::UnexpectedRecipient(_) => {
+                self.routing.abandon(session_id);
+                self.pending_sessions.remove(&session_id);
             }
         }
     }
@@ -1,7 +1,8 @@
 use crate::command::{Command, Event};
 use crate::routing::{Routing, RoutingOptions};
 use crossbeam_channel::{select_biased, unbounded, Receiver, Sender};
-use dn_message::assembler::Assembler;
+use dn_controller::{DiscoveryJitter, DiscoveryMode};
+use dn_message::assembler::{AckMode, Assembler};
 use std::collections::HashMap;
 use wg_2024::network::NodeId;
 use wg_2024::packet::{NodeType, Packet};
@@ -14,6 +15,13 @@ pub struct RouterOptions {
     pub controller_send: Sender<Event>,
     pub packet_recv: Receiver<Packet>,
     pub packet_send: HashMap<NodeId, Sender<Packet>>,
+    // with `OnProcessed`, the last ACK of a message is sent once the controller handled it,
+    // see `Command::MessageProcessed`
+    pub ack_mode: AckMode,
+    // when the router first floods the network to discover the topology
+    pub discovery_mode: DiscoveryMode,
+    // picks the delay of a jittered discovery, random if `None`
+    pub discovery_jitter: Option<DiscoveryJitter>,
 }
 
 pub struct Router {
@@ -29,6 +37,19 @@ pub struct Router {
 
     pub(crate) routing: Routing,
     pub(crate) assembler: Assembler,
+    pub(crate) ack_mode: AckMode,
+    /// ACKs of the last fragment of the messages not handled yet by the controller,
+    /// the key is `(from, session_id)`
+    pub(crate) deferred_acks: HashMap<(NodeId, u64), Packet>,
+    /// messages sent by this node with the file path of the file responses,
+    /// the destination and the number of fragments not acked yet,
+    /// the key is `session_id`
+    pub(crate) pending_sessions: HashMap<u64, (Option<String>, NodeId, usize)>,
+
+    pub(crate) discovery_mode: DiscoveryMode,
+    pub(crate) discovery_jitter: Option<DiscoveryJitter>,
+    /// whether the first discovery of the network is still to be done
+    pub(crate) discovery_pending: bool,
 
     pub(crate) session_id: u64,
     pub(crate) drop_count: u64,
@@ -52,13 +73,19 @@ impl Router {
                 controller_send: opt.controller_send,
             }),
             assembler: Assembler::new(),
+            ack_mode: opt.ack_mode,
+            deferred_acks: HashMap::new(),
+            pending_sessions: HashMap::new(),
+            discovery_mode: opt.discovery_mode,
+            discovery_jitter: opt.discovery_jitter,
+            discovery_pending: false,
             session_id: 0,
             drop_count: 0,
         }
     }
 
     pub fn run(&mut self) {
-        self.flood();
+        let discovery_timer = self.start_discovery();
         loop {
             select_biased! {
                 recv(self.controller_recv) -> command => {
@@ -81,6 +108,11 @@ impl Router {
                         self.send_fragment(fragment, fragment_index, dst);
                     }
                 },
+                recv(discovery_timer) -> _ => {
+                    if self.discovery_pending {
+                        self.flood();
+                    }
+                },
             }
         }
     }
@@ -92,8 +124,16 @@ impl Router {
     }
 
     pub(crate) fn send_fragment(&mut self, fragment: Packet, fragment_index: u64, dst: NodeId) {
+        let session_id = fragment.session_id;
         let sent = self.routing.send_fragment(fragment, fragment_index, dst);
         if !sent {
+            self.controller_send
+                .send(Event::RouteNotFound {
+                    to: dst,
+                    session_id,
+                    fragment_index,
+                })
+                .unwrap();
             self.flood();
         }
     }
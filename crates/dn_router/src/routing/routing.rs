@@ -145,6 +145,10 @@ impl Routing {
         self.topology.remove_node(id);
     }
 
+    pub fn edges(&self) -> Vec<(NodeId, NodeId)> {
+        self.topology.all_edges().map(|(a, b, _)| (a, b)).collect()
+    }
+
     pub fn update_estimated_pdr(&mut self, id: NodeId, dropped: bool) {
         let pdr = self.estimated_pdr.entry(id).or_insert(DEFAULT_PDR);
         *pdr = if dropped { ALPHA } else { 0.0 } + (1.0 - ALPHA) * *pdr;
@@ -172,12 +176,16 @@ impl Routing {
         }
     }
 
-    pub fn ack(&mut self, packet: Packet, fragment_index: u64) {
-        self.pending_ack
-            .remove(&(packet.session_id, fragment_index));
+    /// returns whether the fragment was waiting for this ack
+    pub fn ack(&mut self, packet: Packet, fragment_index: u64) -> bool {
+        let pending = self
+            .pending_ack
+            .remove(&(packet.session_id, fragment_index))
+            .is_some();
         for hop in packet.routing_header.hops {
             self.update_estimated_pdr(hop, false);
         }
+        pending
     }
 
     pub fn nack(&mut self, session_id: u64, fragment_index: u64) {
@@ -185,4 +193,10 @@ impl Routing {
             self.send_fragment(fragment, fragment_index, dst);
         }
     }
+
+    /// drops the fragments of the session still waiting for an ack or a path
+    pub fn abandon(&mut self, session_id: u64) {
+        self.pending_ack.retain(|&(id, _), _| id != session_id);
+        self.pending_path.retain(|&(id, _), _| id != session_id);
+    }
 }
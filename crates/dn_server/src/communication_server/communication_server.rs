@@ -12,15 +12,23 @@
 //! topology based on received events.
 
 use crate::communication_server::communication_server_topology::CommunicationServerNetworkTopology;
+use crate::communication_server::flood_tracker::{FloodStats, FloodTracker, DEFAULT_FLOOD_HISTORY};
+use crate::communication_server::forwarded_messages::{ForwardedMessages, DEFAULT_DEDUP_WINDOW};
+use crate::communication_server::mailbox::Mailbox;
 use crate::communication_server::pending_message_queue::PendingMessagesQueue;
-use crate::communication_server::session_manager::SessionManager;
-use crossbeam_channel::{select_biased, Receiver, Sender};
-use dn_controller::{ServerCommand, ServerEvent};
-use dn_message::assembler::Assembler;
-use std::collections::{HashMap, HashSet};
+use crate::communication_server::session_manager::{SessionId, SessionManager};
+use crossbeam_channel::{at, never, select_biased, Receiver, Sender};
+use dn_controller::{DiscoveryJitter, DiscoveryMode, ServerCommand, ServerEvent};
+use dn_message::assembler::{AckMode, Assembler};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::time::{Duration, Instant};
 use wg_2024::network::NodeId;
 use wg_2024::packet::Packet;
 
+/// Number of sessions rejected by the maximum message size that are remembered, so that the
+/// controller is notified once per session and the rest of their fragments are discarded.
+pub(crate) const REJECTED_SESSIONS_HISTORY: usize = 64;
+
 /// The `CommunicationServer` struct encapsulates the core components required for managing
 /// network communication in a drone network. It handles sending and receiving control
 /// messages and data packets, manages client registration and session state, maintains a queue
@@ -39,6 +47,19 @@ use wg_2024::packet::Packet;
 /// - `assembler`: Responsible for reassembling fragmented messages and serialize messages ready to be sent.
 /// - `network_topology`: Maintains the current view of the network topology for routing decisions.
 /// - `registered_clients`: A set of node IDs representing clients that have been registered with the server.
+/// - `mailbox`: If enabled, stores the messages for registered clients that are currently unreachable.
+/// - `forwarded_messages`: The chat messages recently forwarded, used to suppress retransmitted duplicates.
+/// - `message_acks`: If enabled, every message received from a client is acknowledged with a `MessageAck`.
+/// - `flood_tracker`: The floods recently seen, used to skip duplicate flood responses.
+/// - `max_message_size`: If set, the maximum size in bytes of a message the server accepts to reassemble.
+/// - `rejected_sessions`: The latest `(sender, session)` rejected because of the maximum message size.
+/// - `ack_mode`: Whether the fragments are acknowledged on receipt or once they have been processed.
+/// - `discovery_mode`: When the server first floods the network to discover the topology.
+/// - `discovery_jitter`: If any, picks the delay of a jittered discovery instead of a random one.
+/// - `discovery_pending`: Whether the first discovery of the network is still to be done.
+/// - `min_flood_interval`: If set, the minimum time between two floods started by the server.
+/// - `last_flood`: When the server started its last flood.
+/// - `deferred_flood`: If any, when the floods requested too early since the last one are sent as a single flood.
 pub struct CommunicationServer {
     pub(crate) controller_send: Sender<ServerEvent>,
     pub(crate) controller_recv: Receiver<ServerCommand>,
@@ -53,6 +74,19 @@ pub struct CommunicationServer {
     pub(crate) assembler: Assembler,
     pub(crate) network_topology: CommunicationServerNetworkTopology,
     pub(crate) registered_clients: HashSet<NodeId>,
+    pub(crate) mailbox: Option<Mailbox>,
+    pub(crate) forwarded_messages: ForwardedMessages,
+    pub(crate) message_acks: bool,
+    pub(crate) flood_tracker: FloodTracker,
+    pub(crate) max_message_size: Option<usize>,
+    pub(crate) rejected_sessions: VecDeque<(NodeId, SessionId)>,
+    pub(crate) ack_mode: AckMode,
+    pub(crate) discovery_mode: DiscoveryMode,
+    pub(crate) discovery_jitter: Option<DiscoveryJitter>,
+    pub(crate) discovery_pending: bool,
+    pub(crate) min_flood_interval: Option<Duration>,
+    pub(crate) last_flood: Option<Instant>,
+    pub(crate) deferred_flood: Option<Instant>,
 }
 
 impl CommunicationServer {
@@ -92,20 +126,160 @@ impl CommunicationServer {
             registered_clients: HashSet::new(),
             network_topology: CommunicationServerNetworkTopology::new(),
             assembler: Assembler::new(),
+            mailbox: None,
+            forwarded_messages: ForwardedMessages::new(DEFAULT_DEDUP_WINDOW),
+            message_acks: false,
+            flood_tracker: FloodTracker::new(DEFAULT_FLOOD_HISTORY),
+            max_message_size: None,
+            rejected_sessions: VecDeque::new(),
+            ack_mode: AckMode::OnReceipt,
+            discovery_mode: DiscoveryMode::Immediate,
+            discovery_jitter: None,
+            discovery_pending: false,
+            min_flood_interval: None,
+            last_flood: None,
+            deferred_flood: None,
         }
     }
 
+    /// Enables the store-and-forward mailbox mode.
+    ///
+    /// In this mode, messages for registered clients that are currently unreachable are stored
+    /// and delivered as soon as the client shows some activity, instead of waiting only for the
+    /// next topology update.
+    ///
+    /// # Parameters
+    /// - `capacity`: The maximum number of messages stored for each client.
+    pub fn enable_mailbox(&mut self, capacity: usize) {
+        self.mailbox = Some(Mailbox::new(capacity));
+    }
+
+    /// Enables the message-level acknowledgments.
+    ///
+    /// Once a message from a client is fully reassembled, the server replies with a `MessageAck`
+    /// carrying its session id, so that the client can clear the whole session at once.
+    /// Fragments are acknowledged as usual.
+    pub fn enable_message_acks(&mut self) {
+        self.message_acks = true;
+    }
+
+    /// Sets the maximum size of the messages the server accepts to reassemble.
+    ///
+    /// The size of a message is estimated from the number of fragments it declares, each one
+    /// carrying at most `FRAGMENT_DSIZE` bytes. Fragments of larger messages are acknowledged
+    /// but discarded, so that a single client can't exhaust the memory of the server.
+    ///
+    /// # Parameters
+    /// - `max_message_size`: The maximum size in bytes of a message.
+    pub fn set_max_message_size(&mut self, max_message_size: usize) {
+        self.max_message_size = Some(max_message_size);
+    }
+
+    /// Sets when the fragments received are acknowledged.
+    ///
+    /// By default every fragment is acknowledged on receipt. With `AckMode::OnProcessed` the ACK is
+    /// sent after the fragment is reassembled, and for the last fragment of a message after the
+    /// message has been handled, including the responses sent by the server.
+    ///
+    /// # Parameters
+    /// - `ack_mode`: The acknowledgment mode.
+    pub fn set_ack_mode(&mut self, ack_mode: AckMode) {
+        self.ack_mode = ack_mode;
+    }
+
+    /// Sets when the server first floods the network to discover the topology.
+    ///
+    /// By default the server floods as soon as it runs. With `DiscoveryMode::Jittered` the first flood is
+    /// delayed by a random amount, with `DiscoveryMode::Lazy` it is sent along with the first message.
+    ///
+    /// # Parameters
+    /// - `discovery_mode`: The discovery mode.
+    pub fn set_discovery_mode(&mut self, discovery_mode: DiscoveryMode) {
+        self.discovery_mode = discovery_mode;
+    }
+
+    /// Sets how the delay of a jittered discovery is picked, by default it's random.
+    ///
+    /// # Parameters
+    /// - `jitter`: The function called with the maximum delay of `DiscoveryMode::Jittered`,
+    ///   returning the delay to use.
+    pub fn set_discovery_jitter(&mut self, jitter: DiscoveryJitter) {
+        self.discovery_jitter = Some(jitter);
+    }
+
+    /// Sets the minimum time between two floods started by the server.
+    ///
+    /// Floods requested before the interval has elapsed, e.g. by a burst of NACKs from the same
+    /// broken path, are coalesced into a single flood sent as soon as the interval ends, so a
+    /// topology change is always followed by at least one flood.
+    ///
+    /// # Parameters
+    /// - `min_flood_interval`: The minimum time between two floods, floods aren't limited by default.
+    pub fn set_min_flood_interval(&mut self, min_flood_interval: Duration) {
+        self.min_flood_interval = Some(min_flood_interval);
+    }
+
+    /// Returns the number of floods started by the server and of the ones that received a response.
+    #[must_use]
+    pub fn flood_stats(&self) -> FloodStats {
+        self.flood_tracker.stats()
+    }
+
+    /// Returns the number of ACKs received for sessions that weren't pending, such as late ACKs
+    /// for sessions already completed. They are ignored, the counter only helps diagnosing them.
+    #[must_use]
+    pub fn unknown_acks(&self) -> u64 {
+        self.session_manager.unknown_acks()
+    }
+
+    /// Returns the clients the server can currently route to, whether they registered or not.
+    /// Comparing them with the registered ones helps diagnosing clients discovered by the floods
+    /// that never registered.
+    #[must_use]
+    pub fn reachable_clients(&self) -> Vec<NodeId> {
+        self.network_topology.reachable_clients(self.id)
+    }
+
+    /// Returns the floods started by the server that haven't received any response within `timeout`.
+    ///
+    /// # Parameters
+    /// - `timeout`: The time after which a flood without responses is considered stale.
+    #[must_use]
+    pub fn stale_floods(&self, timeout: Duration) -> Vec<u64> {
+        self.flood_tracker.stale_floods(Instant::now(), timeout)
+    }
+
+    /// Sets the initial cost of a link of the network, making routes through it less likely.
+    ///
+    /// # Parameters
+    /// - `node_a`, `node_b`: The endpoints of the link.
+    /// - `cost`: The extra cost of traversing the link.
+    pub fn set_link_cost(&mut self, node_a: NodeId, node_b: NodeId, cost: u32) {
+        self.network_topology.update_edge_cost(node_a, node_b, cost);
+    }
+
+    /// Sets the bounds of the costs the server estimates for the drones from the NACKs it receives.
+    ///
+    /// # Parameters
+    /// - `min_cost`: The lowest cost of a drone, at least 1.
+    /// - `max_cost`: The highest cost of a drone, 100 by default.
+    pub fn set_node_cost_bounds(&mut self, min_cost: u32, max_cost: u32) {
+        self.network_topology
+            .set_node_cost_bounds(min_cost, max_cost);
+    }
+
     /// Runs the `CommunicationServer`.
     ///
     /// This function starts the server's main event loop by setting the `running` flag to true and
-    /// performing an initial network topology update. The server continuously listens for incoming
+    /// starting the discovery of the network according to the `DiscoveryMode`. The server continuously listens for incoming
     /// commands (via `controller_recv`) and packets (via `packet_recv`). Depending on the received event,
     /// it delegates processing to the appropriate handler functions. The loop continues until the
     /// `running` flag is set to false.
     pub fn run(&mut self) {
         self.running = true;
-        self.update_network_topology(); // first discovery of the network
+        let discovery_timer = self.start_discovery(); // first discovery of the network
         while self.running {
+            let flood_timer = self.deferred_flood.map_or_else(never, at);
             select_biased! {
                 recv(self.controller_recv) -> command => {
                     if let Ok(cmd) = command {
@@ -118,7 +292,13 @@ impl CommunicationServer {
                         self.handle_packet(p);
                         if !self.running { break; }
                     }
-                }
+                },
+                recv(discovery_timer) -> _ => {
+                    if self.discovery_pending {
+                        self.update_network_topology();
+                    }
+                },
+                recv(flood_timer) -> _ => self.send_deferred_flood()
             }
         }
     }
@@ -133,7 +313,7 @@ mod tests {
     use std::time::Duration;
     use wg_2024::network::SourceRoutingHeader;
     use wg_2024::packet::PacketType::MsgFragment;
-    use wg_2024::packet::{Fragment, PacketType};
+    use wg_2024::packet::{Fragment, NodeType, PacketType};
 
     #[test]
     fn test_run() {
@@ -203,4 +383,23 @@ mod tests {
             .send(ServerCommand::Return)
             .expect("Failed to send command to server");
     }
+
+    #[test]
+    fn test_reachable_clients() {
+        let mut helper = TestServerHelper::new();
+        // client 70 is known but only reachable through client 6
+        helper.server.network_topology.add_node(69, NodeType::Drone);
+        helper
+            .server
+            .network_topology
+            .add_node(70, NodeType::Client);
+        helper.server.network_topology.add_edge(6, 69);
+        helper.server.network_topology.add_edge(69, 70);
+        helper.register_client_6();
+
+        assert_eq!(helper.server.reachable_clients(), vec![4, 5, 6]);
+        assert_eq!(helper.server.registered_clients, HashSet::from([6]));
+        // 4 was discovered but never registered
+        assert!(!helper.server.registered_clients.contains(&4));
+    }
 }
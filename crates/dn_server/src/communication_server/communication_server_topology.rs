@@ -15,13 +15,21 @@ use wg_2024::packet::NodeType;
 
 type Topology = UnGraphMap<NodeId, ()>;
 
+/// Default lowest cost of a node, see `update_estimated_pdr`.
+pub const DEFAULT_MIN_NODE_COST: u32 = 1;
+/// Default highest cost of a node, see `update_estimated_pdr`.
+pub const DEFAULT_MAX_NODE_COST: u32 = 100;
+
 /// A struct that represents the network topology of the communication server
 pub struct CommunicationServerNetworkTopology {
     graph: Topology,
     saved_paths: HashMap<NodeId, Vec<NodeId>>, // client_node_id -> path
     node_types: HashMap<NodeId, NodeType>,
     node_costs: HashMap<NodeId, u32>,
+    edge_costs: HashMap<(NodeId, NodeId), u32>, // (min, max) -> extra cost of the link
     lambda: f64,
+    min_node_cost: u32,
+    max_node_cost: u32,
 }
 
 impl CommunicationServerNetworkTopology {
@@ -35,7 +43,10 @@ impl CommunicationServerNetworkTopology {
             node_types: HashMap::new(),
             saved_paths: HashMap::new(),
             node_costs: HashMap::new(),
+            edge_costs: HashMap::new(),
             lambda: 0.4, // 0.2 slow changes, 0.8 rapid adapting
+            min_node_cost: DEFAULT_MIN_NODE_COST,
+            max_node_cost: DEFAULT_MAX_NODE_COST,
         }
     }
 
@@ -87,6 +98,14 @@ impl CommunicationServerNetworkTopology {
         self.graph.remove_edge(node_a, node_b);
     }
 
+    /// Returns all the edges of the network topology.
+    ///
+    /// # Returns
+    /// * `Vec<(NodeId, NodeId)>` - The pairs of connected nodes.
+    pub fn edges(&self) -> Vec<(NodeId, NodeId)> {
+        self.graph.all_edges().map(|(a, b, _)| (a, b)).collect()
+    }
+
     /// Retrieves the type of node from the network topology.
     ///
     /// If the node exists in the `node_types` map, its type is returned.
@@ -138,6 +157,47 @@ impl CommunicationServerNetworkTopology {
         self.node_costs.insert(node_id, cost);
     }
 
+    /// Retrieves the extra cost of traversing the link between two nodes.
+    ///
+    /// # Arguments
+    /// * `node_a` - The ID of the first node.
+    /// * `node_b` - The ID of the second node.
+    ///
+    /// # Returns
+    /// * `Option<u32>` - The cost of the link if it has been set, otherwise `None`.
+    pub fn get_edge_cost(&self, node_a: NodeId, node_b: NodeId) -> Option<u32> {
+        self.edge_costs
+            .get(&(node_a.min(node_b), node_a.max(node_b)))
+            .copied()
+    }
+
+    /// Updates the extra cost of traversing the link between two nodes.
+    ///
+    /// The cost is added to the cost of the node reached through the link, so links without a
+    /// cost behave as if it was 0. The link doesn't need to be in the topology yet.
+    ///
+    /// # Arguments
+    /// * `node_a` - The ID of the first node.
+    /// * `node_b` - The ID of the second node.
+    /// * `cost` - The new cost value to assign.
+    pub fn update_edge_cost(&mut self, node_a: NodeId, node_b: NodeId, cost: u32) {
+        self.edge_costs
+            .insert((node_a.min(node_b), node_a.max(node_b)), cost);
+    }
+
+    /// Sets the bounds of the costs computed by `update_estimated_pdr`.
+    ///
+    /// A ceiling below 100 prevents a burst of NACKs from making a drone practically unusable,
+    /// while a floor above 1 prevents routing from trusting a drone too much.
+    ///
+    /// # Arguments
+    /// * `min_cost` - The lowest cost of a node, at least 1.
+    /// * `max_cost` - The highest cost of a node, at least `min_cost`.
+    pub fn set_node_cost_bounds(&mut self, min_cost: u32, max_cost: u32) {
+        self.min_node_cost = min_cost.max(1);
+        self.max_node_cost = max_cost.max(self.min_node_cost);
+    }
+
     /// Updates the estimated packet drop rate (PDR) for a node based on NACK reception.
     ///
     /// This function adapts the node's estimated PDR using an exponential moving average (EMA).
@@ -146,7 +206,8 @@ impl CommunicationServerNetworkTopology {
     /// how much recent events influence the updated estimate.
     ///
     /// The computed PDR is converted into a cost metric (1-100) for routing purposes,
-    /// where higher values indicate higher packet loss probability.
+    /// where higher values indicate higher packet loss probability. The cost is then clamped to
+    /// the bounds set with `set_node_cost_bounds`.
     ///
     /// # Arguments
     /// * `node_id` - The ID of the node whose PDR should be updated.
@@ -159,7 +220,7 @@ impl CommunicationServerNetworkTopology {
         let updated_pdr = (1.0 - lambda) * old_pdr + lambda * new_pdr;
         #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
         let cost = (updated_pdr * 100.0).max(1.0).floor() as u32;
-        self.update_node_cost(node_id, cost);
+        self.update_node_cost(node_id, cost.clamp(self.min_node_cost, self.max_node_cost));
     }
 
     /// Retrieves a saved path for a given node.
@@ -238,6 +299,10 @@ impl CommunicationServerNetworkTopology {
     /// Finds the shortest path (min cost) between two nodes using Dijkstra's Algorithm.
     ///
     /// This function considers the "cost" of each node when finding the best path.
+    /// Costs are accumulated as saturating `u64`, so expensive long paths can't overflow, and a
+    /// node is unreachable only if it has no recorded distance.
+    /// Nodes with the same cost are visited and their neighbors explored in id order, so among
+    /// routes with the same cost the one through the lowest ids is always chosen.
     ///
     /// # Arguments
     /// * `from` - The starting node.
@@ -246,7 +311,16 @@ impl CommunicationServerNetworkTopology {
     /// # Returns
     /// * `Vec<NodeId>` - The optimal path with the lowest cost, or an empty vector if no path exists.
     fn dijkstra(&mut self, from: NodeId, to: NodeId) -> Vec<NodeId> {
-        let mut distances: HashMap<NodeId, u32> = HashMap::new();
+        let route = self.shortest_path(from, to);
+        if !route.is_empty() {
+            self.save_path(to, route.clone());
+        }
+        route
+    }
+
+    /// Same as `dijkstra`, but the path found is not saved.
+    fn shortest_path(&self, from: NodeId, to: NodeId) -> Vec<NodeId> {
+        let mut distances: HashMap<NodeId, u64> = HashMap::new();
         let mut parent_map: HashMap<NodeId, NodeId> = HashMap::new();
         let mut priority_queue = BinaryHeap::new();
 
@@ -267,12 +341,13 @@ impl CommunicationServerNetworkTopology {
                 }
                 route.push(from);
                 route.reverse();
-                self.save_path(to, route.clone());
                 return route;
             }
 
             // Explore neighbors
-            for neighbor in self.graph.neighbors(node) {
+            let mut neighbors = self.graph.neighbors(node).collect::<Vec<_>>();
+            neighbors.sort_unstable();
+            for neighbor in neighbors {
                 if neighbor != to && neighbor != from {
                     if let Some(node_type) = self.node_types.get(&neighbor) {
                         if *node_type != NodeType::Drone {
@@ -281,10 +356,14 @@ impl CommunicationServerNetworkTopology {
                     }
                 }
 
-                let node_cost = *self.node_costs.get(&neighbor).unwrap_or(&1);
-                let new_cost = cost + node_cost;
+                let node_cost = u64::from(*self.node_costs.get(&neighbor).unwrap_or(&1));
+                let edge_cost = u64::from(self.get_edge_cost(node, neighbor).unwrap_or(0));
+                let new_cost = cost.saturating_add(node_cost).saturating_add(edge_cost);
 
-                if new_cost < *distances.get(&neighbor).unwrap_or(&u32::MAX) {
+                if !distances
+                    .get(&neighbor)
+                    .is_some_and(|&distance| distance <= new_cost)
+                {
                     distances.insert(neighbor, new_cost);
                     parent_map.insert(neighbor, node);
                     priority_queue.push(State {
@@ -298,6 +377,54 @@ impl CommunicationServerNetworkTopology {
         vec![] // No path found
     }
 
+    /// Computes the cost of the route currently chosen from one node to another.
+    ///
+    /// The saved path to `to` is used if available, otherwise the one that `source_routing` would
+    /// compute. Saved paths are never modified.
+    ///
+    /// # Arguments
+    /// * `from` - The ID of the source node.
+    /// * `to` - The ID of the target node.
+    ///
+    /// # Returns
+    /// * `Option<u32>` - The sum of the costs of the nodes between `from` and `to` and of the
+    ///   links of the route (saturating), or `None` if there is no route.
+    pub fn route_cost(&self, from: NodeId, to: NodeId) -> Option<u32> {
+        let route = match self.saved_paths.get(&to) {
+            Some(path) if path.first() == Some(&from) => path.clone(),
+            _ => self.shortest_path(from, to),
+        };
+        if route.len() < 2 {
+            return None;
+        }
+
+        let node_cost = route[1..route.len() - 1].iter().fold(0u32, |cost, node| {
+            cost.saturating_add(self.get_node_cost(*node).unwrap_or(1))
+        });
+        Some(route.windows(2).fold(node_cost, |cost, link| {
+            cost.saturating_add(self.get_edge_cost(link[0], link[1]).unwrap_or(0))
+        }))
+    }
+
+    /// Returns the clients known to the topology that can be reached from a node.
+    ///
+    /// # Arguments
+    /// * `from` - The ID of the source node.
+    ///
+    /// # Returns
+    /// * `Vec<NodeId>` - The reachable clients, sorted by id.
+    pub fn reachable_clients(&self, from: NodeId) -> Vec<NodeId> {
+        let mut clients: Vec<NodeId> = self
+            .node_types
+            .iter()
+            .filter(|&(&node_id, node_type)| *node_type == NodeType::Client && node_id != from)
+            .map(|(&node_id, _)| node_id)
+            .filter(|&node_id| self.route_cost(from, node_id).is_some())
+            .collect();
+        clients.sort_unstable();
+        clients
+    }
+
     #[cfg(test)]
     pub fn contains_node(&self, node_id: NodeId) -> bool {
         self.graph.contains_node(node_id)
@@ -323,14 +450,17 @@ impl Default for CommunicationServerNetworkTopology {
 /// A struct used for ordering nodes in the priority queue for Dijkstra.
 #[derive(Copy, Clone, Eq, PartialEq)]
 struct State {
-    cost: u32,
+    cost: u64,
     node: NodeId,
 }
 
-// Implement ordering so BinaryHeap acts as a min-heap
+// Implement ordering so BinaryHeap acts as a min-heap, ties are broken by the lowest id
 impl Ord for State {
     fn cmp(&self, other: &Self) -> Ordering {
-        other.cost.cmp(&self.cost)
+        other
+            .cost
+            .cmp(&self.cost)
+            .then_with(|| other.node.cmp(&self.node))
     }
 }
 
@@ -508,6 +638,119 @@ mod tests {
         assert_eq!(route[2], 4);
     }
 
+    #[test]
+    fn test_dijkstra_equal_costs() {
+        // the same topology, with the edges added in different orders
+        let topology = |edges: &[(NodeId, NodeId)]| {
+            let mut topology = CommunicationServerNetworkTopology::new();
+            topology.add_node(1, NodeType::Server);
+            topology.add_node(2, NodeType::Drone);
+            topology.add_node(3, NodeType::Drone);
+            topology.add_node(5, NodeType::Drone);
+            topology.add_node(4, NodeType::Client);
+            for &(a, b) in edges {
+                topology.add_edge(a, b);
+            }
+            topology
+        };
+
+        for edges in [
+            [(1, 5), (5, 4), (1, 3), (3, 4), (1, 2), (2, 4)],
+            [(2, 4), (1, 2), (3, 4), (1, 3), (5, 4), (1, 5)],
+        ] {
+            for _ in 0..10 {
+                assert_eq!(topology(&edges).dijkstra(1, 4), vec![1, 2, 4]);
+            }
+        }
+    }
+
+    #[test]
+    fn test_route_cost() {
+        let mut topology = CommunicationServerNetworkTopology::new();
+        topology.add_node(1, NodeType::Server);
+        topology.add_node(2, NodeType::Drone);
+        topology.add_node(3, NodeType::Drone);
+        topology.add_node(4, NodeType::Client);
+        topology.add_node(6, NodeType::Client);
+        topology.add_edge(1, 2);
+        topology.add_edge(2, 3);
+        topology.add_edge(3, 4);
+        topology.update_node_cost(2, 7);
+        topology.update_node_cost(3, 20);
+
+        assert_eq!(topology.route_cost(1, 4), Some(27));
+        // the cost is computed without saving the path
+        assert!(topology.get_saved_path(4).is_empty());
+        assert_eq!(topology.route_cost(1, 6), None);
+
+        // the saved path is evaluated with the current node costs
+        topology.source_routing(1, 4);
+        topology.update_node_cost(3, 50);
+        assert_eq!(topology.get_saved_path(4), vec![1, 2, 3, 4]);
+        assert_eq!(topology.route_cost(1, 4), Some(57));
+
+        topology.update_edge_cost(3, 2, 5);
+        assert_eq!(topology.route_cost(1, 4), Some(62));
+    }
+
+    #[test]
+    fn test_dijkstra_edge_cost() {
+        let mut topology = CommunicationServerNetworkTopology::new();
+        topology.add_node(1, NodeType::Server);
+        topology.add_node(2, NodeType::Drone);
+        topology.add_node(3, NodeType::Drone);
+        topology.add_node(4, NodeType::Client);
+        topology.add_edge(1, 2);
+        topology.add_edge(1, 3);
+        topology.add_edge(2, 4);
+        topology.add_edge(3, 4);
+
+        topology.update_edge_cost(2, 1, 10);
+        assert_eq!(topology.get_edge_cost(1, 2), Some(10));
+        assert_eq!(topology.get_edge_cost(1, 3), None);
+        assert_eq!(topology.dijkstra(1, 4), vec![1, 3, 4]);
+    }
+
+    #[test]
+    fn test_dijkstra_long_expensive_path() {
+        let mut topology = CommunicationServerNetworkTopology::new();
+        topology.add_node(1, NodeType::Server);
+        topology.add_node(4, NodeType::Client);
+
+        // 1 - 10 - 11 - ... - 19 - 4, the total cost exceeds u32::MAX
+        let drones = (10..20).collect::<Vec<NodeId>>();
+        let mut previous = 1;
+        for &drone in &drones {
+            topology.add_node(drone, NodeType::Drone);
+            topology.add_edge(previous, drone);
+            topology.update_node_cost(drone, u32::MAX / 4);
+            previous = drone;
+        }
+        topology.add_edge(previous, 4);
+
+        let route = topology.dijkstra(1, 4);
+        let mut expected = vec![1];
+        expected.extend(drones);
+        expected.push(4);
+        assert_eq!(route, expected);
+    }
+
+    #[test]
+    fn test_update_pdr_bounds() {
+        let mut t = CommunicationServerNetworkTopology::new();
+        t.set_node_cost_bounds(10, 60);
+
+        for _ in 0..50 {
+            t.update_estimated_pdr(5, true);
+        }
+        assert_eq!(t.get_node_cost(5), Some(60));
+
+        for _ in 0..50 {
+            t.update_estimated_pdr(5, false);
+        }
+        assert_eq!(t.get_node_cost(5), Some(10));
+    }
+
     #[test]
     fn test_update_pdr() {
         let mut t = CommunicationServerNetworkTopology::new();
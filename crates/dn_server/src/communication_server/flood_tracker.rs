@@ -0,0 +1,198 @@
+//! # Flood tracker
+//!
+//! This module provides a support structure for the `CommunicationServer` to remember the floods
+//! it has recently seen.
+//!
+//! ## Overview
+//! Every flood is identified by its `(flood_id, initiator)` pair and remembered together with the
+//! time it was started and the path traces of the responses received for it. A response whose
+//! path trace has already been received is a duplicate, so it is not processed again.
+//! Floods started by the server that haven't received any response yet are in progress, and
+//! once they are older than a timeout they are reported as stale.
+//! Only the latest `capacity` floods are remembered, the oldest ones are forgotten.
+
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashSet, VecDeque};
+use std::hash::{Hash, Hasher};
+use std::time::{Duration, Instant};
+use wg_2024::network::NodeId;
+use wg_2024::packet::FloodResponse;
+
+/// Default number of floods remembered by the server.
+pub const DEFAULT_FLOOD_HISTORY: usize = 16;
+
+/// Number of floods started by the server, and of the ones that received at least one response.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct FloodStats {
+    pub initiated: u64,
+    pub completed: u64,
+}
+
+struct TrackedFlood {
+    flood_id: u64,
+    initiator: NodeId,
+    started: Instant,
+    initiated_here: bool,
+    responses: HashSet<u64>, // hashes of the path traces received
+}
+
+pub struct FloodTracker {
+    floods: VecDeque<TrackedFlood>,
+    capacity: usize,
+    stats: FloodStats,
+}
+
+impl FloodTracker {
+    /// Creates a new tracker without any flood.
+    ///
+    /// # Arguments
+    /// * `capacity` - The maximum number of floods remembered.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            floods: VecDeque::new(),
+            capacity,
+            stats: FloodStats::default(),
+        }
+    }
+
+    /// Remembers a flood started by the server.
+    ///
+    /// # Arguments
+    /// * `flood_id` - The id of the flood.
+    /// * `initiator` - The id of the server.
+    /// * `now` - The current instant.
+    pub fn start(&mut self, flood_id: u64, initiator: NodeId, now: Instant) {
+        self.stats.initiated += 1;
+        self.track(TrackedFlood {
+            flood_id,
+            initiator,
+            started: now,
+            initiated_here: true,
+            responses: HashSet::new(),
+        });
+    }
+
+    /// Checks whether a flood response has already been received, and remembers it otherwise.
+    ///
+    /// # Arguments
+    /// * `response` - The flood response received.
+    /// * `now` - The current instant, used if the flood is not known yet.
+    ///
+    /// # Returns
+    /// `true` if the response is a duplicate and must not be processed, `false` otherwise.
+    pub fn is_duplicate(&mut self, response: &FloodResponse, now: Instant) -> bool {
+        let Some(&(initiator, _)) = response.path_trace.first() else {
+            return false;
+        };
+
+        let mut hasher = DefaultHasher::new();
+        for (node_id, _) in &response.path_trace {
+            node_id.hash(&mut hasher);
+        }
+        let path_hash = hasher.finish();
+
+        let position = self
+            .floods
+            .iter()
+            .position(|flood| flood.flood_id == response.flood_id && flood.initiator == initiator);
+        let flood = if let Some(position) = position {
+            &mut self.floods[position]
+        } else {
+            self.track(TrackedFlood {
+                flood_id: response.flood_id,
+                initiator,
+                started: now,
+                initiated_here: false,
+                responses: HashSet::new(),
+            });
+            self.floods.back_mut().unwrap()
+        };
+
+        if flood.initiated_here && flood.responses.is_empty() {
+            self.stats.completed += 1;
+        }
+        !flood.responses.insert(path_hash)
+    }
+
+    /// Returns the floods started by the server that haven't received any response within `timeout`.
+    ///
+    /// # Arguments
+    /// * `now` - The current instant.
+    /// * `timeout` - The time after which a flood without responses is stale.
+    ///
+    /// # Returns
+    /// The ids of the stale floods, from the oldest.
+    pub fn stale_floods(&self, now: Instant, timeout: Duration) -> Vec<u64> {
+        self.floods
+            .iter()
+            .filter(|flood| {
+                flood.initiated_here
+                    && flood.responses.is_empty()
+                    && now.duration_since(flood.started) >= timeout
+            })
+            .map(|flood| flood.flood_id)
+            .collect()
+    }
+
+    /// Returns the number of floods started and completed since the server was created.
+    pub fn stats(&self) -> FloodStats {
+        self.stats
+    }
+
+    fn track(&mut self, flood: TrackedFlood) {
+        self.floods.push_back(flood);
+        while self.floods.len() > self.capacity {
+            self.floods.pop_front();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wg_2024::packet::NodeType;
+
+    fn response(flood_id: u64, drone: NodeId) -> FloodResponse {
+        FloodResponse {
+            flood_id,
+            path_trace: vec![(1, NodeType::Server), (drone, NodeType::Drone)],
+        }
+    }
+
+    #[test]
+    fn test_flood_tracker() {
+        let mut tracker = FloodTracker::new(DEFAULT_FLOOD_HISTORY);
+        let now = Instant::now();
+        let timeout = Duration::from_secs(1);
+
+        tracker.start(0, 1, now);
+        tracker.start(1, 1, now);
+        assert_eq!(tracker.stale_floods(now, timeout), Vec::<u64>::new());
+        assert_eq!(tracker.stale_floods(now + timeout, timeout), vec![0, 1]);
+
+        assert!(!tracker.is_duplicate(&response(0, 2), now));
+        assert!(!tracker.is_duplicate(&response(0, 3), now));
+        assert!(tracker.is_duplicate(&response(0, 2), now));
+        assert_eq!(tracker.stale_floods(now + timeout, timeout), vec![1]);
+        assert_eq!(
+            tracker.stats(),
+            FloodStats {
+                initiated: 2,
+                completed: 1
+            }
+        );
+    }
+
+    #[test]
+    fn test_flood_tracker_capacity() {
+        let mut tracker = FloodTracker::new(2);
+        let now = Instant::now();
+
+        assert!(!tracker.is_duplicate(&response(0, 2), now));
+        tracker.start(1, 1, now);
+        tracker.start(2, 1, now);
+
+        // the first flood has been forgotten
+        assert!(!tracker.is_duplicate(&response(0, 2), now));
+    }
+}
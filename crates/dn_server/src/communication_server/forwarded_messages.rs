@@ -0,0 +1,87 @@
+//! # Forwarded messages
+//!
+//! This module provides a support structure for the `CommunicationServer` to avoid delivering
+//! the same chat message twice.
+//!
+//! ## Overview
+//! If the ACK of a `MessageSend` gets lost, the sender retransmits it and the server would
+//! forward it again. Every forwarded message is remembered as a `(from, to, hash)` tuple for a
+//! time window, and identical messages received within the window are suppressed.
+
+use dn_message::CommunicationMessage;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::time::{Duration, Instant};
+use wg_2024::network::NodeId;
+
+/// Default time a forwarded message is remembered for.
+pub const DEFAULT_DEDUP_WINDOW: Duration = Duration::from_secs(5);
+
+pub struct ForwardedMessages {
+    forwarded: HashMap<(NodeId, NodeId, u64), Instant>, // (from, to, hash) -> forwarding time
+    window: Duration,
+}
+
+impl ForwardedMessages {
+    /// Creates a new empty set of forwarded messages.
+    ///
+    /// # Arguments
+    /// * `window` - The time a forwarded message is remembered for.
+    pub fn new(window: Duration) -> Self {
+        Self {
+            forwarded: HashMap::new(),
+            window,
+        }
+    }
+
+    /// Checks whether a message has already been forwarded within the window, and remembers it
+    /// otherwise. Messages older than the window are forgotten.
+    ///
+    /// # Arguments
+    /// * `message` - The message to be forwarded.
+    /// * `now` - The current instant.
+    ///
+    /// # Returns
+    /// `true` if the message is a duplicate and must not be forwarded, `false` otherwise.
+    pub fn is_duplicate(&mut self, message: &CommunicationMessage, now: Instant) -> bool {
+        self.forwarded
+            .retain(|_, forwarded_at| now.duration_since(*forwarded_at) < self.window);
+
+        let mut hasher = DefaultHasher::new();
+        message.message.hash(&mut hasher);
+        let key = (message.from, message.to, hasher.finish());
+
+        if self.forwarded.contains_key(&key) {
+            return true;
+        }
+        self.forwarded.insert(key, now);
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(text: &str) -> CommunicationMessage {
+        CommunicationMessage {
+            from: 5,
+            to: 6,
+            message: text.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_is_duplicate() {
+        let mut forwarded = ForwardedMessages::new(DEFAULT_DEDUP_WINDOW);
+        let now = Instant::now();
+
+        assert!(!forwarded.is_duplicate(&message("hi"), now));
+        assert!(forwarded.is_duplicate(&message("hi"), now));
+        assert!(!forwarded.is_duplicate(&message("bye"), now));
+
+        // the message is forgotten once the window has passed
+        assert!(!forwarded.is_duplicate(&message("hi"), now + DEFAULT_DEDUP_WINDOW));
+    }
+}
@@ -2,19 +2,26 @@
 //! communication server's configuration.
 
 use crate::communication_server::communication_server::CommunicationServer;
-use dn_controller::ServerCommand;
+use dn_controller::{ServerCommand, ServerEvent};
+use dn_message::Message;
 
 impl CommunicationServer {
     /// Handles incoming commands to modify the server's neighbors.
     ///
     /// This function processes commands sent to the server, allowing the addition or removal
-    /// of packet senders and the server to be stopped from running.
+    /// of packet senders, the inspection of the topology and the server to be stopped from running.
     /// When a sender is added or removed, the network topology is updated to reflect the changes.
     ///
     /// # Arguments
     /// * `command` - The command to be processed. It can be one of the following:
     ///   - `AddSender(node_id, sender)` to add a new sender to the server.
     ///   - `RemoveSender(node_id)` to remove an existing sender from the server.
+    ///   - `QueryTopology(sender)` to reply with the edges of the server's view of the topology.
+    ///   - `PushMessage(body, to)` to send an unsolicited message to a registered client.
+    ///   - `SendMessage(message, to)` to send a message to any node, as if the server originated it.
+    ///   - `QueryRoster(sender)` to reply with the clients registered to the server.
+    ///   - `QuerySessions` to notify the controller of the sessions and messages the server is holding.
+    ///   - `Deregister(client_id)` to drop the registration of a client.
     ///   - `Return` to stop the server's execution.
     pub(crate) fn handle_command(&mut self, command: ServerCommand) {
         match command {
@@ -26,9 +33,106 @@ impl CommunicationServer {
                 self.packet_send.remove(&node_id);
                 self.network_topology.remove_node(node_id);
             }
+            ServerCommand::QueryTopology(sender) => {
+                _ = sender.send(self.network_topology.edges());
+            }
+            ServerCommand::PushMessage(body, to) => {
+                self.push(to, Message::Server(body));
+            }
+            ServerCommand::SendMessage(message, to) => {
+                self.send_message(message, to);
+            }
+            ServerCommand::QueryRoster(sender) => {
+                _ = sender.send(Some(self.sorted_registered_clients()));
+            }
+            ServerCommand::QuerySessions => {
+                self.controller_send
+                    .send(ServerEvent::Sessions {
+                        pending: self.session_manager.pending_session_ids().len(),
+                        waiting_fragments: self.session_manager.waiting_fragments_counts(),
+                        pending_messages: self.pending_messages_queue.pending_messages_counts(),
+                    })
+                    .expect("Error in controller_send");
+            }
+            ServerCommand::Deregister(client_id) => {
+                self.deregister_client(client_id);
+            }
             ServerCommand::Return => {
                 self.running = false;
             }
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::communication_server::test_server_helper::TestServerHelper;
+    use dn_message::{
+        ClientBody, ClientCommunicationBody, CommunicationMessage, ServerBody,
+        ServerCommunicationBody,
+    };
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_deregister() {
+        let mut test_server_helper = TestServerHelper::new();
+        test_server_helper.server.registered_clients.insert(5);
+        test_server_helper.register_client_6();
+
+        test_server_helper
+            .server
+            .handle_command(ServerCommand::Deregister(5));
+        assert_eq!(
+            test_server_helper.server.sorted_registered_clients(),
+            vec![6]
+        );
+
+        let message = Message::Client(ClientBody::ClientCommunication(
+            ClientCommunicationBody::MessageSend(CommunicationMessage {
+                from: 5,
+                to: 6,
+                message: "hi".to_string(),
+            }),
+        ));
+        let response = test_server_helper.send_message_and_get_response(message, vec![5, 1], 5);
+        assert!(matches!(
+            response,
+            Message::Server(ServerBody::ServerCommunication(
+                ServerCommunicationBody::ErrNotRegistered
+            ))
+        ));
+    }
+
+    #[test]
+    fn test_query_sessions() {
+        let mut test_server_helper = TestServerHelper::new();
+        let server = &mut test_server_helper.server;
+        server
+            .session_manager
+            .add_session(4, vec![TestServerHelper::test_fragment(0, 1)], 6);
+        server.session_manager.add_to_waiting_fragments(8, 0, 5);
+        server
+            .pending_messages_queue
+            .add_message(8, Message::Client(ClientBody::ReqServerType));
+
+        server.handle_command(ServerCommand::QuerySessions);
+
+        let event = test_server_helper
+            ._event_recv_from_server
+            .try_iter()
+            .find(|event| matches!(event, ServerEvent::Sessions { .. }));
+        if let Some(ServerEvent::Sessions {
+            pending,
+            waiting_fragments,
+            pending_messages,
+        }) = event
+        {
+            assert_eq!(pending, 1);
+            assert_eq!(waiting_fragments, HashMap::from([(8, 1)]));
+            assert_eq!(pending_messages, HashMap::from([(8, 1)]));
+        } else {
+            panic!("expected the sessions event");
+        }
+    }
+}
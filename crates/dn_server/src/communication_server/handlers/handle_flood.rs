@@ -5,8 +5,11 @@
 //! network topology for routing and communication purposes.
 
 use crate::communication_server::communication_server::CommunicationServer;
-use dn_controller::ServerEvent;
-use wg_2024::network::SourceRoutingHeader;
+use crossbeam_channel::{after, never, Receiver};
+use dn_controller::{DiscoveryMode, ServerEvent};
+use rand::Rng;
+use std::time::{Duration, Instant};
+use wg_2024::network::{NodeId, SourceRoutingHeader};
 use wg_2024::packet::{FloodRequest, FloodResponse, NodeType, Packet, PacketType};
 
 impl CommunicationServer {
@@ -73,9 +76,24 @@ impl CommunicationServer {
     /// If any newly discovered nodes have pending messages waiting to be sent, this function
     /// attempts to send them. The same happens for waiting fragments in the session manager.
     ///
+    /// Responses whose path trace doesn't contain this server are ignored: they can't describe a
+    /// route reachable from here, so they are not ingested in the topology.
+    /// Responses already received for the same flood are ignored as well.
+    ///
     /// # Arguments
     /// * `response` - The flood response to process.
     pub(crate) fn handle_flood_response(&mut self, response: &FloodResponse) {
+        if !response
+            .path_trace
+            .iter()
+            .any(|&(node_id, _)| node_id == self.id)
+        {
+            return;
+        }
+        if self.flood_tracker.is_duplicate(response, Instant::now()) {
+            return;
+        }
+
         for &(node_id, node_type) in &response.path_trace {
             self.network_topology.add_node(node_id, node_type);
         }
@@ -105,17 +123,72 @@ impl CommunicationServer {
         }
     }
 
+    /// Starts the first discovery of the network according to the `DiscoveryMode`.
+    ///
+    /// With `DiscoveryMode::Immediate` the flood request is sent right away. Otherwise the
+    /// discovery is left pending until the returned timer fires or the first message is sent,
+    /// whichever comes first.
+    ///
+    /// # Returns
+    /// The timer of the jittered discovery, a timer that never fires for the other modes.
+    pub(crate) fn start_discovery(&mut self) -> Receiver<Instant> {
+        match self.discovery_mode {
+            DiscoveryMode::Immediate => {
+                self.update_network_topology();
+                never()
+            }
+            DiscoveryMode::Jittered(max_delay) => {
+                self.discovery_pending = true;
+                let delay = match &self.discovery_jitter {
+                    Some(jitter) => jitter(max_delay),
+                    None => rand::rng().random_range(Duration::ZERO..=max_delay),
+                };
+                after(delay)
+            }
+            DiscoveryMode::Lazy => {
+                self.discovery_pending = true;
+                never()
+            }
+        }
+    }
+
+    /// Floods the network to update the server network topology, honoring the `min_flood_interval`.
+    ///
+    /// If the last flood started less than `min_flood_interval` ago, the flood is deferred until the
+    /// interval ends, and any other flood requested in the meantime is coalesced with it.
+    pub(crate) fn update_network_topology(&mut self) {
+        if let (Some(min_flood_interval), Some(last_flood)) =
+            (self.min_flood_interval, self.last_flood)
+        {
+            let next_flood = last_flood + min_flood_interval;
+            if Instant::now() < next_flood {
+                self.deferred_flood.get_or_insert(next_flood);
+                return;
+            }
+        }
+        self.flood_network();
+    }
+
+    /// Sends the flood deferred by `update_network_topology`, if any.
+    pub(crate) fn send_deferred_flood(&mut self) {
+        if self.deferred_flood.is_some() {
+            self.flood_network();
+        }
+    }
+
     /// Sends a flood request to update the server network topology.
     ///
     /// This function generates a flood request to start the process of updating the network
     /// topology. It includes a unique flood ID and the current server's ID in the path trace.
-    /// The request is then sent to all connected nodes to propagate the updated topology.
-    /// Additionally, the controller is notified about the packet being sent.
-    pub(crate) fn update_network_topology(&mut self) {
+    /// The request is then sent to all connected nodes, in ascending id order, to propagate the
+    /// updated topology. Additionally, the controller is notified about the packet being sent.
+    fn flood_network(&mut self) {
         // Univocal flood id
         let flood_id = self.flood_id_counter;
         self.flood_id_counter += 1;
 
+        self.flood_tracker.start(flood_id, self.id, Instant::now());
+
         let flood_request = FloodRequest {
             flood_id,
             initiator_id: self.id,
@@ -132,8 +205,8 @@ impl CommunicationServer {
             session_id,
         };
 
-        for sender in self.packet_send.values() {
-            sender
+        for node_id in self.sorted_neighbors() {
+            self.packet_send[&node_id]
                 .send(flood_request_packet.clone())
                 .expect("Error in send");
 
@@ -143,6 +216,17 @@ impl CommunicationServer {
         }
 
         self.session_manager.already_dropped_clear();
+        self.discovery_pending = false;
+        self.last_flood = Some(Instant::now());
+        self.deferred_flood = None;
+    }
+
+    /// Returns the ids of the server's neighbors in ascending order, so that floods are always
+    /// propagated in the same order.
+    pub(crate) fn sorted_neighbors(&self) -> Vec<NodeId> {
+        let mut neighbors: Vec<NodeId> = self.packet_send.keys().copied().collect();
+        neighbors.sort_unstable();
+        neighbors
     }
 }
 
@@ -150,7 +234,8 @@ impl CommunicationServer {
 mod tests {
     use super::*;
     use crate::communication_server::test_server_helper::TestServerHelper;
-    use crossbeam_channel::unbounded;
+    use crate::communication_server::FloodStats;
+    use crossbeam_channel::{bounded, unbounded};
     use dn_message::Message;
     use dn_message::ServerBody::ErrUnsupportedRequestType;
     use std::collections::HashMap;
@@ -253,6 +338,63 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_handle_flood_response_duplicate() {
+        let helper = TestServerHelper::new();
+        let mut server = helper.server;
+        server.update_network_topology();
+
+        let flood_response = FloodResponse {
+            flood_id: 0,
+            path_trace: vec![
+                (1, NodeType::Server),
+                (2, NodeType::Drone),
+                (25, NodeType::Drone),
+            ],
+        };
+
+        server.handle_flood_response(&flood_response);
+        assert!(server.network_topology.contains_edge(2, 25));
+
+        // the same response isn't ingested again
+        server.network_topology.remove_node(25);
+        server.handle_flood_response(&flood_response);
+        assert!(!server.network_topology.contains_node(25));
+        assert_eq!(
+            server.flood_stats(),
+            FloodStats {
+                initiated: 1,
+                completed: 1
+            }
+        );
+    }
+
+    #[test]
+    fn test_handle_flood_response_unrelated_trace() {
+        let helper = TestServerHelper::new();
+        let mut server = helper.server;
+
+        let flood_response = FloodResponse {
+            flood_id: 1,
+            path_trace: vec![
+                (30, NodeType::Client),
+                (2, NodeType::Drone),
+                (31, NodeType::Drone),
+                (32, NodeType::Server),
+            ],
+        };
+
+        server.handle_flood_response(&flood_response);
+
+        assert!(!server.network_topology.contains_node(30));
+        assert!(!server.network_topology.contains_node(31));
+        assert!(!server.network_topology.contains_node(32));
+        assert!(!server.network_topology.contains_edge(2, 31));
+        assert!(!server.network_topology.contains_type(&30));
+        assert!(!server.network_topology.contains_type(&31));
+        assert!(!server.network_topology.contains_type(&32));
+    }
+
     #[test]
     fn test_handle_flood_response_pending_messages_recovery() {
         let helper = TestServerHelper::new();
@@ -366,4 +508,29 @@ mod tests {
             }
         }
     }
+
+    #[test]
+    fn test_update_network_topology_order() {
+        let helper = TestServerHelper::new();
+        let mut server = helper.server;
+        // rendezvous channels: each send blocks until it's received,
+        // so the flood only completes if it's received in the order it's sent
+        let mut packet_recv = HashMap::new();
+        for id in [2, 3, 4, 5] {
+            let (packet_send, recv) = bounded(0);
+            server.packet_send.insert(id, packet_send);
+            packet_recv.insert(id, recv);
+        }
+
+        assert_eq!(server.sorted_neighbors(), vec![2, 3, 4, 5]);
+
+        let handle = std::thread::spawn(move || server.update_network_topology());
+        for id in [2, 3, 4, 5] {
+            let packet = packet_recv[&id]
+                .recv_timeout(Duration::from_secs(1))
+                .expect("flood request not sent in ascending id order");
+            assert!(matches!(packet.pack_type, PacketType::FloodRequest(_)));
+        }
+        handle.join().unwrap();
+    }
 }
@@ -4,20 +4,21 @@
 //! messages based on their type, and sending acknowledgments and full messages to clients or servers.
 //! Additionally, it handles the sending of fragmented messages using source routing.
 
-use crate::communication_server::communication_server::CommunicationServer;
+use crate::communication_server::communication_server::{
+    CommunicationServer, REJECTED_SESSIONS_HISTORY,
+};
 use crate::communication_server::session_manager::SessionId;
 use dn_controller::ServerEvent;
-use dn_message::Message;
+use dn_message::assembler::AckMode;
+use dn_message::{Message, ServerBody, ServerCommunicationBody};
 use wg_2024::network::{NodeId, SourceRoutingHeader};
-use wg_2024::packet::{Ack, Fragment, Packet, PacketType};
+use wg_2024::packet::{Ack, Fragment, Packet, PacketType, FRAGMENT_DSIZE};
 
 impl CommunicationServer {
     /// Processes a message fragment and handles its acknowledgment.
     ///
-    /// This function processes an incoming message fragment by attempting to assemble it into a
-    /// complete message. If the message is successfully assembled, it delegates the message
-    /// handling to the appropriate method. Regardless of the assembly result, it sends an
-    /// acknowledgment for the processed fragment.
+    /// Regardless of the assembly result, it sends an acknowledgment for the processed fragment,
+    /// before processing it or after, depending on the `AckMode` of the server.
     ///
     /// # Arguments
     /// * `f` - The fragment of the message to process.
@@ -31,9 +32,83 @@ impl CommunicationServer {
         session_id: SessionId,
         arrived_packet_path: &[NodeId],
     ) {
-        self.send_ack(f.fragment_index, session_id, arrived_packet_path);
+        if self.ack_mode == AckMode::OnReceipt {
+            self.send_ack(f.fragment_index, session_id, arrived_packet_path);
+        }
+        self.process_fragment(f, sender_id, session_id);
+        if self.ack_mode == AckMode::OnProcessed {
+            self.send_ack(f.fragment_index, session_id, arrived_packet_path);
+        }
+    }
+
+    /// Attempts to assemble a fragment into a complete message.
+    ///
+    /// If the message is successfully assembled, it delegates the message handling to the
+    /// appropriate method, otherwise the simulation controller is notified about the reassembly
+    /// progress. If message acknowledgments are enabled, the whole messages of the clients are
+    /// acknowledged as well.
+    /// If the message declares more fragments than the maximum message size allows, the session is
+    /// dropped and the simulation controller is notified instead, once per session: the following
+    /// fragments of a rejected session are discarded.
+    /// The simulation controller is notified about the messages discarded because they are corrupted.
+    ///
+    /// # Arguments
+    /// * `f` - The fragment of the message to process.
+    /// * `sender_id` - The ID of the sender of the fragment.
+    /// * `session_id` - The session ID associated with the message.
+    fn process_fragment(&mut self, f: &Fragment, sender_id: NodeId, session_id: SessionId) {
+        if self.rejected_sessions.contains(&(sender_id, session_id)) {
+            return;
+        }
+        let size = (f.total_n_fragments as usize).saturating_mul(FRAGMENT_DSIZE);
+        if self
+            .max_message_size
+            .is_some_and(|max_message_size| size > max_message_size)
+        {
+            self.assembler.discard(sender_id, session_id);
+            if self.rejected_sessions.len() >= REJECTED_SESSIONS_HISTORY {
+                self.rejected_sessions.pop_front();
+            }
+            self.rejected_sessions.push_back((sender_id, session_id));
+            self.controller_send
+                .send(ServerEvent::MessageTooLarge {
+                    from: sender_id,
+                    to: self.id,
+                    session_id,
+                    size,
+                })
+                .expect("Error in controller_send");
+            return;
+        }
         if let Some(message) = self.assembler.handle_fragment(f, sender_id, session_id) {
+            if self.message_acks && matches!(message, Message::Client(_)) {
+                self.send_message(
+                    Message::Server(ServerBody::ServerCommunication(
+                        ServerCommunicationBody::MessageAck(session_id),
+                    )),
+                    sender_id,
+                );
+            }
             self.handle_message(message, sender_id);
+        } else if let Some((received, total)) = self.assembler.progress(sender_id, session_id) {
+            self.controller_send
+                .send(ServerEvent::MessageReassemblyProgress {
+                    from: sender_id,
+                    to: self.id,
+                    session_id,
+                    received,
+                    total,
+                })
+                .expect("Error in controller_send");
+        }
+        for (from, session_id) in self.assembler.take_corrupted_messages() {
+            self.controller_send
+                .send(ServerEvent::MessageCorrupted {
+                    from,
+                    to: self.id,
+                    session_id,
+                })
+                .expect("Error in controller_send");
         }
     }
 
@@ -41,6 +116,7 @@ impl CommunicationServer {
     ///
     /// This function processes client requests. It ignores messages from other servers.
     /// This function also notifies the simulation controller that a message has been assembled.
+    /// Since the client is active, the messages stored in its mailbox are delivered first.
     ///
     /// # Arguments
     /// * `message` - The message to handle.
@@ -54,10 +130,25 @@ impl CommunicationServer {
                     to: self.id,
                 })
                 .expect("Error in controller_send");
+            self.deliver_mailbox(sender_id);
             self.handler_client_body(client_body, sender_id);
         }
     }
 
+    /// Sends the messages stored in the mailbox of a client, if the mailbox mode is enabled.
+    ///
+    /// # Arguments
+    /// * `client_id` - The ID of the client that is reachable again.
+    fn deliver_mailbox(&mut self, client_id: NodeId) {
+        let messages = self
+            .mailbox
+            .as_mut()
+            .and_then(|mailbox| mailbox.take_messages(client_id));
+        for message in messages.into_iter().flatten() {
+            self.send_message(message, client_id);
+        }
+    }
+
     /// Sends an acknowledgment for a message fragment.
     ///
     /// This function creates an acknowledgment packet for the provided fragment and sends it
@@ -123,6 +214,9 @@ impl CommunicationServer {
             let session_id = self.session_manager.get_and_increment_session_id_counter();
             self.send_fragments(session_id, serialized_message, &routing_header);
         }
+        if self.discovery_pending {
+            self.update_network_topology();
+        }
     }
 
     /// Sends message fragments along a predefined route.
@@ -190,7 +284,8 @@ impl CommunicationServer {
 mod tests {
     use super::*;
     use crate::communication_server::test_server_helper::TestServerHelper;
-    use dn_message::ServerBody;
+    use dn_message::assembler::Assembler;
+    use dn_message::{ClientBody, ClientCommunicationBody, ClientContentBody};
 
     #[test]
     fn test_send_ack() {
@@ -264,4 +359,192 @@ mod tests {
             }
         }
     }
+
+    #[test]
+    fn test_reassembly_progress() {
+        let mut test_server_helper = TestServerHelper::new();
+
+        let session_id = 111;
+        for fragment_index in 0..3 {
+            let fragment = TestServerHelper::test_fragment(fragment_index, 5);
+            test_server_helper
+                .server
+                .handle_fragment(&fragment, 6, session_id, &[6, 3, 1]);
+        }
+
+        let progress = test_server_helper
+            ._event_recv_from_server
+            .try_iter()
+            .filter_map(|event| match event {
+                ServerEvent::MessageReassemblyProgress {
+                    from,
+                    session_id,
+                    received,
+                    total,
+                    ..
+                } => Some((from, session_id, received, total)),
+                _ => None,
+            })
+            .last()
+            .expect("Expected a reassembly progress event");
+        assert_eq!(progress, (6, session_id, 3, 5));
+    }
+
+    #[test]
+    fn test_max_message_size() {
+        let mut test_server_helper = TestServerHelper::new();
+        test_server_helper
+            .server
+            .set_max_message_size(10 * FRAGMENT_DSIZE);
+
+        let session_id = 111;
+        for fragment_index in 0..3 {
+            let fragment = TestServerHelper::test_fragment(fragment_index, 10_000);
+            test_server_helper
+                .server
+                .handle_fragment(&fragment, 6, session_id, &[6, 3, 1]);
+        }
+
+        // every fragment is acknowledged, but none of them is kept and the session is rejected once
+        test_server_helper.wait_for_ack_on_node_x(3, 3);
+        assert_eq!(
+            test_server_helper.server.assembler.progress(6, session_id),
+            None
+        );
+        let rejected = test_server_helper
+            ._event_recv_from_server
+            .try_iter()
+            .filter(|event| {
+                matches!(
+                    event,
+                    ServerEvent::MessageTooLarge {
+                        from: 6,
+                        session_id: 111,
+                        size,
+                        ..
+                    } if *size == 10_000 * FRAGMENT_DSIZE
+                )
+            })
+            .count();
+        assert_eq!(rejected, 1);
+
+        // messages within the limit are still reassembled
+        let fragment = TestServerHelper::test_fragment(0, 10);
+        test_server_helper
+            .server
+            .handle_fragment(&fragment, 6, session_id + 1, &[6, 3, 1]);
+        assert_eq!(
+            test_server_helper
+                .server
+                .assembler
+                .progress(6, session_id + 1),
+            Some((1, 10))
+        );
+    }
+
+    #[test]
+    fn test_corrupted_message() {
+        let mut test_server_helper = TestServerHelper::new();
+        let message = Message::Client(ClientBody::ClientContent(ClientContentBody::ReqFile(
+            "a".repeat(300),
+        )));
+        let mut fragments = test_server_helper.serialize_message(&message);
+        assert!(fragments.len() > 1);
+        fragments[1].data[0] ^= 1;
+        for fragment in &fragments {
+            test_server_helper
+                .server
+                .handle_fragment(fragment, 6, 42, &[6, 3, 1]);
+        }
+
+        let corrupted = test_server_helper
+            ._event_recv_from_server
+            .try_iter()
+            .filter(|event| {
+                matches!(
+                    event,
+                    ServerEvent::MessageCorrupted {
+                        from: 6,
+                        to: 1,
+                        session_id: 42,
+                    }
+                )
+            })
+            .count();
+        assert_eq!(corrupted, 1);
+        assert!(test_server_helper
+            .server
+            .assembler
+            .take_corrupted_messages()
+            .is_empty());
+    }
+
+    #[test]
+    fn test_ack_mode() {
+        let packets_after_ping = |ack_mode| {
+            let mut test_server_helper = TestServerHelper::new();
+            test_server_helper.server.set_ack_mode(ack_mode);
+
+            let message = Message::Client(ClientBody::ClientCommunication(
+                ClientCommunicationBody::Ping(7),
+            ));
+            let fragments = test_server_helper.serialize_message(&message);
+            assert_eq!(fragments.len(), 1);
+            test_server_helper
+                .server
+                .handle_fragment(&fragments[0], 6, 42, &[6, 3, 1]);
+
+            test_server_helper
+                .packet_recv_3
+                .try_iter()
+                .map(|packet| matches!(packet.pack_type, PacketType::Ack(_)))
+                .collect::<Vec<_>>()
+        };
+
+        // the pong is sent while the ping is handled
+        assert_eq!(packets_after_ping(AckMode::OnReceipt), vec![true, false]);
+        assert_eq!(packets_after_ping(AckMode::OnProcessed), vec![false, true]);
+    }
+
+    #[test]
+    fn test_message_ack() {
+        let mut test_server_helper = TestServerHelper::new();
+        test_server_helper.server.enable_message_acks();
+
+        let session_id = 42;
+        let message = Message::Client(ClientBody::ClientCommunication(
+            ClientCommunicationBody::ReqRegistrationToChat,
+        ));
+        for fragment in test_server_helper.serialize_message(&message) {
+            test_server_helper
+                .server
+                .handle_fragment(&fragment, 6, session_id, &[6, 3, 1]);
+        }
+
+        // the acks of the fragments are ignored, 6 receives the message ack and the registration
+        let mut assembler = Assembler::new();
+        let messages = test_server_helper
+            .packet_recv_3
+            .try_iter()
+            .filter_map(|packet| match packet.pack_type {
+                PacketType::MsgFragment(fragment) => {
+                    assembler.handle_fragment(&fragment, 1, packet.session_id)
+                }
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(messages.len(), 2);
+        assert_eq!(
+            messages
+                .iter()
+                .filter(|message| matches!(
+                    message,
+                    Message::Server(ServerBody::ServerCommunication(
+                        ServerCommunicationBody::MessageAck(id)
+                    )) if *id == session_id
+                ))
+                .count(),
+            1
+        );
+    }
 }
@@ -12,7 +12,10 @@
 //!                           retransmitting it or re-initiating the routing process.
 
 use crate::communication_server::communication_server::CommunicationServer;
-use crate::communication_server::session_manager::{FragmentIndex, SessionId};
+use crate::communication_server::session_manager::{
+    FragmentIndex, SessionId, MAX_RETRANSMISSIONS,
+};
+use dn_controller::ServerEvent;
 use wg_2024::network::{NodeId, SourceRoutingHeader};
 use wg_2024::packet::{Nack, NackType, NodeType, Packet, PacketType};
 
@@ -57,6 +60,10 @@ impl CommunicationServer {
             NackType::Dropped => {
                 self.network_topology
                     .update_estimated_pdr(source_routing_header.hops[0], true);
+                if self.session_manager.destination(session_id).is_none() {
+                    // late NACK for a session already completed, there is nothing to recover
+                    return;
+                }
 
                 if self
                     .session_manager
@@ -78,8 +85,10 @@ impl CommunicationServer {
     /// Recovers a dropped message fragment after a NACK has been received.
     ///
     /// This function retrieves the destination node ID associated with the given session from the
-    /// session manager (assuming that an entry exists in the pending sessions destination map).
-    /// Then, it removes the saved routing path for that destination from the network topology,
+    /// session manager. NACKs for sessions that aren't pending anymore are ignored.
+    /// If the fragment has already been retransmitted `MAX_RETRANSMISSIONS` times, the whole session
+    /// is abandoned and a `ServerEvent::DeliveryFailed` is sent to the controller.
+    /// Otherwise, it removes the saved routing path for that destination from the network topology,
     /// updates the topology, and finally attempts to recover the dropped fragment by calling
     /// `recover_fragment`.
     ///
@@ -87,20 +96,28 @@ impl CommunicationServer {
     /// * `session_id` - The identifier of the session in which the fragment was dropped.
     /// * `fragment_index` - The index of the fragment that needs to be recovered.
     /// * `send_flood` - True if the caller want to send a flood request to update the topology.
-    ///
-    /// # Panics
-    /// This function will panic if there is no destination associated with the session in the session manager,
-    /// as indicated by the use of `unwrap()` on the result of `get_pending_sessions_destination`.
     fn recover_after_nack(
         &mut self,
         session_id: SessionId,
         fragment_index: FragmentIndex,
         send_flood: bool,
     ) {
-        let dest_id = *self
+        let Some(&dest_id) = self
             .session_manager
             .get_pending_sessions_destination(session_id)
-            .unwrap(); // if a packet was dropped, I'm sure that there is an entry in the HashMap
+        else {
+            return;
+        };
+
+        if self
+            .session_manager
+            .inc_retransmissions(session_id, fragment_index)
+            > MAX_RETRANSMISSIONS
+        {
+            self.fail_session(session_id, fragment_index);
+            return;
+        }
+
         self.network_topology.remove_path(dest_id);
         if send_flood {
             self.update_network_topology();
@@ -115,46 +132,78 @@ impl CommunicationServer {
     /// recipient.
     ///
     /// If the path to the recipient is not known, the fragment index is added to the waiting
-    /// fragments list.
+    /// fragments list. If that evicts an older fragment, the session of the evicted fragment is
+    /// abandoned and a `ServerEvent::DeliveryFailed` is sent to the controller.
+    ///
+    /// If the fragment is not in the session manager anymore, e.g. because of a late NACK for a
+    /// fragment already acknowledged or a session already completed or abandoned, it is ignored.
     ///
     /// ### Arguments:
     /// - `session_id`: The session ID associated with the fragment.
     /// - `fragment_index`: The index of the fragment to recover.
-    ///
-    /// # Panics
-    /// This function panics if the specified fragment does not exist in the session manager.
-    /// This indicates that an invalid session ID or fragment index was provided.
     pub(crate) fn recover_fragment(&mut self, session_id: SessionId, fragment_index: u64) {
-        if let Some((fragment, dest)) = self
+        let Some((fragment, dest)) = self
             .session_manager
             .recover_fragment(session_id, fragment_index)
-        {
-            let hops = self
-                .network_topology
-                .source_routing(self.id, dest)
-                .expect("Error in routing");
-
-            if hops.is_empty() {
-                // I don't know the path to `dest` yet
-                self.session_manager
-                    .add_to_waiting_fragments(dest, fragment_index, session_id);
-            } else {
-                let packet = Packet {
-                    routing_header: SourceRoutingHeader { hop_index: 1, hops },
-                    session_id,
-                    pack_type: PacketType::MsgFragment(fragment),
-                };
-                self.send_packet(packet);
+        else {
+            return;
+        };
+
+        let hops = self
+            .network_topology
+            .source_routing(self.id, dest)
+            .expect("Error in routing");
+
+        if hops.is_empty() {
+            // I don't know the path to `dest` yet
+            if let Some((fragment_index, session_id)) = self
+                .session_manager
+                .add_to_waiting_fragments(dest, fragment_index, session_id)
+            {
+                self.controller_send
+                    .send(ServerEvent::WaitingFragmentEvicted {
+                        from: self.id,
+                        to: dest,
+                        session_id,
+                        fragment_index,
+                    })
+                    .expect("Error in controller_send");
+                // the evicted fragment will never be sent, so its session can't complete
+                self.fail_session(session_id, fragment_index);
             }
         } else {
-            panic!(
-                "tried to recover a fragment that is not in the session_manager.\n\
-                    session_id: {session_id}\n\
-                    fragment_index: {fragment_index}\n"
-            );
+            let packet = Packet {
+                routing_header: SourceRoutingHeader { hop_index: 1, hops },
+                session_id,
+                pack_type: PacketType::MsgFragment(fragment),
+            };
+            self.send_packet(packet);
         }
     }
 
+    /// Abandons a pending session that can't be delivered anymore and sends a
+    /// `ServerEvent::DeliveryFailed` to the controller.
+    ///
+    /// The pending fragments of the session are dropped, including the ones waiting for a path.
+    /// Sessions that aren't pending anymore are ignored.
+    ///
+    /// ### Arguments:
+    /// - `session_id`: The ID of the session to abandon.
+    /// - `fragment_index`: The index of the fragment that caused the failure.
+    fn fail_session(&mut self, session_id: SessionId, fragment_index: FragmentIndex) {
+        let Some(dest_id) = self.session_manager.abandon_session(session_id) else {
+            return;
+        };
+        self.controller_send
+            .send(ServerEvent::DeliveryFailed {
+                from: self.id,
+                to: dest_id,
+                session_id,
+                fragment_index,
+            })
+            .expect("Error in controller_send");
+    }
+
     /// Sends a NACK packet over the network.
     ///
     /// This function wraps the given `Nack` into a `Packet` with a source routing header constructed from
@@ -181,7 +230,9 @@ impl CommunicationServer {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::communication_server::session_manager::MAX_WAITING_FRAGMENTS;
     use crate::communication_server::test_server_helper::TestServerHelper;
+    use std::time::Duration;
     use wg_2024::packet::Ack;
     #[test]
     fn test_handle_nack() {
@@ -399,4 +450,211 @@ mod tests {
             .session_manager
             .hash_waiting_fragments(6));
     }
+
+    #[test]
+    fn test_retransmissions_cap() {
+        let mut test_server_helper = TestServerHelper::new();
+
+        let fragment_index = 0;
+        let (packet, session_id) = TestServerHelper::test_received_packet(
+            PacketType::Nack(Nack {
+                fragment_index,
+                nack_type: NackType::Dropped,
+            }),
+            vec![3, 1],
+        );
+        let fragment = TestServerHelper::test_fragment(fragment_index, 1);
+        test_server_helper
+            .server
+            .session_manager
+            .add_session(session_id, vec![fragment], 6);
+
+        for _ in 0..MAX_RETRANSMISSIONS {
+            test_server_helper.server.handle_packet(packet.clone());
+        }
+        assert_eq!(
+            test_server_helper
+                .server
+                .session_manager
+                .destination(session_id),
+            Some(6)
+        );
+        let retransmitted = test_server_helper
+            .packet_recv_3
+            .try_iter()
+            .filter(|packet| matches!(packet.pack_type, PacketType::MsgFragment(_)))
+            .count();
+        assert_eq!(retransmitted, MAX_RETRANSMISSIONS as usize);
+
+        // the path is permanently broken
+        test_server_helper.server.handle_packet(packet.clone());
+        assert_eq!(
+            test_server_helper
+                .server
+                .session_manager
+                .destination(session_id),
+            None
+        );
+        assert!(test_server_helper
+            .packet_recv_3
+            .try_iter()
+            .all(|packet| !matches!(packet.pack_type, PacketType::MsgFragment(_))));
+        assert!(test_server_helper
+            ._event_recv_from_server
+            .try_iter()
+            .any(|event| matches!(
+                event,
+                ServerEvent::DeliveryFailed {
+                    from: 1,
+                    to: 6,
+                    session_id: id,
+                    fragment_index: 0,
+                } if id == session_id
+            )));
+
+        // late NACKs for the abandoned session are ignored
+        test_server_helper.server.handle_packet(packet);
+        assert_eq!(test_server_helper.packet_recv_3.try_iter().count(), 0);
+    }
+
+    #[test]
+    fn test_waiting_fragment_eviction_fails_session() {
+        let mut test_server_helper = TestServerHelper::new();
+        let session_id = 42;
+        let fragments = (0..=MAX_WAITING_FRAGMENTS as u64)
+            .map(|i| TestServerHelper::test_fragment(i, MAX_WAITING_FRAGMENTS as u64 + 1))
+            .collect();
+        test_server_helper
+            .server
+            .session_manager
+            .add_session(session_id, fragments, 6);
+        for i in 0..MAX_WAITING_FRAGMENTS as u64 {
+            test_server_helper
+                .server
+                .session_manager
+                .add_to_waiting_fragments(6, i, session_id);
+        }
+
+        // there is no path to 6 anymore
+        test_server_helper.server.network_topology.remove_edge(3, 6);
+        test_server_helper
+            .server
+            .recover_fragment(session_id, MAX_WAITING_FRAGMENTS as u64);
+
+        let session_manager = &test_server_helper.server.session_manager;
+        assert!(session_manager.pending_session_ids().is_empty());
+        assert!(!session_manager.hash_waiting_fragments(6));
+        let events: Vec<ServerEvent> = test_server_helper
+            ._event_recv_from_server
+            .try_iter()
+            .collect();
+        assert!(events.iter().any(|event| matches!(
+            event,
+            ServerEvent::WaitingFragmentEvicted {
+                from: 1,
+                to: 6,
+                session_id: 42,
+                fragment_index: 0,
+            }
+        )));
+        assert!(events.iter().any(|event| matches!(
+            event,
+            ServerEvent::DeliveryFailed {
+                from: 1,
+                to: 6,
+                session_id: 42,
+                fragment_index: 0,
+            }
+        )));
+    }
+
+    #[test]
+    fn test_late_nack() {
+        let mut test_server_helper = TestServerHelper::new();
+
+        let (nack, session_id) = TestServerHelper::test_received_packet(
+            PacketType::Nack(Nack {
+                fragment_index: 0,
+                nack_type: NackType::Dropped,
+            }),
+            vec![3, 1],
+        );
+        test_server_helper.server.session_manager.add_session(
+            session_id,
+            vec![
+                TestServerHelper::test_fragment(0, 2),
+                TestServerHelper::test_fragment(1, 2),
+            ],
+            6,
+        );
+        let ack = |fragment_index| {
+            let mut packet = nack.clone();
+            packet.pack_type = PacketType::Ack(Ack { fragment_index });
+            packet
+        };
+
+        // the fragment has already been acknowledged, but the session is still pending
+        test_server_helper.server.handle_packet(ack(0));
+        test_server_helper.server.handle_packet(nack.clone());
+        assert_eq!(test_server_helper.packet_recv_3.try_iter().count(), 0);
+
+        // the session is completed
+        test_server_helper.server.handle_packet(ack(1));
+        test_server_helper.server.handle_packet(nack.clone());
+        test_server_helper.server.handle_packet(nack);
+        assert_eq!(test_server_helper.packet_recv_3.try_iter().count(), 0);
+        assert!(!test_server_helper
+            .server
+            .session_manager
+            .already_dropped(session_id, 0));
+    }
+
+    #[test]
+    fn test_flood_rate_limit() {
+        let mut test_server_helper = TestServerHelper::new();
+        test_server_helper
+            .server
+            .set_min_flood_interval(Duration::from_secs(60));
+
+        let (packet, session_id) = TestServerHelper::test_received_packet(
+            PacketType::Nack(Nack {
+                fragment_index: 0,
+                nack_type: NackType::ErrorInRouting(3),
+            }),
+            vec![2, 1],
+        );
+        let fragment = TestServerHelper::test_fragment(0, 1);
+        test_server_helper
+            .server
+            .session_manager
+            .add_session(session_id, vec![fragment], 6);
+        let floods = |test_server_helper: &TestServerHelper| {
+            test_server_helper
+                .packet_recv_5
+                .try_iter()
+                .filter(|packet| matches!(packet.pack_type, PacketType::FloodRequest(_)))
+                .count()
+        };
+
+        for _ in 0..5 {
+            test_server_helper.server.handle_packet(packet.clone());
+        }
+        assert_eq!(floods(&test_server_helper), 1);
+        // the fragment is retried anyway
+        assert_eq!(
+            test_server_helper
+                .packet_recv_3
+                .try_iter()
+                .filter(|packet| matches!(packet.pack_type, PacketType::MsgFragment(_)))
+                .count(),
+            5
+        );
+
+        // the discoveries requested in the window are coalesced into a single flood
+        assert!(test_server_helper.server.deferred_flood.is_some());
+        test_server_helper.server.send_deferred_flood();
+        assert_eq!(floods(&test_server_helper), 1);
+        test_server_helper.server.send_deferred_flood();
+        assert_eq!(floods(&test_server_helper), 0);
+    }
 }
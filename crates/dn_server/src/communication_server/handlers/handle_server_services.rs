@@ -2,17 +2,24 @@
 //!
 //! ### Functions:
 //! - **`send_server_type`**: sends the type of the server to the specified client.
-//! - **`register_client`**: registers a client by adding its ID to the list of registered clients.
+//! - **`register_client`**: registers a client by adding its ID to the list of registered clients,
+//!   answering idempotently to duplicate registrations.
+//! - **`deregister_client`**: drops the registration of a client, on request of the controller or
+//!   of the client itself.
 //! - **`registered_clients_list`**: sends a list of all registered clients to the requesting client.
 //! - **`forward_message`**: forwards a communication message to the intended recipient if they are registered.
+//! - **`send_pong`**: echoes the nonce of a ping back to the requesting client.
+//! - **`push`**: sends an unsolicited message to a registered client.
 
 use crate::communication_server::communication_server::CommunicationServer;
+use dn_controller::ServerEvent;
 use dn_message::ServerBody::{RespServerType, ServerCommunication};
 use dn_message::ServerCommunicationBody::RespClientList;
 use dn_message::{
     ClientBody, ClientCommunicationBody, CommunicationMessage, Message, ServerBody,
     ServerCommunicationBody, ServerType,
 };
+use std::time::Instant;
 use wg_2024::network::NodeId;
 
 impl CommunicationServer {
@@ -22,7 +29,7 @@ impl CommunicationServer {
     /// them to the appropriate handler function. It determines the request type and
     /// performs the corresponding action, such as sending the server type, handling client
     /// communication, or sending an error messages to the client for messages intended for a
-    /// content server.
+    /// content server. Unsupported requests are also reported to the simulation controller.
     ///
     /// # Arguments
     /// * `client_body` - The request body received from the client.
@@ -36,6 +43,13 @@ impl CommunicationServer {
                 self.handle_client_communication_body(comm_body, sender_id);
             }
             ClientBody::ClientContent(_) => {
+                self.controller_send
+                    .send(ServerEvent::UnsupportedRequest {
+                        category: client_body.category(),
+                        from: sender_id,
+                        to: self.id,
+                    })
+                    .expect("Error in controller_send");
                 let message = Message::Server(ServerBody::ErrUnsupportedRequestType);
                 self.send_message(message, sender_id);
             }
@@ -45,7 +59,7 @@ impl CommunicationServer {
     /// Handles communication-related requests from a client.
     ///
     /// This function processes client communication requests, such as registration
-    /// to a chat, sending messages, or requesting a list of registered clients.
+    /// to a chat, sending messages, requesting a list of registered clients or pinging the server.
     ///
     /// # Arguments
     /// * `client_communication_body` - The specific communication request sent by the client.
@@ -59,12 +73,18 @@ impl CommunicationServer {
             ClientCommunicationBody::ReqRegistrationToChat => {
                 self.register_client(sender_id);
             }
+            ClientCommunicationBody::ReqUnregistrationFromChat => {
+                self.deregister_client(sender_id);
+            }
             ClientCommunicationBody::MessageSend(comm_message) => {
                 self.forward_message(comm_message);
             }
             ClientCommunicationBody::ReqClientList => {
                 self.registered_clients_list(sender_id);
             }
+            ClientCommunicationBody::Ping(nonce) => {
+                self.send_pong(sender_id, nonce);
+            }
         }
     }
 
@@ -86,39 +106,123 @@ impl CommunicationServer {
     /// The client ID is inserted into the internal collection of registered clients, making it
     /// available for further communication and message forwarding.
     /// This function also sends a message to the client communicating that the registration was
-    /// successful.
+    /// successful. If the client was already registered (e.g. it retransmitted its request), the
+    /// registration is left untouched and `AlreadyRegistered` is sent instead.
     ///
     /// ### Arguments:
     /// - `client_id`: The unique identifier of the client to be registered.
     fn register_client(&mut self, client_id: NodeId) {
-        self.registered_clients.insert(client_id);
-        let message: Message = Message::Server(ServerCommunication(
-            ServerCommunicationBody::RegistrationSuccess,
-        ));
+        let body = if self.registered_clients.insert(client_id) {
+            ServerCommunicationBody::RegistrationSuccess
+        } else {
+            ServerCommunicationBody::AlreadyRegistered
+        };
+        let message: Message = Message::Server(ServerCommunication(body));
         self.send_message(message, client_id);
     }
 
+    /// Drops the registration of a client, as requested by the simulation controller or by the
+    /// client itself.
+    ///
+    /// The messages stored in the mailbox of the client are discarded as well. The client is not
+    /// notified: it's told it isn't registered as soon as it sends a message to another client.
+    ///
+    /// ### Arguments:
+    /// - `client_id`: The unique identifier of the client to be deregistered.
+    pub(crate) fn deregister_client(&mut self, client_id: NodeId) {
+        self.registered_clients.remove(&client_id);
+        if let Some(mailbox) = self.mailbox.as_mut() {
+            _ = mailbox.take_messages(client_id);
+        }
+    }
+
     /// Sends a list of all registered clients to the requesting client.
     ///
     /// This function sends a message containing the list of all clients that are currently
-    /// registered with the server.
+    /// registered with the server, sorted by id.
     /// The list is sent to the `client_id` specified.
     ///
     /// ### Arguments:
     /// - `client_id`: The unique identifier of the client who has requested the list of registered clients.
     fn registered_clients_list(&mut self, client_id: NodeId) {
-        let client_list: Vec<NodeId> = self.registered_clients.iter().copied().collect();
+        let client_list = self.sorted_registered_clients();
         let message = Message::Server(ServerCommunication(RespClientList(client_list)));
         self.send_message(message, client_id);
     }
 
+    /// Returns the clients registered with the server, sorted by id.
+    pub(crate) fn sorted_registered_clients(&self) -> Vec<NodeId> {
+        let mut client_list: Vec<NodeId> = self.registered_clients.iter().copied().collect();
+        client_list.sort_unstable();
+        client_list
+    }
+
+    /// Echoes the nonce of a ping back to the requesting client.
+    ///
+    /// Pings are answered even if the client is not registered, so that any client can measure
+    /// the round-trip time to the server.
+    ///
+    /// ### Arguments:
+    /// - `client_id`: The unique identifier of the client who sent the ping.
+    /// - `nonce`: The nonce received with the ping.
+    fn send_pong(&mut self, client_id: NodeId, nonce: u64) {
+        let message = Message::Server(ServerCommunication(ServerCommunicationBody::Pong(nonce)));
+        self.send_message(message, client_id);
+    }
+
+    /// Sends an unsolicited message to a registered client (e.g. a notification).
+    ///
+    /// The message is sent using source routing; if the path to the client is currently unknown,
+    /// it is queued in the `pending_messages_queue` until the topology is updated.
+    ///
+    /// ### Arguments:
+    /// - `to`: The unique identifier of the client to notify.
+    /// - `message`: The server message to send.
+    ///
+    /// ### Returns:
+    /// - `true`: If the message has been sent or queued.
+    /// - `false`: If `to` is not a registered client or `message` is not a server message.
+    pub fn push(&mut self, to: NodeId, message: Message) -> bool {
+        if !self.registered_clients.contains(&to) || !matches!(message, Message::Server(_)) {
+            return false;
+        }
+        self.send_message(message, to);
+        true
+    }
+
+    /// Stores a message in the mailbox of an unreachable client.
+    ///
+    /// ### Arguments:
+    /// - `to`: The recipient of the message.
+    /// - `message`: The message to store.
+    ///
+    /// ### Returns:
+    /// - `true`: If the message has been stored.
+    /// - `false`: If the mailbox mode is disabled, the recipient is reachable or its mailbox is full.
+    fn store_in_mailbox(&mut self, to: NodeId, message: Message) -> bool {
+        if self.mailbox.is_none()
+            || self
+                .network_topology
+                .source_routing(self.id, to)
+                .is_some_and(|hops| !hops.is_empty())
+        {
+            return false;
+        }
+        self.mailbox
+            .as_mut()
+            .is_some_and(|mailbox| mailbox.store(to, message))
+    }
+
     /// Forwards a communication message to the intended recipient if they are registered.
     ///
     /// This function checks:
     /// - If the client `from` is not registered, an error message `ErrNotRegistered` is sent back.
     /// - If it is registered then: this function checks whether the recipient of the communication
     ///   message is a registered client.
-    ///   - If the recipient is registered, the server forwards the message to the recipient.
+    ///   - If the recipient is registered, the server forwards the message to the recipient,
+    ///     unless the same message has been forwarded recently (e.g. it has been retransmitted
+    ///     after a lost ACK). If the mailbox mode is enabled and the recipient is unreachable, the message is stored
+    ///     in its mailbox until the recipient shows some activity.
     ///   - If the recipient is not registered, an error message indicating that the client ID is
     ///     incorrect is sent back to the sender.
     ///
@@ -130,10 +234,18 @@ impl CommunicationServer {
         let to = communication_message.to;
         if self.registered_clients.contains(&from) {
             if self.registered_clients.contains(&to) {
+                if self
+                    .forwarded_messages
+                    .is_duplicate(&communication_message, Instant::now())
+                {
+                    return;
+                }
                 let message: Message = Message::Server(ServerCommunication(
                     ServerCommunicationBody::MessageReceive(communication_message),
                 ));
-                self.send_message(message.clone(), to);
+                if !self.store_in_mailbox(to, message.clone()) {
+                    self.send_message(message, to);
+                }
             } else {
                 let message: Message = Message::Server(ServerCommunication(
                     ServerCommunicationBody::ErrWrongClientId,
@@ -157,7 +269,7 @@ mod tests {
     use dn_message::ServerBody::ServerCommunication;
     use dn_message::ServerCommunicationBody::MessageReceive;
     use dn_message::{ClientCommunicationBody, Message};
-    use wg_2024::packet::PacketType;
+    use wg_2024::packet::{NodeType, PacketType};
 
     #[test]
     fn test_send_server_type() {
@@ -204,6 +316,54 @@ mod tests {
         assert!(false);
     }
 
+    #[test]
+    fn test_register_client_twice() {
+        let mut test_server_helper = TestServerHelper::new();
+        let registration = Message::Client(ClientCommunication(
+            ClientCommunicationBody::ReqRegistrationToChat,
+        ));
+
+        let response = test_server_helper.send_message_and_get_response(
+            registration.clone(),
+            vec![6, 3, 1],
+            3,
+        );
+        assert!(matches!(
+            response,
+            Message::Server(ServerCommunication(
+                ServerCommunicationBody::RegistrationSuccess
+            ))
+        ));
+
+        let response =
+            test_server_helper.send_message_and_get_response(registration, vec![6, 3, 1], 3);
+        assert!(matches!(
+            response,
+            Message::Server(ServerCommunication(
+                ServerCommunicationBody::AlreadyRegistered
+            ))
+        ));
+        assert!(test_server_helper.server.registered_clients.contains(&6));
+        assert_eq!(test_server_helper.server.registered_clients.len(), 1);
+    }
+
+    #[test]
+    fn test_unregister_client() {
+        let mut test_server_helper = TestServerHelper::new();
+        test_server_helper.register_client_6();
+        assert!(test_server_helper.server.registered_clients.contains(&6));
+
+        let unregistration = Message::Client(ClientCommunication(
+            ClientCommunicationBody::ReqUnregistrationFromChat,
+        ));
+        let serialized_message = test_server_helper.serialize_message(&unregistration);
+        let nr_of_fragments = serialized_message.len();
+        test_server_helper.send_fragments_to_server(serialized_message, vec![6, 3, 1]);
+        test_server_helper.wait_for_ack_on_node_x(nr_of_fragments, 3);
+
+        assert!(!test_server_helper.server.registered_clients.contains(&6));
+    }
+
     #[test]
     fn test_registered_client_list() {
         let mut test_server_helper = TestServerHelper::new();
@@ -219,6 +379,116 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_registered_client_list_sorted() {
+        let mut test_server_helper = TestServerHelper::new();
+        for client in [42, 17, 5, 200] {
+            test_server_helper.server.registered_clients.insert(client);
+        }
+        test_server_helper.register_client_6();
+        let response = test_server_helper.send_message_and_get_response(
+            Message::Client(ClientCommunication(ClientCommunicationBody::ReqClientList)),
+            vec![6, 3, 1],
+            3,
+        );
+        if let Message::Server(ServerCommunication(RespClientList(list))) = response {
+            assert_eq!(list, vec![5, 6, 17, 42, 200]);
+        } else {
+            panic!("expected a client list");
+        }
+    }
+
+    #[test]
+    fn test_ping() {
+        let mut test_server_helper = TestServerHelper::new();
+        let nonce = 0xDEAD_BEEF_1234;
+        let response = test_server_helper.send_message_and_get_response(
+            Message::Client(ClientCommunication(ClientCommunicationBody::Ping(nonce))),
+            vec![6, 3, 1],
+            3,
+        );
+        if let Message::Server(ServerCommunication(ServerCommunicationBody::Pong(echo))) = response
+        {
+            assert_eq!(echo, nonce);
+        } else {
+            panic!("expected a pong");
+        }
+        // the client was answered without being registered
+        assert!(test_server_helper.server.registered_clients.is_empty());
+    }
+
+    #[test]
+    fn test_push() {
+        let mut test_server_helper = TestServerHelper::new();
+        let notification =
+            Message::Server(ServerCommunication(MessageReceive(CommunicationMessage {
+                from: 5,
+                to: 6,
+                message: "new message available".to_string(),
+            })));
+
+        // 6 is not registered yet
+        assert!(!test_server_helper.server.push(6, notification.clone()));
+        assert!(test_server_helper.packet_recv_3.try_recv().is_err());
+
+        test_server_helper.server.registered_clients.insert(6);
+        assert!(test_server_helper.server.push(6, notification));
+
+        // the path to 6 goes through node 3
+        let pushed = test_server_helper.reconstruct_response_on_node_x(3);
+        if let Message::Server(ServerCommunication(MessageReceive(cm))) = pushed {
+            assert_eq!(cm.from, 5);
+            assert_eq!(cm.to, 6);
+            assert_eq!(cm.message, "new message available");
+        } else {
+            panic!("expected the pushed message");
+        }
+    }
+
+    #[test]
+    fn test_mailbox() {
+        let mut test_server_helper = TestServerHelper::new();
+        test_server_helper.server.enable_mailbox(4);
+        test_server_helper.server.registered_clients.insert(5);
+        // 8 is registered but the server doesn't know how to reach it
+        test_server_helper.server.registered_clients.insert(8);
+
+        let message = Message::Client(ClientCommunication(ClientCommunicationBody::MessageSend(
+            CommunicationMessage {
+                from: 5,
+                to: 8,
+                message: "are you there?".to_string(),
+            },
+        )));
+        let fragments = test_server_helper.serialize_message(&message);
+        test_server_helper.send_fragments_to_server(fragments, vec![5, 1]);
+
+        let mailbox = test_server_helper.server.mailbox.as_ref().unwrap();
+        assert_eq!(mailbox.len(8), 1);
+        // no flood has been sent to look for 8
+        assert!(test_server_helper.packet_recv_3.try_recv().is_err());
+
+        // 8 connects to 3 and shows some activity
+        let topology = &mut test_server_helper.server.network_topology;
+        topology.add_node(8, NodeType::Client);
+        topology.add_edge(3, 8);
+        let fragments = test_server_helper.serialize_message(&Message::Client(
+            ClientCommunication(ClientCommunicationBody::ReqClientList),
+        ));
+        test_server_helper.send_fragments_to_server(fragments, vec![8, 3, 1]);
+
+        let delivered = test_server_helper.reconstruct_response_on_node_x(3);
+        if let Message::Server(ServerCommunication(MessageReceive(cm))) = delivered {
+            assert_eq!(cm.from, 5);
+            assert_eq!(cm.to, 8);
+            assert_eq!(cm.message, "are you there?");
+        } else {
+            panic!("expected the stored message");
+        }
+        let mailbox = test_server_helper.server.mailbox.as_ref().unwrap();
+        assert_eq!(mailbox.len(8), 0);
+    }
+
     #[test]
     fn test_forward_message() {
         let mut test_server_helper = TestServerHelper::new();
@@ -259,4 +529,33 @@ mod tests {
             assert_eq!(cm.message, "I wanted to say hi!");
         }
     }
+
+    #[test]
+    fn test_forward_message_duplicate() {
+        let mut test_server_helper = TestServerHelper::new();
+        test_server_helper.server.registered_clients.insert(5);
+        test_server_helper.register_client_6();
+        _ = test_server_helper.reconstruct_response_on_node_x(3);
+
+        let message = Message::Client(ClientCommunication(ClientCommunicationBody::MessageSend(
+            CommunicationMessage {
+                from: 5,
+                to: 6,
+                message: "only once".to_string(),
+            },
+        )));
+        let response =
+            test_server_helper.send_message_and_get_response(message.clone(), vec![5, 1], 3);
+        assert!(matches!(
+            response,
+            Message::Server(ServerCommunication(MessageReceive(_)))
+        ));
+
+        // the retransmitted message is acknowledged but not forwarded again
+        let fragments = test_server_helper.serialize_message(&message);
+        let nr_of_fragments = fragments.len();
+        test_server_helper.send_fragments_to_server(fragments, vec![5, 1]);
+        test_server_helper.wait_for_ack_on_node_x(nr_of_fragments, 5);
+        assert!(test_server_helper.packet_recv_3.try_recv().is_err());
+    }
 }
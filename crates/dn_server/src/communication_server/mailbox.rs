@@ -0,0 +1,95 @@
+//! # Mailbox
+//!
+//! This module provides a support structure for the `CommunicationServer` to store messages
+//! addressed to registered clients that are currently unreachable.
+//!
+//! ## Overview
+//! Unlike the pending messages queue, which only waits for the next topology update, the mailbox
+//! keeps the messages until the recipient shows some activity (e.g. it registers again or sends
+//! any request), so that they can be delivered once the client is back online.
+//! Each recipient can store at most `capacity` messages.
+
+use dn_message::Message;
+use std::collections::HashMap;
+use wg_2024::network::NodeId;
+
+pub struct Mailbox {
+    messages: HashMap<NodeId, Vec<Message>>,
+    capacity: usize,
+}
+
+impl Mailbox {
+    /// Creates a new empty mailbox.
+    ///
+    /// # Arguments
+    /// * `capacity` - The maximum number of messages stored for each recipient.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            messages: HashMap::new(),
+            capacity,
+        }
+    }
+
+    /// Stores a message for a recipient, if its mailbox is not full.
+    ///
+    /// # Arguments
+    /// * `node_id` - The recipient of the message.
+    /// * `message` - The message to be stored.
+    ///
+    /// # Returns
+    /// `true` if the message has been stored, `false` if the mailbox of `node_id` is full.
+    pub fn store(&mut self, node_id: NodeId, message: Message) -> bool {
+        let messages = self.messages.entry(node_id).or_default();
+        if messages.len() >= self.capacity {
+            return false;
+        }
+        messages.push(message);
+        true
+    }
+
+    /// Retrieves and removes all the stored messages for a given recipient.
+    ///
+    /// # Arguments
+    /// * `node_id` - The recipient whose messages should be retrieved.
+    ///
+    /// # Returns
+    /// The stored messages in arrival order, or `None` if there were none.
+    pub fn take_messages(&mut self, node_id: NodeId) -> Option<Vec<Message>> {
+        self.messages
+            .remove(&node_id)
+            .filter(|messages| !messages.is_empty())
+    }
+
+    /// Returns the number of messages stored for a given recipient.
+    #[cfg(test)]
+    pub fn len(&self, node_id: NodeId) -> usize {
+        self.messages.get(&node_id).map_or(0, Vec::len)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use dn_message::ClientBody;
+
+    fn dummy_message() -> Message {
+        Message::Client(ClientBody::ReqServerType)
+    }
+
+    #[test]
+    fn test_store_and_take() {
+        let mut mailbox = Mailbox::new(2);
+
+        assert!(mailbox.store(4, dummy_message()));
+        assert!(mailbox.store(4, dummy_message()));
+        // the mailbox of 4 is full, the one of 5 is not
+        assert!(!mailbox.store(4, dummy_message()));
+        assert!(mailbox.store(5, dummy_message()));
+        assert_eq!(mailbox.len(4), 2);
+
+        assert_eq!(mailbox.take_messages(4).map(|m| m.len()), Some(2));
+        assert_eq!(mailbox.len(4), 0);
+        assert!(mailbox.take_messages(4).is_none());
+        assert_eq!(mailbox.len(5), 1);
+    }
+}
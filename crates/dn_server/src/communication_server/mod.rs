@@ -1,9 +1,13 @@
 mod communication_server;
 mod communication_server_topology;
+mod flood_tracker;
+mod forwarded_messages;
 mod handlers;
+mod mailbox;
 mod pending_message_queue;
 mod session_manager;
 #[cfg(test)]
 mod test_server_helper;
 
 pub use communication_server::*;
+pub use flood_tracker::FloodStats;
@@ -63,6 +63,17 @@ impl PendingMessagesQueue {
     pub fn has_pending_messages(&self, node_id: NodeId) -> bool {
         self.pending_messages.contains_key(&node_id)
     }
+
+    /// Returns the number of pending messages for every node that has some.
+    ///
+    /// # Returns
+    /// A map from the node ID to the number of messages waiting for it.
+    pub fn pending_messages_counts(&self) -> HashMap<NodeId, usize> {
+        self.pending_messages
+            .iter()
+            .map(|(node_id, messages)| (*node_id, messages.len()))
+            .collect()
+    }
 }
 
 #[cfg(test)]
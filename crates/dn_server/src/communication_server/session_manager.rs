@@ -23,6 +23,11 @@ pub type SessionId = u64;
 /// A type alias for the fragment index.
 pub type FragmentIndex = u64;
 
+/// Maximum number of fragments waiting for the same destination, the oldest ones are evicted.
+pub const MAX_WAITING_FRAGMENTS: usize = 256;
+/// Maximum number of times a fragment is retransmitted after a NACK, then its session is abandoned.
+pub const MAX_RETRANSMISSIONS: u32 = 16;
+
 /// The `SessionManager` struct is responsible for managing sessions and their associated fragments.
 /// It tracks pending fragments for each session, processes acknowledgments, saves if a fragment has been dropped,
 /// and manages session states.
@@ -37,6 +42,12 @@ pub struct SessionManager {
 
     // a hashset containing entries for fragments that have been dropped
     already_dropped: HashSet<(SessionId, FragmentIndex)>,
+
+    // number of retransmissions of each fragment after a NACK
+    retransmissions: HashMap<(SessionId, FragmentIndex), u32>,
+
+    // number of ACKs received for sessions that aren't pending, e.g. late ACKs after completion
+    unknown_acks: u64,
 }
 
 impl SessionManager {
@@ -54,6 +65,8 @@ impl SessionManager {
             pending_sessions_destination: HashMap::new(),
             waiting_fragments: HashMap::new(),
             already_dropped: HashSet::new(),
+            retransmissions: HashMap::new(),
+            unknown_acks: 0,
         }
     }
 
@@ -82,6 +95,7 @@ impl SessionManager {
     /// from the list of pending fragments associated with a session. If all fragments for the
     /// session are acknowledged, the session is removed from the pending sessions and its destination
     /// is also removed.
+    /// An acknowledgment for a session that isn't pending is ignored, but counted in `unknown_acks`.
     ///
     /// ### Arguments:
     /// - `ack`: The acknowledgment message containing the index of the acknowledged fragment.
@@ -92,12 +106,27 @@ impl SessionManager {
             if fragment_map.is_empty() {
                 self.pending_sessions.remove(&session_id);
                 self.pending_sessions_destination.remove(&session_id);
+                // late NACKs may have marked fragments already acknowledged
+                self.already_dropped
+                    .retain(|&(session, _)| session != session_id);
+                self.retransmissions
+                    .retain(|&(session, _), _| session != session_id);
             }
             self.already_dropped
                 .remove(&(session_id, ack.fragment_index));
+            self.retransmissions
+                .remove(&(session_id, ack.fragment_index));
+        } else {
+            self.unknown_acks += 1;
         }
     }
 
+    /// Returns the number of acknowledgments received for sessions that weren't pending,
+    /// such as late ACKs for sessions already completed.
+    pub fn unknown_acks(&self) -> u64 {
+        self.unknown_acks
+    }
+
     /// Retrieves a specific fragment from the session and returns a copy of it with the destination node.
     ///
     /// This function allows for recovering a fragment by its index from the list of pending fragments in
@@ -141,21 +170,48 @@ impl SessionManager {
     ///
     /// If the destination node does not have any waiting fragments yet, a new entry is created.
     /// The fragment index and the session id are then appended to the corresponding list.
+    /// If the list is already `MAX_WAITING_FRAGMENTS` long, its oldest entry is evicted.
     ///
     /// # Arguments
     /// * `dest` - The ID of the destination node.
     /// * `fragment_index` - The `fragment_index` to be added to the waiting queue.
     /// * `session_id` - The `session_id` associated with that fragment
+    ///
+    /// # Returns
+    /// * `Option<(FragmentIndex, SessionId)>` - The evicted fragment index and session id, if any.
     pub fn add_to_waiting_fragments(
         &mut self,
         dest: NodeId,
         fragment_index: FragmentIndex,
         session_id: SessionId,
-    ) {
+    ) -> Option<(FragmentIndex, SessionId)> {
+        let waiting = self.waiting_fragments.entry(dest).or_default();
+        let evicted = if waiting.len() >= MAX_WAITING_FRAGMENTS {
+            Some(waiting.remove(0))
+        } else {
+            None
+        };
+        waiting.push((fragment_index, session_id));
+        evicted
+    }
+
+    /// Returns the number of fragments waiting for a path to the given destination node.
+    ///
+    /// # Arguments
+    /// * `dest` - The ID of the destination node.
+    pub fn waiting_fragments_count(&self, dest: NodeId) -> usize {
+        self.waiting_fragments.get(&dest).map_or(0, Vec::len)
+    }
+
+    /// Returns the number of fragments waiting for a path, for every destination that has some.
+    ///
+    /// # Returns
+    /// * `HashMap<NodeId, usize>` - The number of waiting fragments per destination node.
+    pub fn waiting_fragments_counts(&self) -> HashMap<NodeId, usize> {
         self.waiting_fragments
-            .entry(dest)
-            .or_default()
-            .push((fragment_index, session_id));
+            .iter()
+            .map(|(dest, fragments)| (*dest, fragments.len()))
+            .collect()
     }
 
     /// Checks if there are any waiting fragments for a given destination node.
@@ -205,6 +261,86 @@ impl SessionManager {
         self.pending_sessions_destination.get(&session_id)
     }
 
+    /// Returns the IDs of all the sessions that still have unacknowledged fragments.
+    ///
+    /// ### Returns:
+    /// - A vector of session IDs, sorted in ascending order.
+    pub fn pending_session_ids(&self) -> Vec<SessionId> {
+        let mut ids: Vec<SessionId> = self.pending_sessions.keys().copied().collect();
+        ids.sort_unstable();
+        ids
+    }
+
+    /// Returns the number of fragments of a session that haven't been acknowledged yet.
+    ///
+    /// ### Arguments:
+    /// - `session_id`: The ID of the session.
+    ///
+    /// ### Returns:
+    /// - `Some(count)`: The number of unacknowledged fragments.
+    /// - `None`: If the session is unknown or already completed.
+    pub fn fragments_remaining(&self, session_id: SessionId) -> Option<usize> {
+        self.pending_sessions.get(&session_id).map(HashMap::len)
+    }
+
+    /// Returns the destination of a pending session.
+    ///
+    /// ### Arguments:
+    /// - `session_id`: The ID of the session.
+    ///
+    /// ### Returns:
+    /// - `Some(node)`: The ID of the message recipient.
+    /// - `None`: If the session is unknown or already completed.
+    pub fn destination(&self, session_id: SessionId) -> Option<NodeId> {
+        self.pending_sessions_destination.get(&session_id).copied()
+    }
+
+    /// Counts a retransmission of a fragment.
+    ///
+    /// ### Arguments:
+    /// - `session_id`: The ID of the session to which the fragment belongs.
+    /// - `fragment_index`: The index of the retransmitted fragment.
+    ///
+    /// ### Returns:
+    /// - The number of retransmissions of the fragment, including this one.
+    pub fn inc_retransmissions(
+        &mut self,
+        session_id: SessionId,
+        fragment_index: FragmentIndex,
+    ) -> u32 {
+        let retransmissions = self
+            .retransmissions
+            .entry((session_id, fragment_index))
+            .or_default();
+        *retransmissions += 1;
+        *retransmissions
+    }
+
+    /// Abandons a pending session, freeing its fragments, including the ones waiting for a path.
+    ///
+    /// ### Arguments:
+    /// - `session_id`: The ID of the session to abandon.
+    ///
+    /// ### Returns:
+    /// - `Some(node)`: The ID of the recipient of the abandoned session.
+    /// - `None`: If the session is unknown or already completed.
+    pub fn abandon_session(&mut self, session_id: SessionId) -> Option<NodeId> {
+        self.pending_sessions.remove(&session_id);
+        let dest = self.pending_sessions_destination.remove(&session_id)?;
+
+        self.already_dropped
+            .retain(|&(session, _)| session != session_id);
+        self.retransmissions
+            .retain(|&(session, _), _| session != session_id);
+        if let Some(waiting) = self.waiting_fragments.get_mut(&dest) {
+            waiting.retain(|&(_, session)| session != session_id);
+            if waiting.is_empty() {
+                self.waiting_fragments.remove(&dest);
+            }
+        }
+        Some(dest)
+    }
+
     pub fn already_dropped_clear(&mut self) {
         self.already_dropped.clear();
     }
@@ -264,4 +400,76 @@ mod tests {
         manager.add_to_waiting_fragments(6, 1, 3);
         assert!(manager.hash_waiting_fragments(6));
     }
+
+    #[test]
+    fn test_waiting_fragments_eviction() {
+        let mut manager = SessionManager::new();
+        for i in 0..MAX_WAITING_FRAGMENTS as u64 {
+            assert_eq!(None, manager.add_to_waiting_fragments(6, i, 1));
+        }
+        assert_eq!(MAX_WAITING_FRAGMENTS, manager.waiting_fragments_count(6));
+
+        // the oldest fragments make room for the new ones
+        assert_eq!(Some((0, 1)), manager.add_to_waiting_fragments(6, 0, 2));
+        assert_eq!(Some((1, 1)), manager.add_to_waiting_fragments(6, 1, 2));
+        assert_eq!(MAX_WAITING_FRAGMENTS, manager.waiting_fragments_count(6));
+        assert_eq!(0, manager.waiting_fragments_count(7));
+
+        let fragments = manager.take_waiting_fragments(6).unwrap();
+        assert_eq!((2, 1), fragments[0]);
+        assert_eq!((1, 2), fragments[MAX_WAITING_FRAGMENTS - 1]);
+    }
+
+    #[test]
+    fn test_session_inspection() {
+        let fragment = |fragment_index| Fragment {
+            fragment_index,
+            total_n_fragments: 3,
+            length: 0,
+            data: [0; 128],
+        };
+        let mut manager = SessionManager::new();
+        assert!(manager.pending_session_ids().is_empty());
+        assert_eq!(None, manager.fragments_remaining(4));
+        assert_eq!(None, manager.destination(4));
+
+        manager.add_session(4, vec![fragment(0), fragment(1), fragment(2)], 6);
+        manager.add_session(2, vec![fragment(0)], 7);
+        assert_eq!(vec![2, 4], manager.pending_session_ids());
+        assert_eq!(Some(3), manager.fragments_remaining(4));
+        assert_eq!(Some(6), manager.destination(4));
+
+        manager.handle_ack(&Ack { fragment_index: 1 }, 4);
+        assert_eq!(Some(2), manager.fragments_remaining(4));
+
+        manager.handle_ack(&Ack { fragment_index: 0 }, 2);
+        assert_eq!(vec![4], manager.pending_session_ids());
+        assert_eq!(None, manager.fragments_remaining(2));
+        assert_eq!(None, manager.destination(2));
+    }
+
+    #[test]
+    fn test_unknown_acks() {
+        let mut manager = SessionManager::new();
+        manager.add_session(
+            2,
+            vec![Fragment {
+                fragment_index: 0,
+                total_n_fragments: 1,
+                length: 0,
+                data: [0; 128],
+            }],
+            7,
+        );
+        manager.handle_ack(&Ack { fragment_index: 0 }, 2);
+        assert_eq!(0, manager.unknown_acks());
+
+        // a late ACK for the completed session
+        manager.handle_ack(&Ack { fragment_index: 0 }, 2);
+        assert_eq!(1, manager.unknown_acks());
+        // an ACK for a session that never existed
+        manager.handle_ack(&Ack { fragment_index: 3 }, 9);
+        assert_eq!(2, manager.unknown_acks());
+        assert!(manager.pending_session_ids().is_empty());
+    }
 }
@@ -1,14 +1,16 @@
 use crossbeam_channel::{select_biased, unbounded, Receiver, Sender};
-use dn_controller::{ServerCommand, ServerEvent};
-use dn_message::ClientContentBody;
-use dn_message::{ClientBody, Message, ServerBody, ServerContentBody, ServerType};
+use dn_controller::{DiscoveryJitter, DiscoveryMode, ServerCommand, ServerEvent};
+use dn_message::{AckMode, ClientContentBody};
+use dn_message::{
+    ClientBody, FileError, FileTree, Message, ServerBody, ServerContentBody, ServerType,
+};
 use dn_router::{
     command::{Command, Event},
     Router, RouterOptions,
 };
 use std::collections::HashMap;
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Component, Path, PathBuf};
 use walkdir::{DirEntry, WalkDir};
 use wg_2024::{
     network::NodeId,
@@ -24,10 +26,27 @@ pub struct ContentServerOptions {
     pub controller_recv: Receiver<ServerCommand>,
     pub packet_recv: Receiver<Packet>,
     pub packet_send: HashMap<NodeId, Sender<Packet>>,
+    // maximum depth of the asset directory walked when listing files, unlimited if `None`
+    pub max_depth: Option<usize>,
+    // maximum size in bytes of a file served, larger ones are refused without being read, unlimited if `None`
+    pub max_file_size: Option<u64>,
+    // named asset directories, whose files are requested as `root:path`. If empty, the files of `ASSET_DIR` are
+    // served with plain paths
+    pub asset_roots: HashMap<String, PathBuf>,
+    // when the fragments received are acknowledged, see `AckMode`
+    pub ack_mode: AckMode,
+    // when the server first floods the network to discover the topology, see `DiscoveryMode`
+    pub discovery_mode: DiscoveryMode,
+    // picks the delay of a jittered discovery, random if `None`
+    pub discovery_jitter: Option<DiscoveryJitter>,
 }
 
 pub struct ContentServer {
     id: NodeId,
+    asset_dir: PathBuf,
+    asset_roots: HashMap<String, PathBuf>,
+    max_depth: Option<usize>,
+    max_file_size: Option<u64>,
     router_opt: RouterOptions,
     controller_send: Sender<ServerEvent>,
     controller_recv: Receiver<ServerCommand>,
@@ -42,6 +61,10 @@ impl ContentServer {
         let (controller_event_send, controller_event_recv) = unbounded();
         Self {
             id: opt.id,
+            asset_dir: PathBuf::from(ASSET_DIR),
+            asset_roots: opt.asset_roots,
+            max_depth: opt.max_depth,
+            max_file_size: opt.max_file_size,
             router_opt: RouterOptions {
                 id: opt.id,
                 node_type: NodeType::Server,
@@ -49,6 +72,9 @@ impl ContentServer {
                 controller_recv: controller_event_recv,
                 packet_recv: opt.packet_recv,
                 packet_send: opt.packet_send,
+                ack_mode: opt.ack_mode,
+                discovery_mode: opt.discovery_mode,
+                discovery_jitter: opt.discovery_jitter,
             },
             controller_send: opt.controller_send,
             controller_recv: opt.controller_recv,
@@ -101,7 +127,36 @@ impl ContentServer {
             ServerCommand::RemoveSender(id) => {
                 self.router_recv.send(Command::RemoveSender(id)).unwrap();
             }
-            ServerCommand::Return => (),
+            ServerCommand::QueryTopology(sender) => {
+                self.router_recv
+                    .send(Command::QueryTopology(sender))
+                    .unwrap();
+            }
+            ServerCommand::PushMessage(body, to) => {
+                self.router_recv
+                    .send(Command::SendMessage(Message::Server(body), to))
+                    .unwrap();
+            }
+            ServerCommand::SendMessage(message, to) => {
+                self.router_recv
+                    .send(Command::SendMessage(message, to))
+                    .unwrap();
+            }
+            ServerCommand::QueryRoster(sender) => {
+                _ = sender.send(None);
+            }
+            // the sessions of a content server are kept by its router
+            ServerCommand::QuerySessions => {
+                self.controller_send
+                    .send(ServerEvent::Sessions {
+                        pending: 0,
+                        waiting_fragments: HashMap::new(),
+                        pending_messages: HashMap::new(),
+                    })
+                    .unwrap();
+            }
+            // clients don't register to a content server
+            ServerCommand::Deregister(_) | ServerCommand::Return => (),
         }
     }
 
@@ -111,10 +166,18 @@ impl ContentServer {
                 .controller_send
                 .send(ServerEvent::PacketReceived(packet, id))
                 .unwrap(),
-            Event::MessageAssembled { body, from, .. } => {
+            Event::MessageAssembled {
+                body,
+                from,
+                session_id,
+                ..
+            } => {
                 if let Message::Client(body) = body {
                     self.handle_client_body(body, from);
                 }
+                self.router_recv
+                    .send(Command::MessageProcessed { from, session_id })
+                    .unwrap();
             }
             Event::MessageFragmented { body, from, to } => {
                 if let Message::Server(body) = body {
@@ -123,10 +186,61 @@ impl ContentServer {
                         .unwrap();
                 }
             }
+            Event::MessageReassemblyProgress {
+                from,
+                to,
+                session_id,
+                received,
+                total,
+            } => self
+                .controller_send
+                .send(ServerEvent::MessageReassemblyProgress {
+                    from,
+                    to,
+                    session_id,
+                    received,
+                    total,
+                })
+                .unwrap(),
             Event::PacketSent(packet) => self
                 .controller_send
                 .send(ServerEvent::PacketSent(packet))
                 .unwrap(),
+            Event::MessageCorrupted {
+                from,
+                to,
+                session_id,
+            } => self
+                .controller_send
+                .send(ServerEvent::MessageCorrupted {
+                    from,
+                    to,
+                    session_id,
+                })
+                .unwrap(),
+            // the client can't be notified either, since there is no path to it
+            Event::RouteNotFound {
+                to,
+                session_id,
+                fragment_index,
+            } => self
+                .controller_send
+                .send(ServerEvent::RouteNotFound {
+                    from: self.id,
+                    to,
+                    session_id,
+                    fragment_index,
+                })
+                .unwrap(),
+            Event::MessageDelivered {
+                path: Some(path),
+                to,
+                ..
+            } => self
+                .controller_send
+                .send(ServerEvent::FileDelivered { to, path })
+                .unwrap(),
+            Event::MessageDelivered { path: None, .. } => (),
         };
     }
 
@@ -150,8 +264,18 @@ impl ContentServer {
             ClientBody::ClientContent(body) => match body {
                 ClientContentBody::ReqFilesList => self.req_file_list(from),
                 ClientContentBody::ReqFile(path) => self.req_file(path, from),
+                ClientContentBody::ReqFiles(paths) => self.req_files(paths, from),
+                ClientContentBody::ReqFileTree => self.req_file_tree(from),
+                ClientContentBody::ReqStats => self.req_stats(from),
             },
             ClientBody::ClientCommunication(_) => {
+                self.controller_send
+                    .send(ServerEvent::UnsupportedRequest {
+                        category: body.category(),
+                        from,
+                        to: self.id,
+                    })
+                    .unwrap();
                 self.router_recv
                     .send(Command::SendMessage(
                         Message::Server(ServerBody::ErrUnsupportedRequestType),
@@ -163,16 +287,26 @@ impl ContentServer {
     }
 
     fn req_file_list(&self, from: NodeId) {
-        let files = WalkDir::new(ASSET_DIR)
+        let files = self
+            .roots()
             .into_iter()
-            .flatten()
-            .map(DirEntry::into_path)
-            .filter(|p| p.is_file())
-            .map(|p| {
-                p.strip_prefix(ASSET_DIR)
-                    .unwrap()
-                    .to_string_lossy()
-                    .to_string()
+            .flat_map(|(name, dir)| {
+                let mut walk_dir = WalkDir::new(dir);
+                if let Some(max_depth) = self.max_depth {
+                    walk_dir = walk_dir.max_depth(max_depth);
+                }
+                walk_dir
+                    .into_iter()
+                    .flatten()
+                    .map(DirEntry::into_path)
+                    .filter(|p| p.is_file())
+                    .map(move |p| {
+                        let path = p.strip_prefix(dir).unwrap().to_string_lossy().to_string();
+                        match name {
+                            Some(name) => format!("{name}:{path}"),
+                            None => path,
+                        }
+                    })
             })
             .collect();
 
@@ -186,26 +320,884 @@ impl ContentServer {
             .unwrap();
     }
 
+    // the unnamed `asset_dir` if there are no named roots, otherwise the named roots sorted by name
+    fn roots(&self) -> Vec<(Option<&str>, &Path)> {
+        if self.asset_roots.is_empty() {
+            return vec![(None, self.asset_dir.as_path())];
+        }
+        let mut roots: Vec<_> = self
+            .asset_roots
+            .iter()
+            .map(|(name, dir)| (Some(name.as_str()), dir.as_path()))
+            .collect();
+        roots.sort_unstable_by_key(|&(name, _)| name);
+        roots
+    }
+
+    // the root a requested path refers to and the path relative to it, `None` if the root is unknown
+    fn resolve<'a>(&'a self, path: &'a str) -> Option<(&'a Path, &'a str)> {
+        if self.asset_roots.is_empty() {
+            return Some((self.asset_dir.as_path(), path));
+        }
+        let (name, path) = path.split_once(':')?;
+        let dir = self.asset_roots.get(name)?;
+        Some((dir.as_path(), path))
+    }
+
+    fn read_file(&self, path: &str) -> Result<Vec<u8>, FileError> {
+        let (dir, path) = self.resolve(path).ok_or(FileError::NotFound)?;
+        read_file(dir, path, self.max_file_size)
+    }
+
     fn req_file(&self, path: String, from: NodeId) {
-        let full_path = PathBuf::from(ASSET_DIR).join(&path);
-        if let Ok(bytes) = fs::read(full_path) {
-            self.router_recv
-                .send(Command::SendMessage(
-                    Message::Server(ServerBody::ServerContent(ServerContentBody::RespFile(
-                        bytes, path,
-                    ))),
-                    from,
-                ))
-                .unwrap();
+        let body = match self.read_file(&path) {
+            Ok(bytes) => {
+                let mime = mime_type(&bytes);
+                ServerContentBody::RespTypedFile(bytes, path, mime)
+            }
+            Err(FileError::NotFound) => ServerContentBody::ErrFileNotFound(path),
+            Err(FileError::TooLarge) => {
+                self.file_too_large(path.clone(), from);
+                ServerContentBody::ErrFileTooLarge(path)
+            }
+        };
+        self.router_recv
+            .send(Command::SendMessage(
+                Message::Server(ServerBody::ServerContent(body)),
+                from,
+            ))
+            .unwrap();
+    }
+
+    fn req_files(&self, paths: Vec<String>, from: NodeId) {
+        let files = paths
+            .into_iter()
+            .map(|path| {
+                let file = self.read_file(&path);
+                if file == Err(FileError::TooLarge) {
+                    self.file_too_large(path.clone(), from);
+                }
+                (path, file)
+            })
+            .collect();
+
+        self.router_recv
+            .send(Command::SendMessage(
+                Message::Server(ServerBody::ServerContent(ServerContentBody::RespFiles(
+                    files,
+                ))),
+                from,
+            ))
+            .unwrap();
+    }
+
+    fn file_too_large(&self, path: String, from: NodeId) {
+        self.controller_send
+            .send(ServerEvent::FileTooLarge {
+                from: self.id,
+                to: from,
+                path,
+            })
+            .unwrap();
+    }
+
+    fn req_file_tree(&self, from: NodeId) {
+        // the root is unnamed, so that the paths built from the tree are relative to the asset dir.
+        // Named roots are its directories instead
+        let tree = if self.asset_roots.is_empty() {
+            file_tree(&self.asset_dir, String::new(), self.max_depth)
         } else {
-            self.router_recv
-                .send(Command::SendMessage(
-                    Message::Server(ServerBody::ServerContent(
-                        ServerContentBody::ErrFileNotFound,
-                    )),
-                    from,
-                ))
-                .unwrap();
+            FileTree {
+                dirs: self
+                    .roots()
+                    .into_iter()
+                    .map(|(name, dir)| {
+                        file_tree(dir, name.unwrap_or_default().to_string(), self.max_depth)
+                    })
+                    .collect(),
+                ..FileTree::default()
+            }
+        };
+
+        self.router_recv
+            .send(Command::SendMessage(
+                Message::Server(ServerBody::ServerContent(ServerContentBody::RespFileTree(
+                    tree,
+                ))),
+                from,
+            ))
+            .unwrap();
+    }
+
+    fn req_stats(&self, from: NodeId) {
+        let (file_count, total_bytes) =
+            self.roots()
+                .into_iter()
+                .fold((0, 0), |(file_count, total_bytes), (_, dir)| {
+                    let (count, bytes) = asset_stats(dir, self.max_depth);
+                    (file_count + count, total_bytes + bytes)
+                });
+
+        self.router_recv
+            .send(Command::SendMessage(
+                Message::Server(ServerBody::ServerContent(ServerContentBody::RespStats {
+                    file_count,
+                    total_bytes,
+                })),
+                from,
+            ))
+            .unwrap();
+    }
+}
+
+// the files counted are the same of the list, so `max_depth` is honored as well
+fn asset_stats(dir: &Path, max_depth: Option<usize>) -> (u64, u64) {
+    let mut walk_dir = WalkDir::new(dir);
+    if let Some(max_depth) = max_depth {
+        walk_dir = walk_dir.max_depth(max_depth);
+    }
+    walk_dir
+        .into_iter()
+        .flatten()
+        .filter(|entry| entry.file_type().is_file())
+        .filter_map(|entry| entry.metadata().ok())
+        .fold((0, 0), |(file_count, total_bytes), metadata| {
+            (file_count + 1, total_bytes + metadata.len())
+        })
+}
+
+// `max_depth` has the same meaning as in `WalkDir`, so the tree holds the same files as the list.
+// Like `WalkDir`, symlinks aren't followed, so a link to a parent dir can't loop forever
+fn file_tree(dir: &Path, name: String, max_depth: Option<usize>) -> FileTree {
+    let mut tree = FileTree {
+        name,
+        ..FileTree::default()
+    };
+    if max_depth == Some(0) {
+        return tree;
+    }
+    for entry in fs::read_dir(dir).into_iter().flatten().flatten() {
+        let path = entry.path();
+        let name = entry.file_name().to_string_lossy().to_string();
+        let Ok(file_type) = entry.file_type() else {
+            continue;
+        };
+        if file_type.is_file() {
+            tree.files.push(name);
+        } else if file_type.is_dir() {
+            tree.dirs
+                .push(file_tree(&path, name, max_depth.map(|depth| depth - 1)));
+        }
+    }
+    tree.files.sort_unstable();
+    tree.dirs.sort_unstable_by(|a, b| a.name.cmp(&b.name));
+    tree
+}
+
+// the size is checked before reading, so that a huge file is never loaded in memory.
+// Paths escaping the asset dir, e.g. through `..`, are refused as not found
+fn read_file(
+    asset_dir: &Path,
+    path: &str,
+    max_file_size: Option<u64>,
+) -> Result<Vec<u8>, FileError> {
+    let path = Path::new(path);
+    if !path
+        .components()
+        .all(|component| matches!(component, Component::Normal(_) | Component::CurDir))
+    {
+        return Err(FileError::NotFound);
+    }
+    let path = asset_dir.join(path);
+    let size = fs::metadata(&path).map_err(|_| FileError::NotFound)?.len();
+    if max_file_size.is_some_and(|max_file_size| size > max_file_size) {
+        return Err(FileError::TooLarge);
+    }
+    fs::read(path).map_err(|_| FileError::NotFound)
+}
+
+fn mime_type(bytes: &[u8]) -> Option<String> {
+    infer::get(bytes).map(|info| info.mime_type().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use dn_message::{Assembler, ClientCommunicationBody};
+    use std::env;
+    use std::ops::Deref;
+    use std::sync::Arc;
+    use std::time::{Duration, Instant};
+    use wg_2024::network::SourceRoutingHeader;
+    use wg_2024::packet::{Ack, FloodResponse, PacketType};
+
+    /// Directory of the files of a test, removed with its content when dropped, even if the test fails.
+    struct TestDir(PathBuf);
+
+    impl Deref for TestDir {
+        type Target = PathBuf;
+
+        fn deref(&self) -> &PathBuf {
+            &self.0
+        }
+    }
+
+    impl AsRef<Path> for TestDir {
+        fn as_ref(&self) -> &Path {
+            &self.0
+        }
+    }
+
+    impl Drop for TestDir {
+        fn drop(&mut self) {
+            _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    /// Creates an empty directory for the test `name`, unique to this process so that concurrent runs
+    /// of the tests don't share it.
+    fn test_dir(name: &str) -> TestDir {
+        let dir = env::temp_dir().join(format!(
+            "dn_content_server_test_{name}_{}",
+            std::process::id()
+        ));
+        _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        TestDir(dir)
+    }
+
+    fn content_server() -> (ContentServer, Receiver<ServerEvent>) {
+        let (controller_send, event_recv) = unbounded();
+        let (_, controller_recv) = unbounded();
+        let (_, packet_recv) = unbounded();
+        let server = ContentServer::new(ContentServerOptions {
+            id: 1,
+            controller_send,
+            controller_recv,
+            packet_recv,
+            packet_send: HashMap::new(),
+            max_depth: None,
+            max_file_size: None,
+            asset_roots: HashMap::new(),
+            ack_mode: AckMode::OnReceipt,
+            discovery_mode: DiscoveryMode::Immediate,
+            discovery_jitter: None,
+        });
+        (server, event_recv)
+    }
+
+    #[test]
+    fn test_req_files() {
+        let asset_dir = test_dir("req_files");
+        fs::write(asset_dir.join("a.txt"), b"a").unwrap();
+        fs::write(asset_dir.join("b.txt"), b"b").unwrap();
+
+        let (mut server, _event_recv) = content_server();
+        server.asset_dir.clone_from(&asset_dir);
+        server.handle_client_body(
+            ClientBody::ClientContent(ClientContentBody::ReqFiles(vec![
+                "a.txt".to_string(),
+                "missing.txt".to_string(),
+                "b.txt".to_string(),
+            ])),
+            5,
+        );
+
+        let Ok(Command::SendMessage(
+            Message::Server(ServerBody::ServerContent(ServerContentBody::RespFiles(files))),
+            5,
+        )) = server.router_opt.controller_recv.try_recv()
+        else {
+            panic!("expected a single RespFiles for node 5");
+        };
+        assert_eq!(
+            files,
+            vec![
+                ("a.txt".to_string(), Ok(b"a".to_vec())),
+                ("missing.txt".to_string(), Err(FileError::NotFound)),
+                ("b.txt".to_string(), Ok(b"b".to_vec())),
+            ]
+        );
+        assert!(server.router_opt.controller_recv.try_recv().is_err());
+    }
+
+    #[test]
+    fn test_req_file_mime_type() {
+        let asset_dir = test_dir("mime_type");
+        fs::write(
+            asset_dir.join("a.html"),
+            b"<!DOCTYPE html><html><body>a</body></html>",
+        )
+        .unwrap();
+        fs::write(asset_dir.join("a.png"), b"\x89PNG\r\n\x1a\n\0\0\0\x0dIHDR").unwrap();
+
+        let (mut server, _event_recv) = content_server();
+        server.asset_dir.clone_from(&asset_dir);
+        let mime_type = |path: &str| {
+            server.handle_client_body(
+                ClientBody::ClientContent(ClientContentBody::ReqFile(path.to_string())),
+                5,
+            );
+            let Ok(Command::SendMessage(
+                Message::Server(ServerBody::ServerContent(ServerContentBody::RespTypedFile(
+                    _,
+                    _,
+                    mime,
+                ))),
+                5,
+            )) = server.router_opt.controller_recv.try_recv()
+            else {
+                panic!("expected a RespTypedFile for node 5");
+            };
+            mime
+        };
+
+        assert_eq!(mime_type("a.html").as_deref(), Some("text/html"));
+        assert_eq!(mime_type("a.png").as_deref(), Some("image/png"));
+    }
+
+    #[test]
+    fn test_unsupported_request() {
+        let (server, event_recv) = content_server();
+        server.handle_client_body(
+            ClientBody::ClientCommunication(ClientCommunicationBody::ReqClientList),
+            5,
+        );
+
+        assert!(matches!(
+            server.router_opt.controller_recv.try_recv(),
+            Ok(Command::SendMessage(
+                Message::Server(ServerBody::ErrUnsupportedRequestType),
+                5
+            ))
+        ));
+        let events = event_recv.try_iter().collect::<Vec<_>>();
+        assert!(events.iter().any(|event| matches!(
+            event,
+            ServerEvent::UnsupportedRequest {
+                category: "communication",
+                from: 5,
+                to: 1
+            }
+        )));
+    }
+
+    #[test]
+    fn test_route_not_found() {
+        let (server, event_recv) = content_server();
+        let mut router = Router::new(server.router_opt.clone());
+        let handle = std::thread::spawn(move || router.run());
+
+        // the server has no neighbors, so there is no path back to node 5
+        server.handle_client_body(ClientBody::ReqServerType, 5);
+        loop {
+            let event = server
+                .router_send
+                .recv_timeout(std::time::Duration::from_secs(1))
+                .expect("expected a RouteNotFound event from the router");
+            let done = matches!(event, Event::RouteNotFound { .. });
+            server.handle_event(event);
+            if done {
+                break;
+            }
+        }
+        server.return_router();
+        handle.join().unwrap();
+
+        let events = event_recv.try_iter().collect::<Vec<_>>();
+        assert!(events.iter().any(|event| matches!(
+            event,
+            ServerEvent::RouteNotFound {
+                from: 1,
+                to: 5,
+                fragment_index: 0,
+                ..
+            }
+        )));
+    }
+
+    #[test]
+    fn test_req_files_list_max_depth() {
+        let asset_dir = test_dir("max_depth");
+        fs::create_dir_all(asset_dir.join("a").join("b")).unwrap();
+        fs::write(asset_dir.join("top.txt"), b"top").unwrap();
+        fs::write(asset_dir.join("a").join("nested.txt"), b"nested").unwrap();
+        fs::write(asset_dir.join("a").join("b").join("deep.txt"), b"deep").unwrap();
+
+        let (mut server, _event_recv) = content_server();
+        server.asset_dir.clone_from(&asset_dir);
+        let list_files = |server: &ContentServer| {
+            server.handle_client_body(
+                ClientBody::ClientContent(ClientContentBody::ReqFilesList),
+                5,
+            );
+            let Ok(Command::SendMessage(
+                Message::Server(ServerBody::ServerContent(ServerContentBody::RespFilesList(
+                    mut files,
+                ))),
+                5,
+            )) = server.router_opt.controller_recv.try_recv()
+            else {
+                panic!("expected a RespFilesList for node 5");
+            };
+            files.sort_unstable();
+            files
+        };
+
+        assert_eq!(list_files(&server).len(), 3);
+        server.max_depth = Some(1);
+        assert_eq!(list_files(&server), vec!["top.txt".to_string()]);
+    }
+
+    #[test]
+    fn test_req_file_tree() {
+        let asset_dir = test_dir("file_tree");
+        fs::create_dir_all(asset_dir.join("a").join("b")).unwrap();
+        fs::create_dir_all(asset_dir.join("c")).unwrap();
+        fs::write(asset_dir.join("top.txt"), b"top").unwrap();
+        fs::write(asset_dir.join("a").join("nested.txt"), b"nested").unwrap();
+        fs::write(asset_dir.join("a").join("other.txt"), b"other").unwrap();
+        fs::write(asset_dir.join("a").join("b").join("deep.txt"), b"deep").unwrap();
+
+        let (mut server, _event_recv) = content_server();
+        server.asset_dir.clone_from(&asset_dir);
+        server.handle_client_body(ClientBody::ClientContent(ClientContentBody::ReqFileTree), 5);
+        let Ok(Command::SendMessage(
+            Message::Server(ServerBody::ServerContent(ServerContentBody::RespFileTree(tree))),
+            5,
+        )) = server.router_opt.controller_recv.try_recv()
+        else {
+            panic!("expected a RespFileTree for node 5");
+        };
+
+        let dir = |name: &str, files: &[&str], dirs: Vec<FileTree>| FileTree {
+            name: name.to_string(),
+            files: files.iter().map(ToString::to_string).collect(),
+            dirs,
+        };
+        assert_eq!(
+            tree,
+            dir(
+                "",
+                &["top.txt"],
+                vec![
+                    dir(
+                        "a",
+                        &["nested.txt", "other.txt"],
+                        vec![dir("b", &["deep.txt"], vec![])]
+                    ),
+                    dir("c", &[], vec![]),
+                ]
+            )
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_file_tree_symlinks() {
+        let asset_dir = test_dir("file_tree_symlinks");
+        fs::create_dir_all(asset_dir.join("a")).unwrap();
+        fs::write(asset_dir.join("top.txt"), b"top").unwrap();
+        std::os::unix::fs::symlink(&asset_dir, asset_dir.join("a").join("loop")).unwrap();
+        std::os::unix::fs::symlink(asset_dir.join("top.txt"), asset_dir.join("link.txt")).unwrap();
+
+        let tree = file_tree(&asset_dir, String::new(), None);
+        assert_eq!(tree.files, vec!["top.txt".to_string()]);
+        assert_eq!(tree.dirs.len(), 1);
+        assert!(tree.dirs[0].files.is_empty() && tree.dirs[0].dirs.is_empty());
+    }
+
+    #[test]
+    fn test_file_delivered() {
+        let asset_dir = test_dir("file_delivered");
+        let bytes = (0..1000).map(|i| (i * 7 % 251) as u8).collect::<Vec<_>>();
+        fs::write(asset_dir.join("a.bin"), bytes).unwrap();
+
+        let (controller_send, event_recv) = unbounded();
+        let (_, controller_recv) = unbounded();
+        let (packet_send_1, packet_recv) = unbounded();
+        let (packet_send_2, packet_recv_2) = unbounded();
+        let mut server = ContentServer::new(ContentServerOptions {
+            id: 1,
+            controller_send,
+            controller_recv,
+            packet_recv,
+            packet_send: HashMap::from([(2, packet_send_2)]),
+            max_depth: None,
+            max_file_size: None,
+            asset_roots: HashMap::new(),
+            ack_mode: AckMode::OnReceipt,
+            discovery_mode: DiscoveryMode::Immediate,
+            discovery_jitter: None,
+        });
+        server.asset_dir.clone_from(&asset_dir);
+        let mut router = Router::new(server.router_opt.clone());
+        let handle = std::thread::spawn(move || router.run());
+        let next_event = |server: &ContentServer| {
+            let event = server
+                .router_send
+                .recv_timeout(Duration::from_secs(1))
+                .expect("expected an event from the router");
+            let delivered = matches!(event, Event::MessageDelivered { .. });
+            server.handle_event(event);
+            delivered
+        };
+
+        // path 1 -> 2 -> 5
+        packet_send_1
+            .send(Packet {
+                routing_header: SourceRoutingHeader {
+                    hop_index: 2,
+                    hops: vec![5, 2, 1],
+                },
+                session_id: 0,
+                pack_type: PacketType::FloodResponse(FloodResponse {
+                    flood_id: 0,
+                    path_trace: vec![
+                        (1, NodeType::Server),
+                        (2, NodeType::Drone),
+                        (5, NodeType::Client),
+                    ],
+                }),
+            })
+            .unwrap();
+        server.handle_client_body(
+            ClientBody::ClientContent(ClientContentBody::ReqFile("a.bin".to_string())),
+            5,
+        );
+
+        let ack = |session_id, fragment_index| Packet {
+            routing_header: SourceRoutingHeader {
+                hop_index: 2,
+                hops: vec![5, 2, 1],
+            },
+            session_id,
+            pack_type: PacketType::Ack(Ack { fragment_index }),
+        };
+        let mut acked = 0;
+        let mut total = None;
+        while total != Some(acked) {
+            let packet = packet_recv_2
+                .recv_timeout(Duration::from_secs(1))
+                .expect("expected the fragments of the file on node 2");
+            if let PacketType::MsgFragment(fragment) = packet.pack_type {
+                total = Some(fragment.total_n_fragments);
+                packet_send_1
+                    .send(ack(packet.session_id, fragment.fragment_index))
+                    .unwrap();
+                // a duplicated ack doesn't complete the delivery twice
+                packet_send_1
+                    .send(ack(packet.session_id, fragment.fragment_index))
+                    .unwrap();
+                acked += 1;
+            }
+        }
+        assert!(total > Some(1));
+        while !next_event(&server) {}
+        server.return_router();
+        handle.join().unwrap();
+        while let Ok(event) = server.router_send.try_recv() {
+            server.handle_event(event);
         }
+
+        let delivered = event_recv
+            .try_iter()
+            .filter(|event| matches!(event, ServerEvent::FileDelivered { .. }))
+            .collect::<Vec<_>>();
+        assert_eq!(delivered.len(), 1);
+        assert!(matches!(
+            &delivered[0],
+            ServerEvent::FileDelivered { to: 5, path } if path == "a.bin"
+        ));
+    }
+
+    #[test]
+    fn test_ack_mode() {
+        // whether the ACK of a single fragment request is sent before the server handles the request
+        let ack_before_handling = |ack_mode| {
+            let (controller_send, _event_recv) = unbounded();
+            let (_, controller_recv) = unbounded();
+            let (packet_send_1, packet_recv) = unbounded();
+            let (packet_send_2, packet_recv_2) = unbounded();
+            let server = ContentServer::new(ContentServerOptions {
+                id: 1,
+                controller_send,
+                controller_recv,
+                packet_recv,
+                packet_send: HashMap::from([(2, packet_send_2)]),
+                max_depth: None,
+                max_file_size: None,
+                asset_roots: HashMap::new(),
+                ack_mode,
+                discovery_mode: DiscoveryMode::Immediate,
+                discovery_jitter: None,
+            });
+            let mut router = Router::new(server.router_opt.clone());
+            let handle = std::thread::spawn(move || router.run());
+
+            let fragments =
+                Assembler::new().serialize_message(&Message::Client(ClientBody::ReqServerType));
+            assert_eq!(fragments.len(), 1);
+            packet_send_1
+                .send(Packet {
+                    routing_header: SourceRoutingHeader {
+                        hop_index: 2,
+                        hops: vec![5, 2, 1],
+                    },
+                    session_id: 0,
+                    pack_type: PacketType::MsgFragment(fragments[0].clone()),
+                })
+                .unwrap();
+            let is_ack = |packet: Packet| matches!(packet.pack_type, PacketType::Ack(_));
+
+            let event = loop {
+                let event = server
+                    .router_send
+                    .recv_timeout(Duration::from_secs(1))
+                    .expect("expected the request to be assembled");
+                if matches!(event, Event::MessageAssembled { .. }) {
+                    break event;
+                }
+                server.handle_event(event);
+            };
+            let before = packet_recv_2.try_iter().any(is_ack);
+            server.handle_event(event);
+            let after = before
+                || std::iter::from_fn(|| packet_recv_2.recv_timeout(Duration::from_secs(1)).ok())
+                    .any(is_ack);
+
+            server.return_router();
+            handle.join().unwrap();
+            assert!(after, "expected the fragment to be acknowledged");
+            before
+        };
+
+        assert!(ack_before_handling(AckMode::OnReceipt));
+        assert!(!ack_before_handling(AckMode::OnProcessed));
+    }
+
+    #[test]
+    fn test_req_stats() {
+        let asset_dir = test_dir("stats");
+        fs::create_dir_all(asset_dir.join("a").join("b")).unwrap();
+        fs::write(asset_dir.join("top.txt"), b"top").unwrap();
+        fs::write(asset_dir.join("a").join("nested.txt"), b"nested").unwrap();
+        fs::write(asset_dir.join("a").join("b").join("deep.txt"), b"deep").unwrap();
+
+        let (mut server, _event_recv) = content_server();
+        server.asset_dir.clone_from(&asset_dir);
+        let stats = |server: &ContentServer| {
+            server.handle_client_body(ClientBody::ClientContent(ClientContentBody::ReqStats), 5);
+            let Ok(Command::SendMessage(
+                Message::Server(ServerBody::ServerContent(ServerContentBody::RespStats {
+                    file_count,
+                    total_bytes,
+                })),
+                5,
+            )) = server.router_opt.controller_recv.try_recv()
+            else {
+                panic!("expected a RespStats for node 5");
+            };
+            (file_count, total_bytes)
+        };
+
+        assert_eq!(stats(&server), (3, 13));
+        server.max_depth = Some(1);
+        assert_eq!(stats(&server), (1, 3));
+    }
+
+    #[test]
+    fn test_max_file_size() {
+        let asset_dir = test_dir("max_file_size");
+        fs::write(asset_dir.join("small.txt"), b"small").unwrap();
+        fs::write(asset_dir.join("big.txt"), vec![b'a'; 1000]).unwrap();
+
+        let (mut server, event_recv) = content_server();
+        server.asset_dir.clone_from(&asset_dir);
+        server.max_file_size = Some(100);
+        let req_file = |path: &str| {
+            server.handle_client_body(
+                ClientBody::ClientContent(ClientContentBody::ReqFile(path.to_string())),
+                5,
+            );
+            let Ok(Command::SendMessage(Message::Server(ServerBody::ServerContent(body)), 5)) =
+                server.router_opt.controller_recv.try_recv()
+            else {
+                panic!("expected a content response for node 5");
+            };
+            body
+        };
+
+        assert!(matches!(
+            req_file("big.txt"),
+            ServerContentBody::ErrFileTooLarge(path) if path == "big.txt"
+        ));
+        assert!(matches!(
+            req_file("small.txt"),
+            ServerContentBody::RespTypedFile(bytes, _, _) if bytes == b"small"
+        ));
+        assert!(matches!(
+            req_file("missing.txt"),
+            ServerContentBody::ErrFileNotFound(path) if path == "missing.txt"
+        ));
+
+        server.handle_client_body(
+            ClientBody::ClientContent(ClientContentBody::ReqFiles(vec![
+                "big.txt".to_string(),
+                "small.txt".to_string(),
+            ])),
+            5,
+        );
+        let Ok(Command::SendMessage(
+            Message::Server(ServerBody::ServerContent(ServerContentBody::RespFiles(files))),
+            5,
+        )) = server.router_opt.controller_recv.try_recv()
+        else {
+            panic!("expected a RespFiles for node 5");
+        };
+        assert_eq!(
+            files,
+            vec![
+                ("big.txt".to_string(), Err(FileError::TooLarge)),
+                ("small.txt".to_string(), Ok(b"small".to_vec())),
+            ]
+        );
+
+        let too_large = event_recv
+            .try_iter()
+            .filter(|event| {
+                matches!(
+                    event,
+                    ServerEvent::FileTooLarge { from: 1, to: 5, path } if path == "big.txt"
+                )
+            })
+            .count();
+        assert_eq!(too_large, 2);
+    }
+
+    #[test]
+    fn test_asset_roots() {
+        let base_dir = test_dir("asset_roots");
+        let docs = base_dir.join("docs");
+        let images = base_dir.join("images");
+        fs::create_dir_all(&docs).unwrap();
+        fs::create_dir_all(&images).unwrap();
+        fs::write(base_dir.join("secret.txt"), b"secret").unwrap();
+        fs::write(docs.join("a.txt"), b"doc").unwrap();
+        fs::write(images.join("a.txt"), b"image").unwrap();
+
+        let (mut server, _event_recv) = content_server();
+        server.asset_roots = HashMap::from([
+            ("docs".to_string(), docs.clone()),
+            ("images".to_string(), images.clone()),
+        ]);
+
+        server.handle_client_body(
+            ClientBody::ClientContent(ClientContentBody::ReqFilesList),
+            5,
+        );
+        let Ok(Command::SendMessage(
+            Message::Server(ServerBody::ServerContent(ServerContentBody::RespFilesList(files))),
+            5,
+        )) = server.router_opt.controller_recv.try_recv()
+        else {
+            panic!("expected a RespFilesList for node 5");
+        };
+        assert_eq!(
+            files,
+            vec!["docs:a.txt".to_string(), "images:a.txt".to_string()]
+        );
+
+        server.handle_client_body(
+            ClientBody::ClientContent(ClientContentBody::ReqFiles(vec![
+                "docs:a.txt".to_string(),
+                "images:a.txt".to_string(),
+                // no root
+                "a.txt".to_string(),
+                "videos:a.txt".to_string(),
+                // the traversal is refused by every root
+                "docs:../secret.txt".to_string(),
+                "images:../docs/a.txt".to_string(),
+            ])),
+            5,
+        );
+        let Ok(Command::SendMessage(
+            Message::Server(ServerBody::ServerContent(ServerContentBody::RespFiles(files))),
+            5,
+        )) = server.router_opt.controller_recv.try_recv()
+        else {
+            panic!("expected a RespFiles for node 5");
+        };
+        let files: Vec<_> = files.into_iter().map(|(_, file)| file).collect();
+        assert_eq!(
+            files,
+            vec![
+                Ok(b"doc".to_vec()),
+                Ok(b"image".to_vec()),
+                Err(FileError::NotFound),
+                Err(FileError::NotFound),
+                Err(FileError::NotFound),
+                Err(FileError::NotFound),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_discovery_mode() {
+        let start_router = |discovery_mode, discovery_jitter| {
+            let (controller_send, _event_recv) = unbounded();
+            let (_, controller_recv) = unbounded();
+            let (packet_send_1, packet_recv) = unbounded();
+            let (packet_send_2, packet_recv_2) = unbounded();
+            let server = ContentServer::new(ContentServerOptions {
+                id: 1,
+                controller_send,
+                controller_recv,
+                packet_recv,
+                packet_send: HashMap::from([(2, packet_send_2)]),
+                max_depth: None,
+                max_file_size: None,
+                asset_roots: HashMap::new(),
+                ack_mode: AckMode::OnReceipt,
+                discovery_mode,
+                discovery_jitter,
+            });
+            let mut router = Router::new(server.router_opt.clone());
+            let handle = std::thread::spawn(move || router.run());
+            (server, handle, packet_send_1, packet_recv_2)
+        };
+        let is_flood_request =
+            |packet: Packet| matches!(packet.pack_type, PacketType::FloodRequest(_));
+
+        // lazy, the network is flooded along with the first message
+        let (server, handle, _packet_send_1, packet_recv_2) =
+            start_router(DiscoveryMode::Lazy, None);
+        assert!(packet_recv_2
+            .recv_timeout(Duration::from_millis(200))
+            .is_err());
+        server
+            .router_recv
+            .send(Command::SendMessage(
+                Message::Server(ServerBody::RespServerType(ServerType::Content)),
+                5,
+            ))
+            .unwrap();
+        let packet = packet_recv_2.recv_timeout(Duration::from_secs(1)).unwrap();
+        assert!(is_flood_request(packet));
+        server.return_router();
+        handle.join().unwrap();
+
+        // jittered, the delay is picked by the injected jitter
+        let delay = Duration::from_millis(100);
+        let start = Instant::now();
+        let (server, handle, _packet_send_1, packet_recv_2) = start_router(
+            DiscoveryMode::Jittered(Duration::from_secs(1)),
+            Some(Arc::new(move |_: Duration| delay)),
+        );
+        let packet = packet_recv_2.recv_timeout(Duration::from_secs(1)).unwrap();
+        assert!(is_flood_request(packet));
+        assert!(start.elapsed() >= delay);
+        server.return_router();
+        handle.join().unwrap();
     }
 }